@@ -0,0 +1,310 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Generating update feeds for Sparkle and WinSparkle.
+
+An appcast is an RSS feed with an item per release, which applications
+with a built-in updater poll for new versions. Enclosures are signed with
+Ed25519 (EdDSA), as Sparkle requires by default.
+
+Items of a previous feed are preserved, so the feed accumulates releases.
+The previous feed is only processed textually: its `<item>` elements are
+copied verbatim.
+*/
+
+use crate::retry::{AttemptError, FailureClass, RetryPolicy};
+use crate::secrets::Secret;
+use crate::xml::xml_escape;
+use base64::Engine;
+use serde::Serialize;
+use slog::{warn, Logger};
+use std::path::{Path, PathBuf};
+
+/// Describes an appcast to write.
+#[derive(Clone, Debug, Serialize)]
+pub struct AppcastFeed {
+    /// URL the appcast is served at.
+    pub feed_url: String,
+
+    /// Title of the feed.
+    pub title: String,
+
+    pub version: String,
+
+    /// URL of the directory artifacts are downloaded from.
+    pub download_url: String,
+
+    /// Path or URL of the previous version of the appcast.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub previous_feed: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub release_notes_url: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub minimum_system_version: Option<String>,
+
+    /// `sparkle:os` of every item, instead of deriving it from filenames.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub os: Option<String>,
+
+    /// Filename of the appcast, relative to the distribution path.
+    pub filename: String,
+}
+
+/// Sign data with an Ed25519 private key, as Sparkle's `sign_update` does.
+///
+/// `key` is the base64 encoded key exported by Sparkle's `generate_keys`:
+/// either the 32 byte seed or the 64 byte seed and public key. Returns the
+/// base64 encoded signature.
+pub fn ed25519_sign(key: &str, data: &[u8]) -> Result<String, String> {
+    let engine = base64::engine::general_purpose::STANDARD;
+    let key = engine
+        .decode(key.trim())
+        .map_err(|e| format!("EdDSA key is not base64: {}", e))?;
+
+    // Key pairs are verified to have a public key matching the seed.
+    let pair = match key.len() {
+        32 => ring::signature::Ed25519KeyPair::from_seed_unchecked(&key),
+        64 => ring::signature::Ed25519KeyPair::from_seed_and_public_key(&key[..32], &key[32..]),
+        n => {
+            return Err(format!(
+                "EdDSA key is {} bytes; expected a 32 byte seed or 64 byte key pair",
+                n
+            ))
+        }
+    }
+    .map_err(|e| format!("invalid EdDSA key: {}", e))?;
+
+    Ok(engine.encode(pair.sign(data).as_ref()))
+}
+
+/// The `sparkle:os` of an artifact, if it isn't for macOS.
+fn artifact_os(filename: &str) -> Option<&'static str> {
+    let lower = filename.to_lowercase();
+
+    if lower.ends_with(".exe") || lower.ends_with(".msi") {
+        Some("windows")
+    } else {
+        None
+    }
+}
+
+/// Extract the `<item>` elements of a feed, except those of `version`.
+fn previous_items(feed: &str, version: &str) -> Vec<String> {
+    let version_element = format!("<sparkle:version>{}</sparkle:version>", xml_escape(version));
+    let version_attribute = format!("sparkle:version=\"{}\"", xml_escape(version));
+    let mut items = Vec::new();
+    let mut rest = feed;
+
+    while let Some(start) = rest.find("<item") {
+        // Skip elements like `<items>` that merely share the prefix.
+        if !rest[start + "<item".len()..].starts_with(|c: char| c == '>' || c.is_whitespace()) {
+            rest = &rest[start + "<item".len()..];
+            continue;
+        }
+
+        let end = match rest[start..].find("</item>") {
+            Some(end) => start + end + "</item>".len(),
+            None => break,
+        };
+        let item = &rest[start..end];
+
+        if !item.contains(&version_element) && !item.contains(&version_attribute) {
+            items.push(item.to_string());
+        }
+
+        rest = &rest[end..];
+    }
+
+    items
+}
+
+/// Obtain the content of a previous feed from a path or URL.
+fn read_previous_feed(
+    logger: &Logger,
+    location: &str,
+    retry: &RetryPolicy,
+) -> Result<String, String> {
+    if location.starts_with("https://") || location.starts_with("http://") {
+        retry.retry(logger, &format!("download of {}", location), || {
+            match ureq::get(location).call() {
+                Ok(response) => response.into_string().map_err(|e| AttemptError {
+                    class: Some(FailureClass::Network),
+                    message: format!("error reading {}: {}", location, e),
+                }),
+                // The feed doesn't exist before the first release.
+                Err(ureq::Error::Status(404, _)) => Ok(String::new()),
+                Err(e) => Err(AttemptError::http(e)),
+            }
+        })
+    } else {
+        std::fs::read_to_string(location).map_err(|e| format!("unable to read {}: {}", location, e))
+    }
+}
+
+/// Render the item describing an artifact of the release.
+fn release_item(
+    feed: &AppcastFeed,
+    pub_date: &str,
+    url: &str,
+    length: u64,
+    os: Option<&str>,
+    signature: Option<&str>,
+) -> String {
+    let mut s = String::from("    <item>\n");
+
+    s.push_str(&format!(
+        "      <title>Version {}</title>\n",
+        xml_escape(&feed.version)
+    ));
+    s.push_str(&format!("      <pubDate>{}</pubDate>\n", pub_date));
+    s.push_str(&format!(
+        "      <sparkle:version>{}</sparkle:version>\n",
+        xml_escape(&feed.version)
+    ));
+    s.push_str(&format!(
+        "      <sparkle:shortVersionString>{}</sparkle:shortVersionString>\n",
+        xml_escape(&feed.version)
+    ));
+
+    if let Some(url) = &feed.release_notes_url {
+        s.push_str(&format!(
+            "      <sparkle:releaseNotesLink>{}</sparkle:releaseNotesLink>\n",
+            xml_escape(url)
+        ));
+    }
+    if let Some(version) = &feed.minimum_system_version {
+        s.push_str(&format!(
+            "      <sparkle:minimumSystemVersion>{}</sparkle:minimumSystemVersion>\n",
+            xml_escape(version)
+        ));
+    }
+
+    s.push_str(&format!(
+        "      <enclosure url=\"{}\" length=\"{}\" type=\"application/octet-stream\"",
+        xml_escape(url),
+        length
+    ));
+    if let Some(os) = os {
+        s.push_str(&format!(" sparkle:os=\"{}\"", os));
+    }
+    if let Some(signature) = signature {
+        s.push_str(&format!(" sparkle:edSignature=\"{}\"", signature));
+    }
+    s.push_str("/>\n    </item>");
+
+    s
+}
+
+/// Write an appcast describing a release of artifacts.
+///
+/// Each artifact becomes an item of the release, preceding the items of
+/// the previous feed. Artifacts are signed with `ed_key` if defined.
+/// `mtime` is the publication time of the release.
+pub fn write_appcast(
+    logger: &Logger,
+    dist_path: &Path,
+    artifacts: &[PathBuf],
+    feed: &AppcastFeed,
+    ed_key: Option<&str>,
+    mtime: u64,
+    retry: &RetryPolicy,
+) -> Result<PathBuf, String> {
+    let pub_date = chrono::DateTime::<chrono::Utc>::from_utc(
+        chrono::NaiveDateTime::from_timestamp_opt(mtime as i64, 0)
+            .unwrap_or_else(|| chrono::NaiveDateTime::from_timestamp(0, 0)),
+        chrono::Utc,
+    )
+    .to_rfc2822();
+
+    let mut items = Vec::new();
+
+    for artifact in artifacts {
+        let filename = artifact
+            .file_name()
+            .map(|f| f.to_string_lossy().to_string())
+            .ok_or_else(|| format!("{} has no filename", artifact.display()))?;
+        let data = std::fs::read(artifact)
+            .map_err(|e| format!("unable to read {}: {}", artifact.display(), e))?;
+
+        let signature = match ed_key {
+            Some(key) => Some(ed25519_sign(key, &data)?),
+            None => None,
+        };
+        let url = format!(
+            "{}/{}",
+            feed.download_url.trim_end_matches('/'),
+            crate::http::percent_encode(&filename)
+        );
+
+        items.push(release_item(
+            feed,
+            &pub_date,
+            &url,
+            data.len() as u64,
+            feed.os.as_deref().or_else(|| artifact_os(&filename)),
+            signature.as_deref(),
+        ));
+    }
+
+    if let Some(location) = &feed.previous_feed {
+        let previous = read_previous_feed(logger, location, retry)?;
+        let previous = previous_items(&previous, &feed.version);
+
+        warn!(
+            logger,
+            "preserving {} items of previous feed {}",
+            previous.len(),
+            location
+        );
+
+        items.extend(previous.into_iter().map(|item| format!("    {}", item)));
+    }
+
+    let mut s = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n",
+        "<rss version=\"2.0\" xmlns:sparkle=\"http://www.andymatuschak.org/xml-namespaces/sparkle\" ",
+        "xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n",
+        "  <channel>\n",
+    ));
+    s.push_str(&format!("    <title>{}</title>\n", xml_escape(&feed.title)));
+    s.push_str(&format!(
+        "    <link>{}</link>\n",
+        xml_escape(&feed.feed_url)
+    ));
+    for item in items {
+        s.push_str(&item);
+        s.push('\n');
+    }
+    s.push_str("  </channel>\n</rss>\n");
+
+    let dest = dist_path.join(&feed.filename);
+    warn!(logger, "writing appcast to {}", dest.display());
+
+    if let Some(parent) = dest.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("unable to create {}: {}", parent.display(), e))?;
+    }
+    std::fs::write(&dest, s).map_err(|e| format!("unable to write {}: {}", dest.display(), e))?;
+
+    Ok(dest)
+}
+
+/// Represents a request to write a Sparkle appcast.
+#[derive(Debug, Clone, Serialize)]
+pub struct Appcast {
+    /// Paths to files relative to the distribution path.
+    pub artifacts: Vec<String>,
+
+    pub feed: AppcastFeed,
+
+    /// Ed25519 private key to sign artifacts with.
+    pub ed_key: Option<Secret>,
+
+    /// How failed operations are retried, if not the pipeline's policy.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry: Option<RetryPolicy>,
+}
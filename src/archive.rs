@@ -0,0 +1,558 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Writing of zip archives.
+
+Like tar archives, zip archives are written with entries in sorted order
+and, when reproducibility mode is enabled, the reproducible timestamp as
+the modification time of every entry.
+
+Archives of either format can be split into fixed-size parts, for
+distribution channels limiting the size of files. Parts are named
+`<archive>.001`, `<archive>.002`, and so on, and concatenating them in
+order restores the archive. A `<archive>.parts.json` manifest records the
+parts and the SHA-256 digests of the parts and the whole archive.
+*/
+
+use crate::compression::{Compression, CompressionSettings};
+use crate::extract::ArchiveFormat;
+use crate::filemanifest::{manifest_dirs, FileContent, FileManifest};
+use crate::mode_policy::ModePolicy;
+use crate::naming::ArtifactName;
+use crate::reproducibility::Reproducibility;
+use chrono::{Datelike, Timelike};
+use serde::Serialize;
+use slog::{warn, Logger};
+use std::io::{Read, Write};
+use std::path::Path;
+
+/// Suffix of the manifest describing the parts of a split archive.
+pub const PARTS_MANIFEST_SUFFIX: &str = ".parts.json";
+
+/// A part of a split archive.
+#[derive(Clone, Debug, Serialize)]
+pub struct ArchivePart {
+    /// Filename of the part.
+    pub name: String,
+
+    pub size: u64,
+
+    /// Hex encoded SHA-256 digest of the part's content.
+    pub sha256: String,
+}
+
+/// Describes how to reassemble a split archive.
+#[derive(Clone, Debug, Serialize)]
+pub struct PartsManifest {
+    /// Filename of the reassembled archive.
+    pub name: String,
+
+    pub size: u64,
+
+    /// Hex encoded SHA-256 digest of the reassembled archive.
+    pub sha256: String,
+
+    /// Parts to concatenate, in order.
+    pub parts: Vec<ArchivePart>,
+}
+
+/// Write a zip archive of files in a manifest.
+///
+/// Symlinks are added as symlink entries. Files and the directories
+/// containing them are given the permissions of `modes`. Files are
+/// deflated, with the level of gzip compression of `compression`. Level 0
+/// stores files uncompressed.
+pub fn write_zip(
+    logger: &Logger,
+    dest_path: &Path,
+    files: &FileManifest,
+    compression: &CompressionSettings,
+    reproducibility: &Reproducibility,
+    modes: &ModePolicy,
+) -> Result<(), String> {
+    let (method, level) = match compression.level(Compression::Gzip)? {
+        0 => (zip::CompressionMethod::Stored, None),
+        level => (zip::CompressionMethod::Deflated, Some(i64::from(level))),
+    };
+
+    let fh = std::fs::File::create(dest_path)
+        .map_err(|e| format!("unable to open {} for writing: {}", dest_path.display(), e))?;
+    let mut writer = zip::ZipWriter::new(std::io::BufWriter::new(fh));
+
+    // Directories sort before their content.
+    let mut entries = files
+        .iter()
+        .map(|(rel_path, content)| (rel_path.clone(), Some(content)))
+        .collect::<Vec<_>>();
+    entries.extend(manifest_dirs(files).into_iter().map(|dir| (dir, None)));
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+    for (rel_path, content) in entries {
+        let content = match content {
+            Some(content) => content,
+            None => {
+                let options = zip::write::SimpleFileOptions::default()
+                    .last_modified_time(zip_time(reproducibility.mtime()))
+                    .unix_permissions(modes.dir_mode());
+
+                writer
+                    .add_directory(rel_path.as_str(), options)
+                    .map_err(|e| format!("unable to add {} to archive: {}", rel_path, e))?;
+
+                continue;
+            }
+        };
+
+        warn!(logger, "adding {} as {}", content.describe(), rel_path);
+
+        let mtime = reproducibility.content_mtime(content)?;
+
+        if let FileContent::Symlink(target) = content {
+            let options = zip::write::SimpleFileOptions::default()
+                .last_modified_time(zip_time(mtime))
+                .unix_permissions(modes.file_mode(content));
+
+            writer
+                .add_symlink(rel_path.as_str(), target.to_string_lossy(), options)
+                .map_err(|e| format!("unable to add {} to archive: {}", rel_path, e))?;
+
+            continue;
+        }
+
+        let options = zip::write::SimpleFileOptions::default()
+            .compression_method(method)
+            .compression_level(level)
+            .last_modified_time(zip_time(mtime))
+            .unix_permissions(modes.file_mode(content))
+            .large_file(content.size()? >= u64::from(u32::MAX));
+
+        writer
+            .start_file(rel_path.as_str(), options)
+            .map_err(|e| format!("unable to add {} to archive: {}", rel_path, e))?;
+        std::io::copy(&mut content.open()?, &mut writer)
+            .map_err(|e| format!("unable to add {} to archive: {}", rel_path, e))?;
+    }
+
+    writer
+        .finish()
+        .map_err(|e| format!("unable to write {}: {}", dest_path.display(), e))?;
+
+    Ok(())
+}
+
+/// Convert seconds since the UNIX epoch to a zip timestamp.
+///
+/// Zip timestamps can't represent times before 1980, so earlier times
+/// are clamped to the start of 1980.
+pub fn zip_time(mtime: u64) -> zip::DateTime {
+    let t = chrono::NaiveDateTime::from_timestamp_opt(mtime as i64, 0)
+        .unwrap_or_else(|| chrono::NaiveDateTime::from_timestamp(0, 0));
+
+    if t.year() < 1980 {
+        return zip::DateTime::default();
+    }
+
+    zip::DateTime::from_date_and_time(
+        t.year() as u16,
+        t.month() as u8,
+        t.day() as u8,
+        t.hour() as u8,
+        t.minute() as u8,
+        t.second() as u8,
+    )
+    .unwrap_or_default()
+}
+
+/// Parse a size in bytes, such as `1500000`, `100M`, or `2GiB`.
+///
+/// `K`, `M`, and `G` (optionally followed by `B`) are powers of 1000.
+/// `KiB`, `MiB`, and `GiB` are powers of 1024.
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+
+    let multiplier: u64 = match unit.trim() {
+        "" | "B" => 1,
+        "K" | "KB" | "k" | "kB" => 1000,
+        "M" | "MB" => 1000 * 1000,
+        "G" | "GB" => 1000 * 1000 * 1000,
+        "KiB" => 1 << 10,
+        "MiB" => 1 << 20,
+        "GiB" => 1 << 30,
+        _ => return Err(format!("invalid size {}; unknown unit {}", value, unit)),
+    };
+
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|number| number.checked_mul(multiplier))
+        .filter(|size| *size > 0)
+        .ok_or_else(|| {
+            format!(
+                "invalid size {}; expected a positive number of bytes",
+                value
+            )
+        })
+}
+
+/// Split a file into parts of at most `part_size` bytes.
+///
+/// Parts are written next to the file, which is removed, along with a
+/// manifest of the parts. Parts left from an earlier split of a file of
+/// the same name are removed, so they aren't mistaken for parts of this
+/// one. A file no larger than `part_size` still becomes a single part,
+/// so artifact names don't depend on the size of the file.
+pub fn split_file(logger: &Logger, path: &Path, part_size: u64) -> Result<PartsManifest, String> {
+    let dir = path
+        .parent()
+        .ok_or_else(|| format!("{} has no parent directory", path.display()))?;
+    let name = path
+        .file_name()
+        .ok_or_else(|| format!("{} has no filename", path.display()))?
+        .to_string_lossy()
+        .to_string();
+
+    remove_stale_parts(dir, &name)?;
+
+    let size = std::fs::metadata(path)
+        .map_err(|e| format!("unable to read {}: {}", path.display(), e))?
+        .len();
+    let sha256 = crate::inventory::sha256_file(path)?;
+
+    let mut fh = std::fs::File::open(path)
+        .map_err(|e| format!("unable to open {}: {}", path.display(), e))?;
+    // An empty file still has a (single, empty) part.
+    let count = std::cmp::max(1, size.div_ceil(part_size));
+    let mut parts = Vec::new();
+
+    for number in 1..=count {
+        let part_name = format!("{}.{:03}", name, number);
+        let part_path = dir.join(&part_name);
+
+        let mut part = std::fs::File::create(&part_path)
+            .map_err(|e| format!("unable to create {}: {}", part_path.display(), e))?;
+        let part_size = std::io::copy(&mut (&mut fh).take(part_size), &mut part)
+            .map_err(|e| format!("unable to write {}: {}", part_path.display(), e))?;
+
+        parts.push(ArchivePart {
+            name: part_name,
+            size: part_size,
+            sha256: crate::inventory::sha256_file(&part_path)?,
+        });
+    }
+
+    std::fs::remove_file(path)
+        .map_err(|e| format!("unable to remove {}: {}", path.display(), e))?;
+
+    warn!(
+        logger,
+        "split {} into {} parts of at most {} bytes",
+        path.display(),
+        parts.len(),
+        part_size
+    );
+
+    let manifest = PartsManifest {
+        name: name.clone(),
+        size,
+        sha256,
+        parts,
+    };
+
+    let manifest_path = dir.join(format!("{}{}", name, PARTS_MANIFEST_SUFFIX));
+    let data = serde_json::to_string_pretty(&manifest).map_err(|e| e.to_string())?;
+    std::fs::write(&manifest_path, data + "\n")
+        .map_err(|e| format!("unable to write {}: {}", manifest_path.display(), e))?;
+
+    Ok(manifest)
+}
+
+/// Remove parts of an earlier split of a file.
+fn remove_stale_parts(dir: &Path, name: &str) -> Result<(), String> {
+    let prefix = format!("{}.", name);
+
+    for entry in
+        std::fs::read_dir(dir).map_err(|e| format!("unable to read {}: {}", dir.display(), e))?
+    {
+        let entry = entry.map_err(|e| format!("unable to read {}: {}", dir.display(), e))?;
+        let filename = entry.file_name().to_string_lossy().to_string();
+
+        let is_part = filename
+            .strip_prefix(&prefix)
+            .map(|number| number.len() >= 3 && number.chars().all(|c| c.is_ascii_digit()))
+            .unwrap_or(false);
+
+        if is_part {
+            std::fs::remove_file(entry.path())
+                .map_err(|e| format!("unable to remove {}: {}", entry.path().display(), e))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Represents a step to produce a tar archive.
+#[derive(Debug, Clone, Serialize)]
+pub struct TarArchive {
+    /// Filename of produced tar archive.
+    pub dest_name: String,
+
+    /// Manifest denoting content to be added to archive.
+    pub file_manifest: FileManifest,
+
+    /// How the archive is compressed.
+    pub compression: CompressionSettings,
+
+    /// User name of the owner of entries.
+    pub owner: String,
+
+    /// Group name of the owner of entries.
+    pub group: String,
+
+    /// Numeric user ID of the owner of entries.
+    pub uid: u64,
+
+    /// Numeric group ID of the owner of entries.
+    pub gid: u64,
+
+    /// Directory all entries are nested under.
+    pub prefix: Option<String>,
+
+    /// Whether symlinks are replaced by the content they refer to.
+    pub dereference: bool,
+
+    /// Maximum size of parts to split the archive into.
+    pub split_size: Option<u64>,
+
+    /// Package the archive belongs to, if its name can be defined by a
+    /// name template.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artifact_name: Option<ArtifactName>,
+}
+
+impl TarArchive {
+    /// Write the archive.
+    ///
+    /// `compression` are the settings in effect, which combine the
+    /// step's settings with its pipeline's.
+    pub fn execute(
+        &self,
+        logger: &slog::Logger,
+        dist_path: &Path,
+        compression: &CompressionSettings,
+        reproducibility: &Reproducibility,
+        modes: &ModePolicy,
+    ) -> Result<(), String> {
+        let dest_path = dist_path.join(&self.dest_name);
+
+        warn!(logger, "writing tarball to {}", dest_path.display());
+
+        std::fs::create_dir_all(dest_path.parent().unwrap()).map_err(|e| {
+            format!(
+                "unable to create directory for {}: {}",
+                dest_path.display(),
+                e
+            )
+        })?;
+
+        let fh = std::fs::File::create(&dest_path)
+            .map_err(|e| format!("unable to open {} for writing: {}", dest_path.display(), e))?;
+
+        match Compression::from_filename(&self.dest_name) {
+            Some(algorithm) => {
+                warn!(logger, "compressing with {:?}", algorithm);
+
+                let mut encoder = compression.encoder(algorithm, std::io::BufWriter::new(fh))?;
+                self.write_tar(logger, &mut encoder, reproducibility, modes)?;
+
+                encoder
+                    .finish()
+                    .map_err(|e| format!("unable to write {}: {}", dest_path.display(), e))?;
+            }
+            None => self.write_tar(logger, fh, reproducibility, modes)?,
+        }
+
+        if let Some(split_size) = self.split_size {
+            crate::archive::split_file(logger, &dest_path, split_size)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_tar<W: Write>(
+        &self,
+        logger: &slog::Logger,
+        writer: W,
+        reproducibility: &Reproducibility,
+        modes: &ModePolicy,
+    ) -> Result<(), String> {
+        let mut builder = tar::Builder::new(writer);
+
+        let files = if self.dereference {
+            crate::filemanifest::dereference_symlinks(&self.file_manifest)?
+        } else {
+            self.file_manifest.clone()
+        };
+
+        let mut entries = files
+            .iter()
+            .map(|(rel_path, content)| {
+                let rel_path = match &self.prefix {
+                    Some(prefix) => format!("{}/{}", prefix, rel_path),
+                    None => rel_path.clone(),
+                };
+
+                (rel_path, Some(content))
+            })
+            .collect::<Vec<_>>();
+
+        // Entries are emitted in sorted order. Directories sort before
+        // their content.
+        let dirs = crate::filemanifest::parent_dirs(entries.iter().map(|(path, _)| path.as_str()));
+        entries.extend(dirs.into_iter().map(|dir| (dir, None)));
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+        for (rel_path, content) in entries {
+            // Long paths and link targets are written with GNU extensions
+            // and sizes of 8 GiB or more with base-256 encoding.
+            let mut header = tar::Header::new_gnu();
+            header.set_uid(self.uid);
+            header.set_gid(self.gid);
+            header
+                .set_username(&self.owner)
+                .map_err(|e| format!("invalid owner {}: {}", self.owner, e))?;
+            header
+                .set_groupname(&self.group)
+                .map_err(|e| format!("invalid group {}: {}", self.group, e))?;
+
+            let content = match content {
+                Some(content) => content,
+                None => {
+                    header.set_entry_type(tar::EntryType::Directory);
+                    header.set_mode(modes.dir_mode());
+                    header.set_size(0);
+                    header.set_mtime(reproducibility.mtime());
+                    builder
+                        .append_data(&mut header, format!("{}/", rel_path), std::io::empty())
+                        .map_err(|e| format!("unable to add {} to archive: {}", rel_path, e))?;
+
+                    continue;
+                }
+            };
+
+            warn!(logger, "adding {} as {}", content.describe(), rel_path);
+
+            let mtime = reproducibility.content_mtime(content)?;
+
+            header.set_mode(modes.file_mode(content));
+            header.set_size(content.size()?);
+            header.set_mtime(mtime);
+
+            if let FileContent::Symlink(target) = content {
+                header.set_entry_type(tar::EntryType::Symlink);
+                builder
+                    .append_link(&mut header, &rel_path, target)
+                    .map_err(|e| format!("unable to add {} to archive: {}", rel_path, e))?;
+
+                continue;
+            }
+
+            builder
+                .append_data(&mut header, &rel_path, content.open()?)
+                .map_err(|e| format!("unable to add {} to archive: {}", rel_path, e))?;
+        }
+
+        builder.finish().map_err(|e| e.to_string())?;
+
+        Ok(())
+    }
+}
+
+/// Represents a step to produce an archive whose format is chosen by filename.
+#[derive(Debug, Clone, Serialize)]
+pub struct Archive {
+    /// Filename of produced archive.
+    pub dest_name: String,
+
+    /// Manifest denoting content to be added to archive.
+    pub file_manifest: FileManifest,
+
+    /// Format of the archive, derived from `dest_name`.
+    pub format: ArchiveFormat,
+
+    /// How the archive is compressed.
+    pub compression: CompressionSettings,
+
+    /// Maximum size of parts to split the archive into.
+    pub split_size: Option<u64>,
+
+    /// Package the archive belongs to, if its name can be defined by a
+    /// name template.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artifact_name: Option<ArtifactName>,
+}
+
+impl Archive {
+    /// Write the archive.
+    ///
+    /// `compression` are the settings in effect, which combine the
+    /// step's settings with its pipeline's.
+    pub fn execute(
+        &self,
+        logger: &slog::Logger,
+        dist_path: &Path,
+        compression: &CompressionSettings,
+        reproducibility: &Reproducibility,
+        modes: &ModePolicy,
+    ) -> Result<(), String> {
+        match self.format {
+            ArchiveFormat::Zip => {
+                let dest_path = dist_path.join(&self.dest_name);
+
+                warn!(logger, "writing zip archive to {}", dest_path.display());
+
+                std::fs::create_dir_all(dest_path.parent().unwrap()).map_err(|e| {
+                    format!(
+                        "unable to create directory for {}: {}",
+                        dest_path.display(),
+                        e
+                    )
+                })?;
+
+                crate::archive::write_zip(
+                    logger,
+                    &dest_path,
+                    &self.file_manifest,
+                    compression,
+                    reproducibility,
+                    modes,
+                )?;
+
+                if let Some(split_size) = self.split_size {
+                    crate::archive::split_file(logger, &dest_path, split_size)?;
+                }
+
+                Ok(())
+            }
+            _ => TarArchive {
+                dest_name: self.dest_name.clone(),
+                file_manifest: self.file_manifest.clone(),
+                compression: self.compression.clone(),
+                owner: "root".to_string(),
+                group: "root".to_string(),
+                uid: 0,
+                gid: 0,
+                prefix: None,
+                dereference: false,
+                split_size: self.split_size,
+                artifact_name: None,
+            }
+            .execute(logger, dist_path, compression, reproducibility, modes),
+        }
+    }
+}
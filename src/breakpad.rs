@@ -0,0 +1,216 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Generating Breakpad symbol files.
+
+Breakpad and Crashpad symbolicate minidumps with text `.sym` files
+describing the functions and line tables of a module. They are produced
+by `dump_syms` from ELF, Mach-O, and PE binaries and PDBs. Symbol servers
+and `minidump_stackwalk` expect them in the layout
+
+```text
+<debug file>/<debug identifier>/<debug file>.sym
+```
+
+where a `.pdb` extension of the debug file is replaced by `.sym`. Both
+values are read from the `MODULE` line starting each symbol file.
+*/
+
+use crate::filemanifest::{
+    install_files, key_path, FileContent, FileManifest, FileType, InstallMode,
+};
+use crate::mode_policy::ModePolicy;
+use serde::Serialize;
+use slog::{warn, Logger};
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// Magic starting PDB files.
+const PDB_MAGIC: &[u8] = b"Microsoft C/C++ MSF 7.00\r\n";
+
+/// The module a symbol file describes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SymbolModule {
+    /// Operating system, e.g. `Linux` or `windows`.
+    pub os: String,
+
+    /// Processor architecture, e.g. `x86_64`.
+    pub arch: String,
+
+    /// Debug identifier of the module.
+    pub id: String,
+
+    /// Name of the debug file, e.g. `libfoo.so` or `foo.pdb`.
+    pub name: String,
+}
+
+impl SymbolModule {
+    /// Parse the `MODULE` line starting a symbol file.
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let mut fields = line.trim_end().splitn(5, ' ');
+
+        if fields.next() != Some("MODULE") {
+            return Err(format!("expected MODULE record; got {}", line.trim_end()));
+        }
+
+        match (fields.next(), fields.next(), fields.next(), fields.next()) {
+            // Both become path components of the symbol file.
+            (Some(os), Some(arch), Some(id), Some(name))
+                if is_path_component(id) && is_path_component(name) =>
+            {
+                Ok(SymbolModule {
+                    os: os.to_string(),
+                    arch: arch.to_string(),
+                    id: id.to_string(),
+                    name: name.to_string(),
+                })
+            }
+            _ => Err(format!("malformed MODULE record: {}", line.trim_end())),
+        }
+    }
+
+    /// Obtain the path of the symbol file in the Breakpad layout.
+    pub fn sym_key(&self) -> String {
+        let sym_name = match self.name.strip_suffix(".pdb") {
+            Some(stem) => format!("{}.sym", stem),
+            None => format!("{}.sym", self.name),
+        };
+
+        format!("{}/{}/{}", self.name, self.id, sym_name)
+    }
+}
+
+fn is_path_component(value: &str) -> bool {
+    !value.is_empty() && value != "." && value != ".." && !value.contains(['/', '\\'])
+}
+
+/// Whether content is a binary or PDB `dump_syms` can process.
+fn has_debug_info(content: &FileContent) -> Result<bool, String> {
+    match content.file_type()? {
+        Some(FileType::Elf) | Some(FileType::Pe) | Some(FileType::MachO) => Ok(true),
+        Some(FileType::Binary) => {
+            let mut header = Vec::with_capacity(PDB_MAGIC.len());
+            content
+                .open()?
+                .take(PDB_MAGIC.len() as u64)
+                .read_to_end(&mut header)
+                .map_err(|e| format!("error reading {}: {}", content.describe(), e))?;
+
+            Ok(header == PDB_MAGIC)
+        }
+        _ => Ok(false),
+    }
+}
+
+/// Run `dump_syms` on a file, writing the symbols to `dest`.
+fn run_dump_syms(path: &Path, dest: &Path) -> Result<(), String> {
+    crate::process::record_program("dump_syms");
+
+    let fh = std::fs::File::create(dest)
+        .map_err(|e| format!("unable to create {}: {}", dest.display(), e))?;
+
+    // Symbol files are large, so they aren't held in memory.
+    let output = Command::new("dump_syms")
+        .arg(path)
+        .stdin(Stdio::null())
+        .stdout(fh)
+        .stderr(Stdio::piped())
+        .output()
+        .map_err(|e| format!("error running dump_syms: {}", e))?;
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "dump_syms failed for {}: {}",
+            path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
+
+/// Write Breakpad symbol files of the binaries in a manifest.
+///
+/// Files of the manifest that aren't ELF, Mach-O, or PE binaries or PDBs
+/// are ignored. They are still installed next to the binaries, so
+/// `dump_syms` finds PDBs and split debug files of a binary. Symbol
+/// files are written to `directory` under `dist_path`, replacing an
+/// existing directory. Returns the paths of the symbol files relative to
+/// `directory`.
+pub fn write_breakpad_symbols(
+    logger: &Logger,
+    dist_path: &Path,
+    directory: &str,
+    files: &FileManifest,
+) -> Result<Vec<String>, String> {
+    let temp_dir = tempfile::Builder::new()
+        .prefix("tugger-breakpad-")
+        .tempdir()
+        .map_err(|e| format!("unable to create temp directory: {}", e))?;
+    let install_path = temp_dir.path().join("files");
+    install_files(
+        &install_path,
+        files,
+        InstallMode::Copy,
+        &ModePolicy::default(),
+    )?;
+
+    let symbols_path = dist_path.join(directory);
+    if symbols_path.exists() {
+        std::fs::remove_dir_all(&symbols_path)
+            .map_err(|e| format!("unable to remove {}: {}", symbols_path.display(), e))?;
+    }
+
+    let mut keys = Vec::new();
+
+    for (key, content) in files {
+        if !has_debug_info(content)? {
+            continue;
+        }
+
+        let sym_path = temp_dir.path().join("out.sym");
+        run_dump_syms(&install_path.join(key_path(key)), &sym_path)?;
+
+        let mut first_line = String::new();
+        let fh = std::fs::File::open(&sym_path)
+            .map_err(|e| format!("unable to open {}: {}", sym_path.display(), e))?;
+        BufReader::new(fh)
+            .read_line(&mut first_line)
+            .map_err(|e| format!("unable to read {}: {}", sym_path.display(), e))?;
+        let module =
+            SymbolModule::parse(&first_line).map_err(|e| format!("symbols of {}: {}", key, e))?;
+
+        // A binary and its PDB describe the same module.
+        let sym_key = module.sym_key();
+        if keys.contains(&sym_key) {
+            continue;
+        }
+
+        let dest = symbols_path.join(key_path(&sym_key));
+        std::fs::create_dir_all(dest.parent().unwrap())
+            .map_err(|e| format!("unable to create {}: {}", dest.display(), e))?;
+        std::fs::copy(&sym_path, &dest)
+            .map_err(|e| format!("unable to write {}: {}", dest.display(), e))?;
+
+        warn!(logger, "wrote symbols of {} to {}", key, dest.display());
+        keys.push(sym_key);
+    }
+
+    if keys.is_empty() {
+        return Err("no binaries with debug information in manifest".to_string());
+    }
+
+    Ok(keys)
+}
+
+/// Represents a request to write Breakpad symbol files of binaries.
+#[derive(Debug, Clone, Serialize)]
+pub struct BreakpadSymbols {
+    pub files: FileManifest,
+
+    /// Directory of the symbol files relative to the distribution path.
+    pub directory: String,
+}
@@ -0,0 +1,235 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Capturing the environment of a build.
+
+Artifacts depend on more than their inputs: the external tools that
+produced them, environment variables influencing those tools, and the
+operating system all play a part. `BuildEnvironment` records these, so
+a release can be audited or reproduced later on an equivalent machine.
+
+Tool versions are obtained by running each program run during the build
+with `--version`.
+*/
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// Environment variables influencing builds.
+///
+/// Variables starting with `TUGGER_` are recorded as well.
+const RECORDED_ENV: &[&str] = &[
+    "CARGO_BUILD_TARGET",
+    "CARGO_TARGET_DIR",
+    "CC",
+    "CFLAGS",
+    "CXX",
+    "CXXFLAGS",
+    "DOCKER_HOST",
+    "LANG",
+    "LC_ALL",
+    "LDFLAGS",
+    "PATH",
+    "RUSTC_WRAPPER",
+    "RUSTFLAGS",
+    "SOURCE_DATE_EPOCH",
+    "TZ",
+];
+
+/// How long to wait for a program to print its version.
+const VERSION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The version of an external program.
+#[derive(Clone, Debug, Serialize)]
+pub struct ToolVersion {
+    /// Name of the program, as it was run.
+    pub program: String,
+
+    /// Path of the program found on `PATH`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+
+    /// First line printed by `<program> --version`, if it succeeded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+}
+
+/// Describes the machine and environment performing a build.
+#[derive(Clone, Debug, Serialize)]
+pub struct BuildEnvironment {
+    /// Operating system, e.g. `linux`.
+    pub os: String,
+
+    /// Processor architecture, e.g. `x86_64`.
+    pub arch: String,
+
+    /// Name and version of the operating system, e.g. `Debian GNU/Linux 12
+    /// (bookworm)`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub os_version: Option<String>,
+
+    /// Version of the kernel.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kernel_version: Option<String>,
+
+    /// Version of tugger performing the build.
+    pub tugger_version: String,
+
+    /// Values of environment variables influencing builds.
+    ///
+    /// Secret values are redacted.
+    pub env: BTreeMap<String, String>,
+
+    /// External programs run during the build.
+    pub tools: Vec<ToolVersion>,
+}
+
+impl BuildEnvironment {
+    /// Capture the environment of this process.
+    ///
+    /// `programs` are the external programs whose versions to record.
+    pub fn capture<P: AsRef<Path>>(programs: &[P]) -> Self {
+        let env = std::env::vars()
+            .filter(|(key, _)| RECORDED_ENV.contains(&key.as_str()) || key.starts_with("TUGGER_"))
+            .map(|(key, value)| (key, crate::secrets::redact(&value)))
+            .collect();
+
+        let tools = programs
+            .iter()
+            .map(|program| {
+                let program = program.as_ref();
+                let path = find_program(program);
+
+                ToolVersion {
+                    program: program.display().to_string(),
+                    version: path.as_deref().and_then(tool_version),
+                    path,
+                }
+            })
+            .collect();
+
+        BuildEnvironment {
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            os_version: os_version(),
+            kernel_version: kernel_version(),
+            tugger_version: env!("CARGO_PKG_VERSION").to_string(),
+            env,
+            tools,
+        }
+    }
+}
+
+/// Obtain the version of a program, as printed by `<program> --version`.
+///
+/// Returns `None` if the program isn't found or doesn't print a version.
+pub fn program_version(program: &Path) -> Option<String> {
+    find_program(program).as_deref().and_then(tool_version)
+}
+
+/// Find a program on `PATH`.
+///
+/// Programs with a directory component are resolved as they are.
+fn find_program(program: &Path) -> Option<PathBuf> {
+    if program.components().count() > 1 {
+        return program.canonicalize().ok();
+    }
+
+    let paths = std::env::var_os("PATH")?;
+
+    std::env::split_paths(&paths).find_map(|dir| {
+        let candidates = if cfg!(windows) {
+            vec![dir.join(program), dir.join(program).with_extension("exe")]
+        } else {
+            vec![dir.join(program)]
+        };
+
+        candidates.into_iter().find(|path| path.is_file())
+    })
+}
+
+/// Obtain the first line a program prints when run with `--version`.
+///
+/// Programs not exiting in time are killed.
+fn tool_version(path: &Path) -> Option<String> {
+    let mut child = Command::new(path)
+        .arg("--version")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .ok()?;
+
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait().ok()? {
+            break status;
+        }
+
+        if start.elapsed() > VERSION_TIMEOUT {
+            let _ = child.kill();
+            let _ = child.wait();
+            return None;
+        }
+
+        std::thread::sleep(Duration::from_millis(20));
+    };
+
+    if !status.success() {
+        return None;
+    }
+
+    // Some programs print their version to stderr.
+    let mut output = String::new();
+    child.stdout.take()?.read_to_string(&mut output).ok()?;
+    if output.trim().is_empty() {
+        child.stderr.take()?.read_to_string(&mut output).ok()?;
+    }
+
+    output
+        .lines()
+        .map(|line| line.trim())
+        .find(|line| !line.is_empty())
+        .map(|line| line.to_string())
+}
+
+/// Obtain the name and version of the operating system.
+fn os_version() -> Option<String> {
+    if cfg!(target_os = "linux") {
+        let release = std::fs::read_to_string("/etc/os-release").ok()?;
+
+        release.lines().find_map(|line| {
+            line.strip_prefix("PRETTY_NAME=")
+                .map(|value| value.trim_matches('"').to_string())
+        })
+    } else if cfg!(target_os = "macos") {
+        let output = Command::new("sw_vers")
+            .arg("-productVersion")
+            .output()
+            .ok()?;
+
+        Some(format!(
+            "macOS {}",
+            String::from_utf8_lossy(&output.stdout).trim()
+        ))
+    } else {
+        None
+    }
+}
+
+/// Obtain the version of the kernel.
+fn kernel_version() -> Option<String> {
+    if cfg!(target_os = "linux") {
+        std::fs::read_to_string("/proc/sys/kernel/osrelease")
+            .ok()
+            .map(|version| version.trim().to_string())
+    } else {
+        None
+    }
+}
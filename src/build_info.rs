@@ -0,0 +1,151 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Build information records.
+
+A `build-info.json` file shipped with artifacts records where and how
+they were built, so an artifact found in the wild can be traced back to
+the commit and configuration that produced it.
+*/
+
+use crate::channel::Channel;
+use serde::Serialize;
+use slog::{warn, Logger};
+use std::path::Path;
+
+/// Default filename of build information.
+pub const BUILD_INFO_FILENAME: &str = "build-info.json";
+
+/// Describes a build.
+#[derive(Clone, Debug, Serialize)]
+pub struct BuildInfo {
+    /// Git commit the working directory is based on.
+    pub git_commit: Option<String>,
+
+    /// Git tag pointing at the commit.
+    pub git_tag: Option<String>,
+
+    /// Whether the working directory has uncommitted changes.
+    pub git_dirty: Option<bool>,
+
+    /// Time of the build, in seconds since the UNIX epoch.
+    pub build_timestamp: u64,
+
+    /// Time of the build as an RFC 3339 UTC timestamp.
+    pub build_time: String,
+
+    pub channel: Channel,
+
+    /// Version of tugger performing the build.
+    pub tugger_version: String,
+
+    /// Target triple of the machine performing the build.
+    pub host_target: String,
+
+    /// Hex encoded SHA-256 digest of the configuration file.
+    pub config_sha256: Option<String>,
+}
+
+impl BuildInfo {
+    /// Collect information about a build from a working directory.
+    pub fn collect(
+        cwd: &Path,
+        build_timestamp: u64,
+        channel: Channel,
+        config_sha256: Option<String>,
+    ) -> Self {
+        let (git_commit, git_tag, git_dirty) = match git2::Repository::discover(cwd) {
+            Ok(repo) => git_state(&repo),
+            Err(_) => (None, None, None),
+        };
+
+        let build_time = chrono::NaiveDateTime::from_timestamp_opt(build_timestamp as i64, 0)
+            .unwrap_or_else(|| chrono::NaiveDateTime::from_timestamp(0, 0))
+            .format("%Y-%m-%dT%H:%M:%SZ")
+            .to_string();
+
+        BuildInfo {
+            git_commit,
+            git_tag,
+            git_dirty,
+            build_timestamp,
+            build_time,
+            channel,
+            tugger_version: env!("CARGO_PKG_VERSION").to_string(),
+            host_target: crate::cargo::host_target(),
+            config_sha256,
+        }
+    }
+
+    /// Serialize to pretty-printed JSON.
+    pub fn to_json(&self) -> Result<Vec<u8>, String> {
+        let mut data = serde_json::to_vec_pretty(self)
+            .map_err(|e| format!("unable to serialize build info: {}", e))?;
+        data.push(b'\n');
+
+        Ok(data)
+    }
+}
+
+/// Obtain the commit, tag, and dirty state of a repository's `HEAD`.
+fn git_state(repo: &git2::Repository) -> (Option<String>, Option<String>, Option<bool>) {
+    let commit = match repo.head().and_then(|head| head.peel_to_commit()) {
+        Ok(commit) => commit,
+        Err(_) => return (None, None, None),
+    };
+
+    let mut tags = repo
+        .tag_names(None)
+        .map(|names| {
+            names
+                .iter()
+                .flatten()
+                .filter(|name| {
+                    repo.revparse_single(&format!("refs/tags/{}", name))
+                        .and_then(|object| object.peel_to_commit())
+                        .map(|tagged| tagged.id() == commit.id())
+                        .unwrap_or(false)
+                })
+                .map(|name| name.to_string())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    tags.sort();
+
+    let dirty = repo
+        .statuses(Some(
+            git2::StatusOptions::new()
+                .include_untracked(false)
+                .include_ignored(false),
+        ))
+        .map(|statuses| !statuses.is_empty())
+        .ok();
+
+    (Some(commit.id().to_string()), tags.pop(), dirty)
+}
+
+/// Represents a request to write build information.
+#[derive(Debug, Clone, Serialize)]
+pub struct BuildInfoFile {
+    pub info: BuildInfo,
+
+    /// Filename relative to the distribution path.
+    pub filename: String,
+}
+
+impl BuildInfoFile {
+    /// Write build information into `dist_path`.
+    pub fn execute(&self, logger: &Logger, dist_path: &Path) -> Result<(), String> {
+        let path = dist_path.join(&self.filename);
+        warn!(logger, "writing build info to {}", path.display());
+
+        let parent = path.parent().unwrap();
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("unable to create {}: {}", parent.display(), e))?;
+
+        std::fs::write(&path, self.info.to_json()?)
+            .map_err(|e| format!("unable to write {}: {}", path.display(), e))
+    }
+}
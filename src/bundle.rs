@@ -0,0 +1,304 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Arranging artifacts into a publishable directory tree.
+
+A release bundle is the directory synced to a download server. It holds
+the artifacts of a release together with their signatures, a
+`SHA256SUMS` file, and optionally an index describing every artifact.
+Artifacts are either placed at the root of the bundle or grouped in a
+directory per platform, such as `linux-x86_64`, inferred from their
+filenames.
+*/
+
+use crate::inventory::sha256_file;
+use crate::xml::xml_escape;
+use serde::Serialize;
+use slog::{warn, Logger};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Filename of the checksums written to the root of a bundle.
+pub const CHECKSUMS_FILENAME: &str = "SHA256SUMS";
+
+/// Suffixes of signatures copied along with an artifact.
+const SIGNATURE_SUFFIXES: &[&str] = &[".asc", ".sig", ".minisig", ".bundle"];
+
+/// Directory of artifacts whose platform can't be determined.
+const ANY_PLATFORM: &str = "any";
+
+/// How artifacts are arranged in a bundle.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BundleLayout {
+    /// A directory per platform.
+    ByPlatform,
+
+    /// All artifacts at the root of the bundle.
+    Flat,
+}
+
+impl FromStr for BundleLayout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "by-platform" => Ok(BundleLayout::ByPlatform),
+            "flat" => Ok(BundleLayout::Flat),
+            _ => Err(format!(
+                "unknown layout {}; expected by-platform or flat",
+                s
+            )),
+        }
+    }
+}
+
+/// Describes an artifact in a bundle.
+#[derive(Clone, Debug, Serialize)]
+pub struct BundleArtifact {
+    /// Filename of the artifact.
+    pub name: String,
+
+    /// Path of the artifact relative to the root of the bundle.
+    pub path: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub platform: Option<String>,
+
+    pub size: u64,
+
+    /// Hex encoded SHA-256 digest of the artifact's content.
+    pub sha256: String,
+
+    /// Paths of signatures of the artifact relative to the root of the bundle.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub signatures: Vec<String>,
+}
+
+/// Index of the artifacts in a bundle, written as `index.json`.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct BundleIndex {
+    pub artifacts: Vec<BundleArtifact>,
+}
+
+/// Infer the platform of an artifact from its filename.
+///
+/// Platforms are named `<os>-<arch>`, e.g. `linux-x86_64`, or just `<os>`
+/// if the filename doesn't mention an architecture.
+pub fn artifact_platform(filename: &str) -> Option<String> {
+    let lower = filename.to_lowercase();
+    let tokens: Vec<&str> = lower.split(|c| "-._+ ".contains(c)).collect();
+    let has = |names: &[&str]| names.iter().any(|name| tokens.contains(name));
+    let extension = |extensions: &[&str]| extensions.iter().any(|ext| lower.ends_with(ext));
+
+    let os = if has(&["windows", "win32", "win64", "msvc"])
+        || extension(&[".exe", ".msi", ".msix", ".nupkg"])
+    {
+        "windows"
+    } else if has(&["darwin", "macos", "osx", "apple"]) || extension(&[".dmg", ".pkg"]) {
+        "macos"
+    } else if has(&["linux"]) || extension(&[".deb", ".rpm", ".snap", ".appimage"]) {
+        "linux"
+    } else if has(&["freebsd"]) {
+        "freebsd"
+    } else {
+        return None;
+    };
+
+    let arch = if lower.contains("x86_64") || has(&["amd64", "x64", "win64"]) {
+        Some("x86_64")
+    } else if has(&["aarch64", "arm64"]) {
+        Some("aarch64")
+    } else if has(&["i686", "i386", "x86", "win32"]) {
+        Some("x86")
+    } else if has(&["armv7", "armv7l", "armhf"]) {
+        Some("armv7")
+    } else {
+        None
+    };
+
+    Some(match arch {
+        Some(arch) => format!("{}-{}", os, arch),
+        None => os.to_string(),
+    })
+}
+
+/// Obtain an HTML page linking to every artifact in a bundle.
+fn index_html(title: &str, index: &BundleIndex) -> String {
+    let mut platforms: BTreeMap<&str, Vec<&BundleArtifact>> = BTreeMap::new();
+    for artifact in &index.artifacts {
+        platforms
+            .entry(artifact.platform.as_deref().unwrap_or(ANY_PLATFORM))
+            .or_default()
+            .push(artifact);
+    }
+
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{0}</title>\n</head>\n<body>\n<h1>{0}</h1>\n",
+        xml_escape(title)
+    );
+
+    for (platform, artifacts) in platforms {
+        html.push_str(&format!("<h2>{}</h2>\n<ul>\n", xml_escape(platform)));
+
+        for artifact in artifacts {
+            html.push_str(&format!(
+                "<li><a href=\"{}\">{}</a> ({})",
+                xml_escape(&artifact.path),
+                xml_escape(&artifact.name),
+                crate::release_notes::format_size(artifact.size)
+            ));
+
+            for signature in &artifact.signatures {
+                let extension = signature.rsplit('.').next().unwrap_or(signature);
+                html.push_str(&format!(
+                    " [<a href=\"{}\">{}</a>]",
+                    xml_escape(signature),
+                    xml_escape(extension)
+                ));
+            }
+
+            html.push_str(&format!(
+                "<br><code>sha256:{}</code></li>\n",
+                artifact.sha256
+            ));
+        }
+
+        html.push_str("</ul>\n");
+    }
+
+    html.push_str("</body>\n</html>\n");
+
+    html
+}
+
+/// Arrange artifacts of a distribution directory into a bundle.
+///
+/// `artifacts` are paths relative to `dist_path`, each with an optional
+/// platform overriding the one inferred from its filename. Signatures
+/// next to artifacts are copied along with them. Any existing bundle
+/// directory is replaced, so files of earlier releases aren't published.
+pub fn write_release_bundle(
+    logger: &Logger,
+    dist_path: &Path,
+    bundle_dir: &str,
+    artifacts: &[(String, Option<String>)],
+    layout: BundleLayout,
+    index: bool,
+) -> Result<BundleIndex, String> {
+    let bundle_path = dist_path.join(bundle_dir);
+
+    if bundle_path.exists() {
+        std::fs::remove_dir_all(&bundle_path)
+            .map_err(|e| format!("unable to remove {}: {}", bundle_path.display(), e))?;
+    }
+
+    let mut bundle = BundleIndex::default();
+    let mut checksums = String::new();
+
+    for (artifact, platform) in artifacts {
+        let source = dist_path.join(artifact);
+        if !source.is_file() {
+            return Err(format!("{} does not exist", source.display()));
+        }
+
+        let name = source
+            .file_name()
+            .ok_or_else(|| format!("{} has no filename", source.display()))?
+            .to_string_lossy()
+            .to_string();
+        let platform = platform.clone().or_else(|| artifact_platform(&name));
+
+        let dir = match layout {
+            BundleLayout::ByPlatform => {
+                format!("{}/", platform.as_deref().unwrap_or(ANY_PLATFORM))
+            }
+            BundleLayout::Flat => String::new(),
+        };
+        let path = format!("{}{}", dir, name);
+
+        if bundle.artifacts.iter().any(|a| a.path == path) {
+            return Err(format!(
+                "multiple artifacts would be written to {}",
+                bundle_path.join(&path).display()
+            ));
+        }
+
+        let dest = bundle_path.join(crate::filemanifest::key_path(&path));
+        std::fs::create_dir_all(dest.parent().unwrap())
+            .map_err(|e| format!("unable to create {}: {}", dest.display(), e))?;
+
+        warn!(logger, "copying {} to {}", source.display(), dest.display());
+        let size = std::fs::copy(&source, &dest)
+            .map_err(|e| format!("unable to copy {}: {}", source.display(), e))?;
+        let sha256 = sha256_file(&dest)?;
+
+        let mut signatures = Vec::new();
+        for suffix in SIGNATURE_SUFFIXES {
+            let signature = crate::signing::sibling_path(&source, suffix);
+            if !signature.is_file() {
+                continue;
+            }
+
+            let signature_path = format!("{}{}", path, suffix);
+            std::fs::copy(&signature, crate::signing::sibling_path(&dest, suffix))
+                .map_err(|e| format!("unable to copy {}: {}", signature.display(), e))?;
+
+            signatures.push(signature_path);
+        }
+
+        checksums.push_str(&format!("{}  {}\n", sha256, path));
+        bundle.artifacts.push(BundleArtifact {
+            name,
+            path,
+            platform,
+            size,
+            sha256,
+            signatures,
+        });
+    }
+
+    std::fs::create_dir_all(&bundle_path)
+        .map_err(|e| format!("unable to create {}: {}", bundle_path.display(), e))?;
+
+    let checksums_path = bundle_path.join(CHECKSUMS_FILENAME);
+    std::fs::write(&checksums_path, checksums)
+        .map_err(|e| format!("unable to write {}: {}", checksums_path.display(), e))?;
+
+    if index {
+        let json_path = bundle_path.join("index.json");
+        let data = serde_json::to_string_pretty(&bundle).map_err(|e| e.to_string())?;
+        std::fs::write(&json_path, data + "\n")
+            .map_err(|e| format!("unable to write {}: {}", json_path.display(), e))?;
+
+        let html_path = bundle_path.join("index.html");
+        std::fs::write(&html_path, index_html(bundle_dir, &bundle))
+            .map_err(|e| format!("unable to write {}: {}", html_path.display(), e))?;
+    }
+
+    warn!(
+        logger,
+        "wrote bundle of {} artifacts to {}",
+        bundle.artifacts.len(),
+        bundle_path.display()
+    );
+
+    Ok(bundle)
+}
+
+/// Represents a request to arrange artifacts into a release bundle.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReleaseBundle {
+    /// Paths of artifacts relative to the distribution path, with the
+    /// platform to file them under if it isn't inferred.
+    pub artifacts: Vec<(String, Option<String>)>,
+
+    pub layout: BundleLayout,
+    pub index: bool,
+
+    /// Directory of the bundle relative to the distribution path.
+    pub directory: String,
+}
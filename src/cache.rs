@@ -0,0 +1,221 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Persistent cache of downloaded content.
+
+Content is stored under the SHA-256 digest of its content, so entries
+never need to be invalidated and can be shared between projects. Entries
+are grouped into namespaces (e.g. `downloads`) by the kind of content.
+`CATEGORIES` describes the namespaces tugger uses.
+
+Using an entry updates its modification time, so pruning entries older
+than some age removes those that haven't been used recently.
+*/
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Environment variable overriding the location of the cache.
+pub const CACHE_DIR_ENV: &str = "TUGGER_CACHE_DIR";
+
+/// Namespaces of the cache and what they hold.
+pub const CATEGORIES: &[(&str, &str)] = &[
+    ("downloads", "resources downloaded by fetch() and for tools"),
+    ("runs", "records of the last execution of pipelines"),
+    ("tools", "external tools provisioned for pipelines"),
+    ("uploads", "state of unfinished resumable uploads"),
+];
+
+/// Parse an age such as `30d`.
+///
+/// The units are `m` (minutes), `h` (hours), `d` (days), and `w` (weeks).
+/// A number without a unit is a number of days.
+pub fn parse_age(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+
+    let seconds: u64 = match unit {
+        "m" => 60,
+        "h" => 60 * 60,
+        "" | "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => {
+            return Err(format!(
+                "invalid age {}; expected a number followed by m, h, d, or w",
+                value
+            ))
+        }
+    };
+
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|number| number.checked_mul(seconds))
+        .map(Duration::from_secs)
+        .ok_or_else(|| {
+            format!(
+                "invalid age {}; expected a number followed by m, h, d, or w",
+                value
+            )
+        })
+}
+
+/// Obtain the path to the cache directory.
+///
+/// This is `$TUGGER_CACHE_DIR` if set, `$XDG_CACHE_HOME/tugger` if
+/// `XDG_CACHE_HOME` is set, and `$HOME/.cache/tugger` otherwise.
+pub fn cache_dir() -> Result<PathBuf, String> {
+    if let Some(path) = std::env::var_os(CACHE_DIR_ENV) {
+        if !path.is_empty() {
+            return Ok(PathBuf::from(path));
+        }
+    }
+
+    if let Some(path) = std::env::var_os("XDG_CACHE_HOME") {
+        if !path.is_empty() {
+            return Ok(PathBuf::from(path).join("tugger"));
+        }
+    }
+
+    match std::env::var_os("HOME") {
+        Some(home) => Ok(PathBuf::from(home).join(".cache").join("tugger")),
+        None => Err("unable to determine cache directory: HOME is not set".to_string()),
+    }
+}
+
+/// Obtain the path of content with a SHA-256 digest in a namespace.
+pub fn content_path(namespace: &str, sha256: &str) -> Result<PathBuf, String> {
+    Ok(cache_dir()?.join(namespace).join(sha256.to_lowercase()))
+}
+
+/// Obtain the path of the directory holding downloaded content.
+pub fn download_dir() -> Result<PathBuf, String> {
+    Ok(cache_dir()?.join("downloads"))
+}
+
+/// Obtain the path of downloaded content with a SHA-256 digest.
+pub fn download_path(sha256: &str) -> Result<PathBuf, String> {
+    Ok(download_dir()?.join(sha256.to_lowercase()))
+}
+
+/// Record that a cache entry was used.
+///
+/// Failures are ignored since they only affect pruning.
+pub fn touch(path: &Path) {
+    if let Ok(fh) = std::fs::OpenOptions::new().write(true).open(path) {
+        let _ = fh.set_modified(SystemTime::now());
+    }
+}
+
+/// An entry in the cache.
+#[derive(Clone, Debug)]
+pub struct CacheEntry {
+    pub namespace: String,
+    pub path: PathBuf,
+    pub size: u64,
+
+    /// When the entry was last used.
+    pub modified: SystemTime,
+}
+
+/// Obtain all entries in the cache, sorted by namespace and path.
+pub fn entries() -> Result<Vec<CacheEntry>, String> {
+    let dir = cache_dir()?;
+
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut entries = Vec::new();
+
+    for entry in walkdir::WalkDir::new(&dir)
+        .min_depth(2)
+        .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+    {
+        let entry = entry.map_err(|e| format!("unable to walk {}: {}", dir.display(), e))?;
+
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        let metadata = entry
+            .metadata()
+            .map_err(|e| format!("unable to stat {}: {}", entry.path().display(), e))?;
+
+        let namespace = entry
+            .path()
+            .strip_prefix(&dir)
+            .map_err(|e| e.to_string())?
+            .components()
+            .next()
+            .map(|c| c.as_os_str().to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        // Lock files of resources aren't cached content. Removing one
+        // while it is locked would let another step acquire the resource.
+        if namespace == crate::resources::LOCKS_DIR {
+            continue;
+        }
+
+        entries.push(CacheEntry {
+            namespace,
+            path: entry.path().to_path_buf(),
+            size: metadata.len(),
+            modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Remove cache entries.
+///
+/// If `older_than` is defined, only entries that haven't been used for at
+/// least that long are removed. Otherwise all entries are removed. If
+/// `namespaces` isn't empty, only entries in those namespaces are removed.
+/// Directories left empty are removed as well.
+///
+/// Returns the removed entries.
+pub fn prune(older_than: Option<Duration>, namespaces: &[&str]) -> Result<Vec<CacheEntry>, String> {
+    let now = SystemTime::now();
+    let dir = cache_dir()?;
+    let mut removed = Vec::new();
+
+    for entry in entries()? {
+        if !namespaces.is_empty() && !namespaces.contains(&entry.namespace.as_str()) {
+            continue;
+        }
+
+        let expired = match older_than {
+            Some(age) => now
+                .duration_since(entry.modified)
+                .map(|d| d >= age)
+                .unwrap_or(false),
+            None => true,
+        };
+
+        if !expired {
+            continue;
+        }
+
+        std::fs::remove_file(&entry.path)
+            .map_err(|e| format!("unable to remove {}: {}", entry.path.display(), e))?;
+
+        // Removing a directory fails if it isn't empty, which ends the
+        // search. Namespace directories are kept.
+        for parent in entry.path.ancestors().skip(1) {
+            if parent.parent() == Some(dir.as_path()) || std::fs::remove_dir(parent).is_err() {
+                break;
+            }
+        }
+
+        removed.push(entry);
+    }
+
+    Ok(removed)
+}
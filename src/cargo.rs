@@ -0,0 +1,387 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Functionality for interacting with Rust projects via `cargo`.
+*/
+
+use crate::retry::{AttemptError, RetryPolicy};
+use crate::secrets::Secret;
+use serde::Serialize;
+use slog::{warn, Logger};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// Base URL of the crates.io sparse index.
+const CRATES_IO_INDEX_URL: &str = "https://index.crates.io";
+
+/// Interval between polls of the registry index.
+const INDEX_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Run `cargo metadata` and return the parsed JSON document.
+///
+/// If `no_deps` is true, only workspace members are described.
+pub fn cargo_metadata(manifest_path: &Path, no_deps: bool) -> Result<serde_json::Value, String> {
+    let mut command = Command::new("cargo");
+    command
+        .arg("metadata")
+        .arg("--format-version")
+        .arg("1")
+        .arg("--manifest-path")
+        .arg(manifest_path);
+
+    if no_deps {
+        command.arg("--no-deps");
+    }
+
+    crate::process::record_program(command.get_program());
+
+    let output = command
+        .output()
+        .map_err(|e| format!("error running cargo metadata: {}", e))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "cargo metadata failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+
+    serde_json::from_slice(&output.stdout)
+        .map_err(|e| format!("unable to parse cargo metadata: {}", e))
+}
+
+/// The target triple of the machine tugger is running on.
+pub fn host_target() -> String {
+    let arch = std::env::consts::ARCH;
+
+    let rest = match std::env::consts::OS {
+        "linux" if cfg!(target_env = "musl") => "unknown-linux-musl",
+        "linux" => "unknown-linux-gnu",
+        "macos" => "apple-darwin",
+        "windows" if cfg!(target_env = "gnu") => "pc-windows-gnu",
+        "windows" => "pc-windows-msvc",
+        "freebsd" => "unknown-freebsd",
+        os => return format!("{}-unknown-{}", arch, os),
+    };
+
+    format!("{}-{}", arch, rest)
+}
+
+/// Tool used to drive a build.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CargoBuilder {
+    /// `cargo build`.
+    Cargo,
+
+    /// `cross build`, which builds inside a container with a toolchain for
+    /// the target.
+    Cross,
+
+    /// `cargo zigbuild`, which uses `zig cc` as the linker.
+    Zigbuild,
+}
+
+impl FromStr for CargoBuilder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "cargo" => Ok(CargoBuilder::Cargo),
+            "cross" => Ok(CargoBuilder::Cross),
+            "zigbuild" => Ok(CargoBuilder::Zigbuild),
+            _ => Err(format!(
+                "unknown builder {}; expected cargo, cross, or zigbuild",
+                s
+            )),
+        }
+    }
+}
+
+/// Options for `cargo_build()`.
+#[derive(Clone, Debug)]
+pub struct CargoBuildOptions {
+    pub profile: String,
+    pub features: Vec<String>,
+
+    /// Target triple to build for. Defaults to the host.
+    pub target: Option<String>,
+
+    pub builder: CargoBuilder,
+
+    /// Directory for build outputs, overriding Cargo's default.
+    pub target_dir: Option<PathBuf>,
+}
+
+/// Build a Rust project and return the paths of produced executables.
+///
+/// Compiler output is written to stderr. Produced executables are found
+/// from the JSON messages `cargo` emits.
+pub fn cargo_build(
+    manifest_path: &Path,
+    options: &CargoBuildOptions,
+) -> Result<Vec<PathBuf>, String> {
+    let mut command = match options.builder {
+        CargoBuilder::Cargo => {
+            let mut command = Command::new("cargo");
+            command.arg("build");
+            command
+        }
+        CargoBuilder::Cross => {
+            let mut command = Command::new("cross");
+            command.arg("build");
+            command
+        }
+        CargoBuilder::Zigbuild => {
+            let mut command = Command::new("cargo");
+            command.arg("zigbuild");
+            command
+        }
+    };
+
+    command
+        .arg("--message-format")
+        .arg("json-render-diagnostics")
+        .arg("--manifest-path")
+        .arg(manifest_path)
+        .arg("--profile")
+        .arg(&options.profile);
+
+    if !options.features.is_empty() {
+        command.arg("--features").arg(options.features.join(","));
+    }
+
+    if let Some(target) = &options.target {
+        command.arg("--target").arg(target);
+    }
+
+    if let Some(target_dir) = &options.target_dir {
+        command.arg("--target-dir").arg(target_dir);
+    }
+
+    crate::process::record_program(command.get_program());
+
+    let output = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| format!("error running cargo build: {}", e))?;
+
+    if !output.status.success() {
+        return Err(format!("cargo build of {} failed", manifest_path.display()));
+    }
+
+    let mut executables = Vec::new();
+
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let message: serde_json::Value = match serde_json::from_str(line) {
+            Ok(message) => message,
+            // cargo passes through output of build scripts, which may not be JSON.
+            Err(_) => continue,
+        };
+
+        if message["reason"].as_str() != Some("compiler-artifact") {
+            continue;
+        }
+
+        let is_bin = message["target"]["kind"]
+            .as_array()
+            .map(|kinds| kinds.iter().any(|k| k.as_str() == Some("bin")))
+            .unwrap_or(false);
+
+        if let (true, Some(executable)) = (is_bin, message["executable"].as_str()) {
+            executables.push(PathBuf::from(executable));
+        }
+    }
+
+    // cross reports paths inside its container, where the target
+    // directory is mounted at /target.
+    if options.builder == CargoBuilder::Cross {
+        let target_dir = match &options.target_dir {
+            Some(path) => path.clone(),
+            None => cargo_metadata(manifest_path, true)?["target_directory"]
+                .as_str()
+                .map(PathBuf::from)
+                .ok_or_else(|| "cargo metadata has no target directory".to_string())?,
+        };
+
+        executables = executables
+            .into_iter()
+            .map(|path| match path.strip_prefix("/target") {
+                Ok(rel_path) if !path.exists() => target_dir.join(rel_path),
+                _ => path,
+            })
+            .collect();
+    }
+
+    Ok(executables)
+}
+
+/// Obtain the name and version of the package defined by a `Cargo.toml`.
+pub fn package_name_version(manifest_path: &Path) -> Result<(String, String), String> {
+    let manifest_path = manifest_path
+        .canonicalize()
+        .map_err(|e| format!("unable to resolve {}: {}", manifest_path.display(), e))?;
+    let metadata = cargo_metadata(&manifest_path, true)?;
+
+    for package in metadata["packages"].as_array().unwrap_or(&vec![]) {
+        if package["manifest_path"].as_str().map(Path::new) != Some(manifest_path.as_path()) {
+            continue;
+        }
+
+        if let (Some(name), Some(version)) = (package["name"].as_str(), package["version"].as_str())
+        {
+            return Ok((name.to_string(), version.to_string()));
+        }
+    }
+
+    Err(format!(
+        "{} does not define a package",
+        manifest_path.display()
+    ))
+}
+
+/// Obtain the path of a crate's file in a sparse registry index.
+fn index_path(name: &str) -> String {
+    let name = name.to_lowercase();
+
+    match name.len() {
+        1 => format!("1/{}", name),
+        2 => format!("2/{}", name),
+        3 => format!("3/{}/{}", &name[0..1], name),
+        _ => format!("{}/{}/{}", &name[0..2], &name[2..4], name),
+    }
+}
+
+/// Whether a crate version is present in the crates.io index.
+fn index_has_version(name: &str, version: &str) -> Result<bool, String> {
+    let url = format!("{}/{}", CRATES_IO_INDEX_URL, index_path(name));
+
+    let body = match ureq::get(&url).call() {
+        Ok(response) => response
+            .into_string()
+            .map_err(|e| format!("error reading {}: {}", url, e))?,
+        Err(ureq::Error::Status(404, _)) => return Ok(false),
+        Err(e) => return Err(format!("error fetching {}: {}", url, e)),
+    };
+
+    for line in body.lines() {
+        let entry: serde_json::Value = serde_json::from_str(line)
+            .map_err(|e| format!("unable to parse index entry for {}: {}", name, e))?;
+
+        if entry["vers"].as_str() == Some(version) {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+/// Publish a crate to crates.io using `cargo publish`.
+///
+/// If `token` is defined, it is the registry token. Otherwise `cargo`'s
+/// own credential handling is used.
+///
+/// Unless `dry_run` is true, waits up to `wait_timeout` for the published
+/// version to appear in the registry index, so subsequent steps can
+/// depend on it.
+///
+/// A failed `cargo publish` is retried according to `retry`.
+pub fn cargo_publish(
+    logger: &Logger,
+    manifest_path: &Path,
+    dry_run: bool,
+    token: Option<&Secret>,
+    wait_timeout: Duration,
+    retry: &RetryPolicy,
+) -> Result<(), String> {
+    let (name, version) = package_name_version(manifest_path)?;
+
+    let mut command = Command::new("cargo");
+    command
+        .arg("publish")
+        .arg("--manifest-path")
+        .arg(manifest_path);
+
+    if dry_run {
+        command.arg("--dry-run");
+    }
+
+    if let Some(token) = token {
+        match token.resolve() {
+            Ok(token) => {
+                command.env("CARGO_REGISTRY_TOKEN", token);
+            }
+            Err(_) if dry_run => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    warn!(
+        logger,
+        "publishing {} {}{}",
+        name,
+        version,
+        if dry_run { " (dry run)" } else { "" }
+    );
+
+    retry.retry(logger, "cargo publish", || {
+        crate::process::run_logged(logger, &mut command).map_err(AttemptError::process)
+    })?;
+
+    if dry_run {
+        return Ok(());
+    }
+
+    warn!(
+        logger,
+        "waiting for {} {} to appear in the index", name, version
+    );
+
+    let start = Instant::now();
+
+    loop {
+        match index_has_version(&name, &version) {
+            Ok(true) => {
+                warn!(logger, "{} {} is available", name, version);
+                return Ok(());
+            }
+            Ok(false) => {}
+            Err(e) => warn!(logger, "{}", e),
+        }
+
+        if start.elapsed() >= wait_timeout {
+            return Err(format!(
+                "{} {} did not appear in the index within {} seconds",
+                name,
+                version,
+                wait_timeout.as_secs()
+            ));
+        }
+
+        std::thread::sleep(INDEX_POLL_INTERVAL);
+    }
+}
+
+/// Represents a request to publish a crate.
+#[derive(Debug, Clone, Serialize)]
+pub struct CargoPublish {
+    /// Path to the `Cargo.toml` of the crate to publish.
+    pub manifest_path: PathBuf,
+
+    pub dry_run: bool,
+
+    /// Registry token.
+    pub token: Option<Secret>,
+
+    /// Seconds to wait for the published version to appear in the index.
+    pub wait_timeout: u64,
+
+    /// How failed operations are retried, if not the pipeline's policy.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry: Option<RetryPolicy>,
+}
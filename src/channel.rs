@@ -0,0 +1,71 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Release channels.
+
+A release channel identifies the release train being built, so a single
+configuration can produce stable releases, betas, and nightlies. The
+channel is chosen with `tugger run --channel` and exposed to Starlark
+as `CHANNEL`. `{channel}` in artifact filenames and upload destinations
+is replaced by the channel name.
+*/
+
+use serde::Serialize;
+use std::str::FromStr;
+
+/// Placeholder replaced by the channel name.
+pub const CHANNEL_PLACEHOLDER: &str = "{channel}";
+
+/// A release train.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Channel {
+    #[default]
+    Stable,
+    Beta,
+    Nightly,
+}
+
+impl FromStr for Channel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "stable" => Ok(Channel::Stable),
+            "beta" => Ok(Channel::Beta),
+            "nightly" => Ok(Channel::Nightly),
+            _ => Err(format!(
+                "unknown channel {}; expected stable, beta, or nightly",
+                s
+            )),
+        }
+    }
+}
+
+impl Channel {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Channel::Stable => "stable",
+            Channel::Beta => "beta",
+            Channel::Nightly => "nightly",
+        }
+    }
+
+    /// The snap grade of releases on this channel.
+    ///
+    /// Snaps with the `devel` grade can't be released to the stable or
+    /// candidate channels of the snap store.
+    pub fn snap_grade(self) -> &'static str {
+        match self {
+            Channel::Stable => "stable",
+            Channel::Beta | Channel::Nightly => "devel",
+        }
+    }
+
+    /// Replace `{channel}` in a string with the channel name.
+    pub fn expand(self, value: &str) -> String {
+        value.replace(CHANNEL_PLACEHOLDER, self.as_str())
+    }
+}
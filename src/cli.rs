@@ -2,15 +2,26 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use super::starlark::eval::evaluate_file;
-use super::starlark::EnvironmentContext;
-use clap::{App, AppSettings, Arg, SubCommand};
+use super::starlark::eval::{diagnostic_location, evaluate_file_with_codemap};
+use super::starlark::CONFIG_FILENAME;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use codemap::CodeMap;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 
+use crate::build_environment::BuildEnvironment;
+use crate::channel::Channel;
+use crate::error::TuggerError;
+use crate::lock::{Lock, LockMode, LOCK_FILENAME};
+use crate::observer::ExecutionObserver;
+use crate::pipeline::{EnvironmentContext, Pipeline};
+use crate::reproducibility::Reproducibility;
+use crate::retry::RetryPolicy;
+use crate::secrets::redact;
 use crate::starlark::eval::EvalResult;
-use crate::starlark::values::Pipeline;
+use crate::starlark::format::format_source;
 use slog::warn;
-use slog::Drain;
+use slog::{Drain, KV};
 
 pub struct PrintlnDrain {
     min_level: slog::Level,
@@ -26,27 +37,213 @@ impl Drain for PrintlnDrain {
         _values: &slog::OwnedKVList,
     ) -> Result<Self::Ok, Self::Err> {
         if record.level().is_at_least(self.min_level) {
-            println!("{}", record.msg());
+            println!("{}", redact(&record.msg().to_string()));
         }
 
         Ok(())
     }
 }
 
-pub fn run_cli() -> Result<(), String> {
+/// A drain that prints each record as a JSON object on its own line.
+///
+/// Key-value pairs attached to the record and its logger (e.g. `pipeline`
+/// and `step`) are emitted as members of the object. Secret values are
+/// redacted from the message and all values.
+pub struct JsonDrain {
+    min_level: slog::Level,
+}
+
+/// Collects slog key-value pairs into a JSON object.
+struct JsonSerializer<'a>(&'a mut serde_json::Map<String, serde_json::Value>);
+
+impl<'a> slog::Serializer for JsonSerializer<'a> {
+    fn emit_usize(&mut self, key: slog::Key, val: usize) -> slog::Result {
+        self.0.insert(key.to_string(), serde_json::Value::from(val));
+        Ok(())
+    }
+
+    fn emit_arguments(&mut self, key: slog::Key, val: &std::fmt::Arguments) -> slog::Result {
+        self.0.insert(
+            key.to_string(),
+            serde_json::Value::from(redact(&val.to_string())),
+        );
+        Ok(())
+    }
+}
+
+impl Drain for JsonDrain {
+    type Ok = ();
+    type Err = std::io::Error;
+
+    fn log(
+        &self,
+        record: &slog::Record,
+        values: &slog::OwnedKVList,
+    ) -> Result<Self::Ok, Self::Err> {
+        if !record.level().is_at_least(self.min_level) {
+            return Ok(());
+        }
+
+        let mut object = serde_json::Map::new();
+        object.insert(
+            "timestamp".to_string(),
+            serde_json::Value::from(chrono::Utc::now().to_rfc3339()),
+        );
+        object.insert(
+            "level".to_string(),
+            serde_json::Value::from(record.level().as_str().to_lowercase()),
+        );
+
+        values.serialize(record, &mut JsonSerializer(&mut object))?;
+        record
+            .kv()
+            .serialize(record, &mut JsonSerializer(&mut object))?;
+
+        object.insert(
+            "message".to_string(),
+            serde_json::Value::from(redact(&record.msg().to_string())),
+        );
+
+        println!("{}", serde_json::Value::Object(object));
+
+        Ok(())
+    }
+}
+
+pub fn run_cli() -> Result<(), TuggerError> {
     let matches = App::new("tugger")
         .setting(AppSettings::ArgRequiredElseHelp)
         .version("0.1")
         .author("Gregory Szorc <gregory.szorc@gmail.com>")
         .long_about("Build distributable applications")
+        .arg(
+            Arg::with_name("log_format")
+                .long("log-format")
+                .global(true)
+                .takes_value(true)
+                .possible_values(&["text", "json"])
+                .default_value("text")
+                .help("Format of log output"),
+        )
+        .arg(
+            Arg::with_name("non_interactive")
+                .long("non-interactive")
+                .global(true)
+                .help("Fail instead of prompting for secrets that aren't set"),
+        )
+        .subcommand(
+            SubCommand::with_name("cache")
+                .about("Inspect and manage the cache of downloads, tools, and uploads")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("clear")
+                        .about("Remove all entries from the cache")
+                        .arg(cache_category_arg()),
+                )
+                .subcommand(
+                    SubCommand::with_name("list")
+                        .about("List entries in the cache")
+                        .arg(cache_category_arg()),
+                )
+                .subcommand(
+                    SubCommand::with_name("prune")
+                        .about("Remove entries from the cache that haven't been used recently")
+                        .arg(
+                            Arg::with_name("older_than")
+                                .long("older-than")
+                                .takes_value(true)
+                                .required(true)
+                                .value_name("AGE")
+                                .help("Remove entries not used for this long, e.g. 30d, 12h, or 2w"),
+                        )
+                        .arg(cache_category_arg()),
+                )
+                .subcommand(
+                    SubCommand::with_name("stats")
+                        .alias("info")
+                        .about("Show the location of the cache and its disk usage per category"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("diff")
+                .about("Compare the contents of two packages or archives")
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(&["text", "json"])
+                        .default_value("text")
+                        .help("Output format"),
+                )
+                .arg(
+                    Arg::with_name("old")
+                        .required(true)
+                        .value_name("OLD")
+                        .help("Path to the old artifact"),
+                )
+                .arg(
+                    Arg::with_name("new")
+                        .required(true)
+                        .value_name("NEW")
+                        .help("Path to the new artifact"),
+                ),
+        )
         .subcommand(
             SubCommand::with_name("eval")
                 .about("Evaluate a tugger configuration file and show results")
                 .arg(
-                    Arg::with_name("path")
+                    Arg::with_name("format")
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(&["text", "json"])
+                        .default_value("text")
+                        .help("Output format for evaluation results"),
+                )
+                .arg(
+                    Arg::with_name("config")
+                        .long("config")
+                        .takes_value(true)
                         .value_name("PATH")
-                        .default_value("tugger.ship")
+                        .conflicts_with("path")
                         .help("Path to file to evaluate"),
+                )
+                .arg(
+                    Arg::with_name("path")
+                        .value_name("PATH")
+                        .help("Path to file to evaluate (default: find tugger.ship)"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("fmt")
+                .about("Format tugger configuration files canonically")
+                .arg(
+                    Arg::with_name("check")
+                        .long("check")
+                        .help("Don't write files; exit non-zero if any file isn't formatted"),
+                )
+                .arg(
+                    Arg::with_name("paths")
+                        .value_name("PATH")
+                        .multiple(true)
+                        .help("Paths to files to format (default: find tugger.ship)"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("inspect")
+                .about("List the contents of a package or archive")
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(&["text", "json"])
+                        .default_value("text")
+                        .help("Output format"),
+                )
+                .arg(
+                    Arg::with_name("path")
+                        .required(true)
+                        .value_name("PATH")
+                        .help("Path to a .deb, .snap, tar, or zip file"),
                 ),
         )
         .subcommand(
@@ -64,31 +261,233 @@ pub fn run_cli() -> Result<(), String> {
                         .value_name("pipeline")
                         .help("Name of pipeline to execute"),
                 )
+                .arg(
+                    Arg::with_name("keep_going")
+                        .long("keep-going")
+                        .help("Continue executing remaining pipelines after a pipeline fails"),
+                )
+                .arg(
+                    Arg::with_name("reproducible").long("reproducible").help(
+                        "Produce reproducible artifacts (implied if SOURCE_DATE_EPOCH is set)",
+                    ),
+                )
+                .arg(
+                    Arg::with_name("channel")
+                        .long("channel")
+                        .takes_value(true)
+                        .possible_values(&["stable", "beta", "nightly"])
+                        .default_value("stable")
+                        .help("Release channel to build"),
+                )
+                .arg(
+                    Arg::with_name("max_attempts")
+                        .long("max-attempts")
+                        .takes_value(true)
+                        .value_name("N")
+                        .help("Maximum attempts of failed network operations unless a pipeline or step defines a retry policy (default: 3)"),
+                )
+                .arg(
+                    Arg::with_name("report")
+                        .long("report")
+                        .takes_value(true)
+                        .value_name("PATH")
+                        .help("Write a JSON report of pipeline execution to this path"),
+                )
+                .arg(
+                    Arg::with_name("report_junit")
+                        .long("report-junit")
+                        .takes_value(true)
+                        .value_name("PATH")
+                        .help("Write a JUnit XML report of pipeline execution to this path"),
+                )
+                .arg(
+                    Arg::with_name("record_environment")
+                        .long("record-environment")
+                        .requires("report")
+                        .help("Record versions of external tools, relevant environment variables, and OS information in the report"),
+                )
+                .arg(
+                    Arg::with_name("ci")
+                        .long("ci")
+                        .takes_value(true)
+                        .possible_values(&["github"])
+                        .help("Emit workflow commands and outputs for a CI system"),
+                )
+                .arg(
+                    Arg::with_name("locked")
+                        .long("locked")
+                        .help("Fail if downloads or programs aren't pinned by tugger.lock instead of updating it"),
+                )
+                .arg(
+                    Arg::with_name("env_files")
+                        .long("env-file")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .value_name("PATH")
+                        .help("Set environment variables defined in a KEY=VALUE file before evaluating"),
+                )
+                .arg(
+                    Arg::with_name("resource_limits")
+                        .long("resource-limit")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .value_name("NAME=LIMIT")
+                        .help("Number of steps that may hold a resource at once, across tugger processes (default: 1)"),
+                )
+                .arg(
+                    Arg::with_name("step")
+                        .long("step")
+                        .takes_value(true)
+                        .value_name("step")
+                        .help("Index or kind of a single step of the pipeline to execute, consuming artifacts of the last run of the pipeline"),
+                )
+                .arg(
+                    Arg::with_name("config")
+                        .long("config")
+                        .takes_value(true)
+                        .value_name("PATH")
+                        .conflicts_with("path")
+                        .help("Path to file to evaluate"),
+                )
                 .arg(
                     Arg::with_name("path")
                         .value_name("PATH")
-                        .default_value("tugger.ship")
+                        .help("Path to file to evaluate (default: find tugger.ship)"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("secret")
+                .about("Manage secrets stored in the system keyring")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("set")
+                        .about("Store a secret, prompting for its value or reading it from stdin")
+                        .arg(
+                            Arg::with_name("name")
+                                .required(true)
+                                .value_name("NAME")
+                                .help("Name of the secret"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("delete")
+                        .about("Remove a secret")
+                        .arg(
+                            Arg::with_name("name")
+                                .required(true)
+                                .value_name("NAME")
+                                .help("Name of the secret"),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("update-lock")
+                .about("Download resources again and rewrite tugger.lock")
+                .arg(
+                    Arg::with_name("config")
+                        .long("config")
+                        .takes_value(true)
+                        .value_name("PATH")
+                        .conflicts_with("path")
                         .help("Path to file to evaluate"),
+                )
+                .arg(
+                    Arg::with_name("path")
+                        .value_name("PATH")
+                        .help("Path to file to evaluate (default: find tugger.ship)"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("verify")
+                .about("Verify artifacts against their recorded inventory and signatures")
+                .arg(
+                    Arg::with_name("report")
+                        .long("report")
+                        .takes_value(true)
+                        .value_name("PATH")
+                        .default_value("dist/artifacts.json")
+                        .help("Path to the artifact inventory; artifacts are relative to its directory"),
+                )
+                .arg(
+                    Arg::with_name("minisign_key")
+                        .long("minisign-key")
+                        .takes_value(true)
+                        .value_name("PATH")
+                        .help("minisign public key verifying .minisig signatures"),
+                )
+                .arg(
+                    Arg::with_name("cosign_key")
+                        .long("cosign-key")
+                        .takes_value(true)
+                        .value_name("PATH")
+                        .help("cosign public key verifying .sig signatures"),
+                )
+                .arg(
+                    Arg::with_name("gpg")
+                        .long("gpg")
+                        .help("Verify .asc signatures with the gpg keyring"),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(&["text", "json"])
+                        .default_value("text")
+                        .help("Output format"),
                 ),
         )
         .get_matches();
 
-    let logger = slog::Logger::root(
-        PrintlnDrain {
-            min_level: slog::Level::Info,
-        }
-        .fuse(),
-        slog::o!(),
-    );
+    if matches.is_present("non_interactive") {
+        crate::secrets::set_interactive(false);
+    }
+
+    let logger = match matches.value_of("log_format") {
+        Some("json") => slog::Logger::root(
+            JsonDrain {
+                min_level: slog::Level::Info,
+            }
+            .fuse(),
+            slog::o!(),
+        ),
+        _ => slog::Logger::root(
+            PrintlnDrain {
+                min_level: slog::Level::Info,
+            }
+            .fuse(),
+            slog::o!(),
+        ),
+    };
 
     let cwd = std::env::current_dir().unwrap();
-    let dist_path = cwd.join("dist");
 
     match matches.subcommand() {
+        ("cache", Some(args)) => run_cache(args),
+        ("diff", Some(args)) => run_diff(args),
+        ("secret", Some(args)) => run_secret(args),
+        ("update-lock", Some(args)) => run_update_lock(&logger, args, &cwd),
+        ("verify", Some(args)) => run_verify(&logger, args),
         ("eval", Some(args)) => {
-            let path = args.value_of("path").unwrap();
+            let path = config_path(args, &cwd)?;
+            let json = args.value_of("format") == Some("json");
+
+            // Log output would corrupt the JSON document on stdout.
+            let logger = if json {
+                slog::Logger::root(slog::Discard, slog::o!())
+            } else {
+                logger
+            };
 
-            let eval_result = eval_file(&logger, path, &dist_path)?;
+            let eval_result = eval_file(
+                &logger,
+                &path,
+                false,
+                Channel::default(),
+                RetryPolicy::default(),
+                LockMode::Update,
+            )?;
 
             let env = eval_result.env;
 
@@ -97,7 +496,30 @@ pub fn run_cli() -> Result<(), String> {
                 .or_else(|e| Err(format!("could not get PIPELINES: {:#?}", e)))?;
 
             if pipelines.get_type() != "list" {
-                return Err("PIPELINES is not a list".to_string());
+                return Err("PIPELINES is not a list".into());
+            }
+
+            if json {
+                let mut values = Vec::new();
+
+                for pipeline in pipelines.into_iter().unwrap() {
+                    let raw_value = pipeline.0.borrow();
+                    let pipeline: &Pipeline = raw_value.as_any().downcast_ref().unwrap();
+
+                    values.push(
+                        serde_json::to_value(pipeline)
+                            .map_err(|e| format!("unable to serialize pipeline: {}", e))?,
+                    );
+                }
+
+                let doc = serde_json::json!({ "pipelines": values });
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&doc)
+                        .map_err(|e| format!("unable to format JSON: {}", e))?
+                );
+
+                return Ok(());
             }
 
             warn!(logger, "found {} pipelines", pipelines.length().unwrap());
@@ -111,53 +533,708 @@ pub fn run_cli() -> Result<(), String> {
 
             Ok(())
         }
-        ("repl", Some(_)) => {
-            let context = EnvironmentContext {
-                cwd,
-                logger,
-                dist_path,
+        ("fmt", Some(args)) => {
+            let check = args.is_present("check");
+            let mut unformatted = Vec::new();
+
+            let paths = match args.values_of("paths") {
+                Some(paths) => paths.map(PathBuf::from).collect(),
+                None => vec![find_config(&cwd)?],
             };
+
+            for path in &paths {
+                let display = path.display();
+                let source = std::fs::read_to_string(path)
+                    .map_err(|e| TuggerError::io(format!("unable to read {}", display), e))?;
+                let formatted = format_source(&source)
+                    .map_err(|e| format!("unable to format {}: {}", display, e))?;
+
+                if formatted == source {
+                    continue;
+                }
+
+                if check {
+                    println!("{} is not formatted", display);
+                    unformatted.push(path);
+                } else {
+                    std::fs::write(path, formatted)
+                        .map_err(|e| TuggerError::io(format!("unable to write {}", display), e))?;
+                    println!("formatted {}", display);
+                }
+            }
+
+            if unformatted.is_empty() {
+                Ok(())
+            } else {
+                Err(format!("{} file(s) need formatting", unformatted.len()).into())
+            }
+        }
+        ("inspect", Some(args)) => run_inspect(args),
+        ("repl", Some(_)) => {
+            let context = EnvironmentContext::new(logger, &cwd)?;
             let env = super::starlark::global_environment(&context)
                 .or_else(|_| Err(String::from("error creating environment")))?;
 
-            starlark_repl::repl(&env, false);
-
-            Ok(())
+            crate::repl::repl(&env).map_err(TuggerError::from)
         }
         ("run", Some(args)) => {
-            let path = args.value_of("path").unwrap();
-            let eval_result = eval_file(&logger, path, &dist_path)?;
+            let path = config_path(args, &cwd)?;
+            let channel = args.value_of("channel").unwrap().parse()?;
+            let retry = match args.value_of("max_attempts") {
+                Some(value) => match value.parse::<u32>() {
+                    Ok(max_attempts) if max_attempts >= 1 => RetryPolicy {
+                        max_attempts,
+                        ..RetryPolicy::default()
+                    },
+                    _ => {
+                        return Err(format!(
+                            "--max-attempts must be a positive integer; got {}",
+                            value
+                        )
+                        .into())
+                    }
+                },
+                None => RetryPolicy::default(),
+            };
+            let resource_limits = args
+                .values_of("resource_limits")
+                .map(|values| {
+                    values
+                        .map(crate::resources::parse_limit)
+                        .collect::<Result<_, _>>()
+                })
+                .transpose()?
+                .unwrap_or_default();
+            // Variables of environment files are visible to evaluation,
+            // to secrets, and to the processes steps spawn.
+            for env_file in args.values_of("env_files").unwrap_or_default() {
+                for (key, value) in crate::env_file::read(&cwd.join(env_file))? {
+                    std::env::set_var(key, value);
+                }
+            }
+            let github = args.value_of("ci") == Some("github");
+            let mut eval_result = match eval_file(
+                &logger,
+                &path,
+                args.is_present("reproducible"),
+                channel,
+                retry,
+                if args.is_present("locked") {
+                    LockMode::Locked
+                } else {
+                    LockMode::Update
+                },
+            ) {
+                Ok(eval_result) => eval_result,
+                Err(e) => {
+                    if let (
+                        true,
+                        TuggerError::Evaluation {
+                            path,
+                            summary,
+                            location,
+                            ..
+                        },
+                    ) = (github, &e)
+                    {
+                        println!(
+                            "{}",
+                            crate::github_actions::error_command(
+                                &format!("error evaluating {}", path.display()),
+                                summary,
+                                location.as_ref()
+                            )
+                        );
+                    }
+
+                    return Err(e);
+                }
+            };
+
+            eval_result.context.resource_limits = resource_limits;
+
+            let mut observers: Vec<Box<dyn ExecutionObserver>> = Vec::new();
 
-            if let Some(pipelines) = args.values_of("pipelines") {
-                for pipeline in pipelines {
-                    eval_result.execute_pipeline(pipeline)?;
+            #[cfg(feature = "otel")]
+            {
+                if let Some(observer) = crate::otel::OtlpObserver::from_env(logger.clone()) {
+                    warn!(logger, "exporting trace {}", observer.trace_id());
+                    observers.push(Box::new(observer));
                 }
+            }
+
+            if github {
+                observers.push(Box::new(
+                    crate::github_actions::GithubActionsObserver::from_env(),
+                ));
+            }
+
+            if !observers.is_empty() {
+                eval_result.set_observer(Box::new(observers));
+            }
+
+            // Installed programs are checked before anything executes.
+            eval_result.context.lock.check_tools()?;
+
+            let res = run_pipelines(&logger, &eval_result, args).and_then(|()| {
+                let programs = crate::process::programs_run();
+
+                for warning in eval_result.context.lock.record_tools(&programs)? {
+                    warn!(logger, "{}", warning);
+                }
+
+                Ok(())
+            });
+
+            if let Some(path) = eval_result.context.lock.write()? {
+                warn!(logger, "updated {}", path.display());
+            }
+
+            let mut report = eval_result.report();
+
+            if args.is_present("record_environment") {
+                report.environment =
+                    Some(BuildEnvironment::capture(&crate::process::programs_run()));
+            }
+
+            if !report.pipelines.is_empty() {
+                warn!(logger, "execution summary:");
+                for line in report.timing_table() {
+                    warn!(logger, "{}", line);
+                }
+            }
+
+            if let Some(report_path) = args.value_of("report") {
+                let data = serde_json::to_vec_pretty(&report)
+                    .map_err(|e| format!("unable to serialize report: {}", e))?;
+                std::fs::write(report_path, data)
+                    .map_err(|e| TuggerError::io(format!("unable to write {}", report_path), e))?;
+            }
+
+            if let Some(report_path) = args.value_of("report_junit") {
+                std::fs::write(report_path, report.to_junit_xml())
+                    .map_err(|e| TuggerError::io(format!("unable to write {}", report_path), e))?;
+            }
+
+            res
+        }
+        _ => Err("invalid sub-command".into()),
+    }
+}
+
+/// Execute `tugger diff`.
+fn run_diff(args: &ArgMatches) -> Result<(), TuggerError> {
+    use crate::inspect::ChangeKind;
+    use crate::release_notes::format_size;
+
+    let old_path = Path::new(args.value_of("old").unwrap());
+    let new_path = Path::new(args.value_of("new").unwrap());
+
+    let old = crate::inspect::inspect(old_path)?;
+    let new = crate::inspect::inspect(new_path)?;
+    let diff = crate::inspect::diff(&old, &new)?;
+
+    if args.value_of("format") == Some("json") {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&diff)
+                .map_err(|e| format!("unable to format JSON: {}", e))?
+        );
+
+        return Ok(());
+    }
+
+    println!(
+        "{} -> {}: {} added, {} removed, {} modified, {} unchanged",
+        old_path.display(),
+        new_path.display(),
+        diff.count(ChangeKind::Added),
+        diff.count(ChangeKind::Removed),
+        diff.count(ChangeKind::Modified),
+        diff.unchanged
+    );
+    println!(
+        "total size: {} -> {}",
+        format_size(old.total_size()),
+        format_size(new.total_size())
+    );
+
+    if !diff.control_removed.is_empty() || !diff.control_added.is_empty() {
+        println!();
+        println!("control:");
+        for line in &diff.control_removed {
+            println!("  - {}", line);
+        }
+        for line in &diff.control_added {
+            println!("  + {}", line);
+        }
+    }
+
+    if !diff.changes.is_empty() {
+        println!();
+    }
+
+    for change in &diff.changes {
+        match change.change {
+            ChangeKind::Added => println!("A {}", change.path),
+            ChangeKind::Removed => println!("D {}", change.path),
+            ChangeKind::Modified => {
+                println!("M {} ({})", change.path, change.details.join(", "))
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Execute `tugger verify`.
+fn run_verify(logger: &slog::Logger, args: &ArgMatches) -> Result<(), TuggerError> {
+    let json = args.value_of("format") == Some("json");
+    let report_path = Path::new(args.value_of("report").unwrap());
+    let dist_path = report_path.parent().unwrap_or_else(|| Path::new(""));
+
+    let keys = crate::signing::VerificationKeys {
+        minisign: args.value_of("minisign_key").map(PathBuf::from),
+        cosign: args.value_of("cosign_key").map(PathBuf::from),
+        gpg: args.is_present("gpg"),
+    };
+
+    // Log output would corrupt the JSON document on stdout.
+    let logger = if json {
+        slog::Logger::root(slog::Discard, slog::o!())
+    } else {
+        logger.clone()
+    };
+
+    let inventory = crate::inventory::Inventory::read(report_path)?;
+    let verification = inventory.verify(&logger, dist_path, &keys)?;
+
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&verification)
+                .map_err(|e| format!("unable to format JSON: {}", e))?
+        );
+    } else {
+        for mismatch in &verification.mismatches {
+            println!("FAILED {}", mismatch);
+        }
+        for name in &verification.unverified_signatures {
+            println!("unverified {}: no key given", name);
+        }
+        for name in &verification.unrecorded {
+            println!("unrecorded {}", name);
+        }
+
+        println!(
+            "{}: {} of {} artifacts verified, {} signatures verified",
+            report_path.display(),
+            verification.verified,
+            inventory.artifacts.len(),
+            verification.signatures.len()
+        );
+    }
+
+    if verification.mismatches.is_empty() {
+        Ok(())
+    } else {
+        Err(TuggerError::Other(format!(
+            "{} artifacts don't match {}",
+            verification.mismatches.len(),
+            report_path.display()
+        )))
+    }
+}
+
+/// Execute `tugger inspect`.
+fn run_inspect(args: &ArgMatches) -> Result<(), TuggerError> {
+    use crate::release_notes::format_size;
+
+    let path = Path::new(args.value_of("path").unwrap());
+    let inspection = crate::inspect::inspect(path)?;
+
+    if args.value_of("format") == Some("json") {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&inspection)
+                .map_err(|e| format!("unable to format JSON: {}", e))?
+        );
+
+        return Ok(());
+    }
+
+    println!(
+        "{}: {}, {} members, {}",
+        path.display(),
+        inspection.format,
+        inspection.members.len(),
+        format_size(inspection.total_size())
+    );
+
+    if let Some(control) = &inspection.control {
+        println!();
+        for line in control.lines() {
+            println!("  {}", line);
+        }
+    }
+
+    println!();
+    for member in &inspection.members {
+        let link = match &member.link_target {
+            Some(target) => format!(" -> {}", target),
+            None => String::new(),
+        };
+
+        println!(
+            "{} {:>12} {}{}",
+            member.mode_string(),
+            member.size,
+            member.path,
+            link
+        );
+    }
+
+    Ok(())
+}
+
+/// Execute a `tugger secret` sub-command.
+fn run_secret(args: &ArgMatches) -> Result<(), TuggerError> {
+    match args.subcommand() {
+        ("set", Some(args)) => {
+            let name = args.value_of("name").unwrap();
+
+            let value = if crate::secrets::is_interactive() {
+                crate::secrets::prompt_hidden(&format!("Enter secret {}: ", name))?
             } else {
-                eval_result.execute_all_pipelines()?;
+                let mut value = String::new();
+                std::io::stdin()
+                    .read_line(&mut value)
+                    .map_err(|e| TuggerError::io("unable to read secret from stdin", e))?;
+                value.trim_end_matches(&['\r', '\n'][..]).to_string()
+            };
+
+            if value.is_empty() {
+                return Err(format!("no value given for secret {}", name).into());
             }
 
+            crate::secrets::store(name, &value)?;
+            println!("stored secret {}", name);
+
             Ok(())
         }
-        _ => Err("invalid sub-command".to_string()),
+        ("delete", Some(args)) => {
+            let name = args.value_of("name").unwrap();
+
+            crate::secrets::delete(name)?;
+            println!("deleted secret {}", name);
+
+            Ok(())
+        }
+        _ => Err("invalid sub-command".into()),
     }
 }
 
-fn eval_file(logger: &slog::Logger, path: &str, dist_path: &Path) -> Result<EvalResult, String> {
-    let path = PathBuf::from(path);
+/// Execute `tugger update-lock`.
+///
+/// Evaluation downloads the resources of `fetch()`. The tools of every
+/// pipeline are then provisioned, without executing any step, and the
+/// versions of the programs of the lockfile are recorded again.
+fn run_update_lock(
+    logger: &slog::Logger,
+    args: &ArgMatches,
+    cwd: &Path,
+) -> Result<(), TuggerError> {
+    let path = config_path(args, cwd)?;
+    let eval_result = eval_file(
+        logger,
+        &path,
+        false,
+        Channel::default(),
+        RetryPolicy::default(),
+        LockMode::Refresh,
+    )?;
+    let context = &eval_result.context;
+
+    let pipelines = eval_result
+        .env
+        .get("PIPELINES")
+        .map_err(|e| format!("could not get PIPELINES: {:#?}", e))?;
+
+    for pipeline in pipelines
+        .into_iter()
+        .map_err(|e| format!("could not iterate PIPELINES: {:#?}", e))?
+    {
+        let raw_value = pipeline.0.borrow();
+        let pipeline: &Pipeline = raw_value.as_any().downcast_ref().unwrap();
+
+        crate::tools::provision_all(
+            logger,
+            &pipeline.tools,
+            &context.lock,
+            pipeline.retry.as_ref().unwrap_or(&context.retry),
+        )?;
+    }
+
+    context.lock.record_tools(&context.lock.locked_tools())?;
+
+    match context.lock.write()? {
+        Some(path) => println!("wrote {}", path.display()),
+        None => println!("{} is up to date", LOCK_FILENAME),
+    }
+
+    Ok(())
+}
+
+/// Obtain the `--category` argument of `tugger cache` sub-commands.
+fn cache_category_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("category")
+        .long("category")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1)
+        .possible_values(
+            &crate::cache::CATEGORIES
+                .iter()
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>(),
+        )
+        .help("Only operate on entries of this category")
+}
+
+/// Execute a `tugger cache` sub-command.
+fn run_cache(args: &ArgMatches) -> Result<(), TuggerError> {
+    use crate::release_notes::format_size;
+
+    let print_removed = |removed: Vec<crate::cache::CacheEntry>| {
+        println!(
+            "removed {} entries, {}",
+            removed.len(),
+            format_size(removed.iter().map(|e| e.size).sum())
+        );
+    };
+
+    match args.subcommand() {
+        ("stats", Some(_)) => {
+            let entries = crate::cache::entries()?;
+            let mut namespaces = std::collections::BTreeMap::new();
+
+            for (namespace, _) in crate::cache::CATEGORIES {
+                namespaces.insert(namespace.to_string(), (0, 0, None));
+            }
+
+            for entry in &entries {
+                let (count, size, last_used) = namespaces
+                    .entry(entry.namespace.clone())
+                    .or_insert((0, 0, None));
+                *count += 1;
+                *size += entry.size;
+                *last_used = (*last_used).max(Some(entry.modified));
+            }
+
+            println!("cache directory: {}", crate::cache::cache_dir()?.display());
+
+            for (namespace, (count, size, last_used)) in &namespaces {
+                let description = crate::cache::CATEGORIES
+                    .iter()
+                    .find(|(name, _)| name == namespace)
+                    .map(|(_, description)| format!(" ({})", description))
+                    .unwrap_or_default();
+                let last_used = match last_used {
+                    Some(time) => format!(
+                        ", last used {}",
+                        chrono::DateTime::<chrono::Local>::from(*time).format("%Y-%m-%d")
+                    ),
+                    None => String::new(),
+                };
+
+                println!(
+                    "{}{}: {} entries, {}{}",
+                    namespace,
+                    description,
+                    count,
+                    format_size(*size),
+                    last_used
+                );
+            }
+
+            println!(
+                "total: {} entries, {}",
+                entries.len(),
+                format_size(entries.iter().map(|e| e.size).sum())
+            );
+
+            Ok(())
+        }
+        ("clear", Some(args)) => {
+            let categories: Vec<&str> = args
+                .values_of("category")
+                .map(|v| v.collect())
+                .unwrap_or_default();
 
-    let normalized = path.canonicalize().unwrap();
+            print_removed(crate::cache::prune(None, &categories)?);
+
+            Ok(())
+        }
+        ("list", Some(args)) => {
+            let categories: Vec<&str> = args
+                .values_of("category")
+                .map(|v| v.collect())
+                .unwrap_or_default();
+
+            for entry in crate::cache::entries()? {
+                if !categories.is_empty() && !categories.contains(&entry.namespace.as_str()) {
+                    continue;
+                }
+
+                println!(
+                    "{}  {}  {}",
+                    chrono::DateTime::<chrono::Local>::from(entry.modified).format("%Y-%m-%d"),
+                    format_size(entry.size),
+                    entry.path.display()
+                );
+            }
+
+            Ok(())
+        }
+        ("prune", Some(args)) => {
+            let older_than = crate::cache::parse_age(args.value_of("older_than").unwrap())?;
+            let categories: Vec<&str> = args
+                .values_of("category")
+                .map(|v| v.collect())
+                .unwrap_or_default();
+
+            print_removed(crate::cache::prune(Some(older_than), &categories)?);
+
+            Ok(())
+        }
+        _ => Err("invalid sub-command".into()),
+    }
+}
+
+/// Execute the pipelines requested by `tugger run` arguments.
+fn run_pipelines(
+    logger: &slog::Logger,
+    eval_result: &EvalResult,
+    args: &ArgMatches,
+) -> Result<(), TuggerError> {
+    if let Some(step) = args.value_of("step") {
+        let pipelines: Vec<&str> = args
+            .values_of("pipelines")
+            .map(|v| v.collect())
+            .unwrap_or_default();
+
+        if pipelines.len() != 1 {
+            return Err("--step requires exactly one --pipeline".into());
+        }
+
+        return eval_result.execute_pipeline_step(pipelines[0], step);
+    }
+
+    let pipelines: Vec<String> = match args.values_of("pipelines") {
+        Some(pipelines) => pipelines.map(|p| p.to_string()).collect(),
+        None => eval_result.pipeline_names()?,
+    };
+
+    let keep_going = args.is_present("keep_going");
+    let mut failures = Vec::new();
+
+    for pipeline in &pipelines {
+        match eval_result.execute_pipeline(pipeline) {
+            Ok(()) => {}
+            Err(e) if keep_going => {
+                warn!(logger, "pipeline {} failed: {}", pipeline, e.render());
+                failures.push((pipeline, e));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    if failures.is_empty() {
+        return Ok(());
+    }
+
+    warn!(
+        logger,
+        "{} of {} pipelines failed:",
+        failures.len(),
+        pipelines.len()
+    );
+    for (pipeline, e) in &failures {
+        warn!(logger, "  {}: {}", pipeline, e.render());
+    }
+
+    Err(TuggerError::PipelinesFailed(
+        failures.into_iter().map(|(p, _)| p.clone()).collect(),
+    ))
+}
+
+/// Find a configuration file in a directory or its ancestors.
+fn find_config(start: &Path) -> Result<PathBuf, TuggerError> {
+    start
+        .ancestors()
+        .map(|dir| dir.join(CONFIG_FILENAME))
+        .find(|path| path.is_file())
+        .ok_or_else(|| {
+            format!(
+                "could not find {} in {} or any parent directory",
+                CONFIG_FILENAME,
+                start.display()
+            )
+            .into()
+        })
+}
+
+/// Resolve the configuration file to evaluate from command arguments.
+///
+/// If no file is specified, it is searched for upwards from `cwd`.
+fn config_path(args: &ArgMatches, cwd: &Path) -> Result<PathBuf, TuggerError> {
+    match args.value_of("config").or_else(|| args.value_of("path")) {
+        Some(path) => Ok(PathBuf::from(path)),
+        None => find_config(cwd),
+    }
+}
+
+/// Evaluate a configuration file.
+///
+/// The file is evaluated from its own directory and artifacts are written
+/// to `dist` next to it, regardless of the current directory.
+fn eval_file(
+    logger: &slog::Logger,
+    path: &Path,
+    reproducible: bool,
+    channel: Channel,
+    retry: RetryPolicy,
+    lock_mode: LockMode,
+) -> Result<EvalResult, TuggerError> {
+    let normalized = path
+        .canonicalize()
+        .map_err(|e| TuggerError::io(format!("unable to resolve {}", path.display()), e))?;
+    let cwd = normalized.parent().unwrap().to_path_buf();
 
     let context = EnvironmentContext {
-        cwd: normalized.parent().unwrap().to_path_buf(),
+        reproducibility: Reproducibility::resolve(reproducible, &cwd)?,
+        channel,
+        config_path: Some(normalized.clone()),
+        retry,
+        lock: Lock::new(&cwd.join(LOCK_FILENAME), lock_mode)?,
+        resource_limits: Default::default(),
+        dist_path: cwd.join("dist"),
+        cwd,
         logger: logger.clone(),
-        dist_path: dist_path.to_path_buf(),
     };
 
-    match evaluate_file(&path, &context) {
+    let map = Arc::new(Mutex::new(CodeMap::new()));
+
+    match evaluate_file_with_codemap(&map, path, &context) {
         Ok(res) => {
             warn!(logger, "evaluation complete");
             Ok(res)
         }
-        Err(e) => Err(format!("error evaluating {}: {:#?}", path.display(), e)),
+        Err(e) => Err(TuggerError::Evaluation {
+            path: path.to_path_buf(),
+            message: format!("{:#?}", e),
+            summary: e.message.clone(),
+            location: diagnostic_location(&map.lock().unwrap(), &e),
+        }),
     }
 }
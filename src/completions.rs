@@ -0,0 +1,74 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Locations of shell completion files.
+
+Shells load completions for a command from well-known directories, which
+differ between distributions and package managers. Debian and Fedora
+install under `/usr`, with zsh completions in a vendor directory on
+Debian. Homebrew installs relative to its prefix, with bash completions
+in `etc/bash_completion.d`.
+*/
+
+use std::str::FromStr;
+
+/// A shell providing command completions.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// The packaging format determining where completions are installed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CompletionLayout {
+    Deb,
+    Rpm,
+    Homebrew,
+}
+
+impl FromStr for CompletionLayout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "deb" => Ok(CompletionLayout::Deb),
+            "rpm" => Ok(CompletionLayout::Rpm),
+            "homebrew" => Ok(CompletionLayout::Homebrew),
+            _ => Err(format!(
+                "unknown completion layout {}; expected deb, rpm, or homebrew",
+                s
+            )),
+        }
+    }
+}
+
+impl CompletionLayout {
+    /// Obtain the manifest path of the completion file for a command.
+    pub fn path(self, shell: Shell, command: &str) -> String {
+        let dir = match (self, shell) {
+            (CompletionLayout::Deb, Shell::Bash) | (CompletionLayout::Rpm, Shell::Bash) => {
+                "usr/share/bash-completion/completions"
+            }
+            (CompletionLayout::Deb, Shell::Zsh) => "usr/share/zsh/vendor-completions",
+            (CompletionLayout::Rpm, Shell::Zsh) => "usr/share/zsh/site-functions",
+            (CompletionLayout::Deb, Shell::Fish) | (CompletionLayout::Rpm, Shell::Fish) => {
+                "usr/share/fish/vendor_completions.d"
+            }
+            (CompletionLayout::Homebrew, Shell::Bash) => "etc/bash_completion.d",
+            (CompletionLayout::Homebrew, Shell::Zsh) => "share/zsh/site-functions",
+            (CompletionLayout::Homebrew, Shell::Fish) => "share/fish/vendor_completions.d",
+        };
+
+        // zsh autoloads functions by file name, which must begin with _.
+        // fish only loads files with a .fish extension.
+        match shell {
+            Shell::Bash => format!("{}/{}", dir, command),
+            Shell::Zsh => format!("{}/_{}", dir, command),
+            Shell::Fish => format!("{}/{}.fish", dir, command),
+        }
+    }
+}
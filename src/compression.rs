@@ -0,0 +1,404 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Compression of archives.
+
+Compression dominates the time spent writing large archives, so all
+encoders use multiple threads. Compressed output doesn't depend on the
+number of threads, so archives remain reproducible.
+
+gzip compression works like [pigz](https://zlib.net/pigz/): input is
+split into fixed size chunks which are deflated in parallel and joined
+into a single gzip stream.
+
+`CompressionSettings` define the algorithm, level, and number of threads
+of the archives of a step or of all steps of a pipeline, trading the time
+spent compressing against the size of archives.
+*/
+
+use flate2::{Compress, FlushCompress, Status};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::io::Write;
+use std::str::FromStr;
+
+/// Size of chunks of input compressed in parallel by gzip.
+const GZIP_CHUNK_SIZE: usize = 128 * 1024;
+
+/// A compression algorithm.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    Gzip,
+    Xz,
+    Zstd,
+}
+
+impl Compression {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Compression::Gzip => "gzip",
+            Compression::Xz => "xz",
+            Compression::Zstd => "zstd",
+        }
+    }
+
+    /// The file extension of data compressed with this algorithm.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Compression::Gzip => "gz",
+            Compression::Xz => "xz",
+            Compression::Zstd => "zst",
+        }
+    }
+
+    /// The range of valid compression levels.
+    pub fn levels(self) -> std::ops::RangeInclusive<u32> {
+        match self {
+            Compression::Gzip => 0..=9,
+            Compression::Xz => 0..=9,
+            Compression::Zstd => 1..=22,
+        }
+    }
+
+    /// The level used if none is defined.
+    pub fn default_level(self) -> u32 {
+        match self {
+            Compression::Gzip => 6,
+            Compression::Xz => 6,
+            Compression::Zstd => 3,
+        }
+    }
+
+    /// Ensure a compression level is valid for this algorithm.
+    pub fn validate_level(self, level: u32) -> Result<(), String> {
+        let levels = self.levels();
+
+        if levels.contains(&level) {
+            Ok(())
+        } else {
+            Err(format!(
+                "{} compression level must be between {} and {}; got {}",
+                self.as_str(),
+                levels.start(),
+                levels.end(),
+                level
+            ))
+        }
+    }
+
+    /// Determine the compression of a tar archive from its filename.
+    ///
+    /// Returns `None` for uncompressed archives.
+    pub fn from_filename(filename: &str) -> Option<Self> {
+        match crate::extract::ArchiveFormat::from_filename(filename) {
+            Some(crate::extract::ArchiveFormat::TarGz) => Some(Compression::Gzip),
+            Some(crate::extract::ArchiveFormat::TarXz) => Some(Compression::Xz),
+            Some(crate::extract::ArchiveFormat::TarZst) => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+
+    /// Obtain an encoder writing compressed data to `writer`.
+    ///
+    /// `level` must be valid for the algorithm.
+    pub fn encoder<'a, W: Write + Send + 'a>(
+        self,
+        writer: W,
+        level: u32,
+        threads: usize,
+    ) -> Result<Box<dyn Encoder + 'a>, String> {
+        self.validate_level(level)?;
+        let threads = threads.max(1);
+
+        match self {
+            Compression::Gzip => Ok(Box::new(ParallelGzEncoder::new(writer, level, threads)?)),
+            Compression::Xz => {
+                let stream = xz2::stream::MtStreamBuilder::new()
+                    .preset(level)
+                    .threads(threads as u32)
+                    .encoder()
+                    .map_err(|e| format!("unable to create xz encoder: {}", e))?;
+
+                Ok(Box::new(xz2::write::XzEncoder::new_stream(writer, stream)))
+            }
+            Compression::Zstd => {
+                let mut encoder = zstd::stream::write::Encoder::new(writer, level as i32)
+                    .map_err(|e| format!("unable to create zstd encoder: {}", e))?;
+
+                // Output is only independent of the number of workers if
+                // there is at least one.
+                encoder
+                    .multithread(threads as u32)
+                    .map_err(|e| format!("unable to enable zstd threads: {}", e))?;
+
+                Ok(Box::new(encoder))
+            }
+        }
+    }
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "gzip" => Ok(Compression::Gzip),
+            "xz" => Ok(Compression::Xz),
+            "zstd" => Ok(Compression::Zstd),
+            _ => Err(format!(
+                "unknown compression algorithm {}; expected gzip, xz, or zstd",
+                s
+            )),
+        }
+    }
+}
+
+/// Settings of compressed archives.
+///
+/// Undefined settings use the settings of the pipeline, if any, then
+/// defaults.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct CompressionSettings {
+    /// Algorithm of formats supporting several, such as Debian packages.
+    ///
+    /// Formats whose algorithm is implied by their filename, such as
+    /// `.tar.gz` archives, ignore it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub algorithm: Option<Compression>,
+
+    /// Compression level, validated against the algorithm used.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub level: Option<u32>,
+
+    /// Number of threads to compress with.
+    ///
+    /// `None` means one per CPU.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub threads: Option<usize>,
+}
+
+impl CompressionSettings {
+    /// Obtain settings with undefined settings taken from `fallback`.
+    pub fn or(&self, fallback: Option<&CompressionSettings>) -> CompressionSettings {
+        match fallback {
+            Some(fallback) => CompressionSettings {
+                algorithm: self.algorithm.or(fallback.algorithm),
+                level: self.level.or(fallback.level),
+                threads: self.threads.or(fallback.threads),
+            },
+            None => self.clone(),
+        }
+    }
+
+    /// Obtain the level to compress with `algorithm`.
+    ///
+    /// Levels invalid for `algorithm` are an error.
+    pub fn level(&self, algorithm: Compression) -> Result<u32, String> {
+        match self.level {
+            Some(level) => algorithm.validate_level(level).map(|_| level),
+            None => Ok(algorithm.default_level()),
+        }
+    }
+
+    /// Ensure the settings apply to data compressed with `algorithm`.
+    pub fn validate(&self, algorithm: Compression) -> Result<(), String> {
+        match self.algorithm {
+            Some(a) if a != algorithm => Err(format!(
+                "data compressed with {} can't use {} compression",
+                algorithm.as_str(),
+                a.as_str()
+            )),
+            _ => self.level(algorithm).map(|_| ()),
+        }
+    }
+
+    /// Obtain the number of threads to compress with.
+    pub fn threads(&self) -> usize {
+        self.threads.unwrap_or_else(default_threads)
+    }
+
+    /// Obtain an encoder compressing with `algorithm`.
+    pub fn encoder<'a, W: Write + Send + 'a>(
+        &self,
+        algorithm: Compression,
+        writer: W,
+    ) -> Result<Box<dyn Encoder + 'a>, String> {
+        algorithm.encoder(writer, self.level(algorithm)?, self.threads())
+    }
+}
+
+/// Default number of threads to compress with.
+pub fn default_threads() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+}
+
+/// A writer of compressed data.
+pub trait Encoder: Write {
+    /// Write remaining data and the end of the compressed stream.
+    fn finish(self: Box<Self>) -> std::io::Result<()>;
+}
+
+impl<W: Write> Encoder for xz2::write::XzEncoder<W> {
+    fn finish(self: Box<Self>) -> std::io::Result<()> {
+        (*self).finish().map(|_| ())
+    }
+}
+
+impl<'a, W: Write> Encoder for zstd::stream::write::Encoder<'a, W> {
+    fn finish(self: Box<Self>) -> std::io::Result<()> {
+        (*self).finish().map(|_| ())
+    }
+}
+
+/// A gzip encoder compressing chunks of input in parallel.
+pub struct ParallelGzEncoder<W: Write> {
+    writer: W,
+    level: flate2::Compression,
+    pool: rayon::ThreadPool,
+    threads: usize,
+    pending: Vec<u8>,
+    crc: flate2::Crc,
+}
+
+impl<W: Write> ParallelGzEncoder<W> {
+    pub fn new(mut writer: W, level: u32, threads: usize) -> Result<Self, String> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .map_err(|e| format!("unable to create thread pool: {}", e))?;
+
+        // A fixed header with no modification time or filename. The extra
+        // flags denote the slowest and fastest levels.
+        let xfl = match level {
+            9 => 2,
+            0 | 1 => 4,
+            _ => 0,
+        };
+        writer
+            .write_all(&[0x1f, 0x8b, 8, 0, 0, 0, 0, 0, xfl, 255])
+            .map_err(|e| format!("unable to write gzip header: {}", e))?;
+
+        Ok(ParallelGzEncoder {
+            writer,
+            level: flate2::Compression::new(level),
+            pool,
+            threads,
+            pending: Vec::new(),
+            crc: flate2::Crc::new(),
+        })
+    }
+
+    /// Compress and write buffered input.
+    ///
+    /// Unless `last` is true, only whole batches of chunks are written, as
+    /// the final chunk must end the deflate stream.
+    fn compress_pending(&mut self, last: bool) -> std::io::Result<()> {
+        let batch_size = GZIP_CHUNK_SIZE * self.threads;
+
+        let len = if last {
+            self.pending.len()
+        } else {
+            // Retain at least one byte so the stream isn't ended early.
+            (self.pending.len().saturating_sub(1) / batch_size) * batch_size
+        };
+
+        if len == 0 && !last {
+            return Ok(());
+        }
+
+        let data = self.pending.drain(..len).collect::<Vec<_>>();
+        let chunks = if data.is_empty() {
+            vec![&data[..]]
+        } else {
+            data.chunks(GZIP_CHUNK_SIZE).collect::<Vec<_>>()
+        };
+        let count = chunks.len();
+
+        let level = self.level;
+        let compressed = self.pool.install(|| {
+            chunks
+                .par_iter()
+                .enumerate()
+                .map(|(index, chunk)| {
+                    let mut crc = flate2::Crc::new();
+                    crc.update(chunk);
+
+                    deflate_chunk(chunk, level, last && index == count - 1).map(|data| (crc, data))
+                })
+                .collect::<std::io::Result<Vec<_>>>()
+        })?;
+
+        for (crc, data) in compressed {
+            self.crc.combine(&crc);
+            self.writer.write_all(&data)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Deflate a chunk of input.
+///
+/// Chunks other than the last are ended with a sync flush, so the
+/// concatenation of all chunks is a single deflate stream.
+fn deflate_chunk(data: &[u8], level: flate2::Compression, last: bool) -> std::io::Result<Vec<u8>> {
+    let mut compress = Compress::new(level, false);
+    let mut out = Vec::with_capacity(data.len() / 2 + 1024);
+    let flush = if last {
+        FlushCompress::Finish
+    } else {
+        FlushCompress::Sync
+    };
+
+    loop {
+        if out.capacity() - out.len() < 1024 {
+            out.reserve(out.capacity().max(1024));
+        }
+
+        let status = compress
+            .compress_vec(&data[compress.total_in() as usize..], &mut out, flush)
+            .map_err(std::io::Error::other)?;
+
+        let consumed = compress.total_in() as usize == data.len();
+
+        // Output space remaining means the flush is complete.
+        if (last && status == Status::StreamEnd)
+            || (!last && consumed && out.len() < out.capacity())
+        {
+            return Ok(out);
+        }
+    }
+}
+
+impl<W: Write> Write for ParallelGzEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+
+        if self.pending.len() > GZIP_CHUNK_SIZE * self.threads {
+            self.compress_pending(false)?;
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl<W: Write> Encoder for ParallelGzEncoder<W> {
+    fn finish(mut self: Box<Self>) -> std::io::Result<()> {
+        self.compress_pending(true)?;
+
+        let crc = self.crc.sum();
+        let size = self.crc.amount();
+        self.writer.write_all(&crc.to_le_bytes())?;
+        self.writer.write_all(&size.to_le_bytes())?;
+        self.writer.flush()
+    }
+}
@@ -0,0 +1,411 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Producing conda packages.
+
+A conda package is an archive of the files to install, relative to the
+environment prefix, plus an `info/` directory describing the package:
+`index.json` is what channel indexes are built from, `paths.json` and
+`files` list the installed files, `about.json` holds descriptive
+metadata, and `recipe/meta.yaml` a recipe equivalent to the package.
+
+Two formats exist. The legacy `.tar.bz2` format is a bzip2 compressed
+tarball of everything. The `.conda` format is an uncompressed zip holding
+a `metadata.json` and two zstd compressed tarballs, one of the package's
+files and one of `info/`, so installers can read the metadata without
+decompressing the files.
+*/
+
+use crate::filemanifest::{FileContent, FileManifest};
+use crate::mode_policy::ModePolicy;
+use crate::package_metadata::PackageMetadata;
+use crate::reproducibility::Reproducibility;
+use serde::Serialize;
+use sha2::Digest;
+use slog::{warn, Logger};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// The archive format of a conda package.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub enum CondaFormat {
+    /// The `.conda` format.
+    Conda,
+
+    /// The legacy `.tar.bz2` format.
+    TarBz2,
+}
+
+impl CondaFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            CondaFormat::Conda => "conda",
+            CondaFormat::TarBz2 => "tar.bz2",
+        }
+    }
+}
+
+impl FromStr for CondaFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "conda" => Ok(CondaFormat::Conda),
+            "tar.bz2" => Ok(CondaFormat::TarBz2),
+            _ => Err(format!(
+                "unknown conda package format {}; expected conda or tar.bz2",
+                s
+            )),
+        }
+    }
+}
+
+/// Obtain the conda subdir, e.g. `linux-64`, of a Rust target triple.
+pub fn conda_subdir(target: &str) -> Option<&'static str> {
+    let arch = target.split('-').next()?;
+
+    Some(
+        match (
+            arch,
+            target.contains("-linux-"),
+            target.contains("-apple-darwin"),
+            target.contains("-windows-"),
+        ) {
+            ("x86_64", true, _, _) => "linux-64",
+            ("i686", true, _, _) => "linux-32",
+            ("aarch64", true, _, _) => "linux-aarch64",
+            ("powerpc64le", true, _, _) => "linux-ppc64le",
+            ("s390x", true, _, _) => "linux-s390x",
+            ("x86_64", _, true, _) => "osx-64",
+            ("aarch64", _, true, _) => "osx-arm64",
+            ("x86_64", _, _, true) => "win-64",
+            ("i686", _, _, true) => "win-32",
+            ("aarch64", _, _, true) => "win-arm64",
+            _ => return None,
+        },
+    )
+}
+
+/// Obtain the `platform` and `arch` of `index.json` for a subdir.
+fn platform_arch(subdir: &str) -> (Option<&str>, Option<&str>) {
+    match subdir.split_once('-') {
+        Some((platform, arch)) => (
+            Some(platform),
+            Some(match arch {
+                "64" => "x86_64",
+                "32" => "x86",
+                arch => arch,
+            }),
+        ),
+        None => (None, None),
+    }
+}
+
+/// Describes a conda package to write.
+#[derive(Clone, Debug, Serialize)]
+pub struct CondaPackageInfo {
+    pub metadata: PackageMetadata,
+
+    /// Build string, distinguishing builds of the same version.
+    pub build: String,
+
+    pub build_number: u64,
+
+    /// Platform subdir of the channel, e.g. `linux-64` or `noarch`.
+    pub subdir: String,
+
+    /// Run requirements, as conda match specifications.
+    pub depends: Vec<String>,
+
+    pub format: CondaFormat,
+}
+
+impl CondaPackageInfo {
+    /// The filename of the package, e.g. `foo-1.0-0.conda`.
+    pub fn filename(&self) -> String {
+        format!("{}.{}", self.stem(), self.format.extension())
+    }
+
+    fn stem(&self) -> String {
+        format!(
+            "{}-{}-{}",
+            self.metadata.name, self.metadata.version, self.build
+        )
+    }
+
+    fn index_json(&self, timestamp: u64) -> serde_json::Value {
+        let (platform, arch) = platform_arch(&self.subdir);
+
+        let mut index = serde_json::json!({
+            "name": self.metadata.name,
+            "version": self.metadata.version,
+            "build": self.build,
+            "build_number": self.build_number,
+            "depends": self.depends,
+            "subdir": self.subdir,
+            "platform": platform,
+            "arch": arch,
+            "timestamp": timestamp * 1000,
+        });
+
+        if let Some(license) = &self.metadata.license {
+            index["license"] = license.as_str().into();
+        }
+        if self.subdir == "noarch" {
+            index["noarch"] = "generic".into();
+        }
+
+        index
+    }
+
+    fn about_json(&self) -> serde_json::Value {
+        let mut about = serde_json::json!({
+            "summary": self.metadata.summary(),
+            "description": self.metadata.description,
+        });
+
+        if let Some(home) = &self.metadata.homepage {
+            about["home"] = home.as_str().into();
+        }
+        if let Some(license) = &self.metadata.license {
+            about["license"] = license.as_str().into();
+        }
+
+        about
+    }
+
+    /// Render a `meta.yaml` equivalent to the package.
+    ///
+    /// Strings are written as JSON strings, which are valid YAML.
+    fn meta_yaml(&self) -> String {
+        let q = |s: &str| serde_json::to_string(s).unwrap();
+
+        let mut s = String::from("package:\n");
+        s.push_str(&format!("  name: {}\n", q(&self.metadata.name)));
+        s.push_str(&format!("  version: {}\n", q(&self.metadata.version)));
+        s.push_str("build:\n");
+        s.push_str(&format!("  number: {}\n", self.build_number));
+        s.push_str(&format!("  string: {}\n", q(&self.build)));
+        if self.subdir == "noarch" {
+            s.push_str("  noarch: generic\n");
+        }
+        if !self.depends.is_empty() {
+            s.push_str("requirements:\n  run:\n");
+            for depend in &self.depends {
+                s.push_str(&format!("    - {}\n", q(depend)));
+            }
+        }
+        s.push_str("about:\n");
+        if let Some(home) = &self.metadata.homepage {
+            s.push_str(&format!("  home: {}\n", q(home)));
+        }
+        if let Some(license) = &self.metadata.license {
+            s.push_str(&format!("  license: {}\n", q(license)));
+        }
+        s.push_str(&format!("  summary: {}\n", q(&self.metadata.summary())));
+
+        s
+    }
+}
+
+fn data(data: impl Into<Vec<u8>>) -> FileContent {
+    FileContent::Data {
+        data: data.into(),
+        executable: false,
+    }
+}
+
+/// Obtain the `info/` files describing a package of `files`.
+fn info_files(
+    info: &CondaPackageInfo,
+    files: &FileManifest,
+    timestamp: u64,
+) -> Result<FileManifest, String> {
+    let mut paths = Vec::new();
+
+    for (key, content) in files {
+        if key.starts_with("info/") {
+            return Err(format!(
+                "{} conflicts with the package's info/ directory",
+                key
+            ));
+        }
+
+        paths.push(match content {
+            FileContent::Symlink(_) => serde_json::json!({
+                "_path": key,
+                "path_type": "softlink",
+            }),
+            _ => serde_json::json!({
+                "_path": key,
+                "path_type": "hardlink",
+                "sha256": format!("{:x}", sha2::Sha256::digest(&content.read()?)),
+                "size_in_bytes": content.size()?,
+            }),
+        });
+    }
+
+    let json = |value: &serde_json::Value| serde_json::to_vec_pretty(value).unwrap();
+    let file_list: String = files.keys().map(|key| format!("{}\n", key)).collect();
+
+    let mut info_files = FileManifest::new();
+    info_files.insert(
+        "info/index.json".to_string(),
+        data(json(&info.index_json(timestamp))),
+    );
+    info_files.insert(
+        "info/about.json".to_string(),
+        data(json(&info.about_json())),
+    );
+    info_files.insert("info/files".to_string(), data(file_list));
+    info_files.insert(
+        "info/paths.json".to_string(),
+        data(json(&serde_json::json!({
+            "paths": paths,
+            "paths_version": 1,
+        }))),
+    );
+    info_files.insert("info/recipe/meta.yaml".to_string(), data(info.meta_yaml()));
+
+    Ok(info_files)
+}
+
+/// Obtain an uncompressed tarball of files.
+fn tar_data(
+    logger: &Logger,
+    files: &FileManifest,
+    reproducibility: &Reproducibility,
+    modes: &ModePolicy,
+) -> Result<Vec<u8>, String> {
+    let mut builder = tar::Builder::new(Vec::new());
+
+    for (rel_path, content) in files {
+        warn!(logger, "adding {} as {}", content.describe(), rel_path);
+
+        let mut header = tar::Header::new_gnu();
+        header.set_mode(modes.file_mode(content));
+        header.set_size(content.size()?);
+        header.set_mtime(reproducibility.content_mtime(content)?);
+        header.set_uid(0);
+        header.set_gid(0);
+
+        if let FileContent::Symlink(target) = content {
+            header.set_entry_type(tar::EntryType::Symlink);
+            builder
+                .append_link(&mut header, rel_path, target)
+                .map_err(|e| format!("unable to add {} to archive: {}", rel_path, e))?;
+
+            continue;
+        }
+
+        builder
+            .append_data(&mut header, rel_path, content.open()?)
+            .map_err(|e| format!("unable to add {} to archive: {}", rel_path, e))?;
+    }
+
+    builder.into_inner().map_err(|e| e.to_string())
+}
+
+fn zstd_data(data: &[u8]) -> Result<Vec<u8>, String> {
+    zstd::stream::encode_all(data, 19).map_err(|e| format!("zstd compression failed: {}", e))
+}
+
+/// Write the `.conda` zip holding the info and package tarballs.
+fn write_conda_zip(
+    path: &Path,
+    stem: &str,
+    info_tar: &[u8],
+    pkg_tar: &[u8],
+    mtime: u64,
+) -> Result<(), String> {
+    let fh = std::fs::File::create(path)
+        .map_err(|e| format!("unable to open {} for writing: {}", path.display(), e))?;
+    let mut writer = zip::ZipWriter::new(std::io::BufWriter::new(fh));
+
+    // The members are already compressed, so they are stored as is.
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Stored)
+        .last_modified_time(crate::archive::zip_time(mtime))
+        .unix_permissions(0o644);
+
+    let members: [(String, &[u8]); 3] = [
+        (
+            "metadata.json".to_string(),
+            b"{\"conda_pkg_format_version\": 2}",
+        ),
+        (format!("pkg-{}.tar.zst", stem), pkg_tar),
+        (format!("info-{}.tar.zst", stem), info_tar),
+    ];
+
+    for (name, data) in &members {
+        writer
+            .start_file(name.as_str(), options)
+            .and_then(|_| writer.write_all(data).map_err(Into::into))
+            .map_err(|e| format!("unable to add {} to {}: {}", name, path.display(), e))?;
+    }
+
+    writer
+        .finish()
+        .map_err(|e| format!("unable to write {}: {}", path.display(), e))?;
+
+    Ok(())
+}
+
+/// Write a conda package of `files` into `dist_path`.
+///
+/// Returns the path of the written package.
+pub fn write_conda_package(
+    logger: &Logger,
+    dist_path: &Path,
+    info: &CondaPackageInfo,
+    files: &FileManifest,
+    reproducibility: &Reproducibility,
+    modes: &ModePolicy,
+) -> Result<PathBuf, String> {
+    let info_files = info_files(info, files, reproducibility.mtime())?;
+
+    std::fs::create_dir_all(dist_path)
+        .map_err(|e| format!("unable to create {}: {}", dist_path.display(), e))?;
+
+    let path = dist_path.join(info.filename());
+    warn!(logger, "writing conda package {}", path.display());
+
+    match info.format {
+        CondaFormat::Conda => {
+            let info_tar = zstd_data(&tar_data(logger, &info_files, reproducibility, modes)?)?;
+            let pkg_tar = zstd_data(&tar_data(logger, files, reproducibility, modes)?)?;
+
+            write_conda_zip(
+                &path,
+                &info.stem(),
+                &info_tar,
+                &pkg_tar,
+                reproducibility.mtime(),
+            )?;
+        }
+        CondaFormat::TarBz2 => {
+            let mut all = files.clone();
+            all.extend(info_files);
+
+            let fh = std::fs::File::create(&path)
+                .map_err(|e| format!("unable to open {} for writing: {}", path.display(), e))?;
+            let mut encoder = bzip2::write::BzEncoder::new(fh, bzip2::Compression::best());
+            encoder
+                .write_all(&tar_data(logger, &all, reproducibility, modes)?)
+                .and_then(|_| encoder.finish().map(|_| ()))
+                .map_err(|e| format!("unable to write {}: {}", path.display(), e))?;
+        }
+    }
+
+    Ok(path)
+}
+
+/// Represents a request to write a conda package.
+#[derive(Debug, Clone, Serialize)]
+pub struct CondaPackage {
+    pub package: CondaPackageInfo,
+    pub files: FileManifest,
+}
@@ -0,0 +1,193 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Running artifacts in containers.
+
+Install tests install a package into a fresh container and run a smoke
+command, validating packaging end to end: dependencies resolve,
+maintainer scripts succeed, and the installed program runs.
+
+Containers are run with `docker` or, if it isn't available, `podman`.
+The `TUGGER_CONTAINER_RUNTIME` environment variable selects a runtime
+explicitly.
+*/
+
+use crate::extract::ArchiveFormat;
+use serde::Serialize;
+use slog::{warn, Logger};
+use std::path::Path;
+use std::process::Command;
+
+/// Environment variable naming the container runtime to use.
+pub const CONTAINER_RUNTIME_ENV: &str = "TUGGER_CONTAINER_RUNTIME";
+
+/// Path artifacts are mounted at inside containers.
+const ARTIFACTS_MOUNT: &str = "/tugger-artifacts";
+
+/// Path extracted tarballs are installed under inside containers.
+pub const TARBALL_PREFIX: &str = "/usr/local";
+
+/// Ways packages are installed.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PackageKind {
+    Deb,
+    Rpm,
+    Archive(ArchiveFormat),
+}
+
+impl PackageKind {
+    /// Determine how to install a package from its filename.
+    pub fn from_filename(filename: &str) -> Option<Self> {
+        let lower = filename.to_lowercase();
+
+        if lower.ends_with(".deb") {
+            Some(PackageKind::Deb)
+        } else if lower.ends_with(".rpm") {
+            Some(PackageKind::Rpm)
+        } else {
+            ArchiveFormat::from_filename(&lower).map(PackageKind::Archive)
+        }
+    }
+}
+
+/// Find the container runtime to use.
+pub fn container_runtime() -> Result<String, String> {
+    if let Ok(runtime) = std::env::var(CONTAINER_RUNTIME_ENV) {
+        return Ok(runtime);
+    }
+
+    let paths = std::env::var_os("PATH").unwrap_or_default();
+
+    for runtime in &["docker", "podman"] {
+        if std::env::split_paths(&paths).any(|dir| dir.join(runtime).is_file()) {
+            return Ok(runtime.to_string());
+        }
+    }
+
+    Err(format!(
+        "no container runtime found; install docker or podman or set {}",
+        CONTAINER_RUNTIME_ENV
+    ))
+}
+
+/// Quote a string for use in a POSIX shell command.
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// Obtain the shell commands installing a package mounted in a container.
+fn install_script(kind: PackageKind, filename: &str) -> String {
+    let path = shell_quote(&format!("{}/{}", ARTIFACTS_MOUNT, filename));
+
+    match kind {
+        PackageKind::Deb => format!(
+            "apt-get update -qq && DEBIAN_FRONTEND=noninteractive apt-get install -y -qq {}",
+            path
+        ),
+        PackageKind::Rpm => format!(
+            "if command -v dnf >/dev/null 2>&1; then dnf install -y {0}; \
+             elif command -v yum >/dev/null 2>&1; then yum install -y {0}; \
+             else zypper --non-interactive install --allow-unsigned-rpm {0}; fi",
+            path
+        ),
+        // Archives are extracted on the host, so images don't need tools
+        // for every compression format.
+        PackageKind::Archive(_) => format!(
+            "mkdir -p {prefix} && cp -a {root}/. {prefix}/",
+            prefix = TARBALL_PREFIX,
+            root = ARTIFACTS_MOUNT
+        ),
+    }
+}
+
+/// Install a package into a fresh container and run a command in it.
+///
+/// Debian packages are installed with `apt-get`, so dependencies are
+/// resolved from the image's repositories. RPMs are installed with `dnf`,
+/// `yum`, or `zypper`. Archives are extracted into `/usr/local`. `command`
+/// is then run with `sh -c`. The container is removed afterwards.
+///
+/// Returns an error if installation or the command fails.
+pub fn test_install(
+    logger: &Logger,
+    artifact: &Path,
+    image: &str,
+    command: &str,
+) -> Result<(), String> {
+    if !artifact.exists() {
+        return Err(format!("{} does not exist", artifact.display()));
+    }
+
+    let filename = artifact
+        .file_name()
+        .ok_or_else(|| format!("{} has no filename", artifact.display()))?
+        .to_string_lossy()
+        .to_string();
+
+    let kind = PackageKind::from_filename(&filename).ok_or_else(|| {
+        format!(
+            "unable to determine how to install {}; expected a .deb, .rpm, or archive",
+            filename
+        )
+    })?;
+
+    let runtime = container_runtime()?;
+
+    // Only the artifact is exposed to the container. Archives are
+    // extracted so their contents can be copied into place.
+    let temp_dir = tempfile::Builder::new()
+        .prefix("tugger-test-install-")
+        .tempdir()
+        .map_err(|e| format!("unable to create temp directory: {}", e))?;
+
+    match kind {
+        PackageKind::Archive(_) => {
+            crate::extract::extract_archive(artifact, &filename, temp_dir.path(), 0)?;
+        }
+        _ => {
+            std::fs::copy(artifact, temp_dir.path().join(&filename))
+                .map_err(|e| format!("unable to copy {}: {}", artifact.display(), e))?;
+        }
+    }
+
+    let script = format!("set -e\n{}\n{}\n", install_script(kind, &filename), command);
+
+    warn!(
+        logger,
+        "installing {} in {} container from {}", filename, runtime, image
+    );
+
+    crate::process::run_logged(
+        logger,
+        Command::new(&runtime)
+            .arg("run")
+            .arg("--rm")
+            .arg("--volume")
+            .arg(format!(
+                "{}:{}:ro",
+                temp_dir.path().display(),
+                ARTIFACTS_MOUNT
+            ))
+            .arg(image)
+            .arg("sh")
+            .arg("-c")
+            .arg(script),
+    )
+    .map_err(|e| format!("install test of {} in {} failed: {}", filename, image, e))
+}
+
+/// Represents a request to install an artifact in a container and run a command.
+#[derive(Debug, Clone, Serialize)]
+pub struct TestInstall {
+    /// Path to the package, relative to the distribution path.
+    pub artifact: String,
+
+    /// Container image to install into.
+    pub image: String,
+
+    /// Shell command run after installation.
+    pub command: String,
+}
@@ -2,12 +2,20 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use crate::filemanifest::FileManifest;
+use crate::compression::CompressionSettings;
+use crate::error::TuggerError;
+use crate::filemanifest::{manifest_dirs, FileContent, FileManifest};
+use crate::mode_policy::ModePolicy;
+use crate::naming::ArtifactName;
+use crate::reproducibility::Reproducibility;
 use ar::{Builder, Header};
-use debian::package::ControlFile;
-use is_executable::IsExecutable;
+use debian::package::{ControlFile, ControlParagraph};
+use rayon::prelude::*;
+use serde::ser::{Error, SerializeMap};
+use serde::{Serialize, Serializer};
 use slog::{warn, Logger};
-use std::io::Write;
+use std::collections::BTreeMap;
+use std::io::{Seek, SeekFrom, Write};
 use std::path::Path;
 use tar::Header as TarHeader;
 
@@ -16,72 +24,156 @@ use tar::Header as TarHeader;
 /// The raw .deb data will be written to `writer`.
 ///
 /// The installed files are defined by `files`.
+///
+/// `system_time` is the modification time to record for all entries.
+///
+/// The control and data tarballs are compressed with the algorithm of
+/// `compression`, if any.
 pub fn build_deb<W>(
     writer: W,
     control_file: &ControlFile,
     files: &FileManifest,
-) -> Result<(), String>
+    scripts: &BTreeMap<String, String>,
+    system_time: u64,
+    compression: &CompressionSettings,
+    modes: &ModePolicy,
+) -> Result<(), TuggerError>
 where
     W: Write,
 {
     // The file format is documented at https://manpages.debian.org/unstable/dpkg-dev/deb.5.en.html.
+    // dpkg requires members in this order: debian-binary, then the
+    // control tarball, then the data tarball.
     let mut ar_builder = Builder::new(writer);
 
-    let system_time = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .or_else(|_| Err("could not compute duration".to_string()))?
-        .as_secs();
-
     // First entry is a debian-binary file with static content.
     let data: &[u8] = b"2.0\n";
-    let mut header = Header::new("debian-binary".as_bytes().to_owned(), data.len() as u64);
-    header.set_mode(0o644);
-    header.set_mtime(system_time);
-    header.set_uid(0);
-    header.set_gid(0);
+    let header = deb_member_header("debian-binary", data.len() as u64, system_time)?;
     ar_builder
         .append(&header, data)
-        .or_else(|e| Err(e.to_string()))?;
+        .map_err(|e| TuggerError::io("unable to append debian-binary", e))?;
 
     // Second entry is a control.tar with metadata.
-    let mut control_tar: Vec<u8> = Vec::new();
-    build_control_tar(&mut control_tar, control_file, files, system_time)?;
-
-    let mut header = Header::new(
-        "control.tar".as_bytes().to_owned(),
-        control_tar.len() as u64,
-    );
-    header.set_mode(0o644);
-    header.set_mtime(system_time);
-    header.set_uid(0);
-    header.set_gid(0);
-    ar_builder
-        .append(&header, &control_tar as &[u8])
-        .or_else(|e| Err(e.to_string()))?;
+    append_ar_member(
+        &mut ar_builder,
+        "control.tar",
+        system_time,
+        compression,
+        |writer| build_control_tar(writer, control_file, files, scripts, system_time),
+    )?;
 
     // Third entry is a data.tar with file content.
-    let mut data_tar: Vec<u8> = Vec::new();
-    build_data_tar(&mut data_tar, files, system_time)?;
+    append_ar_member(
+        &mut ar_builder,
+        "data.tar",
+        system_time,
+        compression,
+        |writer| build_data_tar(writer, files, system_time, modes),
+    )?;
+
+    Ok(())
+}
 
-    // TODO compress data.
+/// Maximum length of ar member names.
+///
+/// The ar crate writes longer names with the BSD extension, which dpkg
+/// doesn't support.
+const AR_NAME_MAX: usize = 16;
 
-    let mut header = Header::new("data.tar".as_bytes().to_owned(), data_tar.len() as u64);
-    header.set_mode(0o644);
-    header.set_mtime(system_time);
+/// Maximum size of ar members, whose size field has 10 decimal digits.
+const AR_SIZE_MAX: u64 = 9_999_999_999;
+
+/// Obtain the ar header of a member of a .deb archive.
+///
+/// Headers are written like `dpkg-deb` writes them: owned by root, with
+/// mode `100644`. The ar crate pads members of odd size with a newline,
+/// so every header starts at an even offset as dpkg requires.
+fn deb_member_header(name: &str, size: u64, mtime: u64) -> Result<Header, String> {
+    if name.len() > AR_NAME_MAX || name.contains(' ') || name.contains('/') {
+        return Err(format!("{} isn't a valid ar member name", name));
+    }
+
+    if size > AR_SIZE_MAX {
+        return Err(format!(
+            "{} is {} bytes; .deb members can't exceed {} bytes",
+            name, size, AR_SIZE_MAX
+        ));
+    }
+
+    let mut header = Header::new(name.as_bytes().to_owned(), size);
+    header.set_mode(0o100644);
+    header.set_mtime(mtime);
     header.set_uid(0);
     header.set_gid(0);
-    ar_builder
-        .append(&header, &data_tar as &[u8])
-        .or_else(|e| Err(e.to_string()))?;
 
-    Ok(())
+    Ok(header)
+}
+
+/// Append a member to an ar archive with content produced by `build`.
+///
+/// ar member headers contain the size of the member, so content is
+/// spooled to a temporary file rather than buffered in memory.
+///
+/// If `compression` defines an algorithm, content is compressed with it
+/// and its extension is appended to `name`, e.g. `data.tar.xz`, so the
+/// name always matches the content.
+fn append_ar_member<W, F>(
+    ar_builder: &mut Builder<W>,
+    name: &str,
+    mtime: u64,
+    compression: &CompressionSettings,
+    build: F,
+) -> Result<(), String>
+where
+    W: Write,
+    F: FnOnce(&mut dyn Write) -> Result<(), String>,
+{
+    let algorithm = compression.algorithm;
+    let name = match algorithm {
+        Some(algorithm) => format!("{}.{}", name, algorithm.extension()),
+        None => name.to_string(),
+    };
+
+    let mut fh = tempfile::tempfile().map_err(|e| format!("unable to create temp file: {}", e))?;
+
+    {
+        let mut writer = std::io::BufWriter::new(&mut fh);
+
+        match algorithm {
+            Some(algorithm) => {
+                let mut encoder = compression.encoder(algorithm, &mut writer)?;
+                build(&mut encoder)?;
+                encoder
+                    .finish()
+                    .map_err(|e| format!("unable to compress {}: {}", name, e))?;
+            }
+            None => build(&mut writer)?,
+        }
+
+        writer
+            .flush()
+            .map_err(|e| format!("unable to write {}: {}", name, e))?;
+    }
+
+    let size = fh
+        .seek(SeekFrom::End(0))
+        .and_then(|size| fh.seek(SeekFrom::Start(0)).map(|_| size))
+        .map_err(|e| format!("unable to seek in temp file: {}", e))?;
+
+    let header = deb_member_header(&name, size, mtime)?;
+    ar_builder
+        .append(&header, fh)
+        .map_err(|e| format!("unable to append {}: {}", name, e))
 }
 
 /// Build tar data stream for a control.tar file embedded in a .deb archive.
+///
+/// `scripts` maps maintainer script names (e.g. `postinst`) to their content.
 pub fn build_control_tar<W>(
     writer: W,
     control_file: &ControlFile,
     files: &FileManifest,
+    scripts: &BTreeMap<String, String>,
     mtime: u64,
 ) -> Result<(), String>
 where
@@ -95,6 +187,8 @@ where
 
     let mut header = TarHeader::new_gnu();
     header.set_mtime(mtime);
+    header.set_uid(0);
+    header.set_gid(0);
     header.set_path("control").unwrap();
     header.set_mode(0o644);
     header.set_size(control_data.len() as u64);
@@ -105,6 +199,8 @@ where
 
     let mut header = TarHeader::new_gnu();
     header.set_mtime(mtime);
+    header.set_uid(0);
+    header.set_gid(0);
     header.set_path("md5sums").unwrap();
     header.set_mode(0o644);
     header.set_size(md5sums.len() as u64);
@@ -113,7 +209,17 @@ where
         .append(&header, &md5sums as &[u8])
         .or_else(|e| Err(format!("unable to append md5sums: {}", e)))?;
 
-    // We could also support maintainer scripts. For another day...
+    for (name, script) in scripts {
+        let mut header = TarHeader::new_gnu();
+        header.set_mtime(mtime);
+        header.set_uid(0);
+        header.set_gid(0);
+        header.set_mode(0o755);
+        header.set_size(script.len() as u64);
+        builder
+            .append_data(&mut header, name, script.as_bytes())
+            .map_err(|e| format!("unable to append {}: {}", name, e))?;
+    }
 
     builder
         .finish()
@@ -123,14 +229,27 @@ where
 }
 
 /// Generate the file content for an md5sums file in a control.tar archive.
+///
+/// Files are hashed in parallel and their content is streamed, so large
+/// files aren't held in memory.
 pub fn make_md5sums(files: &FileManifest) -> Result<Vec<u8>, std::io::Error> {
-    let mut res = Vec::new();
+    // Like dpkg, only regular files are listed.
+    let digests = files
+        .par_iter()
+        .filter(|(_, content)| !matches!(content, FileContent::Symlink(_)))
+        .map(|(rel_path, content)| {
+            let mut reader = content.open().map_err(std::io::Error::other)?;
+            let mut context = md5::Context::new();
+            std::io::copy(&mut reader, &mut context)?;
+
+            Ok((rel_path, context.compute()))
+        })
+        .collect::<Result<Vec<_>, std::io::Error>>()?;
 
-    for (rel_path, source_path) in files.iter() {
-        let file_content = std::fs::read(&source_path)?;
-        let digest = md5::compute(&file_content);
+    let mut res = Vec::new();
 
-        res.write_all(&digest.to_ascii_lowercase())?;
+    for (rel_path, digest) in digests {
+        write!(res, "{:x}", digest)?;
         res.write_all(b"  ")?;
         res.write_all(rel_path.as_bytes())?;
         res.write_all(&[b'\n'])?;
@@ -157,42 +276,213 @@ pub fn serialize_control_file(control_file: &ControlFile) -> Result<Vec<u8>, Str
     }
 }
 
+/// Obtain the ordered `(key, value)` entries of a Debian control paragraph.
+///
+/// `ControlParagraph` doesn't expose its entries, so we round-trip through
+/// the serialized form. Continuation lines are folded into the preceding
+/// entry's value.
+pub fn control_paragraph_entries(
+    paragraph: &ControlParagraph,
+) -> Result<Vec<(String, String)>, String> {
+    let mut control_file = ControlFile::new();
+    control_file.add_paragraph(paragraph.clone());
+
+    let data = serialize_control_file(&control_file)?;
+    let text =
+        String::from_utf8(data).map_err(|e| format!("control file is not valid UTF-8: {}", e))?;
+
+    let mut entries: Vec<(String, String)> = Vec::new();
+
+    for line in text.lines() {
+        if line.is_empty() {
+            continue;
+        }
+
+        if line.starts_with(' ') || line.starts_with('\t') {
+            if let Some(entry) = entries.last_mut() {
+                entry.1.push('\n');
+                entry.1.push_str(line);
+            }
+        } else if let Some(pos) = line.find(':') {
+            let (key, value) = line.split_at(pos);
+            entries.push((key.to_string(), value[1..].trim().to_string()));
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Serializes a `ControlParagraph` as a map of its entries.
+struct SerializableParagraph<'a>(&'a ControlParagraph);
+
+impl<'a> Serialize for SerializableParagraph<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let entries = control_paragraph_entries(self.0).map_err(S::Error::custom)?;
+
+        let mut map = serializer.serialize_map(Some(entries.len()))?;
+        for (key, value) in &entries {
+            map.serialize_entry(key, value)?;
+        }
+        map.end()
+    }
+}
+
+/// Serialize a `ControlParagraph` as a map of its entries.
+///
+/// For use with `#[serde(serialize_with)]`.
+pub fn serialize_paragraph<S: Serializer>(
+    paragraph: &ControlParagraph,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    SerializableParagraph(paragraph).serialize(serializer)
+}
+
+/// Serialize `ControlParagraph`s as a sequence of maps of their entries.
+///
+/// For use with `#[serde(serialize_with)]`.
+pub fn serialize_paragraphs<S: Serializer>(
+    paragraphs: &[ControlParagraph],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(paragraphs.iter().map(SerializableParagraph))
+}
+
 /// Build tar data stream for a data.tar file in a .deb archive.
-pub fn build_data_tar<W>(writer: W, files: &FileManifest, mtime: u64) -> Result<(), String>
+///
+/// Files and the directories containing them are given the permissions
+/// of `modes`.
+/// Append an entry to a tar archive, storing `name` verbatim.
+///
+/// `tar::Builder` strips the leading `./` that dpkg-deb writes on every
+/// data.tar member, so names and link targets are written into the header
+/// directly, with GNU long name/link entries for anything over 100 bytes.
+fn append_member<W: Write, R: std::io::Read>(
+    builder: &mut tar::Builder<W>,
+    header: &mut TarHeader,
+    name: &str,
+    link_target: Option<&Path>,
+    data: R,
+) -> std::io::Result<()> {
+    fn append_long<W: Write>(
+        builder: &mut tar::Builder<W>,
+        entry_type: tar::EntryType,
+        value: &[u8],
+    ) -> std::io::Result<()> {
+        let mut long = TarHeader::new_gnu();
+        let name = b"././@LongLink";
+        long.as_old_mut().name[..name.len()].copy_from_slice(name);
+        long.set_entry_type(entry_type);
+        long.set_mode(0o644);
+        long.set_mtime(0);
+        long.set_uid(0);
+        long.set_gid(0);
+        long.set_size(value.len() as u64 + 1);
+        long.set_cksum();
+
+        let mut data = value.to_vec();
+        data.push(0);
+        builder.append(&long, data.as_slice())
+    }
+
+    fn set_field(field: &mut [u8], value: &[u8]) {
+        let len = value.len().min(field.len());
+        field.iter_mut().for_each(|b| *b = 0);
+        field[..len].copy_from_slice(&value[..len]);
+    }
+
+    let name = name.as_bytes();
+    if name.len() >= header.as_old().name.len() {
+        append_long(builder, tar::EntryType::GNULongName, name)?;
+    }
+    set_field(&mut header.as_old_mut().name, name);
+
+    if let Some(target) = link_target {
+        let target = target.to_string_lossy();
+        let target = target.as_bytes();
+        if target.len() >= header.as_old().linkname.len() {
+            append_long(builder, tar::EntryType::GNULongLink, target)?;
+        }
+        set_field(&mut header.as_old_mut().linkname, target);
+    }
+
+    header.set_cksum();
+    builder.append(header, data)
+}
+
+pub fn build_data_tar<W>(
+    writer: W,
+    files: &FileManifest,
+    mtime: u64,
+    modes: &ModePolicy,
+) -> Result<(), String>
 where
     W: Write,
 {
     let mut builder = tar::Builder::new(writer);
 
-    // TODO add directories.
-
-    // We use a BTreeMap, so this should be deterministic.
-    for (rel_path, source_path) in files.iter() {
-        let file_content = std::fs::read(&source_path).or_else(|e| {
-            Err(format!(
-                "error reading file {}: {}",
-                source_path.display(),
-                e
-            ))
-        })?;
+    // Directories sort before their content, so this is deterministic.
+    let mut entries = files
+        .iter()
+        .map(|(rel_path, content)| (rel_path.clone(), Some(content)))
+        .collect::<Vec<_>>();
+    entries.extend(manifest_dirs(files).into_iter().map(|dir| (dir, None)));
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
 
+    for (rel_path, content) in entries {
         let mut header = TarHeader::new_gnu();
         header.set_mtime(mtime);
-        header.set_path(format!("./{}", rel_path)).unwrap();
-        header.set_mode(if source_path.is_executable() {
-            0o755
-        } else {
-            0o644
-        });
-        header.set_size(file_content.len() as u64);
-        header.set_cksum();
-        builder
-            .append(&header, &file_content as &[u8])
-            .or_else(|e| Err(format!("unable to append data file: {}", e)))?;
+        header.set_uid(0);
+        header.set_gid(0);
+
+        let content = match content {
+            Some(content) => content,
+            None => {
+                header.set_entry_type(tar::EntryType::Directory);
+                header.set_mode(modes.dir_mode());
+                header.set_size(0);
+                append_member(
+                    &mut builder,
+                    &mut header,
+                    &format!("./{}/", rel_path),
+                    None,
+                    std::io::empty(),
+                )
+                .map_err(|e| format!("unable to append directory {}: {}", rel_path, e))?;
+
+                continue;
+            }
+        };
+
+        // Content is streamed into the archive to bound memory use.
+        let size = content.size()?;
+        header.set_mode(modes.file_mode(content));
+
+        if let FileContent::Symlink(target) = content {
+            header.set_entry_type(tar::EntryType::Symlink);
+            header.set_size(0);
+            append_member(
+                &mut builder,
+                &mut header,
+                &format!("./{}", rel_path),
+                Some(target),
+                std::io::empty(),
+            )
+            .map_err(|e| format!("unable to append symlink {}: {}", rel_path, e))?;
+
+            continue;
+        }
+
+        header.set_size(size);
+        append_member(
+            &mut builder,
+            &mut header,
+            &format!("./{}", rel_path),
+            None,
+            content.open()?,
+        )
+        .map_err(|e| format!("unable to append data file {}: {}", rel_path, e))?;
     }
 
-    // We could also support maintainer scripts. For another day...
-
     builder
         .finish()
         .or_else(|e| Err(format!("unable to finish archive: {}", e)))?;
@@ -200,26 +490,183 @@ where
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn execute_deb_archive(
     logger: &Logger,
     dist_path: &Path,
+    filename: &str,
     control_paragraph: &debian::package::ControlParagraph,
     files: &FileManifest,
-) -> Result<(), String> {
-    let basename = format!(
-        "{}_{}.deb",
-        control_paragraph.get_entry("Package").unwrap(),
-        control_paragraph.get_entry("Version").unwrap()
-    );
-
-    let dest_path = dist_path.join(basename);
+    systemd: bool,
+    compression: &CompressionSettings,
+    reproducibility: &Reproducibility,
+    modes: &ModePolicy,
+) -> Result<(), TuggerError> {
+    let dest_path = dist_path.join(filename);
     warn!(logger, "writing Debian package to {}", dest_path.display());
 
     let mut control_file = ControlFile::new();
     control_file.add_paragraph(control_paragraph.clone());
 
+    std::fs::create_dir_all(dist_path)
+        .map_err(|e| TuggerError::io(format!("unable to create {}", dist_path.display()), e))?;
+
     let fh = std::fs::File::create(&dest_path)
-        .or_else(|e| Err(format!("unable to create {}: {}", dest_path.display(), e)))?;
+        .map_err(|e| TuggerError::io(format!("unable to create {}", dest_path.display()), e))?;
+
+    let package_error = |source: TuggerError| TuggerError::Debian {
+        path: dest_path.clone(),
+        source: Box::new(source),
+    };
+
+    let scripts = if systemd {
+        let units = crate::systemd::enabled_units(files).map_err(|e| package_error(e.into()))?;
+        if !units.is_empty() {
+            warn!(logger, "adding maintainer scripts for {}", units.join(", "));
+        }
+
+        crate::systemd::deb_maintainer_scripts(&units)
+    } else {
+        BTreeMap::new()
+    };
+
+    build_deb(
+        fh,
+        &control_file,
+        files,
+        &scripts,
+        reproducibility.mtime(),
+        compression,
+        modes,
+    )
+    .map_err(package_error)
+}
+
+/// Obtain the Debian architecture name of a Rust target triple.
+///
+/// Returns `None` if the target's architecture has no Debian equivalent.
+pub fn debian_architecture(target: &str) -> Option<&'static str> {
+    let arch = target.split('-').next()?;
+
+    Some(match arch {
+        "x86_64" => "amd64",
+        "i586" | "i686" => "i386",
+        "aarch64" => "arm64",
+        "armv7" | "thumbv7neon" if target.ends_with("eabihf") => "armhf",
+        "arm" if target.ends_with("eabihf") => "armhf",
+        "arm" | "armv5te" => "armel",
+        "mips64el" => "mips64el",
+        "mipsel" => "mipsel",
+        "powerpc64le" => "ppc64el",
+        "riscv64gc" => "riscv64",
+        "s390x" => "s390x",
+        _ => return None,
+    })
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DebianDebArchive {
+    #[serde(serialize_with = "serialize_paragraph")]
+    pub control_file: ControlParagraph,
+    pub files: FileManifest,
+    pub systemd: bool,
+    pub compression: CompressionSettings,
+
+    /// Filename of the package.
+    pub dest_name: String,
 
-    build_deb(fh, &control_file, files)
+    /// Name and version of the package, for naming it with a name template.
+    pub artifact_name: ArtifactName,
+}
+
+impl DebianDebArchive {
+    /// Define a package with the conventional filename,
+    /// `<package>_<version>.deb`.
+    pub fn new(
+        control_file: ControlParagraph,
+        files: FileManifest,
+        systemd: bool,
+        compression: CompressionSettings,
+        name_template: Option<String>,
+    ) -> Self {
+        let entry = |name| control_file.get_entry(name).unwrap_or_default().to_string();
+        let artifact_name = ArtifactName {
+            name: entry("Package"),
+            version: entry("Version"),
+            ext: "deb".to_string(),
+            template: name_template,
+        };
+
+        DebianDebArchive {
+            dest_name: format!("{}_{}.deb", artifact_name.name, artifact_name.version),
+            control_file,
+            files,
+            systemd,
+            compression,
+            artifact_name,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_data_tar_member_names() -> Result<(), String> {
+        let long_dir = format!("usr/share/{}", "d".repeat(100));
+        let long_target = format!("../{}/app.txt", "t".repeat(100));
+
+        let mut files = FileManifest::new();
+        files.insert(
+            "usr/bin/app".to_string(),
+            FileContent::Data {
+                data: b"app".to_vec(),
+                executable: true,
+            },
+        );
+        files.insert(
+            format!("{}/app.txt", long_dir),
+            FileContent::Data {
+                data: b"text".to_vec(),
+                executable: false,
+            },
+        );
+        files.insert(
+            "usr/lib/link".to_string(),
+            FileContent::Symlink(PathBuf::from(&long_target)),
+        );
+
+        let mut data = Vec::new();
+        build_data_tar(&mut data, &files, 0, &ModePolicy::default())?;
+
+        let mut archive = tar::Archive::new(data.as_slice());
+        let mut names = vec![];
+        for entry in archive.entries().map_err(|e| e.to_string())? {
+            let entry = entry.map_err(|e| e.to_string())?;
+            let name = String::from_utf8(entry.path_bytes().to_vec()).unwrap();
+            if let Some(target) = entry.link_name_bytes() {
+                assert_eq!(name, "./usr/lib/link");
+                assert_eq!(target.as_ref(), long_target.as_bytes());
+            }
+            names.push(name);
+        }
+
+        assert_eq!(
+            names,
+            vec![
+                "./usr/".to_string(),
+                "./usr/bin/".to_string(),
+                "./usr/bin/app".to_string(),
+                "./usr/lib/".to_string(),
+                "./usr/lib/link".to_string(),
+                "./usr/share/".to_string(),
+                format!("./{}/", long_dir),
+                format!("./{}/app.txt", long_dir),
+            ]
+        );
+
+        Ok(())
+    }
 }
@@ -0,0 +1,358 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Parsing and comparison of Debian package versions.
+
+Debian versions have the form `[epoch:]upstream_version[-debian_revision]`
+and are ordered by the rules of `dpkg --compare-versions`, documented in
+[Debian Policy](https://www.debian.org/doc/debian-policy/ch-controlfields.html#version).
+Notably, `~` sorts before anything, even the end of the version, so
+`1.0~rc1` precedes `1.0`, and letters sort before other punctuation.
+*/
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+/// A Debian package version.
+///
+/// Equality follows the ordering of versions, so `1.0` equals `0:1.0`
+/// and `1.0-0`.
+#[derive(Clone, Debug)]
+pub struct DebianVersion {
+    /// The epoch, `0` if not defined.
+    pub epoch: u32,
+
+    /// The upstream version.
+    pub upstream_version: String,
+
+    /// The Debian revision, if any.
+    pub debian_revision: Option<String>,
+}
+
+impl FromStr for DebianVersion {
+    type Err = String;
+
+    /// Parse a version like dpkg does.
+    ///
+    /// Only versions dpkg refuses are an error. Versions dpkg accepts with
+    /// a warning, such as `v1.0`, are accepted; see `warnings()`.
+    fn from_str(s: &str) -> Result<Self, String> {
+        let invalid = |reason: &str| format!("invalid Debian version {}: {}", s, reason);
+        let version = s.trim();
+
+        if version.is_empty() {
+            return Err(invalid("version is empty"));
+        }
+
+        if version.contains(char::is_whitespace) {
+            return Err(invalid("version contains whitespace"));
+        }
+
+        // Only the first colon separates the epoch, so upstream versions
+        // of versions with an epoch may contain colons.
+        let (epoch, rest) = match version.split_once(':') {
+            Some((epoch, rest)) => {
+                if epoch.is_empty() {
+                    return Err(invalid("epoch is empty"));
+                }
+
+                if !epoch.chars().all(|c| c.is_ascii_digit()) {
+                    return Err(invalid("epoch must be a number"));
+                }
+
+                let epoch = epoch
+                    .parse::<u32>()
+                    .map_err(|_| invalid("epoch is too large"))?;
+
+                if rest.is_empty() {
+                    return Err(invalid("nothing after colon"));
+                }
+
+                (epoch, rest)
+            }
+            None => (0, version),
+        };
+
+        let (upstream_version, debian_revision) = match rest.rsplit_once('-') {
+            Some((_, "")) => return Err(invalid("revision is empty")),
+            Some((upstream, revision)) => (upstream, Some(revision)),
+            None => (rest, None),
+        };
+
+        if upstream_version.is_empty() {
+            return Err(invalid("upstream version is empty"));
+        }
+
+        Ok(DebianVersion {
+            epoch,
+            upstream_version: upstream_version.to_string(),
+            debian_revision: debian_revision.map(|r| r.to_string()),
+        })
+    }
+}
+
+impl DebianVersion {
+    /// Obtain the ways the version violates Debian Policy.
+    ///
+    /// dpkg accepts such versions with a warning, but packages built with
+    /// them may be rejected by repository tooling.
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+
+        if !self
+            .upstream_version
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_digit())
+        {
+            warnings.push("upstream version doesn't start with a digit".to_string());
+        }
+
+        if let Some(c) = self
+            .upstream_version
+            .chars()
+            .find(|c| !(c.is_ascii_alphanumeric() || ".+~-:".contains(*c)))
+        {
+            warnings.push(format!("upstream version contains {:?}", c));
+        }
+
+        if let Some(c) = self
+            .debian_revision
+            .as_deref()
+            .unwrap_or("")
+            .chars()
+            .find(|c| !(c.is_ascii_alphanumeric() || ".+~".contains(*c)))
+        {
+            warnings.push(format!("Debian revision contains {:?}", c));
+        }
+
+        warnings
+    }
+}
+
+impl fmt::Display for DebianVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Upstream versions with colons require an explicit epoch.
+        if self.epoch > 0 || self.upstream_version.contains(':') {
+            write!(f, "{}:", self.epoch)?;
+        }
+
+        write!(f, "{}", self.upstream_version)?;
+
+        if let Some(revision) = &self.debian_revision {
+            write!(f, "-{}", revision)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Ord for DebianVersion {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.epoch
+            .cmp(&other.epoch)
+            .then_with(|| compare_part(&self.upstream_version, &other.upstream_version))
+            .then_with(|| {
+                compare_part(
+                    self.debian_revision.as_deref().unwrap_or(""),
+                    other.debian_revision.as_deref().unwrap_or(""),
+                )
+            })
+    }
+}
+
+impl PartialOrd for DebianVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for DebianVersion {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for DebianVersion {}
+
+/// The weight of a non-digit character when comparing versions.
+///
+/// `None` is the end of the string.
+fn order(c: Option<u8>) -> i32 {
+    match c {
+        None => 0,
+        Some(c) if c.is_ascii_digit() => 0,
+        Some(c) if c.is_ascii_alphabetic() => i32::from(c),
+        Some(b'~') => -1,
+        Some(c) => i32::from(c) + 256,
+    }
+}
+
+/// Compare upstream versions or Debian revisions like dpkg.
+///
+/// Strings are compared as alternating runs of non-digits, compared by
+/// character with `order()`, and digits, compared numerically.
+fn compare_part(a: &str, b: &str) -> Ordering {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    let (mut i, mut j) = (0, 0);
+    let is_digit = |s: &[u8], i: usize| s.get(i).is_some_and(|c| c.is_ascii_digit());
+
+    while i < a.len() || j < b.len() {
+        while (i < a.len() && !is_digit(a, i)) || (j < b.len() && !is_digit(b, j)) {
+            let (ac, bc) = (order(a.get(i).copied()), order(b.get(j).copied()));
+
+            if ac != bc {
+                return ac.cmp(&bc);
+            }
+
+            i += 1;
+            j += 1;
+        }
+
+        while a.get(i) == Some(&b'0') {
+            i += 1;
+        }
+        while b.get(j) == Some(&b'0') {
+            j += 1;
+        }
+
+        let mut first_diff = Ordering::Equal;
+
+        while is_digit(a, i) && is_digit(b, j) {
+            if first_diff == Ordering::Equal {
+                first_diff = a[i].cmp(&b[j]);
+            }
+
+            i += 1;
+            j += 1;
+        }
+
+        if is_digit(a, i) {
+            return Ordering::Greater;
+        }
+        if is_digit(b, j) {
+            return Ordering::Less;
+        }
+        if first_diff != Ordering::Equal {
+            return first_diff;
+        }
+    }
+
+    Ordering::Equal
+}
+
+/// Compare two Debian versions.
+pub fn compare_versions(a: &str, b: &str) -> Result<Ordering, String> {
+    Ok(DebianVersion::from_str(a)?.cmp(&DebianVersion::from_str(b)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cmp(a: &str, b: &str) -> Ordering {
+        compare_versions(a, b).unwrap()
+    }
+
+    #[test]
+    fn test_tilde_sorts_first() {
+        assert_eq!(cmp("1.0~rc1", "1.0"), Ordering::Less);
+        assert_eq!(cmp("1.0~~", "1.0~"), Ordering::Less);
+        assert_eq!(cmp("1.0~~a", "1.0~~"), Ordering::Greater);
+        assert_eq!(cmp("1.0-1~bpo1", "1.0-1"), Ordering::Less);
+    }
+
+    #[test]
+    fn test_numeric_parts() {
+        assert_eq!(cmp("1.10", "1.9"), Ordering::Greater);
+        assert_eq!(cmp("1.002", "1.2"), Ordering::Equal);
+        assert_eq!(cmp("9", "10"), Ordering::Less);
+        assert_eq!(cmp("2.0.0", "2.0"), Ordering::Greater);
+    }
+
+    #[test]
+    fn test_letters_before_punctuation() {
+        assert_eq!(cmp("1.0a", "1.0+"), Ordering::Less);
+        assert_eq!(cmp("1.2.3", "1.2.3a"), Ordering::Less);
+    }
+
+    #[test]
+    fn test_epochs() {
+        assert_eq!(cmp("1:0.9", "2.0"), Ordering::Greater);
+        assert_eq!(cmp("1:1.0", "2:0.1"), Ordering::Less);
+        assert_eq!(cmp("0:1.0", "1.0"), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_revisions() {
+        assert_eq!(cmp("1.0-1", "1.0"), Ordering::Greater);
+        assert_eq!(cmp("1.0-0", "1.0"), Ordering::Equal);
+        assert_eq!(cmp("1.0-1ubuntu1", "1.0-1"), Ordering::Greater);
+        assert_eq!(cmp("1.0-a", "1.0-1"), Ordering::Greater);
+        assert_eq!(cmp("1.0-2", "1.0-10"), Ordering::Less);
+    }
+
+    #[test]
+    fn test_equality() {
+        let a: DebianVersion = "1.0".parse().unwrap();
+        let b: DebianVersion = "0:1.0".parse().unwrap();
+        let c: DebianVersion = "1.0-0".parse().unwrap();
+
+        assert_eq!(a, b);
+        assert_eq!(b, c);
+        assert_eq!(a, c);
+    }
+
+    #[test]
+    fn test_parse() {
+        let v: DebianVersion = "2:1.0~rc1-3ubuntu1".parse().unwrap();
+        assert_eq!(v.epoch, 2);
+        assert_eq!(v.upstream_version, "1.0~rc1");
+        assert_eq!(v.debian_revision.as_deref(), Some("3ubuntu1"));
+        assert_eq!(v.to_string(), "2:1.0~rc1-3ubuntu1");
+
+        let v: DebianVersion = "1.0-2-3".parse().unwrap();
+        assert_eq!(v.upstream_version, "1.0-2");
+        assert_eq!(v.debian_revision.as_deref(), Some("3"));
+
+        let v: DebianVersion = "1:2:3".parse().unwrap();
+        assert_eq!(v.upstream_version, "2:3");
+        assert!(v.warnings().is_empty());
+
+        let v: DebianVersion = "0:a:b".parse().unwrap();
+        assert_eq!(v.to_string(), "0:a:b");
+    }
+
+    #[test]
+    fn test_lenient_parse() {
+        let v: DebianVersion = "v1.0".parse().unwrap();
+        assert_eq!(v.warnings().len(), 1);
+
+        let v: DebianVersion = "1.0_beta".parse().unwrap();
+        assert_eq!(v.warnings().len(), 1);
+    }
+
+    #[test]
+    fn test_invalid() {
+        for version in &[
+            "",
+            " ",
+            "1.0 1",
+            ":1.0",
+            "a:1.0",
+            "1:",
+            "1.0-",
+            "-1",
+            "99999999999:1.0",
+        ] {
+            assert!(
+                version.parse::<DebianVersion>().is_err(),
+                "{:?} should be invalid",
+                version
+            );
+        }
+    }
+}
@@ -0,0 +1,97 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Producing metadata for incremental updates.
+
+Users updating from a previous release over slow connections needn't
+download artifacts in full. `.zsync` files, produced with `zsyncmake`,
+let zsync clients fetch only the blocks of an artifact they don't already
+have. bsdiff patches, produced with `bsdiff`, transform the artifact of
+a previous release into the current one.
+*/
+
+use crate::signing::sibling_path;
+use serde::Serialize;
+use slog::{warn, Logger};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn file_name(path: &Path) -> Result<String, String> {
+    path.file_name()
+        .map(|f| f.to_string_lossy().to_string())
+        .ok_or_else(|| format!("{} has no filename", path.display()))
+}
+
+/// Produce a `.zsync` file next to an artifact.
+///
+/// `url` is the URL of the directory the artifact is downloaded from. If
+/// not defined, the `.zsync` file refers to the artifact relative to its
+/// own URL. The artifact is treated as opaque data, even if it is
+/// compressed.
+pub fn zsync_make(logger: &Logger, artifact: &Path, url: Option<&str>) -> Result<PathBuf, String> {
+    let filename = file_name(artifact)?;
+    let dest = sibling_path(artifact, ".zsync");
+    let url = match url {
+        Some(url) => format!("{}/{}", url.trim_end_matches('/'), filename),
+        None => filename,
+    };
+
+    warn!(logger, "writing {}", dest.display());
+
+    crate::process::run_logged(
+        logger,
+        Command::new("zsyncmake")
+            .arg("-Z")
+            .arg("-u")
+            .arg(url)
+            .arg("-o")
+            .arg(&dest)
+            .arg(artifact),
+    )?;
+
+    Ok(dest)
+}
+
+/// Produce a bsdiff patch transforming `previous` into `artifact`.
+///
+/// The patch is written next to the artifact, with `.bsdiff` appended to
+/// its name.
+pub fn bsdiff(logger: &Logger, previous: &Path, artifact: &Path) -> Result<PathBuf, String> {
+    let dest = sibling_path(artifact, ".bsdiff");
+
+    warn!(
+        logger,
+        "writing {} (from {})",
+        dest.display(),
+        previous.display()
+    );
+
+    crate::process::run_logged(
+        logger,
+        Command::new("bsdiff")
+            .arg(previous)
+            .arg(artifact)
+            .arg(&dest),
+    )?;
+
+    Ok(dest)
+}
+
+/// Represents a request to produce incremental update metadata.
+#[derive(Debug, Clone, Serialize)]
+pub struct DeltaUpdate {
+    /// Paths to files relative to the distribution path.
+    pub artifacts: Vec<String>,
+
+    /// Previous versions of artifacts to produce patches from.
+    pub previous: BTreeMap<String, PathBuf>,
+
+    /// Whether to produce `.zsync` files.
+    pub zsync: bool,
+
+    /// URL of the directory artifacts are downloaded from.
+    pub url: Option<String>,
+}
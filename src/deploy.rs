@@ -0,0 +1,626 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Functionality for deploying artifacts to remote servers.
+*/
+
+use crate::debian_version::DebianVersion;
+use crate::retry::{AttemptError, RetryPolicy};
+use serde::Serialize;
+use slog::{warn, Logger};
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+
+/// Protocol used to copy files to a remote host.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RemoteProtocol {
+    Sftp,
+    Rsync,
+}
+
+impl FromStr for RemoteProtocol {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "sftp" => Ok(RemoteProtocol::Sftp),
+            "rsync" => Ok(RemoteProtocol::Rsync),
+            _ => Err(format!("unknown protocol {}; expected sftp or rsync", s)),
+        }
+    }
+}
+
+/// How to treat SSH host keys of remote servers.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HostKeyPolicy {
+    /// Host must be present in known hosts.
+    Strict,
+
+    /// Unknown hosts are added to known hosts. Changed keys are rejected.
+    AcceptNew,
+
+    /// Host keys aren't verified.
+    Insecure,
+}
+
+impl FromStr for HostKeyPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "strict" => Ok(HostKeyPolicy::Strict),
+            "accept-new" => Ok(HostKeyPolicy::AcceptNew),
+            "insecure" => Ok(HostKeyPolicy::Insecure),
+            _ => Err(format!(
+                "unknown host key policy {}; expected strict, accept-new, or insecure",
+                s
+            )),
+        }
+    }
+}
+
+/// Options for SSH connections.
+#[derive(Clone, Debug, Serialize)]
+pub struct SshOptions {
+    pub host_key_policy: HostKeyPolicy,
+
+    /// Path to a known hosts file to use instead of the default.
+    pub known_hosts_file: Option<PathBuf>,
+}
+
+impl SshOptions {
+    /// Arguments to pass to `ssh` and `sftp`.
+    pub fn args(&self) -> Vec<String> {
+        let strict = match self.host_key_policy {
+            HostKeyPolicy::Strict => "yes",
+            HostKeyPolicy::AcceptNew => "accept-new",
+            HostKeyPolicy::Insecure => "no",
+        };
+
+        let mut args = vec![
+            "-o".to_string(),
+            "BatchMode=yes".to_string(),
+            "-o".to_string(),
+            format!("StrictHostKeyChecking={}", strict),
+        ];
+
+        if let Some(path) = &self.known_hosts_file {
+            args.push("-o".to_string());
+            args.push(format!("UserKnownHostsFile={}", path.display()));
+        } else if self.host_key_policy == HostKeyPolicy::Insecure {
+            args.push("-o".to_string());
+            args.push("UserKnownHostsFile=/dev/null".to_string());
+        }
+
+        args
+    }
+}
+
+/// Quote a value for use in an `sftp` batch file or `ssh` command line.
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Copy files to a directory on a remote host over SSH.
+///
+/// `host` is an SSH destination (e.g. `user@example.com`). `dest` is the
+/// directory on the remote host to copy files into. It is created if
+/// missing.
+///
+/// Files are uploaded to a temporary name and renamed into place so
+/// clients of the remote server never see partially written files. Up to
+/// `concurrency` files are copied at once, each over its own connection.
+/// Failed copies of a file are retried according to `retry`.
+#[allow(clippy::too_many_arguments)]
+pub fn remote_copy(
+    logger: &Logger,
+    host: &str,
+    dest: &str,
+    artifacts: &[PathBuf],
+    protocol: RemoteProtocol,
+    ssh: &SshOptions,
+    concurrency: usize,
+    retry: &RetryPolicy,
+) -> Result<(), String> {
+    for artifact in artifacts {
+        if !artifact.is_file() {
+            return Err(format!("{} is not a file", artifact.display()));
+        }
+    }
+
+    warn!(
+        logger,
+        "copying {} files to {}:{} via {:?}",
+        artifacts.len(),
+        host,
+        dest,
+        protocol
+    );
+
+    crate::upload::upload_concurrently(logger, artifacts, concurrency, |artifact| {
+        let artifacts = [artifact.to_path_buf()];
+
+        retry.retry(
+            logger,
+            &format!("copy of {} to {}", artifact.display(), host),
+            || {
+                match protocol {
+                    RemoteProtocol::Sftp => sftp_copy(logger, host, dest, &artifacts, ssh),
+                    RemoteProtocol::Rsync => rsync_copy(logger, host, dest, &artifacts, ssh),
+                }
+                .map_err(AttemptError::process)
+            },
+        )
+    })
+}
+
+fn artifact_filename(path: &Path) -> Result<String, String> {
+    path.file_name()
+        .map(|f| f.to_string_lossy().to_string())
+        .ok_or_else(|| format!("{} has no filename", path.display()))
+}
+
+fn sftp_copy(
+    logger: &Logger,
+    host: &str,
+    dest: &str,
+    artifacts: &[PathBuf],
+    ssh: &SshOptions,
+) -> Result<(), String> {
+    let dest = dest.trim_end_matches('/');
+
+    // Commands prefixed with `-` are allowed to fail.
+    let mut batch = format!("-mkdir {}\n", quote(dest));
+
+    for artifact in artifacts {
+        let filename = artifact_filename(artifact)?;
+        let temp_path = format!("{}/.{}.tugger-tmp", dest, filename);
+        let final_path = format!("{}/{}", dest, filename);
+
+        // sftp's rename uses the posix-rename extension when the server
+        // supports it, atomically replacing any existing file.
+        batch.push_str(&format!(
+            "put {} {}\nrename {} {}\n",
+            quote(&artifact.display().to_string()),
+            quote(&temp_path),
+            quote(&temp_path),
+            quote(&final_path)
+        ));
+    }
+
+    let mut batch_file =
+        tempfile::NamedTempFile::new().map_err(|e| format!("unable to create temp file: {}", e))?;
+    batch_file
+        .write_all(batch.as_bytes())
+        .map_err(|e| format!("unable to write sftp batch file: {}", e))?;
+
+    crate::process::run_logged(
+        logger,
+        Command::new("sftp")
+            .args(ssh.args())
+            .arg("-b")
+            .arg(batch_file.path())
+            .arg(host),
+    )
+}
+
+fn rsync_copy(
+    logger: &Logger,
+    host: &str,
+    dest: &str,
+    artifacts: &[PathBuf],
+    ssh: &SshOptions,
+) -> Result<(), String> {
+    let ssh_command = std::iter::once("ssh".to_string())
+        .chain(ssh.args().iter().map(|a| quote(a)))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    // --delay-updates writes all files to temporary names and renames
+    // them into place at the end of the transfer.
+    crate::process::run_logged(
+        logger,
+        Command::new("rsync")
+            .arg("--times")
+            .arg("--delay-updates")
+            .arg("--mkpath")
+            .arg("-e")
+            .arg(ssh_command)
+            .args(artifacts)
+            .arg(format!("{}:{}/", host, dest.trim_end_matches('/'))),
+    )
+}
+
+/// A destination for synchronizing a directory tree.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum SyncDestination {
+    /// An S3 bucket and prefix, synchronized with the AWS CLI.
+    S3 { url: String },
+
+    /// A GCS bucket and prefix, synchronized with the Google Cloud CLI.
+    Gcs { url: String },
+
+    /// A directory on a host reachable over SSH, synchronized with rsync.
+    Ssh { host: String, path: String },
+}
+
+impl FromStr for SyncDestination {
+    type Err = String;
+
+    /// Parse `s3://bucket/prefix`, `gs://bucket/prefix`, or `[user@]host:path`.
+    fn from_str(s: &str) -> Result<Self, String> {
+        if s.starts_with("s3://") {
+            Ok(SyncDestination::S3 {
+                url: s.trim_end_matches('/').to_string(),
+            })
+        } else if s.starts_with("gs://") {
+            Ok(SyncDestination::Gcs {
+                url: s.trim_end_matches('/').to_string(),
+            })
+        } else if let Some(pos) = s.find(':') {
+            Ok(SyncDestination::Ssh {
+                host: s[..pos].to_string(),
+                path: s[pos + 1..].trim_end_matches('/').to_string(),
+            })
+        } else {
+            Err(format!(
+                "unrecognized destination {}; expected s3://, gs://, or host:path",
+                s
+            ))
+        }
+    }
+}
+
+/// Filenames of signed apt repository metadata.
+///
+/// Clients fetch these first, so they must be uploaded last.
+const APT_RELEASE_FILES: &[&str] = &["InRelease", "Release", "Release.gpg"];
+
+/// Synchronize a local directory to a destination.
+///
+/// If `exclude_release` is true, apt release files are skipped.
+fn sync_dir(
+    logger: &Logger,
+    source: &Path,
+    dest: &SyncDestination,
+    subdir: &str,
+    exclude_release: bool,
+    ssh: &SshOptions,
+) -> Result<(), String> {
+    let mut command;
+
+    match dest {
+        SyncDestination::S3 { url } => {
+            command = Command::new("aws");
+            command
+                .arg("s3")
+                .arg("sync")
+                .arg("--no-progress")
+                .arg(source)
+                .arg(format!("{}/{}/", url, subdir));
+
+            if exclude_release {
+                for name in APT_RELEASE_FILES {
+                    command.arg("--exclude").arg(format!("*{}", name));
+                }
+            }
+        }
+        SyncDestination::Gcs { url } => {
+            command = Command::new("gcloud");
+            command
+                .arg("storage")
+                .arg("rsync")
+                .arg("--recursive")
+                .arg(source)
+                .arg(format!("{}/{}/", url, subdir));
+
+            if exclude_release {
+                command.arg(format!(
+                    "--exclude=(^|.*/)({})$",
+                    APT_RELEASE_FILES.join("|").replace('.', "\\.")
+                ));
+            }
+        }
+        SyncDestination::Ssh { host, path } => {
+            let ssh_command = std::iter::once("ssh".to_string())
+                .chain(ssh.args().iter().map(|a| quote(a)))
+                .collect::<Vec<_>>()
+                .join(" ");
+
+            command = Command::new("rsync");
+            command
+                .arg("--recursive")
+                .arg("--times")
+                .arg("--delay-updates")
+                .arg("--mkpath")
+                .arg("-e")
+                .arg(ssh_command);
+
+            if exclude_release {
+                for name in APT_RELEASE_FILES {
+                    command.arg("--exclude").arg(name);
+                }
+            }
+
+            // A trailing slash on the source copies the directory's content.
+            command
+                .arg(format!("{}/", source.display()))
+                .arg(format!("{}:{}/{}/", host, path, subdir));
+        }
+    }
+
+    crate::process::run_logged(logger, &mut command)
+}
+
+/// An entry of a `Packages` index of an apt repository.
+#[derive(Clone, Debug)]
+struct AptIndexEntry {
+    package: String,
+    architecture: String,
+    version: DebianVersion,
+    filename: String,
+}
+
+/// Parse a `Packages` index of an apt repository.
+///
+/// Paragraphs without a `Package` field are ignored. Entries missing
+/// fields or with versions dpkg refuses are skipped with a warning, so
+/// legacy entries of existing repositories don't prevent publishing.
+fn parse_packages_index(logger: &Logger, data: &str, path: &Path) -> Vec<AptIndexEntry> {
+    let mut entries = Vec::new();
+
+    for paragraph in data.split("\n\n") {
+        let mut fields = BTreeMap::new();
+
+        for line in paragraph.lines() {
+            // Continuation lines of multi-line fields start with whitespace.
+            if line.starts_with(' ') || line.starts_with('\t') {
+                continue;
+            }
+
+            if let Some((key, value)) = line.split_once(':') {
+                fields.insert(key.trim(), value.trim());
+            }
+        }
+
+        let package = match fields.get("Package") {
+            Some(package) => package.to_string(),
+            None => continue,
+        };
+        let (architecture, version, filename) = match (
+            fields.get("Architecture"),
+            fields.get("Version"),
+            fields.get("Filename"),
+        ) {
+            (Some(architecture), Some(version), Some(filename)) => {
+                (architecture, version, filename)
+            }
+            _ => {
+                warn!(
+                    logger,
+                    "{}: ignoring {} without Architecture, Version, or Filename",
+                    path.display(),
+                    package
+                );
+                continue;
+            }
+        };
+
+        let version = match version.parse::<DebianVersion>() {
+            Ok(version) => version,
+            Err(e) => {
+                warn!(logger, "{}: ignoring {}: {}", path.display(), package, e);
+                continue;
+            }
+        };
+
+        entries.push(AptIndexEntry {
+            architecture: architecture.to_string(),
+            version,
+            filename: filename.to_string(),
+            package,
+        });
+    }
+
+    entries
+}
+
+/// Check the package indices of an apt repository before it is published.
+///
+/// Listing the same version of a package twice with different files is
+/// an error, as apt can't tell which file to install. For packages listed
+/// with several versions, the version apt installs, i.e. the highest, is
+/// logged along with the versions it supersedes. Older versions are
+/// still published.
+fn check_apt_indices(logger: &Logger, dists: &Path) -> Result<(), String> {
+    for entry in walkdir::WalkDir::new(dists).sort_by(|a, b| a.file_name().cmp(b.file_name())) {
+        let entry = entry.map_err(|e| format!("unable to walk {}: {}", dists.display(), e))?;
+        let path = entry.path();
+
+        let data = match path.file_name().and_then(|name| name.to_str()) {
+            Some("Packages") => std::fs::read_to_string(path)
+                .map_err(|e| format!("unable to read {}: {}", path.display(), e))?,
+            // Compressed indices are only read if there is no plain index.
+            Some("Packages.gz") if !path.with_file_name("Packages").exists() => {
+                let mut data = String::new();
+                let fh = std::fs::File::open(path)
+                    .map_err(|e| format!("unable to open {}: {}", path.display(), e))?;
+                flate2::read::GzDecoder::new(fh)
+                    .read_to_string(&mut data)
+                    .map_err(|e| format!("unable to read {}: {}", path.display(), e))?;
+                data
+            }
+            _ => continue,
+        };
+
+        let mut packages: BTreeMap<(String, String), Vec<AptIndexEntry>> = BTreeMap::new();
+        for entry in parse_packages_index(logger, &data, path) {
+            packages
+                .entry((entry.package.clone(), entry.architecture.clone()))
+                .or_default()
+                .push(entry);
+        }
+
+        for ((package, architecture), mut entries) in packages {
+            entries.sort_by(|a, b| b.version.cmp(&a.version));
+
+            for pair in entries.windows(2) {
+                if pair[0].version == pair[1].version && pair[0].filename != pair[1].filename {
+                    return Err(format!(
+                        "{} lists {} {} ({}) as both {} and {}",
+                        path.display(),
+                        package,
+                        pair[0].version,
+                        architecture,
+                        pair[0].filename,
+                        pair[1].filename
+                    ));
+                }
+            }
+
+            if entries.len() > 1 {
+                warn!(
+                    logger,
+                    "apt installs {} {} ({}), superseding {}",
+                    package,
+                    entries[0].version,
+                    architecture,
+                    entries[1..]
+                        .iter()
+                        .map(|entry| entry.version.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Publish a local apt repository to a remote destination.
+///
+/// `repo_dir` must contain the `pool` and `dists` directories of a
+/// repository. Its package indices are checked first, see
+/// `check_apt_indices()`. Files are uploaded in an order that ensures clients never
+/// see metadata referring to files that don't exist yet: first the
+/// package pool, then the indices, and finally the signed release files.
+///
+/// If `cloudfront_distribution` is defined, cached metadata in that
+/// CloudFront distribution is invalidated after upload.
+///
+/// Each upload is retried according to `retry`. Uploads are incremental,
+/// so retries don't upload files again.
+pub fn publish_apt_repository(
+    logger: &Logger,
+    repo_dir: &Path,
+    dest: &SyncDestination,
+    ssh: &SshOptions,
+    cloudfront_distribution: Option<&str>,
+    retry: &RetryPolicy,
+) -> Result<(), String> {
+    let pool = repo_dir.join("pool");
+    let dists = repo_dir.join("dists");
+
+    for dir in &[&pool, &dists] {
+        if !dir.is_dir() {
+            return Err(format!(
+                "{} is not an apt repository: {} does not exist",
+                repo_dir.display(),
+                dir.display()
+            ));
+        }
+    }
+
+    check_apt_indices(logger, &dists)?;
+
+    warn!(logger, "uploading package pool");
+    retry.retry(logger, "upload of package pool", || {
+        sync_dir(logger, &pool, dest, "pool", false, ssh).map_err(AttemptError::process)
+    })?;
+
+    warn!(logger, "uploading package indices");
+    retry.retry(logger, "upload of package indices", || {
+        sync_dir(logger, &dists, dest, "dists", true, ssh).map_err(AttemptError::process)
+    })?;
+
+    warn!(logger, "uploading release files");
+    retry.retry(logger, "upload of release files", || {
+        sync_dir(logger, &dists, dest, "dists", false, ssh).map_err(AttemptError::process)
+    })?;
+
+    if let Some(distribution) = cloudfront_distribution {
+        let prefix = match dest {
+            SyncDestination::S3 { url } => url
+                .trim_start_matches("s3://")
+                .split_once('/')
+                .map(|(_, p)| format!("/{}", p))
+                .unwrap_or_default(),
+            _ => "".to_string(),
+        };
+
+        warn!(
+            logger,
+            "invalidating CloudFront distribution {}", distribution
+        );
+
+        retry.retry(logger, "CloudFront invalidation", || {
+            crate::process::run_logged(
+                logger,
+                Command::new("aws")
+                    .arg("cloudfront")
+                    .arg("create-invalidation")
+                    .arg("--distribution-id")
+                    .arg(distribution)
+                    .arg("--paths")
+                    .arg(format!("{}/dists/*", prefix)),
+            )
+            .map_err(AttemptError::process)
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Represents a request to copy artifacts to a remote host.
+#[derive(Debug, Clone, Serialize)]
+pub struct RemoteCopy {
+    pub host: String,
+    pub dest: String,
+
+    /// Paths to files relative to the distribution path.
+    pub artifacts: Vec<String>,
+
+    pub protocol: RemoteProtocol,
+    pub ssh: SshOptions,
+
+    /// Maximum number of files copied at once.
+    pub concurrency: usize,
+
+    /// How failed operations are retried, if not the pipeline's policy.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry: Option<RetryPolicy>,
+}
+
+/// Represents a request to publish an apt repository.
+#[derive(Debug, Clone, Serialize)]
+pub struct AptRepositoryPublish {
+    /// Path to the repository relative to the distribution path.
+    pub repo_dir: String,
+
+    pub destination: SyncDestination,
+    pub ssh: SshOptions,
+    pub cloudfront_distribution: Option<String>,
+
+    /// How failed operations are retried, if not the pipeline's policy.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry: Option<RetryPolicy>,
+}
@@ -0,0 +1,171 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Desktop entry files.
+
+Desktop entries describe how applications are launched and presented in
+menus, per the
+[Desktop Entry Specification](https://specifications.freedesktop.org/desktop-entry-spec/latest/).
+They are installed to `share/applications/` under a data directory, such
+as `/usr` for Debian packages and AppImages or `/app` for Flatpaks.
+*/
+
+/// Main categories defined by the freedesktop menu specification.
+///
+/// Every application should be in at least one of these.
+const MAIN_CATEGORIES: &[&str] = &[
+    "AudioVideo",
+    "Audio",
+    "Video",
+    "Development",
+    "Education",
+    "Game",
+    "Graphics",
+    "Network",
+    "Office",
+    "Science",
+    "Settings",
+    "System",
+    "Utility",
+];
+
+/// Describes a desktop entry for an application.
+#[derive(Clone, Debug, Default)]
+pub struct DesktopEntry {
+    /// Desktop file ID, which is also the basename of the file.
+    pub id: String,
+    pub name: String,
+    pub exec: String,
+    pub icon: Option<String>,
+    pub categories: Vec<String>,
+    pub comment: Option<String>,
+    pub generic_name: Option<String>,
+    pub keywords: Vec<String>,
+    pub mime_types: Vec<String>,
+    pub terminal: bool,
+}
+
+impl DesktopEntry {
+    /// Ensure the entry is valid.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.id.is_empty()
+            || !self
+                .id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.')
+        {
+            return Err(format!(
+                "invalid desktop file ID {}; only letters, digits, _, -, and . are allowed",
+                self.id
+            ));
+        }
+
+        if self.name.trim().is_empty() {
+            return Err("desktop entry name must not be empty".to_string());
+        }
+
+        if self.exec.trim().is_empty() {
+            return Err("desktop entry exec must not be empty".to_string());
+        }
+
+        for value in self
+            .categories
+            .iter()
+            .chain(&self.keywords)
+            .chain(&self.mime_types)
+        {
+            if value.is_empty() || value.contains(';') {
+                return Err(format!(
+                    "invalid desktop entry list value {:?}; values must be non-empty and not contain ;",
+                    value
+                ));
+            }
+        }
+
+        if !self.categories.is_empty()
+            && !self
+                .categories
+                .iter()
+                .any(|c| MAIN_CATEGORIES.contains(&c.as_str()))
+        {
+            return Err(format!(
+                "desktop entry categories must include one of {}",
+                MAIN_CATEGORIES.join(", ")
+            ));
+        }
+
+        for mime_type in &self.mime_types {
+            if !mime_type.contains('/') {
+                return Err(format!("invalid MIME type {}", mime_type));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Obtain the filename of the entry.
+    pub fn filename(&self) -> String {
+        format!("{}.desktop", self.id)
+    }
+
+    /// Render the content of the `.desktop` file.
+    pub fn render(&self) -> String {
+        let mut lines = vec![
+            "[Desktop Entry]".to_string(),
+            "Type=Application".to_string(),
+            format!("Name={}", escape(&self.name)),
+        ];
+
+        if let Some(generic_name) = &self.generic_name {
+            lines.push(format!("GenericName={}", escape(generic_name)));
+        }
+
+        if let Some(comment) = &self.comment {
+            lines.push(format!("Comment={}", escape(comment)));
+        }
+
+        lines.push(format!("Exec={}", escape(&self.exec)));
+
+        if let Some(icon) = &self.icon {
+            lines.push(format!("Icon={}", escape(icon)));
+        }
+
+        lines.push(format!(
+            "Terminal={}",
+            if self.terminal { "true" } else { "false" }
+        ));
+
+        for (key, values) in &[
+            ("Categories", &self.categories),
+            ("Keywords", &self.keywords),
+            ("MimeType", &self.mime_types),
+        ] {
+            if !values.is_empty() {
+                lines.push(format!(
+                    "{}={};",
+                    key,
+                    values
+                        .iter()
+                        .map(|v| escape(v))
+                        .collect::<Vec<_>>()
+                        .join(";")
+                ));
+            }
+        }
+
+        let mut s = lines.join("\n");
+        s.push('\n');
+        s
+    }
+}
+
+/// Escape a string value per the specification.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t")
+        .replace('\r', "\\r")
+}
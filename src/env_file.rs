@@ -0,0 +1,213 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Environment files defining variables of runs.
+
+An environment file has a `KEY=VALUE` pair on each line, like the
+`.env` files read by many other tools:
+
+* Blank lines and lines starting with `#` are ignored.
+* Keys may be preceded by `export `.
+* Unquoted values are trimmed and end at ` #`, which starts a comment.
+* Values in single quotes are literal.
+* Values in double quotes may contain the escapes `\n`, `\r`, `\t`,
+  `\"`, `\\`, and `\$`.
+* Quoted values may span several lines.
+*/
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+fn valid_key(key: &str) -> bool {
+    let mut chars = key.chars();
+
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Parse the content of an environment file.
+///
+/// Variables defined more than once have the last value defined.
+pub fn parse(data: &str) -> Result<BTreeMap<String, String>, String> {
+    let mut vars = BTreeMap::new();
+    let mut lines = data.lines().enumerate();
+
+    while let Some((index, line)) = lines.next() {
+        let number = index + 1;
+        let line = line.trim_start();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let line = line.strip_prefix("export ").unwrap_or(line).trim_start();
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("line {}: expected KEY=VALUE", number))?;
+        let key = key.trim_end();
+
+        if !valid_key(key) {
+            return Err(format!("line {}: invalid variable name {:?}", number, key));
+        }
+
+        let value = value.trim_start();
+
+        let (value, rest) = match value.chars().next() {
+            Some(quote @ '\'') | Some(quote @ '"') => {
+                let mut text = value[1..].to_string();
+
+                // Quoted values continue on the following lines until the
+                // closing quote.
+                let end = loop {
+                    if let Some(end) = closing_quote(&text, quote) {
+                        break end;
+                    }
+
+                    match lines.next() {
+                        Some((_, next)) => {
+                            text.push('\n');
+                            text.push_str(next);
+                        }
+                        None => {
+                            return Err(format!(
+                                "line {}: unterminated quoted value of {}",
+                                number, key
+                            ))
+                        }
+                    }
+                };
+
+                let value = if quote == '"' {
+                    unescape(&text[..end])
+                } else {
+                    text[..end].to_string()
+                };
+
+                (value, text[end + 1..].to_string())
+            }
+            _ => match value.find(" #").or_else(|| value.find("\t#")) {
+                Some(comment) => (value[..comment].trim_end().to_string(), String::new()),
+                None => (value.trim_end().to_string(), String::new()),
+            },
+        };
+
+        let rest = rest.trim_start();
+        if !rest.is_empty() && !rest.starts_with('#') {
+            return Err(format!(
+                "line {}: unexpected {:?} after the value of {}",
+                number, rest, key
+            ));
+        }
+
+        vars.insert(key.to_string(), value);
+    }
+
+    Ok(vars)
+}
+
+/// Find the quote closing a quoted value, skipping escaped double quotes.
+fn closing_quote(text: &str, quote: char) -> Option<usize> {
+    let mut escaped = false;
+
+    for (index, c) in text.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' && quote == '"' {
+            escaped = true;
+        } else if c == quote {
+            return Some(index);
+        }
+    }
+
+    None
+}
+
+fn unescape(value: &str) -> String {
+    let mut res = String::with_capacity(value.len());
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            res.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => res.push('\n'),
+            Some('r') => res.push('\r'),
+            Some('t') => res.push('\t'),
+            Some(c @ '"') | Some(c @ '\\') | Some(c @ '$') => res.push(c),
+            Some(c) => {
+                res.push('\\');
+                res.push(c);
+            }
+            None => res.push('\\'),
+        }
+    }
+
+    res
+}
+
+/// Read an environment file.
+pub fn read(path: &Path) -> Result<BTreeMap<String, String>, String> {
+    let data = std::fs::read_to_string(path)
+        .map_err(|e| format!("unable to read {}: {}", path.display(), e))?;
+
+    parse(&data).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_ok(data: &str) -> Vec<(String, String)> {
+        parse(data).unwrap().into_iter().collect()
+    }
+
+    fn pair(key: &str, value: &str) -> (String, String) {
+        (key.to_string(), value.to_string())
+    }
+
+    #[test]
+    fn test_unquoted() {
+        assert_eq!(
+            parse_ok("# release\n\nA=1\nexport B = two words  # comment\nC=x#y\nD=\n"),
+            vec![
+                pair("A", "1"),
+                pair("B", "two words"),
+                pair("C", "x#y"),
+                pair("D", "")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_quoted() {
+        assert_eq!(
+            parse_ok("A='a \\n $b # c'\nB=\"x\\ty\\\"z\\\\\" # comment\n"),
+            vec![pair("A", "a \\n $b # c"), pair("B", "x\ty\"z\\")]
+        );
+        assert_eq!(
+            parse_ok("KEY=\"-----BEGIN-----\nabc\n-----END-----\"\nNEXT=1"),
+            vec![
+                pair("KEY", "-----BEGIN-----\nabc\n-----END-----"),
+                pair("NEXT", "1")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(parse("A").is_err());
+        assert!(parse("1A=x").is_err());
+        assert!(parse("A-B=x").is_err());
+        assert!(parse("A=\"x").is_err());
+        assert!(parse("A='x' y").is_err());
+    }
+}
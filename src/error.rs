@@ -0,0 +1,134 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Error types.
+
+Errors describe what failed without repeating their cause: the cause is
+available from `std::error::Error::source()`. `TuggerError::render()`
+joins an error and its causes into a single message for display.
+*/
+
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// A position in a configuration file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SourceLocation {
+    pub path: PathBuf,
+
+    /// 1-based line number.
+    pub line: usize,
+
+    /// 1-based column number.
+    pub column: usize,
+}
+
+/// An error from tugger.
+#[derive(Debug, Error)]
+pub enum TuggerError {
+    /// An I/O operation failed.
+    #[error("{context}")]
+    Io {
+        /// Description of the operation, such as `unable to create foo`.
+        context: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// A configuration file could not be evaluated.
+    #[error("error evaluating {}: {message}", path.display())]
+    Evaluation {
+        path: PathBuf,
+        message: String,
+
+        /// The error message, without the details of `message`.
+        summary: String,
+
+        /// Where in a configuration file evaluation failed, if known.
+        location: Option<SourceLocation>,
+    },
+
+    /// A pipeline with the requested name isn't defined.
+    #[error("could not find pipeline {0}")]
+    PipelineNotFound(String),
+
+    /// A step of a pipeline failed.
+    #[error("step {index} ({kind}) of pipeline {pipeline} failed")]
+    Step {
+        pipeline: String,
+        index: usize,
+        kind: String,
+        #[source]
+        source: Box<TuggerError>,
+    },
+
+    /// Pipelines failed when continuing past failures.
+    #[error("{} pipeline(s) failed: {}", .0.len(), .0.join(", "))]
+    PipelinesFailed(Vec<String>),
+
+    /// A Debian package could not be built.
+    #[error("unable to build Debian package {}", path.display())]
+    Debian {
+        path: PathBuf,
+        #[source]
+        source: Box<TuggerError>,
+    },
+
+    /// A snap could not be built.
+    #[error("unable to build snap {name} in {}", build_path.display())]
+    Snap {
+        name: String,
+        build_path: PathBuf,
+        #[source]
+        source: Box<TuggerError>,
+    },
+
+    /// `snapcraft` failed.
+    #[error("{message}; last output:\n{}", output.join("\n"))]
+    Snapcraft {
+        message: String,
+
+        /// The last lines of output of `snapcraft`.
+        output: Vec<String>,
+    },
+
+    #[error("{0}")]
+    Other(String),
+}
+
+impl TuggerError {
+    /// Construct an error for a failed I/O operation.
+    pub fn io(context: impl Into<String>, source: std::io::Error) -> Self {
+        TuggerError::Io {
+            context: context.into(),
+            source,
+        }
+    }
+
+    /// Format the error and the chain of errors causing it.
+    pub fn render(&self) -> String {
+        let mut s = self.to_string();
+        let mut source = std::error::Error::source(self);
+
+        while let Some(e) = source {
+            s.push_str(&format!(": {}", e));
+            source = e.source();
+        }
+
+        s
+    }
+}
+
+impl From<String> for TuggerError {
+    fn from(message: String) -> Self {
+        TuggerError::Other(message)
+    }
+}
+
+impl From<&str> for TuggerError {
+    fn from(message: &str) -> Self {
+        TuggerError::Other(message.to_string())
+    }
+}
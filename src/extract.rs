@@ -0,0 +1,538 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Extraction of archives.
+
+Archives are untrusted input. Entries whose paths are absolute or
+contain `..` components are rejected, symlinks must point within the
+extraction directory, and files are never written through symlinks, so
+extraction can't modify files outside the destination directory.
+*/
+
+use crate::filemanifest::{manifest_key, FileContent, FileManifest};
+use serde::Serialize;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+/// Archive formats that can be extracted.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArchiveFormat {
+    Tar,
+    TarBz2,
+    TarGz,
+    TarXz,
+    TarZst,
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// Determine the format of an archive from its filename.
+    pub fn from_filename(filename: &str) -> Option<Self> {
+        let filename = filename.to_lowercase();
+
+        if filename.ends_with(".tar") {
+            Some(ArchiveFormat::Tar)
+        } else if filename.ends_with(".tar.bz2") || filename.ends_with(".tbz2") {
+            Some(ArchiveFormat::TarBz2)
+        } else if filename.ends_with(".tar.gz") || filename.ends_with(".tgz") {
+            Some(ArchiveFormat::TarGz)
+        } else if filename.ends_with(".tar.xz") || filename.ends_with(".txz") {
+            Some(ArchiveFormat::TarXz)
+        } else if filename.ends_with(".tar.zst") || filename.ends_with(".tzst") {
+            Some(ArchiveFormat::TarZst)
+        } else if filename.ends_with(".zip") {
+            Some(ArchiveFormat::Zip)
+        } else {
+            None
+        }
+    }
+
+    /// The canonical filename extension of the format, without a leading dot.
+    pub fn extension(self) -> &'static str {
+        match self {
+            ArchiveFormat::Tar => "tar",
+            ArchiveFormat::TarBz2 => "tar.bz2",
+            ArchiveFormat::TarGz => "tar.gz",
+            ArchiveFormat::TarXz => "tar.xz",
+            ArchiveFormat::TarZst => "tar.zst",
+            ArchiveFormat::Zip => "zip",
+        }
+    }
+}
+
+/// Validate an archive member path and strip leading components from it.
+///
+/// Returns `None` if the path is consumed entirely by stripping.
+fn member_path(name: &Path, strip_components: usize) -> Result<Option<PathBuf>, String> {
+    let mut components = Vec::new();
+
+    for component in name.components() {
+        match component {
+            Component::Normal(c) => components.push(c),
+            Component::CurDir => {}
+            _ => {
+                return Err(format!(
+                    "archive member {} has an unsafe path",
+                    name.display()
+                ))
+            }
+        }
+    }
+
+    if components.len() <= strip_components {
+        return Ok(None);
+    }
+
+    Ok(Some(components[strip_components..].iter().collect()))
+}
+
+/// Ensure no directory between `dest` and `dest/rel_path` is a symlink.
+///
+/// This prevents writing through symlinks created by earlier members.
+fn check_no_symlink_parents(dest: &Path, rel_path: &Path) -> Result<(), String> {
+    let mut path = dest.to_path_buf();
+
+    if let Some(parent) = rel_path.parent() {
+        for component in parent.components() {
+            path.push(component);
+
+            if let Ok(metadata) = std::fs::symlink_metadata(&path) {
+                if metadata.file_type().is_symlink() {
+                    return Err(format!(
+                        "archive member {} is inside symlink {}",
+                        rel_path.display(),
+                        path.display()
+                    ));
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Ensure a symlink target doesn't point outside the extraction directory.
+fn check_symlink_target(rel_path: &Path, target: &Path) -> Result<(), String> {
+    let mut depth: isize = rel_path.components().count() as isize - 1;
+
+    for component in target.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir => depth -= 1,
+            _ => depth = -1,
+        }
+
+        if depth < 0 {
+            return Err(format!(
+                "symlink {} points outside the extraction directory",
+                rel_path.display()
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Write a member to the filesystem.
+fn write_file(
+    dest: &Path,
+    rel_path: &Path,
+    reader: &mut dyn Read,
+    mode: Option<u32>,
+) -> Result<PathBuf, String> {
+    check_no_symlink_parents(dest, rel_path)?;
+
+    let path = dest.join(rel_path);
+    std::fs::create_dir_all(path.parent().unwrap())
+        .map_err(|e| format!("unable to create directory for {}: {}", path.display(), e))?;
+
+    // Remove any existing file first so a symlink left in its place by
+    // an earlier member isn't followed.
+    if std::fs::symlink_metadata(&path).is_ok() {
+        std::fs::remove_file(&path)
+            .map_err(|e| format!("unable to remove {}: {}", path.display(), e))?;
+    }
+
+    let mut fh = std::fs::File::create(&path)
+        .map_err(|e| format!("unable to create {}: {}", path.display(), e))?;
+    std::io::copy(reader, &mut fh)
+        .map_err(|e| format!("unable to write {}: {}", path.display(), e))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let executable = mode.map(|m| m & 0o111 != 0).unwrap_or(false);
+        let mode = if executable { 0o755 } else { 0o644 };
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode))
+            .map_err(|e| format!("unable to set permissions of {}: {}", path.display(), e))?;
+    }
+
+    #[cfg(not(unix))]
+    let _ = mode;
+
+    Ok(path)
+}
+
+/// Create a symlink member on the filesystem.
+fn write_symlink(dest: &Path, rel_path: &Path, target: &Path) -> Result<FileContent, String> {
+    check_symlink_target(rel_path, target)?;
+    check_no_symlink_parents(dest, rel_path)?;
+
+    let path = dest.join(rel_path);
+    std::fs::create_dir_all(path.parent().unwrap())
+        .map_err(|e| format!("unable to create directory for {}: {}", path.display(), e))?;
+
+    if std::fs::symlink_metadata(&path).is_ok() {
+        std::fs::remove_file(&path)
+            .map_err(|e| format!("unable to remove {}: {}", path.display(), e))?;
+    }
+
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(target, &path)
+        .map_err(|e| format!("unable to create symlink {}: {}", path.display(), e))?;
+
+    #[cfg(not(unix))]
+    return Err(format!(
+        "unable to create symlink {}: symlinks are not supported on this platform",
+        path.display()
+    ));
+
+    #[cfg(unix)]
+    Ok(FileContent::Symlink(target.to_path_buf()))
+}
+
+fn extract_tar(
+    reader: impl Read,
+    dest: &Path,
+    strip_components: usize,
+) -> Result<FileManifest, String> {
+    let mut manifest = FileManifest::new();
+    let mut archive = tar::Archive::new(reader);
+
+    let entries = archive
+        .entries()
+        .map_err(|e| format!("unable to read tar archive: {}", e))?;
+
+    for entry in entries {
+        let mut entry = entry.map_err(|e| format!("unable to read tar archive: {}", e))?;
+
+        let name = entry
+            .path()
+            .map_err(|e| format!("invalid path in tar archive: {}", e))?
+            .to_path_buf();
+
+        let rel_path = match member_path(&name, strip_components)? {
+            Some(path) => path,
+            None => continue,
+        };
+
+        let entry_type = entry.header().entry_type();
+
+        let content = if entry_type.is_file() {
+            let mode = entry.header().mode().ok();
+            write_file(dest, &rel_path, &mut entry, mode)?.into()
+        } else if entry_type.is_symlink() {
+            let target = entry
+                .link_name()
+                .map_err(|e| format!("invalid link in tar archive: {}", e))?
+                .ok_or_else(|| format!("symlink {} has no target", name.display()))?
+                .to_path_buf();
+
+            write_symlink(dest, &rel_path, &target)?
+        } else if entry_type.is_hard_link() {
+            let target = entry
+                .link_name()
+                .map_err(|e| format!("invalid link in tar archive: {}", e))?
+                .ok_or_else(|| format!("hard link {} has no target", name.display()))?
+                .to_path_buf();
+
+            // Hard links refer to earlier members, which must have been
+            // extracted as files.
+            let target = member_path(&target, strip_components)?
+                .and_then(|target| manifest_key(&target).ok())
+                .and_then(|key| manifest.get(&key).cloned())
+                .and_then(|content| match content {
+                    FileContent::Path(path) => Some(path),
+                    _ => None,
+                })
+                .ok_or_else(|| {
+                    format!(
+                        "hard link {} refers to a member that wasn't extracted",
+                        name.display()
+                    )
+                })?;
+
+            let mut fh = std::fs::File::open(&target)
+                .map_err(|e| format!("unable to open {}: {}", target.display(), e))?;
+            let mode = entry.header().mode().ok();
+
+            write_file(dest, &rel_path, &mut fh, mode)?.into()
+        } else {
+            // Directories are created as needed. Other member types
+            // (devices, FIFOs) have no place in a FileManifest.
+            continue;
+        };
+
+        manifest.insert(manifest_key(&rel_path)?, content);
+    }
+
+    Ok(manifest)
+}
+
+fn extract_zip(path: &Path, dest: &Path, strip_components: usize) -> Result<FileManifest, String> {
+    let mut manifest = FileManifest::new();
+
+    let fh = std::fs::File::open(path)
+        .map_err(|e| format!("unable to open {}: {}", path.display(), e))?;
+    let mut archive = zip::ZipArchive::new(fh)
+        .map_err(|e| format!("unable to read {}: {}", path.display(), e))?;
+
+    for index in 0..archive.len() {
+        let mut file = archive
+            .by_index(index)
+            .map_err(|e| format!("unable to read {}: {}", path.display(), e))?;
+
+        if file.is_dir() {
+            continue;
+        }
+
+        let rel_path = match member_path(Path::new(file.name()), strip_components)? {
+            Some(path) => path,
+            None => continue,
+        };
+
+        let mode = file.unix_mode();
+
+        let content = if file.is_symlink() {
+            let mut target = String::new();
+            file.read_to_string(&mut target)
+                .map_err(|e| format!("unable to read symlink {}: {}", file.name(), e))?;
+
+            write_symlink(dest, &rel_path, Path::new(&target))?
+        } else {
+            write_file(dest, &rel_path, &mut file, mode)?.into()
+        };
+
+        manifest.insert(manifest_key(&rel_path)?, content);
+    }
+
+    Ok(manifest)
+}
+
+/// Extract an archive into a directory.
+///
+/// The format of the archive is derived from `filename`, which is
+/// typically the archive's own filename. The first `strip_components`
+/// components of member paths are removed.
+///
+/// Returns a `FileManifest` of the extracted files.
+pub fn extract_archive(
+    archive: &Path,
+    filename: &str,
+    dest: &Path,
+    strip_components: usize,
+) -> Result<FileManifest, String> {
+    let format = ArchiveFormat::from_filename(filename)
+        .ok_or_else(|| format!("unable to determine archive format of {}", filename))?;
+
+    std::fs::create_dir_all(dest)
+        .map_err(|e| format!("unable to create {}: {}", dest.display(), e))?;
+
+    if format == ArchiveFormat::Zip {
+        extract_zip(archive, dest, strip_components)
+    } else {
+        extract_tar_format(archive, format, dest, strip_components)
+    }
+}
+
+fn extract_tar_format(
+    archive: &Path,
+    format: ArchiveFormat,
+    dest: &Path,
+    strip_components: usize,
+) -> Result<FileManifest, String> {
+    let fh = std::fs::File::open(archive)
+        .map_err(|e| format!("unable to open {}: {}", archive.display(), e))?;
+    let fh = std::io::BufReader::new(fh);
+
+    match format {
+        ArchiveFormat::Tar => extract_tar(fh, dest, strip_components),
+        ArchiveFormat::TarBz2 => {
+            extract_tar(bzip2::read::BzDecoder::new(fh), dest, strip_components)
+        }
+        ArchiveFormat::TarGz => {
+            extract_tar(flate2::read::GzDecoder::new(fh), dest, strip_components)
+        }
+        ArchiveFormat::TarXz => extract_tar(xz2::read::XzDecoder::new(fh), dest, strip_components),
+        ArchiveFormat::TarZst => extract_tar(
+            zstd::stream::read::Decoder::with_buffer(fh)
+                .map_err(|e| format!("unable to read {}: {}", archive.display(), e))?,
+            dest,
+            strip_components,
+        ),
+        ArchiveFormat::Zip => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Append a member to a tar archive without validating its path.
+    fn append_raw(
+        builder: &mut tar::Builder<Vec<u8>>,
+        name: &str,
+        entry_type: tar::EntryType,
+        link: Option<&str>,
+        data: &[u8],
+    ) {
+        let mut header = tar::Header::new_gnu();
+        header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+        if let Some(link) = link {
+            header.as_old_mut().linkname[..link.len()].copy_from_slice(link.as_bytes());
+        }
+        header.set_entry_type(entry_type);
+        header.set_mode(0o644);
+        header.set_size(data.len() as u64);
+        header.set_cksum();
+
+        builder.append(&header, data).unwrap();
+    }
+
+    fn extract(
+        members: &[(&str, tar::EntryType, Option<&str>, &[u8])],
+    ) -> (tempfile::TempDir, Result<FileManifest, String>) {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (name, entry_type, link, data) in members {
+            append_raw(&mut builder, name, *entry_type, *link, data);
+        }
+        let data = builder.into_inner().unwrap();
+
+        let temp = tempfile::tempdir().unwrap();
+        let archive = temp.path().join("archive.tar");
+        std::fs::write(&archive, data).unwrap();
+
+        let res = extract_archive(&archive, "archive.tar", &temp.path().join("out"), 0);
+        (temp, res)
+    }
+
+    #[test]
+    fn test_extract_tar() {
+        let (temp, res) = extract(&[
+            ("./a/b.txt", tar::EntryType::Regular, None, b"b"),
+            ("a/c", tar::EntryType::Symlink, Some("b.txt"), b""),
+        ]);
+        let manifest = res.unwrap();
+
+        assert_eq!(manifest.keys().collect::<Vec<_>>(), vec!["a/b.txt", "a/c"]);
+        assert_eq!(
+            std::fs::read(temp.path().join("out").join("a").join("c")).unwrap(),
+            b"b"
+        );
+    }
+
+    #[test]
+    fn test_parent_dir_rejected() {
+        for name in &["../evil.txt", "a/../../evil.txt", "a/../b.txt"] {
+            let (temp, res) = extract(&[(name, tar::EntryType::Regular, None, b"evil")]);
+
+            assert_eq!(
+                res.unwrap_err(),
+                format!("archive member {} has an unsafe path", name)
+            );
+            assert!(!temp.path().join("evil.txt").exists());
+        }
+    }
+
+    #[test]
+    fn test_absolute_path_rejected() {
+        let (temp, res) = extract(&[("/tmp/evil.txt", tar::EntryType::Regular, None, b"evil")]);
+
+        assert_eq!(
+            res.unwrap_err(),
+            "archive member /tmp/evil.txt has an unsafe path"
+        );
+        assert!(!temp.path().join("out").join("tmp").exists());
+    }
+
+    #[test]
+    fn test_symlink_outside_rejected() {
+        for target in &["..", "../evil", "a/../../evil", "/etc"] {
+            let (_temp, res) = extract(&[("link", tar::EntryType::Symlink, Some(target), b"")]);
+
+            assert_eq!(
+                res.unwrap_err(),
+                "symlink link points outside the extraction directory"
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_write_through_symlink_rejected() {
+        let (temp, res) = extract(&[
+            ("dir/keep.txt", tar::EntryType::Regular, None, b"keep"),
+            ("link", tar::EntryType::Symlink, Some("dir"), b""),
+            ("link/keep.txt", tar::EntryType::Regular, None, b"evil"),
+        ]);
+
+        assert_eq!(
+            res.unwrap_err(),
+            format!(
+                "archive member link/keep.txt is inside symlink {}",
+                temp.path().join("out").join("link").display()
+            )
+        );
+        assert_eq!(
+            std::fs::read(temp.path().join("out").join("dir").join("keep.txt")).unwrap(),
+            b"keep"
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_file_replaces_symlink() {
+        let (temp, res) = extract(&[
+            ("keep.txt", tar::EntryType::Regular, None, b"keep"),
+            ("file.txt", tar::EntryType::Symlink, Some("keep.txt"), b""),
+            ("file.txt", tar::EntryType::Regular, None, b"new"),
+        ]);
+        res.unwrap();
+
+        let out = temp.path().join("out");
+        assert_eq!(std::fs::read(out.join("keep.txt")).unwrap(), b"keep");
+        assert_eq!(std::fs::read(out.join("file.txt")).unwrap(), b"new");
+        assert!(!std::fs::symlink_metadata(out.join("file.txt"))
+            .unwrap()
+            .file_type()
+            .is_symlink());
+    }
+
+    #[test]
+    fn test_zip_parent_dir_rejected() {
+        use std::io::Write;
+
+        let temp = tempfile::tempdir().unwrap();
+        let archive = temp.path().join("archive.zip");
+
+        let mut writer = zip::ZipWriter::new(std::fs::File::create(&archive).unwrap());
+        writer
+            .start_file("../evil.txt", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(b"evil").unwrap();
+        writer.finish().unwrap();
+
+        let res = extract_archive(&archive, "archive.zip", &temp.path().join("out"), 0);
+
+        assert_eq!(
+            res.unwrap_err(),
+            "archive member ../evil.txt has an unsafe path"
+        );
+        assert!(!temp.path().join("evil.txt").exists());
+    }
+}
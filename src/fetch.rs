@@ -0,0 +1,150 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Downloading of remote files.
+
+Downloads are pinned to a SHA-256 digest, making them reproducible, and
+are stored in the cache so each file is only downloaded once.
+*/
+
+use crate::retry::{AttemptError, FailureClass, RetryPolicy};
+use sha2::{Digest, Sha256};
+use slog::Logger;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Download a URL, verifying its content has the expected SHA-256 digest.
+///
+/// Returns the path of the file in the cache. If the cache already holds
+/// content with the digest, nothing is downloaded. Failed downloads are
+/// retried according to `retry`.
+pub fn fetch_url(
+    logger: &Logger,
+    url: &str,
+    sha256: &str,
+    retry: &RetryPolicy,
+) -> Result<PathBuf, String> {
+    let sha256 = sha256.to_lowercase();
+
+    if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("{} is not a SHA-256 digest", sha256));
+    }
+
+    let path = crate::cache::download_path(&sha256)?;
+
+    if path.exists() && crate::inventory::sha256_file(&path)? == sha256 {
+        crate::cache::touch(&path);
+        return Ok(path);
+    }
+
+    let dir = path.parent().unwrap();
+    std::fs::create_dir_all(dir)
+        .map_err(|e| format!("unable to create {}: {}", dir.display(), e))?;
+
+    // Download to a temporary file in the same directory so the final
+    // rename is atomic and interrupted downloads never populate the cache.
+    let (temp, _) = retry.retry(logger, &format!("download of {}", url), || {
+        download(url, dir, Some(&sha256))
+    })?;
+
+    persist(temp, &path)?;
+
+    Ok(path)
+}
+
+/// Download a URL whose digest isn't known in advance.
+///
+/// Returns the path of the file in the cache and its SHA-256 digest. The
+/// URL is always downloaded, since its content may have changed.
+pub fn fetch_url_unpinned(
+    logger: &Logger,
+    url: &str,
+    retry: &RetryPolicy,
+) -> Result<(PathBuf, String), String> {
+    let dir = crate::cache::download_dir()?;
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| format!("unable to create {}: {}", dir.display(), e))?;
+
+    let (temp, sha256) = retry.retry(logger, &format!("download of {}", url), || {
+        download(url, &dir, None)
+    })?;
+
+    let path = crate::cache::download_path(&sha256)?;
+    persist(temp, &path)?;
+
+    Ok((path, sha256))
+}
+
+/// Move a downloaded temporary file into the cache.
+fn persist(temp: tempfile::NamedTempFile, path: &Path) -> Result<(), String> {
+    // Temporary files are only readable by their owner.
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        temp.as_file()
+            .set_permissions(std::fs::Permissions::from_mode(0o644))
+            .map_err(|e| format!("unable to set permissions: {}", e))?;
+    }
+
+    temp.persist(path)
+        .map_err(|e| format!("unable to write {}: {}", path.display(), e))?;
+
+    Ok(())
+}
+
+/// Download a URL to a temporary file in `dir`.
+///
+/// If `sha256` is defined, the content must have that digest. Returns the
+/// file and the digest of its content.
+fn download(
+    url: &str,
+    dir: &Path,
+    sha256: Option<&str>,
+) -> Result<(tempfile::NamedTempFile, String), AttemptError> {
+    let mut temp = tempfile::NamedTempFile::new_in(dir)
+        .map_err(|e| format!("unable to create temp file: {}", e))?;
+
+    let mut reader = ureq::get(url)
+        .call()
+        .map_err(|e| {
+            let e = AttemptError::http(e);
+
+            AttemptError {
+                message: format!("error fetching {}: {}", url, e.message),
+                ..e
+            }
+        })?
+        .into_reader();
+
+    let mut hasher = Sha256::new();
+    let mut buffer = [0; 32768];
+
+    loop {
+        let count = reader.read(&mut buffer).map_err(|e| AttemptError {
+            class: Some(FailureClass::Network),
+            message: format!("error reading {}: {}", url, e),
+        })?;
+
+        if count == 0 {
+            break;
+        }
+
+        hasher.input(&buffer[0..count]);
+        temp.write_all(&buffer[0..count])
+            .map_err(|e| format!("unable to write temp file: {}", e))?;
+    }
+
+    let actual = format!("{:x}", hasher.result());
+
+    match sha256 {
+        Some(sha256) if actual != sha256 => Err(format!(
+            "digest mismatch for {}: expected {}; got {}",
+            url, sha256, actual
+        )
+        .into()),
+        _ => Ok((temp, actual)),
+    }
+}
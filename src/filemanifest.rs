@@ -2,23 +2,807 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use std::collections::BTreeMap;
+use crate::mode_policy::ModePolicy;
+use is_executable::IsExecutable;
+use serde::{Serialize, Serializer};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Read;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-pub type FileManifest = BTreeMap<String, PathBuf>;
+/// Represents the content of a file in a `FileManifest`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FileContent {
+    /// Content is read from a file on the filesystem.
+    Path(PathBuf),
+
+    /// Content is held in memory.
+    Data { data: Vec<u8>, executable: bool },
+
+    /// A symbolic link to a target path.
+    ///
+    /// Relative targets are relative to the directory containing the link.
+    Symlink(PathBuf),
+}
+
+impl FileContent {
+    /// Obtain the content of the file.
+    pub fn read(&self) -> Result<Vec<u8>, String> {
+        match self {
+            FileContent::Path(path) => std::fs::read(path)
+                .map_err(|e| format!("error reading file {}: {}", path.display(), e)),
+            FileContent::Data { data, .. } => Ok(data.clone()),
+            FileContent::Symlink(target) => Err(format!(
+                "unable to read symlink to {} as a file",
+                target.display()
+            )),
+        }
+    }
+
+    /// Obtain a reader of the content of the file.
+    ///
+    /// Unlike `read()`, this doesn't hold the entire content in memory.
+    pub fn open(&self) -> Result<Box<dyn Read + '_>, String> {
+        match self {
+            FileContent::Path(path) => {
+                Ok(Box::new(std::fs::File::open(path).map_err(|e| {
+                    format!("error opening file {}: {}", path.display(), e)
+                })?))
+            }
+            FileContent::Data { data, .. } => Ok(Box::new(data.as_slice())),
+            FileContent::Symlink(target) => Err(format!(
+                "unable to read symlink to {} as a file",
+                target.display()
+            )),
+        }
+    }
+
+    /// Obtain the size of the file in bytes.
+    ///
+    /// Symlinks have no content and a size of 0.
+    pub fn size(&self) -> Result<u64, String> {
+        match self {
+            FileContent::Path(path) => std::fs::metadata(path)
+                .map(|m| m.len())
+                .map_err(|e| format!("error reading file {}: {}", path.display(), e)),
+            FileContent::Data { data, .. } => Ok(data.len() as u64),
+            FileContent::Symlink(_) => Ok(0),
+        }
+    }
+
+    /// Whether the file should be marked executable.
+    pub fn is_executable(&self) -> bool {
+        match self {
+            FileContent::Path(path) => path.is_executable(),
+            FileContent::Data { executable, .. } => *executable,
+            FileContent::Symlink(_) => false,
+        }
+    }
+
+    /// Describe where content comes from, for use in messages.
+    pub fn describe(&self) -> String {
+        match self {
+            FileContent::Path(path) => path.display().to_string(),
+            FileContent::Data { data, .. } => format!("<{} bytes in memory>", data.len()),
+            FileContent::Symlink(target) => format!("<symlink to {}>", target.display()),
+        }
+    }
+
+    /// Detect the type of the file from its content.
+    ///
+    /// Symlinks have no type.
+    pub fn file_type(&self) -> Result<Option<FileType>, String> {
+        if let FileContent::Symlink(_) = self {
+            return Ok(None);
+        }
+
+        let mut header = Vec::with_capacity(FILE_TYPE_SAMPLE_SIZE);
+        self.open()?
+            .take(FILE_TYPE_SAMPLE_SIZE as u64)
+            .read_to_end(&mut header)
+            .map_err(|e| format!("error reading {}: {}", self.describe(), e))?;
+
+        Ok(Some(FileType::detect(&header)))
+    }
+}
+
+impl From<PathBuf> for FileContent {
+    fn from(path: PathBuf) -> Self {
+        FileContent::Path(path)
+    }
+}
+
+impl Serialize for FileContent {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            FileContent::Path(path) => path.serialize(serializer),
+            FileContent::Data { .. } | FileContent::Symlink(_) => {
+                serializer.serialize_str(&self.describe())
+            }
+        }
+    }
+}
+
+pub type FileManifest = BTreeMap<String, FileContent>;
+
+/// Obtain the manifest key of a relative path.
+///
+/// Keys always use `/` as the separator, regardless of platform, as they
+/// become names of archive members. Current directory components are
+/// dropped. Absolute paths and paths with `..` components are rejected,
+/// as installing them could write outside the installation directory.
+pub fn manifest_key(path: &Path) -> Result<String, String> {
+    let mut components = Vec::new();
+
+    for component in path.components() {
+        match component {
+            std::path::Component::Normal(name) => components.push(name.to_string_lossy()),
+            std::path::Component::CurDir => {}
+            _ => {
+                return Err(format!(
+                    "{} is not a relative path without .. components",
+                    path.display()
+                ))
+            }
+        }
+    }
+
+    Ok(components.join("/"))
+}
+
+/// Ensure a manifest key refers to a path inside the directory it is
+/// installed to.
+pub fn validate_key(key: &str) -> Result<(), String> {
+    let safe = !key.starts_with('/')
+        && key_path(key).components().all(|c| {
+            matches!(
+                c,
+                std::path::Component::Normal(_) | std::path::Component::CurDir
+            )
+        });
+
+    if safe {
+        Ok(())
+    } else {
+        Err(format!(
+            "manifest path {} is not a relative path without .. components",
+            key
+        ))
+    }
+}
+
+/// Obtain the relative filesystem path of a manifest key.
+pub fn key_path(key: &str) -> PathBuf {
+    key.split('/').filter(|c| !c.is_empty()).collect()
+}
+
+/// Number of bytes at the start of a file used to detect its type.
+const FILE_TYPE_SAMPLE_SIZE: usize = 8192;
+
+/// Type of a file, detected from its content.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileType {
+    /// An ELF binary, e.g. a Linux executable or shared library.
+    Elf,
+
+    /// A PE binary, e.g. a Windows executable or DLL.
+    Pe,
+
+    /// A Mach-O binary, including universal binaries.
+    MachO,
+
+    /// A script starting with a `#!` line.
+    Script,
+
+    /// UTF-8 text.
+    Text,
+
+    /// Anything else.
+    Binary,
+}
+
+impl FileType {
+    /// Detect the type of a file from the bytes it starts with.
+    pub fn detect(header: &[u8]) -> Self {
+        if header.starts_with(b"#!") {
+            return FileType::Script;
+        }
+
+        if let Some(magic) = header.get(0..16) {
+            let mut bytes = [0u8; 16];
+            bytes.copy_from_slice(magic);
+
+            match goblin::peek_bytes(&bytes) {
+                Ok(goblin::Hint::Elf(_)) => return FileType::Elf,
+                Ok(goblin::Hint::PE) => return FileType::Pe,
+                Ok(goblin::Hint::Mach(_)) | Ok(goblin::Hint::MachFat(_)) => return FileType::MachO,
+                _ => {}
+            }
+        }
+
+        // A multi-byte character may be cut off at the end of the sample.
+        let is_utf8 = match std::str::from_utf8(header) {
+            Ok(_) => true,
+            Err(e) => e.error_len().is_none(),
+        };
+
+        if is_utf8 && !header.contains(&0) {
+            FileType::Text
+        } else {
+            FileType::Binary
+        }
+    }
+}
+
+impl FromStr for FileType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "elf" => Ok(FileType::Elf),
+            "pe" => Ok(FileType::Pe),
+            "macho" => Ok(FileType::MachO),
+            "script" => Ok(FileType::Script),
+            "text" => Ok(FileType::Text),
+            "binary" => Ok(FileType::Binary),
+            _ => Err(format!(
+                "unknown file type {}; expected elf, pe, macho, script, text, or binary",
+                s
+            )),
+        }
+    }
+}
+
+/// Predicates on the content of files in a manifest.
+///
+/// A file must satisfy every defined predicate to match.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct FileFilter {
+    /// Types of matching files. Files of any type match if empty.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub types: Vec<FileType>,
+
+    /// Minimum size of matching files in bytes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_size: Option<u64>,
+
+    /// Maximum size of matching files in bytes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_size: Option<u64>,
+
+    /// Whether matching files are executable.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub executable: Option<bool>,
+}
+
+impl FileFilter {
+    /// Whether a file satisfies the filter.
+    pub fn matches(&self, content: &FileContent) -> Result<bool, String> {
+        if let Some(executable) = self.executable {
+            if content.is_executable() != executable {
+                return Ok(false);
+            }
+        }
+
+        if self.min_size.is_some() || self.max_size.is_some() {
+            let size = content.size()?;
+
+            if self.min_size.map(|min| size < min).unwrap_or(false)
+                || self.max_size.map(|max| size > max).unwrap_or(false)
+            {
+                return Ok(false);
+            }
+        }
+
+        if !self.types.is_empty() {
+            return Ok(match content.file_type()? {
+                Some(file_type) => self.types.contains(&file_type),
+                None => false,
+            });
+        }
+
+        Ok(true)
+    }
+}
+
+/// Obtain the files of a manifest satisfying a filter.
+pub fn filter_files(files: &FileManifest, filter: &FileFilter) -> Result<FileManifest, String> {
+    let mut res = FileManifest::new();
+
+    for (key, content) in files {
+        if filter.matches(content)? {
+            res.insert(key.clone(), content.clone());
+        }
+    }
+
+    Ok(res)
+}
+
+/// Obtain a `FileManifest` of the files in a directory.
+///
+/// Directories named in `exclude_dirs` are skipped. An optional `prefix`
+/// is prepended to paths in the manifest. Symlinks are recorded as
+/// symlinks rather than followed.
+pub fn manifest_from_dir(
+    dir: &Path,
+    exclude_dirs: &[&str],
+    prefix: Option<&str>,
+) -> Result<FileManifest, String> {
+    let mut manifest = FileManifest::new();
+
+    let walk = walkdir::WalkDir::new(dir)
+        .into_iter()
+        .filter_entry(|entry| {
+            !(entry.file_type().is_dir()
+                && exclude_dirs
+                    .iter()
+                    .any(|name| entry.file_name() == std::ffi::OsStr::new(name)))
+        });
+
+    for entry in walk {
+        let entry = entry.map_err(|e| format!("unable to walk {}: {}", dir.display(), e))?;
+
+        let content =
+            if entry.file_type().is_file() {
+                FileContent::Path(entry.path().to_path_buf())
+            } else if entry.file_type().is_symlink() {
+                FileContent::Symlink(std::fs::read_link(entry.path()).map_err(|e| {
+                    format!("unable to read symlink {}: {}", entry.path().display(), e)
+                })?)
+            } else {
+                continue;
+            };
+
+        let rel_path = entry.path().strip_prefix(dir).map_err(|e| e.to_string())?;
+
+        let rel_path = match prefix {
+            Some(prefix) => PathBuf::from(prefix).join(rel_path),
+            None => rel_path.to_path_buf(),
+        };
+
+        manifest.insert(manifest_key(&rel_path)?, content);
+    }
+
+    Ok(manifest)
+}
+
+/// How files on the filesystem are installed by `install_files()`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InstallMode {
+    /// Copy file content.
+    Copy,
+
+    /// Create hard links to source files.
+    ///
+    /// Installed files share content and permissions with their sources,
+    /// so modifying an installed file modifies its source.
+    Hardlink,
+
+    /// Create copy-on-write clones of source files.
+    ///
+    /// Only supported by some Linux filesystems, such as Btrfs and XFS.
+    Reflink,
+}
+
+impl FromStr for InstallMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "copy" => Ok(InstallMode::Copy),
+            "hardlink" => Ok(InstallMode::Hardlink),
+            "reflink" => Ok(InstallMode::Reflink),
+            _ => Err(format!(
+                "unknown install mode {}; expected copy, hardlink, or reflink",
+                s
+            )),
+        }
+    }
+}
+
+/// Clone a file using the `FICLONE` ioctl.
+#[cfg(target_os = "linux")]
+fn reflink(source: &Path, dest: &Path) -> std::io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let source_fh = std::fs::File::open(source)?;
+    let dest_fh = std::fs::File::create(dest)?;
+
+    if unsafe { libc::ioctl(dest_fh.as_raw_fd(), libc::FICLONE, source_fh.as_raw_fd()) } != 0 {
+        let err = std::io::Error::last_os_error();
+        drop(dest_fh);
+        let _ = std::fs::remove_file(dest);
+        return Err(err);
+    }
+
+    dest_fh.set_permissions(source_fh.metadata()?.permissions())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn reflink(_source: &Path, _dest: &Path) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Other,
+        "reflinks are not supported on this platform",
+    ))
+}
+
+/// Install a file on the filesystem to a destination path.
+///
+/// Hard links and reflinks fall back to copying when they can't be
+/// created, e.g. when source and destination are on different
+/// filesystems.
+///
+/// Returns whether the file was hard linked.
+fn install_path(source: &Path, dest: &Path, mode: InstallMode) -> std::io::Result<bool> {
+    let (linked, hard_linked) = match mode {
+        InstallMode::Copy => (false, false),
+        InstallMode::Hardlink => {
+            let linked = std::fs::hard_link(source, dest).is_ok();
+            (linked, linked)
+        }
+        InstallMode::Reflink => (reflink(source, dest).is_ok(), false),
+    };
+
+    if !linked {
+        std::fs::copy(source, dest)?;
+    }
+
+    Ok(hard_linked)
+}
 
 /// Install files in a files manifest to a destination directory.
-pub fn install_files(dest_dir: &Path, files: &FileManifest) {
-    for (key, source_path) in files.iter() {
-        let rel_path = PathBuf::from(key);
-        let rel_dir = rel_path.parent().unwrap();
+///
+/// `mode` controls how files on the filesystem are installed. Files and
+/// the directories containing them are given the permissions of `modes`,
+/// except for hard links, which share permissions with their source.
+pub fn install_files(
+    dest_dir: &Path,
+    files: &FileManifest,
+    mode: InstallMode,
+    modes: &ModePolicy,
+) -> Result<(), String> {
+    for (key, content) in files.iter() {
+        validate_key(key)?;
+        let dest_path = dest_dir.join(key_path(key));
+
+        if let Some(d) = dest_path.parent() {
+            if !d.exists() {
+                std::fs::create_dir_all(d)
+                    .map_err(|e| format!("unable to create directory {}: {}", d.display(), e))?;
+            }
+        }
+
+        // An existing file may be a hard link from a previous install, so
+        // it must be replaced rather than written to.
+        if std::fs::symlink_metadata(&dest_path).is_ok() {
+            std::fs::remove_file(&dest_path)
+                .map_err(|e| format!("unable to remove {}: {}", dest_path.display(), e))?;
+        }
+
+        let hard_linked = match content {
+            FileContent::Path(source_path) => {
+                install_path(source_path, &dest_path, mode).map_err(|e| {
+                    format!(
+                        "unable to install {} to {}: {}",
+                        source_path.display(),
+                        dest_path.display(),
+                        e
+                    )
+                })?
+            }
+            FileContent::Data { data, .. } => {
+                std::fs::write(&dest_path, data)
+                    .map_err(|e| format!("unable to write {}: {}", dest_path.display(), e))?;
+
+                false
+            }
+            FileContent::Symlink(target) => {
+                create_symlink(target, &dest_path).map_err(|e| {
+                    format!("unable to create symlink {}: {}", dest_path.display(), e)
+                })?;
+
+                continue;
+            }
+        };
+
+        if !hard_linked {
+            set_mode(&dest_path, modes.file_mode(content))?;
+        }
+    }
+
+    // Directories are handled last, in case their mode doesn't allow
+    // writing to them.
+    for dir in manifest_dirs(files) {
+        set_mode(&dest_dir.join(key_path(&dir)), modes.dir_mode())?;
+    }
+
+    Ok(())
+}
+
+/// Obtain the directories containing the files of a manifest.
+///
+/// Parents sort before the directories they contain.
+pub fn manifest_dirs(files: &FileManifest) -> BTreeSet<String> {
+    parent_dirs(files.keys().map(|key| key.as_str()))
+}
+
+/// Obtain the directories containing `/` separated paths.
+pub fn parent_dirs<'a>(paths: impl IntoIterator<Item = &'a str>) -> BTreeSet<String> {
+    let mut dirs = BTreeSet::new();
+
+    for path in paths {
+        let mut dir = path;
+
+        while let Some(pos) = dir.rfind('/') {
+            dir = &dir[0..pos];
+
+            if !dirs.insert(dir.to_string()) {
+                break;
+            }
+        }
+    }
+
+    dirs
+}
+
+#[cfg(unix)]
+fn create_symlink(target: &Path, path: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, path)
+}
+
+#[cfg(not(unix))]
+fn create_symlink(_target: &Path, _path: &Path) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Other,
+        "symlinks are not supported on this platform",
+    ))
+}
+
+/// Maximum number of symlinks followed when resolving a path, like the kernel.
+const MAX_SYMLINK_DEPTH: usize = 40;
+
+/// Replace symlinks in a manifest with the content they refer to.
+///
+/// Targets are resolved within the manifest, following chains of links.
+/// Symlinks to directories are replaced by copies of the directory's
+/// entries. It is an error for a symlink to refer to something that isn't
+/// in the manifest.
+pub fn dereference_symlinks(files: &FileManifest) -> Result<FileManifest, String> {
+    let mut files = files.clone();
+
+    // Copied directories may themselves contain symlinks to directories.
+    let mut expanded = true;
+    for _ in 0..MAX_SYMLINK_DEPTH {
+        if !expanded {
+            break;
+        }
+        expanded = false;
+
+        let links = files
+            .iter()
+            .filter_map(|(key, content)| match content {
+                FileContent::Symlink(target) => Some((key.clone(), target.clone())),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        for (key, target) in links {
+            let dir = resolve_link_key(&key, &target).ok_or_else(|| {
+                format!(
+                    "symlink {} refers to {}, which is outside the manifest",
+                    key,
+                    target.display()
+                )
+            })?;
+
+            if files.contains_key(&dir) {
+                continue;
+            }
+
+            let dir_prefix = format!("{}/", dir);
+            let children = files
+                .range(dir_prefix.clone()..)
+                .take_while(|(k, _)| k.starts_with(&dir_prefix))
+                .map(|(k, content)| {
+                    (
+                        format!("{}/{}", key, &k[dir_prefix.len()..]),
+                        content.clone(),
+                    )
+                })
+                .collect::<Vec<_>>();
+
+            if children.is_empty() {
+                return Err(format!(
+                    "symlink {} refers to {}, which isn't in the manifest",
+                    key, dir
+                ));
+            }
+
+            files.remove(&key);
+            files.extend(children);
+            expanded = true;
+        }
+    }
+
+    if expanded {
+        return Err("too many levels of symlinks to directories".to_string());
+    }
+
+    let mut res = FileManifest::new();
+
+    for (key, content) in &files {
+        let mut current = key.clone();
+        let mut resolved = content;
+
+        for _ in 0..MAX_SYMLINK_DEPTH {
+            let target = match resolved {
+                FileContent::Symlink(target) => target,
+                _ => break,
+            };
+
+            current = resolve_link_key(&current, target).ok_or_else(|| {
+                format!(
+                    "symlink {} refers to {}, which is outside the manifest",
+                    key,
+                    target.display()
+                )
+            })?;
+            resolved = files.get(&current).ok_or_else(|| {
+                format!(
+                    "symlink {} refers to {}, which isn't in the manifest",
+                    key, current
+                )
+            })?;
+        }
 
-        let d = dest_dir.join(rel_dir);
-        if !d.exists() {
-            std::fs::create_dir_all(&d).expect("unable to create directory");
+        if let FileContent::Symlink(_) = resolved {
+            return Err(format!("too many levels of symlinks resolving {}", key));
         }
 
-        let dest_path = dest_dir.join(rel_path);
-        std::fs::copy(&source_path, &dest_path).expect("unable to copy file");
+        res.insert(key.clone(), resolved.clone());
+    }
+
+    Ok(res)
+}
+
+/// Resolve the target of a symlink at manifest key `key` to a manifest key.
+///
+/// Returns `None` if the target is absolute or outside the manifest.
+fn resolve_link_key(key: &str, target: &Path) -> Option<String> {
+    if target.is_absolute() {
+        return None;
+    }
+
+    let mut components = key.split('/').map(String::from).collect::<Vec<_>>();
+    components.pop();
+
+    for component in target.components() {
+        match component {
+            std::path::Component::Normal(name) => {
+                components.push(name.to_string_lossy().to_string())
+            }
+            std::path::Component::ParentDir => {
+                components.pop()?;
+            }
+            std::path::Component::CurDir => {}
+            _ => return None,
+        }
+    }
+
+    Some(components.join("/"))
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> Result<(), String> {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+        .map_err(|e| format!("unable to set permissions of {}: {}", path.display(), e))
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> Result<(), String> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(path: &str) -> String {
+        manifest_key(Path::new(path)).unwrap()
+    }
+
+    #[test]
+    fn test_manifest_key() {
+        assert_eq!(key("usr/bin/tool"), "usr/bin/tool");
+        assert_eq!(key("./usr/./bin/"), "usr/bin");
+        assert_eq!(key("."), "");
+    }
+
+    #[test]
+    fn test_manifest_key_unsafe() {
+        for path in &["/usr/bin", "a/../b", "../b", "a/.."] {
+            assert!(manifest_key(Path::new(path)).is_err(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn test_validate_key() {
+        for key in &["tool", "usr/bin/tool", "./a/b", "a b/c.d", "a..b/..c"] {
+            assert!(validate_key(key).is_ok(), "{}", key);
+        }
+
+        for key in &["/usr/bin", "a/../b", "../b", ".."] {
+            assert!(validate_key(key).is_err(), "{}", key);
+        }
+    }
+
+    #[test]
+    fn test_install_files_unsafe_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("install");
+
+        let mut files = FileManifest::new();
+        files.insert(
+            "../escape".to_string(),
+            FileContent::Data {
+                data: b"escaped".to_vec(),
+                executable: false,
+            },
+        );
+
+        let err =
+            install_files(&dest, &files, InstallMode::Copy, &ModePolicy::default()).unwrap_err();
+        assert!(err.contains("../escape"), "{}", err);
+        assert!(!dir.path().join("escape").exists());
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn test_manifest_key_backslashes() {
+        assert_eq!(key(r"usr\bin\tool.exe"), "usr/bin/tool.exe");
+        assert_eq!(key(r".\usr\.\bin"), "usr/bin");
+        assert!(manifest_key(Path::new(r"C:\usr\bin")).is_err());
+        assert!(manifest_key(Path::new(r"usr\..\bin")).is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_manifest_key_backslashes() {
+        // Backslashes are valid in filenames on Unix.
+        assert_eq!(key(r"usr\bin"), r"usr\bin");
+        assert_eq!(key(r"a\b/c"), r"a\b/c");
+        assert_eq!(key(r"a\..\b"), r"a\..\b");
+    }
+
+    #[test]
+    fn test_key_path() {
+        assert_eq!(
+            key_path("usr/bin/tool"),
+            Path::new("usr").join("bin").join("tool")
+        );
+        assert_eq!(key_path("usr//bin/"), Path::new("usr").join("bin"));
+        assert_eq!(key_path("/usr/bin"), Path::new("usr").join("bin"));
+        assert_eq!(key_path("a/../b"), Path::new("a").join("..").join("b"));
+    }
+
+    #[test]
+    fn test_key_round_trip() {
+        for k in &["tool", "usr/bin/tool", "a b/c.d"] {
+            assert_eq!(&key(key_path(k).to_str().unwrap()), k);
+        }
+
+        // Redundant separators and current directory components are
+        // normalized away.
+        for (k, normalized) in &[("usr//bin/", "usr/bin"), ("./a/./b", "a/b")] {
+            assert_eq!(&key(key_path(k).to_str().unwrap()), normalized);
+        }
+
+        for path in &["usr/bin/tool", "a b/c.d"] {
+            assert_eq!(key_path(&key(path)), Path::new(path));
+        }
     }
 }
@@ -0,0 +1,78 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Checking out Git repositories.
+
+Checkouts are performed with the `git` command-line tool, as it supports
+shallow fetches of arbitrary revisions.
+*/
+
+use crate::filemanifest::FileManifest;
+use std::path::Path;
+use std::process::Command;
+
+fn run_git(dest: &Path, args: &[&str]) -> Result<String, String> {
+    crate::process::record_program("git");
+
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dest)
+        .args(args)
+        .output()
+        .map_err(|e| format!("error running git: {}", e))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Check out a revision of a remote repository into a directory.
+///
+/// `rev` can be a branch, tag, or commit. If `dest` is already a checkout,
+/// it is updated in place. If `shallow` is true, only the requested
+/// revision is fetched.
+///
+/// Returns the ID of the checked out commit.
+pub fn checkout(url: &str, rev: &str, dest: &Path, shallow: bool) -> Result<String, String> {
+    if !dest.join(".git").exists() {
+        std::fs::create_dir_all(dest)
+            .map_err(|e| format!("unable to create {}: {}", dest.display(), e))?;
+        run_git(dest, &["init", "--quiet"])?;
+    }
+
+    // Point the remote at the requested URL even if the checkout was
+    // originally created from another one.
+    if run_git(dest, &["remote", "set-url", "origin", url]).is_err() {
+        run_git(dest, &["remote", "add", "origin", url])?;
+    }
+
+    let mut fetch = vec!["fetch", "--quiet", "--tags"];
+    if shallow {
+        fetch.push("--depth=1");
+    }
+    fetch.push("origin");
+    fetch.push(rev);
+
+    run_git(dest, &fetch)?;
+    run_git(
+        dest,
+        &["checkout", "--quiet", "--force", "--detach", "FETCH_HEAD"],
+    )?;
+
+    run_git(dest, &["rev-parse", "HEAD"])
+}
+
+/// Obtain a `FileManifest` of the files in a checkout.
+///
+/// The `.git` directory is excluded.
+pub fn checkout_manifest(dest: &Path) -> Result<FileManifest, String> {
+    crate::filemanifest::manifest_from_dir(dest, &[".git"], None)
+}
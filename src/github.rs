@@ -0,0 +1,191 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Uploading artifacts to GitHub releases.
+
+Artifacts are uploaded as release assets using the GitHub REST API. The
+release is created if it doesn't exist yet.
+*/
+
+use crate::retry::{AttemptError, FailureClass, RetryPolicy};
+use slog::{warn, Logger};
+use std::path::PathBuf;
+
+/// Default base URL of the GitHub API.
+pub const GITHUB_API_URL: &str = "https://api.github.com";
+
+/// A GitHub release.
+struct Release {
+    id: u64,
+
+    /// URL assets are uploaded to, without its URI template suffix.
+    upload_url: String,
+
+    /// Names and IDs of existing assets.
+    assets: Vec<(String, u64)>,
+}
+
+impl Release {
+    fn from_json(value: &serde_json::Value) -> Result<Self, String> {
+        let id = value["id"]
+            .as_u64()
+            .ok_or_else(|| "GitHub release has no id".to_string())?;
+        let upload_url = value["upload_url"]
+            .as_str()
+            .ok_or_else(|| "GitHub release has no upload_url".to_string())?;
+
+        // The URL is a URI template like `.../assets{?name,label}`.
+        let upload_url = match upload_url.find('{') {
+            Some(pos) => &upload_url[..pos],
+            None => upload_url,
+        };
+
+        let assets = value["assets"]
+            .as_array()
+            .unwrap_or(&vec![])
+            .iter()
+            .filter_map(|asset| Some((asset["name"].as_str()?.to_string(), asset["id"].as_u64()?)))
+            .collect();
+
+        Ok(Release {
+            id,
+            upload_url: upload_url.to_string(),
+            assets,
+        })
+    }
+}
+
+fn request(method: &str, url: &str, token: &str) -> ureq::Request {
+    ureq::request(method, url)
+        .set("Accept", "application/vnd.github+json")
+        .set("Authorization", &format!("Bearer {}", token))
+        .set("X-GitHub-Api-Version", "2022-11-28")
+}
+
+fn read_json(response: ureq::Response) -> Result<serde_json::Value, AttemptError> {
+    let body = response.into_string().map_err(|e| AttemptError {
+        class: Some(FailureClass::Network),
+        message: format!("error reading GitHub response: {}", e),
+    })?;
+
+    Ok(serde_json::from_str(&body)
+        .map_err(|e| format!("unable to parse GitHub response: {}", e))?)
+}
+
+/// Obtain the release of a tag, creating it if it doesn't exist.
+fn get_or_create_release(
+    logger: &Logger,
+    api_url: &str,
+    repo: &str,
+    tag: &str,
+    token: &str,
+    retry: &RetryPolicy,
+) -> Result<Release, String> {
+    let url = format!(
+        "{}/repos/{}/releases/tags/{}",
+        api_url,
+        repo,
+        crate::http::percent_encode(tag)
+    );
+
+    let existing = retry.retry(logger, "GitHub release lookup", || {
+        match request("GET", &url, token).call() {
+            Ok(response) => Ok(Some(read_json(response)?)),
+            Err(ureq::Error::Status(404, _)) => Ok(None),
+            Err(e) => Err(AttemptError::http(e)),
+        }
+    })?;
+
+    let value = match existing {
+        Some(value) => value,
+        None => {
+            warn!(logger, "creating GitHub release {} of {}", tag, repo);
+
+            let url = format!("{}/repos/{}/releases", api_url, repo);
+            let body = serde_json::json!({ "tag_name": tag, "name": tag });
+
+            // Creating a release isn't idempotent, so it isn't retried.
+            let response = request("POST", &url, token)
+                .set("Content-Type", "application/json")
+                .send_string(&body.to_string())
+                .map_err(|e| {
+                    format!(
+                        "unable to create GitHub release {}: {}",
+                        tag,
+                        crate::http::error_message(e)
+                    )
+                })?;
+
+            read_json(response).map_err(|e| e.message)?
+        }
+    };
+
+    Release::from_json(&value)
+}
+
+/// Upload artifacts as assets of the GitHub release of a tag.
+///
+/// `repo` is of the form `owner/repo`. The release is created if it
+/// doesn't exist. Existing assets with the same name as an artifact are
+/// replaced. Up to `concurrency` assets are uploaded at once. Failed
+/// requests are retried according to `retry`.
+#[allow(clippy::too_many_arguments)]
+pub fn github_release_upload(
+    logger: &Logger,
+    api_url: &str,
+    repo: &str,
+    tag: &str,
+    artifacts: &[PathBuf],
+    token: &str,
+    concurrency: usize,
+    retry: &RetryPolicy,
+) -> Result<(), String> {
+    let api_url = api_url.trim_end_matches('/');
+    let release = get_or_create_release(logger, api_url, repo, tag, token, retry)?;
+
+    crate::upload::upload_concurrently(logger, artifacts, concurrency, |artifact| {
+        let filename = artifact
+            .file_name()
+            .map(|f| f.to_string_lossy().to_string())
+            .ok_or_else(|| format!("{} has no filename", artifact.display()))?;
+        let data = std::fs::read(artifact)
+            .map_err(|e| format!("unable to read {}: {}", artifact.display(), e))?;
+
+        if let Some((_, id)) = release.assets.iter().find(|(name, _)| name == &filename) {
+            warn!(logger, "replacing existing asset {}", filename);
+
+            let url = format!("{}/repos/{}/releases/assets/{}", api_url, repo, id);
+
+            retry.retry(
+                logger,
+                &format!("deletion of asset {}", filename),
+                || match request("DELETE", &url, token).call() {
+                    Ok(_) | Err(ureq::Error::Status(404, _)) => Ok(()),
+                    Err(e) => Err(AttemptError::http(e)),
+                },
+            )?;
+        }
+
+        warn!(
+            logger,
+            "uploading {} to GitHub release {} of {} (release {})", filename, tag, repo, release.id
+        );
+
+        let url = format!(
+            "{}?name={}",
+            release.upload_url,
+            crate::http::percent_encode(&filename)
+        );
+
+        retry.retry(logger, &format!("upload of {}", filename), || {
+            request("POST", &url, token)
+                .set("Content-Type", "application/octet-stream")
+                .send_bytes(&data)
+                .map_err(AttemptError::http)
+        })?;
+
+        Ok(())
+    })
+}
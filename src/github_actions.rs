@@ -0,0 +1,191 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Integration with GitHub Actions.
+
+GitHub Actions interprets
+[workflow commands](https://docs.github.com/en/actions/using-workflows/workflow-commands-for-github-actions)
+printed by a job: `::group::` and `::endgroup::` fold the log output
+between them, and `::error` creates an annotation shown on the workflow
+run and, given a file and line, in the diff of a pull request. Outputs
+of a job step are written to the file named by `GITHUB_OUTPUT`, so later
+steps of a workflow can use them.
+*/
+
+use crate::error::SourceLocation;
+use crate::observer::ExecutionObserver;
+use crate::pipeline::{Pipeline, Step};
+use crate::report::{PipelineReport, StepReport};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Environment variable naming the file step outputs are written to.
+pub const GITHUB_OUTPUT: &str = "GITHUB_OUTPUT";
+
+/// Escape the message of a workflow command.
+fn escape_data(value: &str) -> String {
+    value
+        .replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Escape a property value of a workflow command.
+fn escape_property(value: &str) -> String {
+    escape_data(value).replace(':', "%3A").replace(',', "%2C")
+}
+
+/// Obtain an `::error` command annotating a message.
+///
+/// If a location is known, the annotation is attached to that line of
+/// the file.
+pub fn error_command(title: &str, message: &str, location: Option<&SourceLocation>) -> String {
+    let mut properties = vec![format!("title={}", escape_property(title))];
+
+    if let Some(location) = location {
+        properties.push(format!(
+            "file={}",
+            escape_property(&location.path.display().to_string())
+        ));
+        properties.push(format!("line={}", location.line));
+        properties.push(format!("col={}", location.column));
+    }
+
+    format!(
+        "::error {}::{}",
+        properties.join(","),
+        escape_data(&crate::secrets::redact(message))
+    )
+}
+
+/// Obtain the name of the output listing the artifacts of a pipeline.
+///
+/// Characters output names can't contain are replaced by `_`.
+pub fn artifacts_output_name(pipeline: &str) -> String {
+    let name: String = pipeline
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    format!("{}-artifacts", name)
+}
+
+/// Append a multi-line output to a `GITHUB_OUTPUT` file.
+fn write_output(path: &Path, name: &str, lines: &[String]) -> Result<(), String> {
+    // The delimiter must not occur in the value.
+    let mut delimiter = "TUGGER_EOF".to_string();
+    while lines.iter().any(|line| line == &delimiter) {
+        delimiter.push('_');
+    }
+
+    let mut fh = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("unable to open {}: {}", path.display(), e))?;
+
+    let mut data = format!("{}<<{}\n", name, delimiter);
+    for line in lines {
+        data.push_str(line);
+        data.push('\n');
+    }
+    data.push_str(&delimiter);
+    data.push('\n');
+
+    fh.write_all(data.as_bytes())
+        .map_err(|e| format!("unable to write {}: {}", path.display(), e))
+}
+
+/// An `ExecutionObserver` emitting workflow commands for GitHub Actions.
+///
+/// The output of each step is folded into a group, and failed steps are
+/// annotated. After each pipeline, the paths of its artifacts are set as
+/// the `<pipeline>-artifacts` output, and the artifacts of all pipelines
+/// so far as the `artifacts` output, one per line.
+pub struct GithubActionsObserver {
+    /// File step outputs are written to, if running in GitHub Actions.
+    output_path: Option<PathBuf>,
+
+    /// Artifacts of the executing pipeline.
+    pipeline_artifacts: Vec<String>,
+
+    /// Artifacts of all executed pipelines.
+    artifacts: Vec<String>,
+}
+
+impl GithubActionsObserver {
+    /// Construct an observer writing outputs to the file `GITHUB_OUTPUT` names.
+    pub fn from_env() -> Self {
+        GithubActionsObserver {
+            output_path: std::env::var_os(GITHUB_OUTPUT).map(PathBuf::from),
+            pipeline_artifacts: Vec::new(),
+            artifacts: Vec::new(),
+        }
+    }
+}
+
+impl ExecutionObserver for GithubActionsObserver {
+    fn on_pipeline_start(&mut self, _pipeline: &Pipeline) {
+        self.pipeline_artifacts.clear();
+    }
+
+    fn on_pipeline_end(&mut self, pipeline: &Pipeline, _report: &PipelineReport) {
+        let output_path = match &self.output_path {
+            Some(path) => path,
+            None => return,
+        };
+
+        let res = write_output(
+            output_path,
+            &artifacts_output_name(&pipeline.name),
+            &self.pipeline_artifacts,
+        )
+        .and_then(|_| write_output(output_path, "artifacts", &self.artifacts));
+
+        if let Err(e) = res {
+            println!("::warning::{}", escape_data(&e));
+        }
+    }
+
+    fn on_step_start(&mut self, pipeline: &Pipeline, index: usize, step: &Step) {
+        println!(
+            "::group::{}",
+            escape_data(&format!("{} / {}: {}", pipeline.name, index, step.kind()))
+        );
+    }
+
+    fn on_step_end(&mut self, pipeline: &Pipeline, report: &StepReport) {
+        println!("::endgroup::");
+
+        if let Some(error) = &report.error {
+            println!(
+                "{}",
+                error_command(
+                    &format!(
+                        "step {} ({}) of pipeline {} failed",
+                        report.index, report.kind, pipeline.name
+                    ),
+                    error,
+                    None
+                )
+            );
+        }
+    }
+
+    fn on_artifact(&mut self, _pipeline: &Pipeline, path: &Path) {
+        let path = path.display().to_string();
+
+        if !self.artifacts.contains(&path) {
+            self.artifacts.push(path.clone());
+        }
+        self.pipeline_artifacts.push(path);
+    }
+}
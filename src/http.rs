@@ -0,0 +1,33 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Helpers for performing HTTP requests.
+*/
+
+/// Convert an HTTP error into a message, including any response body.
+///
+/// The URL isn't included in messages because it may contain credentials.
+pub fn error_message(e: ureq::Error) -> String {
+    match e {
+        ureq::Error::Status(code, response) => format!(
+            "HTTP {}: {}",
+            code,
+            response.into_string().unwrap_or_default().trim()
+        ),
+        ureq::Error::Transport(e) => format!("HTTP request failed: {}", e),
+    }
+}
+
+/// Percent-encode a string for use in a URL component.
+pub fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
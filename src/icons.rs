@@ -0,0 +1,104 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Application icons.
+
+Icons are installed into the `hicolor` theme, which every freedesktop
+icon theme falls back to, as PNGs at several sizes in
+`usr/share/icons/hicolor/<size>x<size>/apps/`. SVG sources are also
+installed as scalable icons.
+
+PNG sources are resized in process. SVG sources are rasterized with
+`rsvg-convert`, from librsvg.
+*/
+
+use image::imageops::FilterType;
+use image::{GenericImageView, ImageFormat};
+use std::path::Path;
+use std::process::Command;
+
+/// Icon sizes installed by default.
+pub const DEFAULT_SIZES: &[u32] = &[16, 32, 48, 128, 256];
+
+/// Directory in a manifest containing the hicolor icon theme.
+pub const HICOLOR_DIR: &str = "usr/share/icons/hicolor";
+
+/// Obtain the manifest path of a PNG icon of the given size.
+pub fn png_path(name: &str, size: u32) -> String {
+    format!("{}/{}x{}/apps/{}.png", HICOLOR_DIR, size, size, name)
+}
+
+/// Obtain the manifest path of a scalable SVG icon.
+pub fn svg_path(name: &str) -> String {
+    format!("{}/scalable/apps/{}.svg", HICOLOR_DIR, name)
+}
+
+/// Whether an icon source is an SVG image.
+pub fn is_svg(source: &Path) -> bool {
+    source
+        .extension()
+        .map(|ext| ext.eq_ignore_ascii_case("svg") || ext.eq_ignore_ascii_case("svgz"))
+        .unwrap_or(false)
+}
+
+/// Render an icon source to a square PNG of the given size.
+pub fn render_png(source: &Path, size: u32) -> Result<Vec<u8>, String> {
+    if is_svg(source) {
+        rasterize_svg(source, size)
+    } else {
+        resize_png(source, size)
+    }
+}
+
+fn rasterize_svg(source: &Path, size: u32) -> Result<Vec<u8>, String> {
+    crate::process::record_program("rsvg-convert");
+
+    let output = Command::new("rsvg-convert")
+        .arg("--format=png")
+        .arg(format!("--width={}", size))
+        .arg(format!("--height={}", size))
+        .arg("--keep-aspect-ratio")
+        .arg(source)
+        .output()
+        .map_err(|e| format!("error running rsvg-convert: {}", e))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "rsvg-convert failed for {}: {}",
+            source.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(output.stdout)
+}
+
+fn resize_png(source: &Path, size: u32) -> Result<Vec<u8>, String> {
+    let image = image::open(source)
+        .map_err(|e| format!("unable to read icon {}: {}", source.display(), e))?;
+
+    let (width, height) = image.dimensions();
+    if width != height {
+        return Err(format!(
+            "icon {} must be square; got {}x{}",
+            source.display(),
+            width,
+            height
+        ));
+    }
+
+    let resized = if width == size {
+        image
+    } else {
+        image.resize_exact(size, size, FilterType::Lanczos3)
+    };
+
+    let mut data = Vec::new();
+    resized
+        .write_to(&mut std::io::Cursor::new(&mut data), ImageFormat::Png)
+        .map_err(|e| format!("unable to encode icon: {}", e))?;
+
+    Ok(data)
+}
@@ -0,0 +1,626 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Inspection of produced artifacts.
+
+`tugger inspect` lists the members of Debian packages, tar archives,
+zip archives, and snaps, so packages can be double-checked without
+reaching for `dpkg-deb`, `tar`, or `unsquashfs` directly. Debian
+packages additionally have their control paragraph shown.
+
+`tugger diff` compares two artifacts of the same format member by
+member, reporting added and removed members and changes to the type,
+size, mode, link target, and content (by SHA-256) of the others.
+
+Snaps are squashfs images, which are listed with `unsquashfs`. Their
+member contents aren't hashed, so content changes in snaps are only
+detected when they change the size.
+*/
+
+use crate::extract::ArchiveFormat;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::Path;
+use std::process::Command;
+
+/// The type of an archive member.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MemberKind {
+    File,
+    Directory,
+    Symlink,
+    HardLink,
+    Other,
+}
+
+impl MemberKind {
+    /// The character `ls -l` uses for the type.
+    fn ls_char(self) -> char {
+        match self {
+            MemberKind::File | MemberKind::HardLink => '-',
+            MemberKind::Directory => 'd',
+            MemberKind::Symlink => 'l',
+            MemberKind::Other => '?',
+        }
+    }
+}
+
+/// Describes a member of an artifact.
+#[derive(Clone, Debug, Serialize)]
+pub struct Member {
+    pub path: String,
+    pub kind: MemberKind,
+    pub size: u64,
+
+    /// Permission bits, if the format records them.
+    pub mode: Option<u32>,
+
+    /// Target of a symlink or hard link.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub link_target: Option<String>,
+
+    /// Hex encoded SHA-256 digest of the content of a file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+}
+
+impl Member {
+    /// Render the type and mode like `ls -l`, e.g. `-rwxr-xr-x`.
+    pub fn mode_string(&self) -> String {
+        let mut res = String::with_capacity(10);
+        res.push(self.kind.ls_char());
+
+        match self.mode {
+            Some(mode) => {
+                for shift in &[6, 3, 0] {
+                    let bits = (mode >> shift) & 0o7;
+                    res.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+                    res.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+                    res.push(if bits & 0o1 != 0 { 'x' } else { '-' });
+                }
+            }
+            None => res.push_str("?????????"),
+        }
+
+        res
+    }
+}
+
+/// The contents of an artifact.
+#[derive(Clone, Debug, Serialize)]
+pub struct Inspection {
+    /// Format of the artifact, e.g. `deb`, `tar.gz`, or `snap`.
+    pub format: String,
+
+    /// Control paragraph of a Debian package.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub control: Option<String>,
+
+    pub members: Vec<Member>,
+}
+
+impl Inspection {
+    /// Total size of all members.
+    pub fn total_size(&self) -> u64 {
+        self.members.iter().map(|m| m.size).sum()
+    }
+}
+
+/// Inspect an artifact.
+///
+/// The format is derived from the filename.
+pub fn inspect(path: &Path) -> Result<Inspection, String> {
+    let filename = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    if filename.ends_with(".deb") {
+        inspect_deb(path)
+    } else if filename.ends_with(".snap") {
+        inspect_snap(path)
+    } else if let Some(format) = ArchiveFormat::from_filename(&filename) {
+        let members = if format == ArchiveFormat::Zip {
+            zip_members(path)?
+        } else {
+            let fh = std::fs::File::open(path)
+                .map_err(|e| format!("unable to open {}: {}", path.display(), e))?;
+            tar_members(decompress(std::io::BufReader::new(fh), format)?)?
+        };
+
+        Ok(Inspection {
+            format: format.extension().to_string(),
+            control: None,
+            members,
+        })
+    } else {
+        Err(format!(
+            "unable to determine format of {}; expected a .deb, .snap, tar, or zip file",
+            path.display()
+        ))
+    }
+}
+
+/// Wrap a reader of a tar archive with a decompressor for its format.
+fn decompress<'a>(
+    reader: impl Read + 'a,
+    format: ArchiveFormat,
+) -> Result<Box<dyn Read + 'a>, String> {
+    Ok(match format {
+        ArchiveFormat::Tar => Box::new(reader),
+        ArchiveFormat::TarBz2 => Box::new(bzip2::read::BzDecoder::new(reader)),
+        ArchiveFormat::TarGz => Box::new(flate2::read::GzDecoder::new(reader)),
+        ArchiveFormat::TarXz => Box::new(xz2::read::XzDecoder::new(reader)),
+        ArchiveFormat::TarZst => Box::new(
+            zstd::stream::read::Decoder::new(reader)
+                .map_err(|e| format!("unable to read zstd stream: {}", e))?,
+        ),
+        ArchiveFormat::Zip => return Err("zip archives aren't tar streams".to_string()),
+    })
+}
+
+/// Normalize a member path by removing a leading `./` and trailing `/`.
+fn member_name(path: &str) -> String {
+    path.trim_start_matches("./")
+        .trim_end_matches('/')
+        .to_string()
+}
+
+/// Compute the hex encoded SHA-256 digest of a member's content.
+fn sha256_member(reader: &mut impl Read, name: &str) -> Result<String, String> {
+    let mut hasher = Sha256::new();
+    let mut buffer = [0; 32768];
+
+    loop {
+        let count = reader
+            .read(&mut buffer)
+            .map_err(|e| format!("unable to read {}: {}", name, e))?;
+
+        if count == 0 {
+            break;
+        }
+
+        hasher.input(&buffer[0..count]);
+    }
+
+    Ok(format!("{:x}", hasher.result()))
+}
+
+fn tar_members(reader: impl Read) -> Result<Vec<Member>, String> {
+    let mut archive = tar::Archive::new(reader);
+    let mut members = Vec::new();
+
+    let entries = archive
+        .entries()
+        .map_err(|e| format!("unable to read tar archive: {}", e))?;
+
+    for entry in entries {
+        let mut entry = entry.map_err(|e| format!("unable to read tar archive: {}", e))?;
+
+        let path = entry
+            .path()
+            .map_err(|e| format!("invalid path in tar archive: {}", e))?;
+        let path = member_name(&path.to_string_lossy());
+        if path.is_empty() {
+            continue;
+        }
+
+        let entry_type = entry.header().entry_type();
+        let kind = if entry_type.is_file() {
+            MemberKind::File
+        } else if entry_type.is_dir() {
+            MemberKind::Directory
+        } else if entry_type.is_symlink() {
+            MemberKind::Symlink
+        } else if entry_type.is_hard_link() {
+            MemberKind::HardLink
+        } else {
+            MemberKind::Other
+        };
+
+        let link_target = entry
+            .link_name()
+            .map_err(|e| format!("invalid link in tar archive: {}", e))?
+            .map(|target| target.to_string_lossy().to_string());
+
+        let size = entry.header().size().unwrap_or(0);
+        let mode = entry.header().mode().ok().map(|mode| mode & 0o7777);
+
+        let sha256 = if kind == MemberKind::File {
+            Some(sha256_member(&mut entry, &path)?)
+        } else {
+            None
+        };
+
+        members.push(Member {
+            path,
+            kind,
+            size,
+            mode,
+            link_target,
+            sha256,
+        });
+    }
+
+    Ok(members)
+}
+
+/// Read the content of a file in a tar archive.
+#[cfg(feature = "deb")]
+fn tar_file(reader: impl Read, name: &str) -> Result<Option<String>, String> {
+    let mut archive = tar::Archive::new(reader);
+
+    let entries = archive
+        .entries()
+        .map_err(|e| format!("unable to read tar archive: {}", e))?;
+
+    for entry in entries {
+        let mut entry = entry.map_err(|e| format!("unable to read tar archive: {}", e))?;
+
+        let path = entry
+            .path()
+            .map_err(|e| format!("invalid path in tar archive: {}", e))?;
+        if member_name(&path.to_string_lossy()) != name {
+            continue;
+        }
+
+        let mut data = String::new();
+        entry
+            .read_to_string(&mut data)
+            .map_err(|e| format!("unable to read {}: {}", name, e))?;
+
+        return Ok(Some(data));
+    }
+
+    Ok(None)
+}
+
+fn zip_members(path: &Path) -> Result<Vec<Member>, String> {
+    let fh = std::fs::File::open(path)
+        .map_err(|e| format!("unable to open {}: {}", path.display(), e))?;
+    let mut archive = zip::ZipArchive::new(fh)
+        .map_err(|e| format!("unable to read {}: {}", path.display(), e))?;
+
+    let mut members = Vec::new();
+
+    for index in 0..archive.len() {
+        let mut file = archive
+            .by_index(index)
+            .map_err(|e| format!("unable to read {}: {}", path.display(), e))?;
+
+        let name = file.name().trim_end_matches('/').to_string();
+        let size = file.size();
+        let mode = file.unix_mode().map(|mode| mode & 0o7777);
+
+        let (kind, link_target, sha256) = if file.is_dir() {
+            (MemberKind::Directory, None, None)
+        } else if file.is_symlink() {
+            let mut target = String::new();
+            file.read_to_string(&mut target)
+                .map_err(|e| format!("unable to read symlink {}: {}", name, e))?;
+
+            (MemberKind::Symlink, Some(target), None)
+        } else {
+            (
+                MemberKind::File,
+                None,
+                Some(sha256_member(&mut file, &name)?),
+            )
+        };
+
+        members.push(Member {
+            path: name,
+            kind,
+            size,
+            mode,
+            link_target,
+            sha256,
+        });
+    }
+
+    Ok(members)
+}
+
+#[cfg(feature = "deb")]
+fn inspect_deb(path: &Path) -> Result<Inspection, String> {
+    let fh = std::fs::File::open(path)
+        .map_err(|e| format!("unable to open {}: {}", path.display(), e))?;
+    let mut archive = ar::Archive::new(fh);
+
+    let mut control = None;
+    let mut members = None;
+
+    while let Some(entry) = archive.next_entry() {
+        let entry = entry.map_err(|e| format!("unable to read {}: {}", path.display(), e))?;
+        let name = String::from_utf8_lossy(entry.header().identifier()).to_string();
+
+        if name.starts_with("control.tar") {
+            let format = ArchiveFormat::from_filename(&name)
+                .ok_or_else(|| format!("unsupported control archive {}", name))?;
+            control = tar_file(decompress(entry, format)?, "control")?;
+        } else if name.starts_with("data.tar") {
+            let format = ArchiveFormat::from_filename(&name)
+                .ok_or_else(|| format!("unsupported data archive {}", name))?;
+            members = Some(tar_members(decompress(entry, format)?)?);
+        }
+    }
+
+    Ok(Inspection {
+        format: "deb".to_string(),
+        control: Some(control.ok_or_else(|| format!("{} has no control file", path.display()))?),
+        members: members.ok_or_else(|| format!("{} has no data archive", path.display()))?,
+    })
+}
+
+#[cfg(not(feature = "deb"))]
+fn inspect_deb(_path: &Path) -> Result<Inspection, String> {
+    Err("inspecting Debian packages requires the deb feature".to_string())
+}
+
+fn inspect_snap(path: &Path) -> Result<Inspection, String> {
+    crate::process::record_program("unsquashfs");
+
+    let output = Command::new("unsquashfs")
+        .arg("-lln")
+        .arg(path)
+        .output()
+        .map_err(|e| {
+            format!(
+                "unable to run unsquashfs (is squashfs-tools installed?): {}",
+                e
+            )
+        })?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "unsquashfs failed listing {}: {}",
+            path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(Inspection {
+        format: "snap".to_string(),
+        control: None,
+        members: String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(parse_unsquashfs_line)
+            .collect(),
+    })
+}
+
+/// Parse a line of `unsquashfs -lln` output.
+///
+/// Lines look like `-rw-r--r-- 0/0 123 2020-01-01 00:00 squashfs-root/path`.
+/// Lines not describing a member yield `None`.
+fn parse_unsquashfs_line(line: &str) -> Option<Member> {
+    let mut fields = Vec::with_capacity(5);
+    let mut rest = line.trim_start();
+
+    for _ in 0..5 {
+        let end = rest.find(char::is_whitespace)?;
+        fields.push(&rest[..end]);
+        rest = rest[end..].trim_start();
+    }
+
+    let perms = fields[0].as_bytes();
+    if perms.len() != 10 {
+        return None;
+    }
+
+    let kind = match perms[0] {
+        b'-' => MemberKind::File,
+        b'd' => MemberKind::Directory,
+        b'l' => MemberKind::Symlink,
+        _ => MemberKind::Other,
+    };
+
+    let mode = perms[1..]
+        .iter()
+        .fold(0, |mode, c| (mode << 1) | if *c == b'-' { 0 } else { 1 });
+
+    let rest = rest.strip_prefix("squashfs-root")?.trim_start_matches('/');
+    if rest.is_empty() {
+        return None;
+    }
+
+    let (path, link_target) = match (kind, rest.find(" -> ")) {
+        (MemberKind::Symlink, Some(pos)) => (&rest[..pos], Some(rest[pos + 4..].to_string())),
+        _ => (rest, None),
+    };
+
+    Some(Member {
+        path: path.to_string(),
+        kind,
+        size: fields[2].parse().ok()?,
+        mode: Some(mode),
+        link_target,
+        sha256: None,
+    })
+}
+
+/// How a member differs between two artifacts.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Modified,
+}
+
+/// Describes a member that differs between two artifacts.
+#[derive(Clone, Debug, Serialize)]
+pub struct MemberChange {
+    pub path: String,
+    pub change: ChangeKind,
+
+    /// Descriptions of the modifications, e.g. `size 10 -> 12`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub details: Vec<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old: Option<Member>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new: Option<Member>,
+}
+
+/// The differences between two artifacts.
+#[derive(Clone, Debug, Serialize)]
+pub struct ArtifactDiff {
+    pub format: String,
+
+    /// Lines of the Debian control paragraph only in the old artifact.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub control_removed: Vec<String>,
+
+    /// Lines of the Debian control paragraph only in the new artifact.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub control_added: Vec<String>,
+
+    /// Changed members, sorted by path.
+    pub changes: Vec<MemberChange>,
+
+    /// Number of members that are identical in both artifacts.
+    pub unchanged: usize,
+}
+
+impl ArtifactDiff {
+    /// Number of changes of a kind.
+    pub fn count(&self, change: ChangeKind) -> usize {
+        self.changes.iter().filter(|c| c.change == change).count()
+    }
+
+    /// Whether the artifacts are identical.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty() && self.control_removed.is_empty() && self.control_added.is_empty()
+    }
+}
+
+/// Describe how a member was modified.
+fn member_details(old: &Member, new: &Member) -> Vec<String> {
+    let mut details = Vec::new();
+
+    if old.kind != new.kind {
+        details.push(format!("type {:?} -> {:?}", old.kind, new.kind).to_lowercase());
+    }
+
+    if old.mode != new.mode {
+        details.push(format!(
+            "mode {} -> {}",
+            old.mode_string(),
+            new.mode_string()
+        ));
+    }
+
+    if old.size != new.size {
+        details.push(format!("size {} -> {}", old.size, new.size));
+    }
+
+    if old.link_target != new.link_target {
+        details.push(format!(
+            "link {} -> {}",
+            old.link_target.as_deref().unwrap_or("(none)"),
+            new.link_target.as_deref().unwrap_or("(none)")
+        ));
+    }
+
+    if let (Some(old_hash), Some(new_hash)) = (&old.sha256, &new.sha256) {
+        if old_hash != new_hash {
+            details.push("content".to_string());
+        }
+    }
+
+    details
+}
+
+/// Compare the inspections of two artifacts.
+///
+/// Both artifacts must be of the same format.
+pub fn diff(old: &Inspection, new: &Inspection) -> Result<ArtifactDiff, String> {
+    if old.format != new.format {
+        return Err(format!(
+            "unable to compare artifacts of different formats: {} and {}",
+            old.format, new.format
+        ));
+    }
+
+    let old_members = old
+        .members
+        .iter()
+        .map(|m| (m.path.as_str(), m))
+        .collect::<BTreeMap<_, _>>();
+    let new_members = new
+        .members
+        .iter()
+        .map(|m| (m.path.as_str(), m))
+        .collect::<BTreeMap<_, _>>();
+
+    let mut changes = Vec::new();
+    let mut unchanged = 0;
+
+    for (path, old_member) in &old_members {
+        match new_members.get(path) {
+            Some(new_member) => {
+                let details = member_details(old_member, new_member);
+
+                if details.is_empty() {
+                    unchanged += 1;
+                } else {
+                    changes.push(MemberChange {
+                        path: path.to_string(),
+                        change: ChangeKind::Modified,
+                        details,
+                        old: Some((*old_member).clone()),
+                        new: Some((*new_member).clone()),
+                    });
+                }
+            }
+            None => changes.push(MemberChange {
+                path: path.to_string(),
+                change: ChangeKind::Removed,
+                details: vec![],
+                old: Some((*old_member).clone()),
+                new: None,
+            }),
+        }
+    }
+
+    for (path, new_member) in &new_members {
+        if !old_members.contains_key(path) {
+            changes.push(MemberChange {
+                path: path.to_string(),
+                change: ChangeKind::Added,
+                details: vec![],
+                old: None,
+                new: Some((*new_member).clone()),
+            });
+        }
+    }
+
+    changes.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let old_control = old.control.as_deref().unwrap_or("");
+    let new_control = new.control.as_deref().unwrap_or("");
+
+    Ok(ArtifactDiff {
+        format: old.format.clone(),
+        control_removed: old_control
+            .lines()
+            .filter(|line| !new_control.lines().any(|l| l == *line))
+            .map(|line| line.to_string())
+            .collect(),
+        control_added: new_control
+            .lines()
+            .filter(|line| !old_control.lines().any(|l| l == *line))
+            .map(|line| line.to_string())
+            .collect(),
+        changes,
+        unchanged,
+    })
+}
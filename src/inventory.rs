@@ -0,0 +1,329 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Inventory of artifacts in a distribution directory.
+
+The inventory records the name, size, and SHA-256 digest of every file
+produced by a pipeline so artifacts can be described in release notes
+and verified before they are published.
+
+`Inventory::verify()` hashes the files of a distribution directory again
+and compares them with an inventory, so artifacts built earlier can be
+checked before they are uploaded or mirrored.
+*/
+
+use crate::signing::{sibling_path, verify_signature, VerificationKeys, SIGNATURE_SUFFIXES};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use slog::Logger;
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Filename of the inventory written to the distribution directory.
+pub const INVENTORY_FILENAME: &str = "artifacts.json";
+
+/// Describes a single artifact.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Artifact {
+    /// Path of the artifact relative to the distribution directory.
+    ///
+    /// Always uses `/` as the directory separator.
+    pub name: String,
+
+    pub size: u64,
+
+    /// Hex encoded SHA-256 digest of the artifact's content.
+    pub sha256: String,
+
+    /// URL the artifact can be downloaded from, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+/// A collection of artifacts.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Inventory {
+    pub artifacts: Vec<Artifact>,
+}
+
+/// How a file differs from its inventory entry.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "problem", rename_all = "snake_case")]
+pub enum Mismatch {
+    /// The artifact doesn't exist.
+    Missing { name: String },
+
+    /// The artifact has a different size.
+    Size {
+        name: String,
+        expected: u64,
+        actual: u64,
+    },
+
+    /// The artifact has different content.
+    Digest {
+        name: String,
+        expected: String,
+        actual: String,
+    },
+
+    /// The signature of an artifact is invalid.
+    Signature { name: String, error: String },
+}
+
+impl Mismatch {
+    /// Name of the artifact that doesn't match.
+    pub fn name(&self) -> &str {
+        match self {
+            Mismatch::Missing { name }
+            | Mismatch::Size { name, .. }
+            | Mismatch::Digest { name, .. }
+            | Mismatch::Signature { name, .. } => name,
+        }
+    }
+}
+
+impl std::fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Mismatch::Missing { name } => write!(f, "{}: missing", name),
+            Mismatch::Size {
+                name,
+                expected,
+                actual,
+            } => write!(f, "{}: size {} != {}", name, actual, expected),
+            Mismatch::Digest {
+                name,
+                expected,
+                actual,
+            } => write!(f, "{}: sha256 {} != {}", name, actual, expected),
+            Mismatch::Signature { name, error } => {
+                write!(f, "{}: invalid signature: {}", name, error)
+            }
+        }
+    }
+}
+
+/// Result of verifying a distribution directory against an inventory.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Verification {
+    /// Number of artifacts matching the inventory.
+    pub verified: usize,
+
+    /// Signatures that were verified.
+    pub signatures: Vec<String>,
+
+    /// Signatures that weren't verified because no key was given for them.
+    pub unverified_signatures: Vec<String>,
+
+    pub mismatches: Vec<Mismatch>,
+
+    /// Files in the directory that aren't in the inventory.
+    pub unrecorded: Vec<String>,
+}
+
+/// Compute the hex encoded SHA-256 digest of a file.
+pub fn sha256_file(path: &Path) -> Result<String, String> {
+    let mut fh = std::fs::File::open(path)
+        .map_err(|e| format!("unable to open {}: {}", path.display(), e))?;
+
+    let mut hasher = Sha256::new();
+    let mut buffer = [0; 32768];
+
+    loop {
+        let count = fh
+            .read(&mut buffer)
+            .map_err(|e| format!("unable to read {}: {}", path.display(), e))?;
+
+        if count == 0 {
+            break;
+        }
+
+        hasher.input(&buffer[0..count]);
+    }
+
+    Ok(format!("{:x}", hasher.result()))
+}
+
+impl Inventory {
+    /// Build an inventory of files in a distribution directory.
+    ///
+    /// Files whose relative path is in `exclude` are ignored, as is any
+    /// existing inventory file. If `download_url` is defined, each
+    /// artifact's URL is the artifact name appended to it.
+    pub fn scan(
+        dist_path: &Path,
+        exclude: &[&str],
+        download_url: Option<&str>,
+    ) -> Result<Self, String> {
+        let files = list_files(dist_path, exclude)?;
+
+        // Hashing dominates, so files are hashed in parallel.
+        let artifacts = files
+            .into_par_iter()
+            .map(|(name, path, size)| {
+                Ok(Artifact {
+                    url: download_url
+                        .map(|base| format!("{}/{}", base.trim_end_matches('/'), name)),
+                    sha256: sha256_file(&path)?,
+                    name,
+                    size,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Ok(Inventory { artifacts })
+    }
+
+    /// Verify the files of a distribution directory against the inventory.
+    ///
+    /// Every artifact is hashed again. Detached signatures of artifacts
+    /// in the inventory are verified if `keys` has a key for them.
+    pub fn verify(
+        &self,
+        logger: &Logger,
+        dist_path: &Path,
+        keys: &VerificationKeys,
+    ) -> Result<Verification, String> {
+        let mut files = list_files(dist_path, &[])?
+            .into_iter()
+            .map(|(name, path, size)| (name, (path, size)))
+            .collect::<BTreeMap<_, _>>();
+
+        let results = self
+            .artifacts
+            .par_iter()
+            .map(|artifact| {
+                let (path, size) = match files.get(&artifact.name) {
+                    Some((path, size)) => (path, *size),
+                    None => {
+                        return Ok(Some(Mismatch::Missing {
+                            name: artifact.name.clone(),
+                        }))
+                    }
+                };
+
+                if size != artifact.size {
+                    return Ok(Some(Mismatch::Size {
+                        name: artifact.name.clone(),
+                        expected: artifact.size,
+                        actual: size,
+                    }));
+                }
+
+                let sha256 = sha256_file(path)?;
+                if sha256 != artifact.sha256 {
+                    return Ok(Some(Mismatch::Digest {
+                        name: artifact.name.clone(),
+                        expected: artifact.sha256.clone(),
+                        actual: sha256,
+                    }));
+                }
+
+                Ok(None)
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        let mut verification = Verification::default();
+
+        for (artifact, mismatch) in self.artifacts.iter().zip(results) {
+            match mismatch {
+                Some(mismatch) => verification.mismatches.push(mismatch),
+                None => verification.verified += 1,
+            }
+
+            files.remove(&artifact.name);
+        }
+
+        // Only signatures of intact artifacts are worth verifying.
+        let intact = self
+            .artifacts
+            .iter()
+            .map(|a| a.name.as_str())
+            .filter(|name| !verification.mismatches.iter().any(|m| m.name() == *name))
+            .collect::<Vec<_>>();
+
+        for name in &intact {
+            for suffix in SIGNATURE_SUFFIXES {
+                let signature = format!("{}{}", name, suffix);
+                if !intact.contains(&signature.as_str()) {
+                    continue;
+                }
+
+                let path = dist_path.join(name);
+                match verify_signature(logger, &path, &sibling_path(&path, suffix), keys) {
+                    Ok(true) => verification.signatures.push(signature),
+                    Ok(false) => verification.unverified_signatures.push(signature),
+                    Err(error) => verification.mismatches.push(Mismatch::Signature {
+                        name: signature,
+                        error,
+                    }),
+                }
+            }
+        }
+
+        verification.unrecorded = files.into_keys().collect();
+
+        Ok(verification)
+    }
+
+    /// Read an inventory from a JSON file.
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let data =
+            std::fs::read(path).map_err(|e| format!("unable to read {}: {}", path.display(), e))?;
+
+        serde_json::from_slice(&data)
+            .map_err(|e| format!("unable to parse {}: {}", path.display(), e))
+    }
+
+    /// Write the inventory to a JSON file.
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        let data = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+
+        std::fs::write(path, data + "\n")
+            .map_err(|e| format!("unable to write {}: {}", path.display(), e))
+    }
+}
+
+/// List the files of a distribution directory in sorted order.
+///
+/// Returns the name, path, and size of each file. Names are relative to
+/// `dist_path` and use `/` as the directory separator. The inventory file
+/// and files whose name is in `exclude` are ignored.
+fn list_files(dist_path: &Path, exclude: &[&str]) -> Result<Vec<(String, PathBuf, u64)>, String> {
+    let mut files = Vec::new();
+
+    for entry in walkdir::WalkDir::new(dist_path).sort_by(|a, b| a.file_name().cmp(b.file_name())) {
+        let entry = entry.map_err(|e| format!("unable to walk {}: {}", dist_path.display(), e))?;
+
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        let name = entry
+            .path()
+            .strip_prefix(dist_path)
+            .map_err(|e| e.to_string())?
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().to_string())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        if name == INVENTORY_FILENAME || exclude.contains(&name.as_str()) {
+            continue;
+        }
+
+        let size = entry
+            .metadata()
+            .map_err(|e| format!("unable to stat {}: {}", entry.path().display(), e))?
+            .len();
+
+        files.push((name, entry.path().to_path_buf(), size));
+    }
+
+    Ok(files)
+}
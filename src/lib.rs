@@ -33,15 +33,133 @@ turned into `pipeline` execution actions via the `tugger` command-line
 tool or by calling appropriate functions in this crate.
 
 Tugger configuration files are named `tugger.ship` by convention.
+Unless a file is given with `--config`, `tugger` searches for
+`tugger.ship` in the current directory and its parents, so commands work
+from any subdirectory of a project. Files are evaluated from their own
+directory and artifacts are written to `dist` next to them.
 
 See the [`starlark`](starlark/index.html) module for documentation of the
 Starlark dialect.
+
+Pipelines can also be defined from Rust without a configuration file,
+using `Pipeline::builder()` to add steps and execute them in an
+`EnvironmentContext`. See the [`pipeline`](pipeline/index.html) module.
+Implementations of
+[`ExecutionObserver`](observer/trait.ExecutionObserver.html) are notified
+as pipelines, steps, and artifacts progress.
+
+## Cargo Features
+
+Functionality with heavyweight dependencies is behind cargo features, so
+library users only compile what they need. `deb` provides the Debian
+package writer, `snap` snapcraft support, and `icons` icon resizing.
+`starlark` provides the configuration dialect and enables all format
+backends. Pipelines defined from Rust don't require it; steps of
+backends that aren't enabled are unavailable. `cli` provides the
+`tugger` program and is enabled by default.
+`keyring` reads and stores secrets directly in the platform credential
+store (Secret Service, macOS Keychain, or Windows Credential Manager)
+instead of invoking command-line tools, and enables `tugger secret`.
+`otel` exports traces of pipeline execution to an OpenTelemetry collector
+when `OTEL_EXPORTER_OTLP_ENDPOINT` is set. See the [`otel`](otel/index.html)
+module for details.
+
+## Reproducible Artifacts
+
+`tugger run --reproducible` enables a mode where archive writers produce
+byte-identical artifacts for identical inputs. The mode is also enabled
+when the `SOURCE_DATE_EPOCH` environment variable is set. See the
+[`reproducibility`](reproducibility/index.html) module for details.
+
+## Release Channels
+
+`tugger run --channel` selects the release channel being built: `stable`
+(the default), `beta`, or `nightly`. Configuration files can use it to
+vary artifact names and upload destinations, so one file handles every
+release train. See the [`channel`](channel/index.html) module for details.
+
+## Retries
+
+Steps performing network operations retry transient failures with an
+exponential backoff. `tugger run --max-attempts` sets how often failed
+operations are attempted, unless a pipeline or step defines its own
+policy. See the [`retry`](retry/index.html) module for details.
 */
 
+pub mod appcast;
+pub mod archive;
+pub mod breakpad;
+pub mod build_environment;
+pub mod build_info;
+pub mod bundle;
+pub mod cache;
+pub mod cargo;
+pub mod channel;
+#[cfg(feature = "cli")]
 pub mod cli;
+pub mod completions;
+pub mod compression;
+pub mod conda;
+pub mod container;
+#[cfg(feature = "deb")]
 pub mod debian;
+pub mod debian_version;
+pub mod delta;
+pub mod deploy;
+pub mod desktop;
+pub mod env_file;
+pub mod error;
+pub mod extract;
+pub mod fetch;
 pub mod filemanifest;
+pub mod git;
+pub mod github;
+pub mod github_actions;
 pub mod glob;
+pub mod http;
+#[cfg(feature = "icons")]
+pub mod icons;
+pub mod inspect;
+pub mod inventory;
+pub mod licenses;
+pub mod lock;
+pub mod make;
+pub mod mirror;
+pub mod mode_policy;
+pub mod multipart;
+pub mod naming;
+pub mod node;
+pub mod notify;
+pub mod observer;
+#[cfg(feature = "otel")]
+pub mod otel;
+pub mod package_hosting;
+pub mod package_metadata;
+pub mod pe_resources;
+pub mod pipeline;
+pub mod process;
+pub mod release_notes;
+#[cfg(feature = "cli")]
+pub mod repl;
+pub mod report;
+pub mod reproducibility;
+pub mod resources;
+pub mod resumable;
+pub mod retry;
+pub mod rpm;
+pub mod runs;
+pub mod secrets;
+pub mod shared_libs;
+pub mod signing;
+#[cfg(feature = "snap")]
 pub mod snap;
-#[allow(unused)]
+pub mod squirrel;
+pub mod stamp;
+#[cfg(feature = "starlark")]
 pub mod starlark;
+pub mod symbols;
+pub mod systemd;
+pub mod tools;
+pub mod upload;
+pub mod version;
+pub mod xml;
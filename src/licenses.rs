@@ -0,0 +1,191 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Aggregation of third-party license texts.
+
+Distributing binaries typically requires shipping the license texts of
+the third-party components they contain. The functions in this module
+discover those components and license texts and render them into a
+single notices document.
+*/
+
+use std::collections::HashSet;
+use std::path::Path;
+
+/// Filename prefixes of files holding license texts.
+const LICENSE_FILE_PREFIXES: &[&str] = &["LICENSE", "LICENCE", "COPYING", "NOTICE", "UNLICENSE"];
+
+/// A third-party component and its license texts.
+#[derive(Clone, Debug)]
+pub struct ThirdPartyComponent {
+    pub name: String,
+    pub version: Option<String>,
+
+    /// License expression, if known (e.g. `MIT OR Apache-2.0`).
+    pub license: Option<String>,
+
+    /// `(filename, text)` of license files.
+    pub texts: Vec<(String, String)>,
+}
+
+/// Find license files in a directory.
+///
+/// Returns `(filename, text)` pairs sorted by filename.
+pub fn find_license_files(dir: &Path) -> Result<Vec<(String, String)>, String> {
+    let mut res = Vec::new();
+
+    let entries =
+        std::fs::read_dir(dir).map_err(|e| format!("unable to read {}: {}", dir.display(), e))?;
+
+    for entry in entries {
+        let entry = entry.map_err(|e| format!("unable to read {}: {}", dir.display(), e))?;
+        let path = entry.path();
+
+        if !path.is_file() {
+            continue;
+        }
+
+        let filename = entry.file_name().to_string_lossy().to_string();
+        let upper = filename.to_uppercase();
+
+        if LICENSE_FILE_PREFIXES.iter().any(|p| upper.starts_with(p)) {
+            res.push((filename, read_text(&path)?));
+        }
+    }
+
+    res.sort();
+
+    Ok(res)
+}
+
+fn read_text(path: &Path) -> Result<String, String> {
+    let data =
+        std::fs::read(path).map_err(|e| format!("unable to read {}: {}", path.display(), e))?;
+
+    Ok(String::from_utf8_lossy(&data).to_string())
+}
+
+/// Obtain the third-party components of a Cargo project.
+///
+/// Runs `cargo metadata` against `manifest_path` and returns every package
+/// that isn't a workspace member.
+pub fn cargo_components(manifest_path: &Path) -> Result<Vec<ThirdPartyComponent>, String> {
+    let metadata = crate::cargo::cargo_metadata(manifest_path, false)?;
+
+    let workspace_members: HashSet<&str> = metadata["workspace_members"]
+        .as_array()
+        .map(|members| members.iter().filter_map(|m| m.as_str()).collect())
+        .unwrap_or_default();
+
+    let mut res = Vec::new();
+
+    for package in metadata["packages"].as_array().unwrap_or(&vec![]) {
+        if let Some(id) = package["id"].as_str() {
+            if workspace_members.contains(id) {
+                continue;
+            }
+        }
+
+        let name = package["name"]
+            .as_str()
+            .ok_or_else(|| "package in cargo metadata has no name".to_string())?;
+        let package_dir = package["manifest_path"]
+            .as_str()
+            .and_then(|p| Path::new(p).parent())
+            .ok_or_else(|| format!("package {} has no manifest path", name))?;
+
+        let mut texts = find_license_files(package_dir)?;
+
+        if let Some(license_file) = package["license_file"].as_str() {
+            let path = package_dir.join(license_file);
+            let filename = path
+                .file_name()
+                .map(|f| f.to_string_lossy().to_string())
+                .unwrap_or_else(|| license_file.to_string());
+
+            if !texts.iter().any(|(f, _)| f == &filename) {
+                texts.push((filename, read_text(&path)?));
+            }
+        }
+
+        res.push(ThirdPartyComponent {
+            name: name.to_string(),
+            version: package["version"].as_str().map(|v| v.to_string()),
+            license: package["license"].as_str().map(|l| l.to_string()),
+            texts,
+        });
+    }
+
+    res.sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+
+    Ok(res)
+}
+
+/// Obtain components from a directory of vendored dependencies.
+///
+/// Each subdirectory of `vendor_dir` is treated as a component.
+pub fn vendored_components(vendor_dir: &Path) -> Result<Vec<ThirdPartyComponent>, String> {
+    let mut res = Vec::new();
+
+    let entries = std::fs::read_dir(vendor_dir)
+        .map_err(|e| format!("unable to read {}: {}", vendor_dir.display(), e))?;
+
+    for entry in entries {
+        let entry = entry.map_err(|e| format!("unable to read {}: {}", vendor_dir.display(), e))?;
+        let path = entry.path();
+
+        if !path.is_dir() {
+            continue;
+        }
+
+        res.push(ThirdPartyComponent {
+            name: entry.file_name().to_string_lossy().to_string(),
+            version: None,
+            license: None,
+            texts: find_license_files(&path)?,
+        });
+    }
+
+    res.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(res)
+}
+
+/// Render a notices document describing third-party components.
+pub fn render_notices(components: &[ThirdPartyComponent]) -> String {
+    let mut s = String::new();
+
+    s.push_str("THIRD-PARTY SOFTWARE NOTICES\n\n");
+    s.push_str("This software includes the following third-party components.\n");
+
+    for component in components {
+        s.push('\n');
+        s.push_str(&"=".repeat(80));
+        s.push('\n');
+
+        match &component.version {
+            Some(version) => s.push_str(&format!("{} {}\n", component.name, version)),
+            None => s.push_str(&format!("{}\n", component.name)),
+        }
+
+        if let Some(license) = &component.license {
+            s.push_str(&format!("License: {}\n", license));
+        }
+
+        if component.texts.is_empty() {
+            s.push_str("(no license text found)\n");
+        }
+
+        for (filename, text) in &component.texts {
+            s.push_str(&"-".repeat(80));
+            s.push('\n');
+            s.push_str(&format!("{}:\n\n", filename));
+            s.push_str(text.trim_end());
+            s.push('\n');
+        }
+    }
+
+    s
+}
@@ -0,0 +1,349 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Lockfiles pinning downloaded resources.
+
+`tugger.lock`, next to the configuration file, records the SHA-256 digest
+of every resource downloaded by `fetch()` or `tool()`. Resources whose
+digest isn't pinned in the configuration are pinned by the lockfile
+instead, so every machine uses the same content.
+
+The lockfile also records the versions of external programs steps run,
+such as `snapcraft` or `gpg`, as printed by `<program> --version`.
+These can't be downloaded again, so they are only compared against the
+versions installed.
+
+Runs add resources they download and programs they run to the lockfile.
+In locked mode, runs instead fail if a resource isn't in the lockfile or
+the configuration pins it to a different digest, and if a program isn't
+in the lockfile or another version of it is installed. Refreshing the
+lockfile downloads unpinned resources again, drops resources no longer
+used, and records the versions of the programs installed.
+*/
+
+use crate::build_environment::program_version;
+use crate::retry::RetryPolicy;
+use serde::{Deserialize, Serialize};
+use slog::Logger;
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Filename of lockfiles, in the directory of the configuration file.
+pub const LOCK_FILENAME: &str = "tugger.lock";
+
+/// The content of a lockfile.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Lockfile {
+    /// Hex encoded SHA-256 digests of downloaded resources, by URL.
+    #[serde(default)]
+    pub resources: BTreeMap<String, String>,
+
+    /// Versions of external programs, by program name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tools: BTreeMap<String, String>,
+}
+
+impl Lockfile {
+    /// Read a lockfile.
+    ///
+    /// A missing file is an empty lockfile.
+    pub fn read(path: &Path) -> Result<Self, String> {
+        if !path.exists() {
+            return Ok(Lockfile::default());
+        }
+
+        let data =
+            std::fs::read(path).map_err(|e| format!("unable to read {}: {}", path.display(), e))?;
+
+        serde_json::from_slice(&data).map_err(|e| format!("invalid {}: {}", path.display(), e))
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        let mut data = serde_json::to_vec_pretty(self)
+            .map_err(|e| format!("unable to serialize lockfile: {}", e))?;
+        data.push(b'\n');
+
+        std::fs::write(path, data).map_err(|e| format!("unable to write {}: {}", path.display(), e))
+    }
+}
+
+/// How the lockfile is used.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LockMode {
+    /// Use digests of the lockfile and add new resources to it.
+    Update,
+
+    /// Fail if resources aren't pinned to the digests of the lockfile.
+    Locked,
+
+    /// Ignore the lockfile and replace it with the resources used.
+    Refresh,
+}
+
+#[derive(Debug)]
+struct LockState {
+    path: Option<PathBuf>,
+    mode: LockMode,
+
+    /// The lockfile as read.
+    existing: Lockfile,
+
+    /// Resources used so far.
+    used: Lockfile,
+}
+
+/// Tracks resources downloaded while evaluating and executing pipelines.
+///
+/// Clones share their state, so resources are recorded no matter which
+/// clone downloads them.
+#[derive(Clone, Debug)]
+pub struct Lock {
+    state: Arc<Mutex<LockState>>,
+}
+
+impl Default for Lock {
+    /// A lock without a lockfile, recording resources in memory.
+    fn default() -> Self {
+        Lock::from_lockfile(None, LockMode::Update, Lockfile::default())
+    }
+}
+
+impl Lock {
+    /// Obtain a lock using the lockfile at `path`.
+    pub fn new(path: &Path, mode: LockMode) -> Result<Self, String> {
+        let existing = Lockfile::read(path)?;
+
+        if mode == LockMode::Locked && !path.exists() {
+            return Err(format!(
+                "{} doesn't exist; run `tugger update-lock` to create it",
+                path.display()
+            ));
+        }
+
+        Ok(Lock::from_lockfile(
+            Some(path.to_path_buf()),
+            mode,
+            existing,
+        ))
+    }
+
+    fn from_lockfile(path: Option<PathBuf>, mode: LockMode, existing: Lockfile) -> Self {
+        Lock {
+            state: Arc::new(Mutex::new(LockState {
+                path,
+                mode,
+                existing,
+                used: Lockfile::default(),
+            })),
+        }
+    }
+
+    pub fn mode(&self) -> LockMode {
+        self.state.lock().unwrap().mode
+    }
+
+    /// Obtain the digest a resource must have, if known.
+    ///
+    /// `sha256` is the digest the configuration pins the resource to.
+    pub fn expected_digest(
+        &self,
+        url: &str,
+        sha256: Option<&str>,
+    ) -> Result<Option<String>, String> {
+        let state = self.state.lock().unwrap();
+        let sha256 = sha256.map(|s| s.to_lowercase());
+        let locked = state.existing.resources.get(url);
+
+        match state.mode {
+            LockMode::Locked => match (sha256, locked) {
+                (_, None) => Err(format!(
+                    "{} isn't in {}; run `tugger update-lock`",
+                    url, LOCK_FILENAME
+                )),
+                (Some(sha256), Some(locked)) if &sha256 != locked => Err(format!(
+                    "{} is pinned to {} but {} records {}; run `tugger update-lock`",
+                    url, sha256, LOCK_FILENAME, locked
+                )),
+                (_, Some(locked)) => Ok(Some(locked.clone())),
+            },
+            LockMode::Update => Ok(sha256.or_else(|| locked.cloned())),
+            LockMode::Refresh => Ok(sha256),
+        }
+    }
+
+    /// Record that a resource with a digest was used.
+    pub fn record(&self, url: &str, sha256: &str) {
+        self.state
+            .lock()
+            .unwrap()
+            .used
+            .resources
+            .insert(url.to_string(), sha256.to_lowercase());
+    }
+
+    /// Ensure the programs of the lockfile are installed in the versions
+    /// it records.
+    ///
+    /// Only checks anything in locked mode, so mismatches are found before
+    /// any step executes.
+    pub fn check_tools(&self) -> Result<(), String> {
+        let state = self.state.lock().unwrap();
+
+        if state.mode != LockMode::Locked {
+            return Ok(());
+        }
+
+        for (program, locked) in &state.existing.tools {
+            check_tool_version(program, program_version(Path::new(program)), locked)?;
+        }
+
+        Ok(())
+    }
+
+    /// Obtain the programs recorded by the lockfile.
+    pub fn locked_tools(&self) -> Vec<String> {
+        self.state
+            .lock()
+            .unwrap()
+            .existing
+            .tools
+            .keys()
+            .cloned()
+            .collect()
+    }
+
+    /// Record the versions of external programs that were run.
+    ///
+    /// Programs not printing a version are ignored. In locked mode,
+    /// programs must be in the lockfile with the version installed. In
+    /// update mode, the versions of the lockfile are kept; differing
+    /// versions installed are returned as warnings.
+    pub fn record_tools<P: AsRef<OsStr>>(&self, programs: &[P]) -> Result<Vec<String>, String> {
+        let mut warnings = Vec::new();
+
+        for program in programs {
+            let program = Path::new(program.as_ref());
+            let version = match program_version(program) {
+                Some(version) => version,
+                None => continue,
+            };
+            let program = program.display().to_string();
+
+            let mut state = self.state.lock().unwrap();
+
+            match (state.mode, state.existing.tools.get(&program)) {
+                (LockMode::Locked, None) => {
+                    return Err(format!(
+                        "{} isn't in {}; run `tugger update-lock`",
+                        program, LOCK_FILENAME
+                    ))
+                }
+                (LockMode::Locked, Some(locked)) => {
+                    check_tool_version(&program, Some(version.clone()), locked)?
+                }
+                (LockMode::Update, Some(locked)) if locked != &version => warnings.push(format!(
+                    "{} is {} but {} records {}",
+                    program, version, LOCK_FILENAME, locked
+                )),
+                _ => {}
+            }
+
+            state.used.tools.insert(program, version);
+        }
+
+        Ok(warnings)
+    }
+
+    /// Download a resource pinned by the configuration or the lockfile.
+    ///
+    /// `sha256` is the digest the configuration pins the resource to.
+    /// Returns the path of the file in the cache and its digest.
+    pub fn fetch(
+        &self,
+        logger: &Logger,
+        url: &str,
+        sha256: Option<&str>,
+        retry: &RetryPolicy,
+    ) -> Result<(PathBuf, String), String> {
+        let (path, sha256) = match self.expected_digest(url, sha256)? {
+            Some(sha256) => (
+                crate::fetch::fetch_url(logger, url, &sha256, retry)?,
+                sha256,
+            ),
+            None => crate::fetch::fetch_url_unpinned(logger, url, retry)?,
+        };
+
+        self.record(url, &sha256);
+
+        Ok((path, sha256))
+    }
+
+    /// Write the lockfile if its content changed.
+    ///
+    /// In update mode, resources and programs used are added to those of
+    /// the lockfile. In refresh mode, they replace them. Nothing is written in locked
+    /// mode, without a lockfile path, or if a lockfile would be created
+    /// without resources.
+    ///
+    /// Returns the path written to, if any.
+    pub fn write(&self) -> Result<Option<PathBuf>, String> {
+        let state = self.state.lock().unwrap();
+
+        let path = match (&state.path, state.mode) {
+            (Some(path), LockMode::Update) | (Some(path), LockMode::Refresh) => path,
+            _ => return Ok(None),
+        };
+
+        let mut lockfile = match state.mode {
+            LockMode::Refresh => Lockfile::default(),
+            _ => state.existing.clone(),
+        };
+        lockfile.resources.extend(
+            state
+                .used
+                .resources
+                .iter()
+                .map(|(url, sha256)| (url.clone(), sha256.clone())),
+        );
+
+        for (program, version) in &state.used.tools {
+            lockfile
+                .tools
+                .entry(program.clone())
+                .or_insert_with(|| version.clone());
+        }
+
+        let unchanged = if path.exists() {
+            lockfile == state.existing
+        } else {
+            lockfile.resources.is_empty() && lockfile.tools.is_empty()
+        };
+
+        if unchanged {
+            return Ok(None);
+        }
+
+        lockfile.write(path)?;
+
+        Ok(Some(path.clone()))
+    }
+}
+
+/// Ensure the installed version of a program is the version of the lockfile.
+fn check_tool_version(program: &str, version: Option<String>, locked: &str) -> Result<(), String> {
+    match version {
+        Some(version) if version == locked => Ok(()),
+        Some(version) => Err(format!(
+            "{} is {} but {} records {}; run `tugger update-lock`",
+            program, version, LOCK_FILENAME, locked
+        )),
+        None => Err(format!(
+            "{} isn't installed but {} records {}; run `tugger update-lock`",
+            program, LOCK_FILENAME, locked
+        )),
+    }
+}
@@ -2,16 +2,81 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+pub mod appcast;
+pub mod archive;
+pub mod breakpad;
+pub mod build_environment;
+pub mod build_info;
+pub mod bundle;
+pub mod cache;
+pub mod cargo;
+pub mod channel;
 pub mod cli;
+pub mod completions;
+pub mod compression;
+pub mod conda;
+pub mod container;
 pub mod debian;
+pub mod debian_version;
+pub mod delta;
+pub mod deploy;
+pub mod desktop;
+pub mod env_file;
+pub mod error;
+pub mod extract;
+pub mod fetch;
 pub mod filemanifest;
+pub mod git;
+pub mod github;
+pub mod github_actions;
 pub mod glob;
+pub mod http;
+pub mod icons;
+pub mod inspect;
+pub mod inventory;
+pub mod licenses;
+pub mod lock;
+pub mod make;
+pub mod mirror;
+pub mod mode_policy;
+pub mod multipart;
+pub mod naming;
+pub mod node;
+pub mod notify;
+pub mod observer;
+#[cfg(feature = "otel")]
+pub mod otel;
+pub mod package_hosting;
+pub mod package_metadata;
+pub mod pe_resources;
+pub mod pipeline;
+pub mod process;
+pub mod release_notes;
+pub mod repl;
+pub mod report;
+pub mod reproducibility;
+pub mod resources;
+pub mod resumable;
+pub mod retry;
+pub mod rpm;
+pub mod runs;
+pub mod secrets;
+pub mod shared_libs;
+pub mod signing;
 pub mod snap;
+pub mod squirrel;
+pub mod stamp;
 pub mod starlark;
+pub mod symbols;
+pub mod systemd;
+pub mod tools;
+pub mod upload;
+pub mod version;
+pub mod xml;
 
 fn main() {
     if let Err(e) = cli::run_cli() {
-        println!("Error: {}", e);
+        println!("Error: {}", secrets::redact(&e.render()));
         std::process::exit(1);
     }
 }
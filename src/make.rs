@@ -0,0 +1,106 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Installing make and CMake projects into staging directories.
+*/
+
+use serde::Serialize;
+use std::path::Path;
+use std::process::Command;
+use std::str::FromStr;
+
+/// A build system whose install rules can be run.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BuildSystem {
+    Make,
+    Cmake,
+}
+
+impl FromStr for BuildSystem {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "make" => Ok(BuildSystem::Make),
+            "cmake" => Ok(BuildSystem::Cmake),
+            _ => Err(format!(
+                "unknown build system {}; expected make or cmake",
+                s
+            )),
+        }
+    }
+}
+
+impl BuildSystem {
+    /// Determine the build system of a configured build directory.
+    ///
+    /// CMake build directories are identified by their `CMakeCache.txt`.
+    /// Defaults to make.
+    pub fn detect(dir: &Path) -> Self {
+        if dir.join("CMakeCache.txt").exists() {
+            BuildSystem::Cmake
+        } else {
+            BuildSystem::Make
+        }
+    }
+
+    fn program(self) -> &'static str {
+        match self {
+            BuildSystem::Make => "make",
+            BuildSystem::Cmake => "cmake",
+        }
+    }
+}
+
+/// Run the install rules of a configured build directory.
+///
+/// Files are installed into `destdir` via the `DESTDIR` convention, so
+/// they end up at `destdir` joined with their install path. `prefix`
+/// overrides the install prefix of CMake projects. The prefix of make
+/// projects is chosen when they are configured.
+pub fn make_install(
+    dir: &Path,
+    build_system: BuildSystem,
+    destdir: &Path,
+    prefix: Option<&str>,
+) -> Result<(), String> {
+    std::fs::create_dir_all(destdir)
+        .map_err(|e| format!("unable to create {}: {}", destdir.display(), e))?;
+
+    let mut command = match build_system {
+        BuildSystem::Make => {
+            let mut command = Command::new(build_system.program());
+            command
+                .arg("-C")
+                .arg(dir)
+                .arg("install")
+                .arg(format!("DESTDIR={}", destdir.display()));
+            command
+        }
+        BuildSystem::Cmake => {
+            let mut command = Command::new(build_system.program());
+            command.arg("--install").arg(dir).env("DESTDIR", destdir);
+
+            if let Some(prefix) = prefix {
+                command.arg("--prefix").arg(prefix);
+            }
+
+            command
+        }
+    };
+
+    crate::process::record_program(build_system.program());
+
+    let status = command
+        .status()
+        .map_err(|e| format!("error running {}: {}", build_system.program(), e))?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("installing {} failed", dir.display()))
+    }
+}
@@ -0,0 +1,465 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Uploading the same artifacts to several destinations.
+
+Releases are commonly published to more than one place, e.g. a bucket
+backing a download site, a mirror server, and a GitHub release.
+Mirroring uploads artifacts to every destination, continuing after
+failures, and reports the outcome of each.
+*/
+
+use crate::deploy::{RemoteProtocol, SshOptions};
+use crate::retry::{AttemptError, RetryPolicy};
+use crate::secrets::Secret;
+use serde::Serialize;
+use slog::{warn, Logger};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+
+/// A place artifacts are uploaded to.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MirrorDestination {
+    /// An S3 bucket and prefix, uploaded to with the AWS CLI.
+    S3 {
+        url: String,
+
+        /// Files larger than this are uploaded in resumable parts of
+        /// this size.
+        part_size: u64,
+
+        concurrency: Option<usize>,
+    },
+
+    /// A Google Cloud Storage bucket and prefix, uploaded to with
+    /// `gcloud storage`.
+    Gcs {
+        url: String,
+
+        /// Files larger than this are uploaded in resumable sessions, in
+        /// chunks of this size.
+        part_size: u64,
+
+        /// Base URL of the JSON API resumable uploads are made with.
+        api_url: String,
+
+        concurrency: Option<usize>,
+    },
+
+    /// A directory on a host reachable over SSH, uploaded to with `sftp`.
+    Sftp {
+        host: String,
+        dest: String,
+        ssh: SshOptions,
+        concurrency: Option<usize>,
+    },
+
+    /// A GitHub release.
+    GithubRelease {
+        /// Repository of the form `owner/repo`.
+        repo: String,
+
+        tag: String,
+        token: Secret,
+
+        /// Base URL of the GitHub API.
+        url: String,
+
+        concurrency: Option<usize>,
+    },
+
+    /// A URL files are uploaded to over HTTP.
+    Http {
+        url: String,
+        protocol: HttpProtocol,
+
+        /// Size of chunks of resumable uploads.
+        part_size: u64,
+
+        /// Bearer token sent with requests, if any.
+        token: Option<Secret>,
+
+        concurrency: Option<usize>,
+    },
+}
+
+/// How files are uploaded to an HTTP destination.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HttpProtocol {
+    /// A `PUT` request of each file below the URL.
+    Put,
+
+    /// Resumable uploads to a tus server.
+    Tus,
+}
+
+impl FromStr for HttpProtocol {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "put" => Ok(HttpProtocol::Put),
+            "tus" => Ok(HttpProtocol::Tus),
+            _ => Err(format!("unknown protocol {}; expected put or tus", s)),
+        }
+    }
+}
+
+impl MirrorDestination {
+    /// Describe the destination in messages.
+    pub fn describe(&self) -> String {
+        match self {
+            MirrorDestination::S3 { url, .. }
+            | MirrorDestination::Gcs { url, .. }
+            | MirrorDestination::Http { url, .. } => url.clone(),
+            MirrorDestination::Sftp { host, dest, .. } => format!("{}:{}", host, dest),
+            MirrorDestination::GithubRelease { repo, tag, .. } => {
+                format!("GitHub release {} of {}", tag, repo)
+            }
+        }
+    }
+
+    /// Maximum number of files uploaded to this destination at once, if
+    /// it overrides that of the mirror.
+    pub fn concurrency(&self) -> Option<usize> {
+        match self {
+            MirrorDestination::S3 { concurrency, .. }
+            | MirrorDestination::Gcs { concurrency, .. }
+            | MirrorDestination::Sftp { concurrency, .. }
+            | MirrorDestination::GithubRelease { concurrency, .. }
+            | MirrorDestination::Http { concurrency, .. } => *concurrency,
+        }
+    }
+
+    /// Upload artifacts to this destination.
+    ///
+    /// Up to `concurrency` files are uploaded at once unless the
+    /// destination defines its own limit.
+    pub fn upload(
+        &self,
+        logger: &Logger,
+        artifacts: &[PathBuf],
+        concurrency: usize,
+        retry: &RetryPolicy,
+    ) -> Result<(), String> {
+        let concurrency = self.concurrency().unwrap_or(concurrency);
+
+        match self {
+            MirrorDestination::S3 { url, part_size, .. } => {
+                s3_upload(logger, url, artifacts, *part_size, concurrency, retry)
+            }
+            MirrorDestination::Gcs {
+                url,
+                part_size,
+                api_url,
+                ..
+            } => gcs_upload(
+                logger,
+                url,
+                api_url,
+                artifacts,
+                *part_size,
+                concurrency,
+                retry,
+            ),
+            MirrorDestination::Sftp {
+                host, dest, ssh, ..
+            } => crate::deploy::remote_copy(
+                logger,
+                host,
+                dest,
+                artifacts,
+                RemoteProtocol::Sftp,
+                ssh,
+                concurrency,
+                retry,
+            ),
+            MirrorDestination::GithubRelease {
+                repo,
+                tag,
+                token,
+                url,
+                ..
+            } => crate::github::github_release_upload(
+                logger,
+                url,
+                repo,
+                tag,
+                artifacts,
+                &token.resolve()?,
+                concurrency,
+                retry,
+            ),
+            MirrorDestination::Http {
+                url,
+                protocol,
+                part_size,
+                token,
+                ..
+            } => {
+                let token = match token {
+                    Some(token) => Some(token.resolve()?),
+                    None => None,
+                };
+
+                match protocol {
+                    HttpProtocol::Put => {
+                        http_upload(logger, url, token.as_deref(), artifacts, concurrency, retry)
+                    }
+                    HttpProtocol::Tus => crate::upload::upload_concurrently(
+                        logger,
+                        artifacts,
+                        concurrency,
+                        |artifact| {
+                            crate::resumable::tus_upload(
+                                logger,
+                                artifact,
+                                url,
+                                token.as_deref(),
+                                *part_size,
+                                retry,
+                            )
+                        },
+                    ),
+                }
+            }
+        }
+    }
+}
+
+/// Obtain the filename of an artifact.
+fn artifact_filename(artifact: &Path) -> Result<String, String> {
+    artifact
+        .file_name()
+        .map(|f| f.to_string_lossy().to_string())
+        .ok_or_else(|| format!("{} has no filename", artifact.display()))
+}
+
+/// Copy files into an S3 bucket and prefix using the AWS CLI.
+///
+/// Files larger than `part_size` are uploaded with a resumable multipart
+/// upload. Up to `concurrency` files are uploaded at once.
+fn s3_upload(
+    logger: &Logger,
+    url: &str,
+    artifacts: &[PathBuf],
+    part_size: u64,
+    concurrency: usize,
+    retry: &RetryPolicy,
+) -> Result<(), String> {
+    crate::upload::upload_concurrently(logger, artifacts, concurrency, |artifact| {
+        let filename = artifact_filename(artifact)?;
+
+        let size = std::fs::metadata(artifact)
+            .map_err(|e| format!("unable to stat {}: {}", artifact.display(), e))?
+            .len();
+        if size > part_size {
+            return crate::multipart::s3_multipart_upload(
+                logger,
+                artifact,
+                &format!("{}/{}", url, filename),
+                part_size,
+                retry,
+            );
+        }
+
+        retry.retry(logger, &format!("upload of {}", filename), || {
+            crate::process::run_logged(
+                logger,
+                Command::new("aws")
+                    .arg("s3")
+                    .arg("cp")
+                    .arg("--no-progress")
+                    .arg(artifact)
+                    .arg(format!("{}/{}", url, filename)),
+            )
+            .map_err(AttemptError::process)
+        })
+    })
+}
+
+/// Copy files into a Google Cloud Storage bucket and prefix.
+///
+/// Files larger than `part_size` are uploaded in a resumable session of
+/// the JSON API at `api_url`, whose state is recorded in the cache. Up to
+/// `concurrency` files are uploaded at once.
+fn gcs_upload(
+    logger: &Logger,
+    url: &str,
+    api_url: &str,
+    artifacts: &[PathBuf],
+    part_size: u64,
+    concurrency: usize,
+    retry: &RetryPolicy,
+) -> Result<(), String> {
+    crate::upload::upload_concurrently(logger, artifacts, concurrency, |artifact| {
+        let filename = artifact_filename(artifact)?;
+
+        let size = std::fs::metadata(artifact)
+            .map_err(|e| format!("unable to stat {}: {}", artifact.display(), e))?
+            .len();
+        if size > part_size {
+            return crate::resumable::gcs_resumable_upload(
+                logger,
+                artifact,
+                &format!("{}/{}", url, filename),
+                api_url,
+                part_size,
+                retry,
+            );
+        }
+
+        retry.retry(logger, &format!("upload of {}", filename), || {
+            crate::process::run_logged(
+                logger,
+                Command::new("gcloud")
+                    .arg("storage")
+                    .arg("cp")
+                    .arg("--no-user-output-enabled")
+                    .arg(artifact)
+                    .arg(format!("{}/{}", url, filename)),
+            )
+            .map_err(AttemptError::process)
+        })
+    })
+}
+
+/// Upload files below a URL with HTTP `PUT` requests.
+///
+/// Each file is uploaded to `<url>/<filename>`, with `token` as bearer
+/// token if defined. Up to `concurrency` files are uploaded at once.
+fn http_upload(
+    logger: &Logger,
+    url: &str,
+    token: Option<&str>,
+    artifacts: &[PathBuf],
+    concurrency: usize,
+    retry: &RetryPolicy,
+) -> Result<(), String> {
+    crate::upload::upload_concurrently(logger, artifacts, concurrency, |artifact| {
+        let filename = artifact_filename(artifact)?;
+        let url = format!("{}/{}", url, crate::http::percent_encode(&filename));
+
+        retry.retry(logger, &format!("upload of {}", filename), || {
+            let file = std::fs::File::open(artifact).map_err(|e| {
+                AttemptError::permanent(format!("unable to open {}: {}", artifact.display(), e))
+            })?;
+            let size = file.metadata().map(|m| m.len()).unwrap_or(0);
+
+            let mut request = ureq::put(&url)
+                .set("Content-Type", "application/octet-stream")
+                .set("Content-Length", &size.to_string());
+            if let Some(token) = token {
+                request = request.set("Authorization", &format!("Bearer {}", token));
+            }
+
+            request.send(file).map_err(AttemptError::http)
+        })?;
+
+        Ok(())
+    })
+}
+
+/// Which destinations must succeed for mirroring to succeed.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MirrorRequirement {
+    All,
+    Any,
+}
+
+impl FromStr for MirrorRequirement {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "all" => Ok(MirrorRequirement::All),
+            "any" => Ok(MirrorRequirement::Any),
+            _ => Err(format!("unknown requirement {}; expected all or any", s)),
+        }
+    }
+}
+
+/// Upload artifacts to several destinations.
+///
+/// Every destination is attempted, even if uploading to an earlier one
+/// failed. The outcome of each is logged. Fails if any destination failed
+/// and `require` is `All`, or if every destination failed. Up to
+/// `concurrency` files are uploaded to a destination at once.
+pub fn mirror(
+    logger: &Logger,
+    artifacts: &[PathBuf],
+    destinations: &[MirrorDestination],
+    require: MirrorRequirement,
+    concurrency: usize,
+    retry: &RetryPolicy,
+) -> Result<(), String> {
+    for artifact in artifacts {
+        if !artifact.is_file() {
+            return Err(format!("{} is not a file", artifact.display()));
+        }
+    }
+
+    let mut failures = Vec::new();
+
+    for destination in destinations {
+        warn!(
+            logger,
+            "mirroring {} files to {}",
+            artifacts.len(),
+            destination.describe()
+        );
+
+        if let Err(e) = destination.upload(logger, artifacts, concurrency, retry) {
+            warn!(
+                logger,
+                "mirroring to {} failed: {}",
+                destination.describe(),
+                e
+            );
+            failures.push(format!("{}: {}", destination.describe(), e));
+        }
+    }
+
+    let succeeded = destinations.len() - failures.len();
+
+    warn!(
+        logger,
+        "mirrored to {} of {} destinations",
+        succeeded,
+        destinations.len()
+    );
+
+    if failures.is_empty() || (require == MirrorRequirement::Any && succeeded > 0) {
+        Ok(())
+    } else {
+        Err(format!(
+            "mirroring to {} of {} destinations failed: {}",
+            failures.len(),
+            destinations.len(),
+            failures.join("; ")
+        ))
+    }
+}
+
+/// Represents a request to upload artifacts to several destinations.
+#[derive(Debug, Clone, Serialize)]
+pub struct Mirror {
+    /// Paths to files relative to the distribution path.
+    pub artifacts: Vec<String>,
+
+    pub destinations: Vec<MirrorDestination>,
+    pub require: MirrorRequirement,
+
+    /// Maximum number of files uploaded to a destination at once.
+    pub concurrency: usize,
+
+    /// How failed operations are retried, if not the pipeline's policy.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry: Option<RetryPolicy>,
+}
@@ -0,0 +1,87 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Permissions of files written by archive and install backends.
+
+Files in a `FileManifest` only record whether they are executable. The
+permissions they are given in archives, packages, and install
+directories are defined by a `ModePolicy`, so they are the same across
+backends and can be configured per pipeline.
+*/
+
+use crate::filemanifest::FileContent;
+use serde::Serialize;
+
+/// Mode of symlinks. Permissions of symlinks are ignored on most systems.
+pub const SYMLINK_MODE: u32 = 0o777;
+
+/// Defines the permissions of files and directories.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct ModePolicy {
+    /// Mode of files that aren't executable.
+    pub files: u32,
+
+    /// Mode of executable files.
+    pub executables: u32,
+
+    /// Mode of directories.
+    pub dirs: u32,
+
+    /// Permission bits cleared from every mode.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub umask: Option<u32>,
+}
+
+impl Default for ModePolicy {
+    fn default() -> Self {
+        ModePolicy {
+            files: 0o644,
+            executables: 0o755,
+            dirs: 0o755,
+            umask: None,
+        }
+    }
+}
+
+impl ModePolicy {
+    /// Validate that modes are permission bits.
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, mode) in &[
+            ("files", Some(self.files)),
+            ("executables", Some(self.executables)),
+            ("dirs", Some(self.dirs)),
+            ("umask", self.umask),
+        ] {
+            if let Some(mode) = mode {
+                if *mode > 0o7777 {
+                    return Err(format!(
+                        "{} mode {:o} has bits other than permission bits",
+                        name, mode
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn apply_umask(&self, mode: u32) -> u32 {
+        mode & !self.umask.unwrap_or(0)
+    }
+
+    /// Obtain the mode of a file in a manifest.
+    pub fn file_mode(&self, content: &FileContent) -> u32 {
+        match content {
+            FileContent::Symlink(_) => SYMLINK_MODE,
+            _ if content.is_executable() => self.apply_umask(self.executables),
+            _ => self.apply_umask(self.files),
+        }
+    }
+
+    /// Obtain the mode of directories.
+    pub fn dir_mode(&self) -> u32 {
+        self.apply_umask(self.dirs)
+    }
+}
@@ -0,0 +1,373 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Resumable multipart uploads to S3.
+
+Large artifacts are uploaded to S3 in parts with the multipart upload
+API of the AWS CLI (`aws s3api`). The upload ID of an unfinished upload
+is persisted in the `uploads` namespace of the cache, so running the
+upload again, after a dropped connection or a killed process, only
+uploads the parts S3 doesn't have yet instead of starting from zero.
+
+Which parts S3 has is obtained with `list-parts`, so parts uploaded
+right before an interruption aren't uploaded twice. If the upload no
+longer exists, e.g. because a lifecycle rule aborted it, or the artifact
+changed since it started, a new upload is started.
+*/
+
+use crate::retry::{AttemptError, RetryPolicy};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use slog::{warn, Logger};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::UNIX_EPOCH;
+
+/// Default size of parts of multipart uploads.
+pub const DEFAULT_PART_SIZE: u64 = 64 << 20;
+
+/// Smallest size of parts S3 accepts, except for the last part.
+pub const MIN_PART_SIZE: u64 = 5 << 20;
+
+/// Largest number of parts of an upload S3 accepts.
+const MAX_PARTS: u64 = 10_000;
+
+/// Cache namespace holding the state of unfinished uploads.
+const UPLOADS_NAMESPACE: &str = "uploads";
+
+/// State of an unfinished upload, persisted between runs.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+struct UploadState {
+    bucket: String,
+    key: String,
+    upload_id: String,
+    part_size: u64,
+
+    /// Size and modification time of the artifact when the upload started.
+    size: u64,
+    modified: u64,
+}
+
+/// A part S3 has.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct Part {
+    part_number: u64,
+    #[serde(rename = "ETag")]
+    etag: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ListPartsOutput {
+    #[serde(default)]
+    parts: Vec<Part>,
+}
+
+/// Split an `s3://bucket/key` URL into the bucket and key.
+pub fn parse_s3_url(url: &str) -> Result<(String, String), String> {
+    let path = url
+        .strip_prefix("s3://")
+        .ok_or_else(|| format!("{} is not an s3:// URL", url))?;
+
+    match path.split_once('/') {
+        Some((bucket, key)) if !bucket.is_empty() && !key.is_empty() => {
+            Ok((bucket.to_string(), key.to_string()))
+        }
+        _ => Err(format!("{} doesn't name an object in a bucket", url)),
+    }
+}
+
+/// Obtain the part size of an upload of `size` bytes.
+///
+/// The part size is increased if the upload would have too many parts.
+pub fn effective_part_size(size: u64, part_size: u64) -> u64 {
+    part_size.max(MIN_PART_SIZE).max(size.div_ceil(MAX_PARTS))
+}
+
+/// Obtain the size and modification time of a file.
+///
+/// Unfinished uploads are only resumed if both are unchanged.
+pub(crate) fn file_version(artifact: &Path) -> Result<(u64, u64), String> {
+    let metadata = std::fs::metadata(artifact)
+        .map_err(|e| format!("unable to stat {}: {}", artifact.display(), e))?;
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    Ok((metadata.len(), modified))
+}
+
+/// Run an `aws` command and parse the JSON it prints.
+fn aws_json<T: for<'de> Deserialize<'de> + Default>(command: &mut Command) -> Result<T, String> {
+    crate::process::record_program("aws");
+
+    let output = command
+        .arg("--output")
+        .arg("json")
+        .output()
+        .map_err(|e| format!("error running aws: {}", e))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "aws failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    // Some commands print nothing if there is nothing to report.
+    if output.stdout.iter().all(|b| b.is_ascii_whitespace()) {
+        return Ok(T::default());
+    }
+
+    serde_json::from_slice(&output.stdout)
+        .map_err(|e| format!("unable to parse output of aws: {}", e))
+}
+
+fn s3api(subcommand: &str, bucket: &str, key: &str) -> Command {
+    let mut command = Command::new("aws");
+    command
+        .arg("s3api")
+        .arg(subcommand)
+        .arg("--bucket")
+        .arg(bucket)
+        .arg("--key")
+        .arg(key);
+
+    command
+}
+
+/// Obtain the path of the state of an upload of an artifact to a URL.
+pub(crate) fn state_path(url: &str, artifact: &Path) -> Result<PathBuf, String> {
+    let artifact = artifact
+        .canonicalize()
+        .map_err(|e| format!("unable to resolve {}: {}", artifact.display(), e))?;
+
+    let mut hasher = Sha256::new();
+    hasher.input(url.as_bytes());
+    hasher.input(b"\0");
+    hasher.input(artifact.to_string_lossy().as_bytes());
+
+    Ok(crate::cache::cache_dir()?
+        .join(UPLOADS_NAMESPACE)
+        .join(format!("{:x}.json", hasher.result())))
+}
+
+pub(crate) fn read_state<T: for<'de> Deserialize<'de>>(path: &Path) -> Option<T> {
+    let data = std::fs::read(path).ok()?;
+
+    serde_json::from_slice(&data).ok()
+}
+
+pub(crate) fn write_state<T: Serialize>(path: &Path, state: &T) -> Result<(), String> {
+    std::fs::create_dir_all(path.parent().unwrap())
+        .map_err(|e| format!("unable to create {}: {}", path.display(), e))?;
+
+    let data = serde_json::to_vec_pretty(state).map_err(|e| e.to_string())?;
+    std::fs::write(path, data).map_err(|e| format!("unable to write {}: {}", path.display(), e))
+}
+
+/// Obtain the parts S3 has of an unfinished upload.
+///
+/// Returns `None` if the upload can't be resumed.
+fn resume(logger: &Logger, state: &UploadState) -> Option<Vec<Part>> {
+    let res: Result<ListPartsOutput, String> = aws_json(
+        s3api("list-parts", &state.bucket, &state.key)
+            .arg("--upload-id")
+            .arg(&state.upload_id),
+    );
+
+    match res {
+        Ok(output) => Some(output.parts),
+        Err(e) => {
+            warn!(
+                logger,
+                "unable to resume upload to s3://{}/{}: {}", state.bucket, state.key, e
+            );
+            None
+        }
+    }
+}
+
+/// Write a part of a file to a temporary file.
+///
+/// `aws s3api upload-part` uploads whole files.
+fn write_part(
+    source: &mut std::fs::File,
+    dest: &Path,
+    offset: u64,
+    len: u64,
+) -> Result<(), String> {
+    source
+        .seek(SeekFrom::Start(offset))
+        .map_err(|e| format!("unable to seek: {}", e))?;
+
+    let mut fh = std::fs::File::create(dest)
+        .map_err(|e| format!("unable to create {}: {}", dest.display(), e))?;
+    let copied = std::io::copy(&mut source.take(len), &mut fh)
+        .map_err(|e| format!("unable to write {}: {}", dest.display(), e))?;
+
+    if copied != len {
+        return Err("artifact was truncated during upload".to_string());
+    }
+
+    Ok(())
+}
+
+/// Upload a file to S3 in parts, resuming an unfinished upload of it.
+///
+/// `url` is the `s3://bucket/key` URL of the object to write.
+pub fn s3_multipart_upload(
+    logger: &Logger,
+    artifact: &Path,
+    url: &str,
+    part_size: u64,
+    retry: &RetryPolicy,
+) -> Result<(), String> {
+    let (bucket, key) = parse_s3_url(url)?;
+
+    let (size, modified) = file_version(artifact)?;
+    let part_size = effective_part_size(size, part_size);
+
+    let state_path = state_path(url, artifact)?;
+
+    let previous = read_state::<UploadState>(&state_path).filter(|state| {
+        state.bucket == bucket
+            && state.key == key
+            && state.part_size == part_size
+            && state.size == size
+            && state.modified == modified
+    });
+
+    let (state, mut parts) = match previous.and_then(|state| {
+        let parts = resume(logger, &state)?;
+        Some((state, parts))
+    }) {
+        Some((state, parts)) => {
+            warn!(
+                logger,
+                "resuming upload of {} to {} with {} parts uploaded",
+                artifact.display(),
+                url,
+                parts.len()
+            );
+            (state, parts)
+        }
+        None => {
+            #[derive(Default, Deserialize)]
+            #[serde(rename_all = "PascalCase")]
+            struct CreateOutput {
+                upload_id: String,
+            }
+
+            let output: CreateOutput =
+                retry.retry(logger, &format!("upload to {}", url), || {
+                    aws_json(&mut s3api("create-multipart-upload", &bucket, &key))
+                        .map_err(AttemptError::process)
+                })?;
+
+            if output.upload_id.is_empty() {
+                return Err(format!("aws didn't start an upload to {}", url));
+            }
+
+            let state = UploadState {
+                bucket: bucket.clone(),
+                key: key.clone(),
+                upload_id: output.upload_id,
+                part_size,
+                size,
+                modified,
+            };
+            write_state(&state_path, &state)?;
+
+            (state, Vec::new())
+        }
+    };
+
+    let part_count = size.div_ceil(part_size).max(1);
+
+    let temp_dir = tempfile::Builder::new()
+        .prefix("tugger-upload-")
+        .tempdir()
+        .map_err(|e| format!("unable to create temp directory: {}", e))?;
+    let part_path = temp_dir.path().join("part");
+
+    let mut fh = std::fs::File::open(artifact)
+        .map_err(|e| format!("unable to open {}: {}", artifact.display(), e))?;
+
+    for part_number in 1..=part_count {
+        if parts.iter().any(|p| p.part_number == part_number) {
+            continue;
+        }
+
+        let offset = (part_number - 1) * part_size;
+        write_part(&mut fh, &part_path, offset, part_size.min(size - offset))?;
+
+        #[derive(Default, Deserialize)]
+        struct UploadPartOutput {
+            #[serde(rename = "ETag")]
+            etag: String,
+        }
+
+        warn!(
+            logger,
+            "uploading part {} of {} of {}",
+            part_number,
+            part_count,
+            artifact.display()
+        );
+
+        let output: UploadPartOutput = retry.retry(
+            logger,
+            &format!("upload of part {} to {}", part_number, url),
+            || {
+                aws_json(
+                    s3api("upload-part", &bucket, &key)
+                        .arg("--upload-id")
+                        .arg(&state.upload_id)
+                        .arg("--part-number")
+                        .arg(part_number.to_string())
+                        .arg("--body")
+                        .arg(&part_path),
+                )
+                .map_err(AttemptError::process)
+            },
+        )?;
+
+        parts.push(Part {
+            part_number,
+            etag: output.etag,
+        });
+    }
+
+    parts.sort_by_key(|p| p.part_number);
+    parts.retain(|p| p.part_number <= part_count);
+
+    let parts_path = temp_dir.path().join("parts.json");
+    std::fs::write(
+        &parts_path,
+        serde_json::json!({ "Parts": parts }).to_string(),
+    )
+    .map_err(|e| format!("unable to write {}: {}", parts_path.display(), e))?;
+
+    retry.retry(logger, &format!("upload to {}", url), || {
+        aws_json::<serde_json::Value>(
+            s3api("complete-multipart-upload", &bucket, &key)
+                .arg("--upload-id")
+                .arg(&state.upload_id)
+                .arg("--multipart-upload")
+                .arg(format!("file://{}", parts_path.display())),
+        )
+        .map_err(AttemptError::process)
+    })?;
+
+    let _ = std::fs::remove_file(&state_path);
+
+    Ok(())
+}
@@ -0,0 +1,174 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Naming artifacts with templates.
+
+A name template such as `{{name}}-{{version}}-{{target}}.{{ext}}`
+defines the filenames of artifacts of archive and packaging steps in
+one place. Its placeholders are replaced by values of the package the
+artifact belongs to and the pipeline building it:
+
+* `{{name}}` - the name of the package.
+* `{{version}}` - the version of the package.
+* `{{target}}` - the target triple of the pipeline.
+* `{{channel}}` - the release channel being built.
+* `{{ext}}` - the extension of the artifact's format, without the
+  leading `.`, e.g. `tar.gz`.
+*/
+
+use crate::channel::Channel;
+use serde::Serialize;
+
+/// Placeholders of name templates.
+pub const PLACEHOLDERS: &[&str] = &["name", "version", "target", "channel", "ext"];
+
+/// Extensions consisting of several components.
+const COMPOUND_EXTENSIONS: &[&str] = &["tar.gz", "tar.xz", "tar.zst", "tar.bz2"];
+
+/// Values of the placeholders of a name template.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NameValues<'a> {
+    pub name: &'a str,
+    pub version: &'a str,
+    pub target: &'a str,
+    pub channel: Channel,
+    pub ext: &'a str,
+}
+
+impl NameValues<'_> {
+    fn get(&self, placeholder: &str) -> Option<&str> {
+        match placeholder {
+            "name" => Some(self.name),
+            "version" => Some(self.version),
+            "target" => Some(self.target),
+            "channel" => Some(self.channel.as_str()),
+            "ext" => Some(self.ext),
+            _ => None,
+        }
+    }
+}
+
+/// Render a name template.
+///
+/// The result must be a filename: it can't be empty, `.`, or `..`, or
+/// contain path separators.
+pub fn render(template: &str, values: &NameValues) -> Result<String, String> {
+    let mut name = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        name.push_str(&rest[..start]);
+
+        let end = rest[start..]
+            .find("}}")
+            .ok_or_else(|| format!("unterminated placeholder in name template {}", template))?;
+        let placeholder = rest[start + 2..start + end].trim();
+
+        name.push_str(values.get(placeholder).ok_or_else(|| {
+            format!(
+                "unknown placeholder {{{{{}}}}} in name template {}; expected one of {}",
+                placeholder,
+                template,
+                PLACEHOLDERS.join(", ")
+            )
+        })?);
+
+        rest = &rest[start + end + 2..];
+    }
+    name.push_str(rest);
+
+    if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+        return Err(format!(
+            "name template {} must produce a filename; got {:?}",
+            template, name
+        ));
+    }
+
+    Ok(name)
+}
+
+/// Validate a name template without rendering it.
+pub fn validate(template: &str) -> Result<(), String> {
+    render(
+        template,
+        &NameValues {
+            name: "name",
+            version: "version",
+            target: "target",
+            channel: Channel::default(),
+            ext: "ext",
+        },
+    )
+    .map(|_| ())
+}
+
+/// Obtain the extension of a filename, without the leading `.`.
+///
+/// Extensions of compressed tar archives, such as `tar.gz`, are kept
+/// whole.
+pub fn extension(filename: &str) -> Option<&str> {
+    for ext in COMPOUND_EXTENSIONS {
+        if filename.len() > ext.len() + 1
+            && filename.ends_with(ext)
+            && filename[..filename.len() - ext.len()].ends_with('.')
+        {
+            return Some(&filename[filename.len() - ext.len()..]);
+        }
+    }
+
+    match filename.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() && !ext.is_empty() => Some(ext),
+        _ => None,
+    }
+}
+
+/// An artifact whose filename can be defined by a name template.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ArtifactName {
+    /// Name of the package the artifact belongs to.
+    pub name: String,
+
+    /// Version of the package.
+    pub version: String,
+
+    /// Extension of the artifact's format.
+    pub ext: String,
+
+    /// Template of the step producing the artifact.
+    ///
+    /// It takes precedence over the template of the pipeline.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+}
+
+impl ArtifactName {
+    /// Obtain the filename of the artifact.
+    ///
+    /// `template` is the template of the pipeline. Returns `None` if
+    /// neither the artifact nor the pipeline define a template.
+    pub fn resolve(
+        &self,
+        template: Option<&str>,
+        target: &str,
+        channel: Channel,
+    ) -> Result<Option<String>, String> {
+        let template = match self.template.as_deref().or(template) {
+            Some(template) => template,
+            None => return Ok(None),
+        };
+
+        render(
+            template,
+            &NameValues {
+                name: &self.name,
+                version: &self.version,
+                target,
+                channel,
+                ext: &self.ext,
+            },
+        )
+        .map(Some)
+    }
+}
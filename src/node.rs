@@ -0,0 +1,167 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Building JavaScript projects with Node.js package managers.
+*/
+
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+
+/// Name of file in `node_modules` recording the lockfile it was installed from.
+const INSTALL_STAMP: &str = ".tugger-lockfile-sha256";
+
+/// A Node.js package manager.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PackageManager {
+    Npm,
+    Pnpm,
+    Yarn,
+}
+
+impl FromStr for PackageManager {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "npm" => Ok(PackageManager::Npm),
+            "pnpm" => Ok(PackageManager::Pnpm),
+            "yarn" => Ok(PackageManager::Yarn),
+            _ => Err(format!(
+                "unknown package manager {}; expected npm, pnpm, or yarn",
+                s
+            )),
+        }
+    }
+}
+
+impl PackageManager {
+    /// Determine the package manager of a project from its lockfile.
+    ///
+    /// Defaults to npm.
+    pub fn detect(dir: &Path) -> Self {
+        if dir.join("pnpm-lock.yaml").exists() {
+            PackageManager::Pnpm
+        } else if dir.join("yarn.lock").exists() {
+            PackageManager::Yarn
+        } else {
+            PackageManager::Npm
+        }
+    }
+
+    fn program(self) -> &'static str {
+        match self {
+            PackageManager::Npm => "npm",
+            PackageManager::Pnpm => "pnpm",
+            PackageManager::Yarn => "yarn",
+        }
+    }
+
+    fn lockfile(self) -> &'static str {
+        match self {
+            PackageManager::Npm => "package-lock.json",
+            PackageManager::Pnpm => "pnpm-lock.yaml",
+            PackageManager::Yarn => "yarn.lock",
+        }
+    }
+
+    /// Arguments to install dependencies exactly as locked.
+    fn install_args(self) -> &'static [&'static str] {
+        match self {
+            PackageManager::Npm => &["ci"],
+            PackageManager::Pnpm => &["install", "--frozen-lockfile"],
+            PackageManager::Yarn => &["install", "--frozen-lockfile"],
+        }
+    }
+}
+
+fn run(dir: &Path, program: &str, args: &[&str]) -> Result<(), String> {
+    crate::process::record_program(program);
+
+    let status = Command::new(program)
+        .args(args)
+        .current_dir(dir)
+        .status()
+        .map_err(|e| format!("error running {}: {}", program, e))?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} {} failed in {}",
+            program,
+            args.join(" "),
+            dir.display()
+        ))
+    }
+}
+
+/// Install the dependencies of a project.
+///
+/// Installation is skipped if `node_modules` was installed from the
+/// current lockfile by a previous call.
+pub fn install_dependencies(dir: &Path, package_manager: PackageManager) -> Result<(), String> {
+    let lockfile = dir.join(package_manager.lockfile());
+    let stamp_path = dir.join("node_modules").join(INSTALL_STAMP);
+
+    let digest = if lockfile.exists() {
+        Some(crate::inventory::sha256_file(&lockfile)?)
+    } else {
+        None
+    };
+
+    if let Some(digest) = &digest {
+        if std::fs::read_to_string(&stamp_path).ok().as_ref() == Some(digest) {
+            return Ok(());
+        }
+    }
+
+    // Without a lockfile, dependencies can't be installed exactly as locked.
+    if digest.is_some() {
+        run(
+            dir,
+            package_manager.program(),
+            package_manager.install_args(),
+        )?;
+    } else {
+        run(dir, package_manager.program(), &["install"])?;
+    }
+
+    if let Some(digest) = digest {
+        // Projects without dependencies don't get a node_modules.
+        std::fs::create_dir_all(stamp_path.parent().unwrap())
+            .map_err(|e| format!("unable to create {}: {}", stamp_path.display(), e))?;
+        std::fs::write(&stamp_path, digest)
+            .map_err(|e| format!("unable to write {}: {}", stamp_path.display(), e))?;
+    }
+
+    Ok(())
+}
+
+/// Build a project by running a script defined in its `package.json`.
+///
+/// Returns the path of `output_dir` within the project.
+pub fn node_build(
+    dir: &Path,
+    package_manager: PackageManager,
+    command: &str,
+    output_dir: &str,
+) -> Result<PathBuf, String> {
+    install_dependencies(dir, package_manager)?;
+    run(dir, package_manager.program(), &["run", command])?;
+
+    let output_path = dir.join(output_dir);
+
+    if !output_path.is_dir() {
+        return Err(format!(
+            "build did not produce output directory {}",
+            output_path.display()
+        ));
+    }
+
+    Ok(output_path)
+}
@@ -0,0 +1,259 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Notifications about pipeline execution.
+
+Notifications are JSON documents posted to webhooks. Payloads can be
+shaped for Slack or Discord incoming webhooks or be a generic document
+describing the pipeline for consumption by other services.
+*/
+
+use crate::inventory::{Inventory, INVENTORY_FILENAME};
+use crate::report::PipelineReport;
+use crate::retry::{AttemptError, RetryPolicy};
+use crate::secrets::Secret;
+use serde::Serialize;
+use slog::{warn, Logger};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Template used when one isn't provided.
+pub const DEFAULT_TEMPLATE: &str = "Pipeline {{pipeline}} {{status}}
+
+{{steps}}
+
+{{artifacts}}";
+
+/// The shape of a notification payload.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotifyFormat {
+    /// A document describing the pipeline and its artifacts.
+    Json,
+
+    /// A Slack incoming webhook message.
+    Slack,
+
+    /// A Discord webhook message.
+    Discord,
+}
+
+impl FromStr for NotifyFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "json" => Ok(NotifyFormat::Json),
+            "slack" => Ok(NotifyFormat::Slack),
+            "discord" => Ok(NotifyFormat::Discord),
+            _ => Err(format!(
+                "unknown format {}; expected json, slack, or discord",
+                s
+            )),
+        }
+    }
+}
+
+/// When a notification is sent.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotifyWhen {
+    Always,
+    Success,
+    Failure,
+}
+
+impl NotifyWhen {
+    /// Whether to notify given whether the pipeline has failed.
+    pub fn applies(self, failed: bool) -> bool {
+        match self {
+            NotifyWhen::Always => true,
+            NotifyWhen::Success => !failed,
+            NotifyWhen::Failure => failed,
+        }
+    }
+}
+
+impl FromStr for NotifyWhen {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "always" => Ok(NotifyWhen::Always),
+            "success" => Ok(NotifyWhen::Success),
+            "failure" => Ok(NotifyWhen::Failure),
+            _ => Err(format!(
+                "unknown condition {}; expected always, success, or failure",
+                s
+            )),
+        }
+    }
+}
+
+fn status(report: &PipelineReport) -> &'static str {
+    if report.error.is_some() {
+        "failed"
+    } else {
+        "succeeded"
+    }
+}
+
+/// Render a notification message from a template.
+///
+/// `{{pipeline}}`, `{{status}}`, `{{error}}`, `{{steps}}`, and
+/// `{{artifacts}}` in the template are replaced with the pipeline name,
+/// `succeeded` or `failed`, the error message, a list of executed steps,
+/// and a list of artifacts, respectively.
+pub fn render_message(
+    template: &str,
+    report: &PipelineReport,
+    inventory: Option<&Inventory>,
+) -> String {
+    let steps = report
+        .steps
+        .iter()
+        .map(|step| {
+            format!(
+                "- {}: {} {} ({:.2}s)",
+                step.index,
+                step.kind,
+                if step.error.is_some() { "failed" } else { "ok" },
+                step.duration_secs
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let artifacts = inventory
+        .map(|inventory| {
+            inventory
+                .artifacts
+                .iter()
+                .map(|artifact| match &artifact.url {
+                    Some(url) => format!("- {} ({})", artifact.name, url),
+                    None => format!("- {}", artifact.name),
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+        .unwrap_or_default();
+
+    template
+        .replace("{{pipeline}}", &report.name)
+        .replace("{{status}}", status(report))
+        .replace("{{error}}", report.error.as_deref().unwrap_or(""))
+        .replace("{{steps}}", &steps)
+        .replace("{{artifacts}}", &artifacts)
+        .trim()
+        .to_string()
+}
+
+/// Build the JSON payload of a notification.
+pub fn payload(
+    format: NotifyFormat,
+    message: &str,
+    report: &PipelineReport,
+    inventory: Option<&Inventory>,
+) -> serde_json::Value {
+    match format {
+        NotifyFormat::Json => serde_json::json!({
+            "pipeline": report.name,
+            "status": status(report),
+            "message": message,
+            "report": report,
+            "artifacts": inventory.map(|i| &i.artifacts),
+        }),
+        NotifyFormat::Slack => serde_json::json!({ "text": message }),
+        NotifyFormat::Discord => serde_json::json!({ "content": message }),
+    }
+}
+
+/// Post a notification payload to a webhook.
+///
+/// Failed requests are retried according to `retry`.
+pub fn send(
+    logger: &Logger,
+    webhook_url: &str,
+    payload: &serde_json::Value,
+    retry: &RetryPolicy,
+) -> Result<(), String> {
+    warn!(logger, "sending notification");
+
+    let body = payload.to_string();
+
+    retry.retry(logger, "notification", || {
+        ureq::post(webhook_url)
+            .set("Content-Type", "application/json")
+            .send_string(&body)
+            .map_err(AttemptError::http)
+    })?;
+
+    Ok(())
+}
+
+/// Represents a request to send a notification to a webhook.
+#[derive(Debug, Clone, Serialize)]
+pub struct Notify {
+    /// URL of the webhook.
+    ///
+    /// Not serialized because webhook URLs typically embed credentials.
+    #[serde(skip)]
+    pub webhook_url: Option<String>,
+
+    /// Secret holding the webhook URL.
+    pub webhook_url_env: Option<Secret>,
+
+    pub template: Option<String>,
+    pub format: NotifyFormat,
+    pub when: NotifyWhen,
+
+    /// How failed operations are retried, if not the pipeline's policy.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry: Option<RetryPolicy>,
+}
+
+impl Notify {
+    /// Send a notification describing pipeline execution so far.
+    ///
+    /// The notification is only sent if `when` applies to the state of
+    /// the pipeline. Failed requests are retried according to `retry`.
+    pub fn execute(
+        &self,
+        logger: &Logger,
+        dist_path: &Path,
+        report: &PipelineReport,
+        retry: &RetryPolicy,
+    ) -> Result<(), String> {
+        if !self.when.applies(report.error.is_some()) {
+            return Ok(());
+        }
+
+        let webhook_url = match (&self.webhook_url, &self.webhook_url_env) {
+            (Some(url), _) => url.clone(),
+            (None, Some(secret)) => secret.resolve()?,
+            (None, None) => return Err("no webhook URL defined".to_string()),
+        };
+
+        let inventory_path = dist_path.join(INVENTORY_FILENAME);
+        let inventory = if inventory_path.exists() {
+            Some(Inventory::read(&inventory_path)?)
+        } else {
+            None
+        };
+
+        let message = render_message(
+            self.template.as_deref().unwrap_or(DEFAULT_TEMPLATE),
+            report,
+            inventory.as_ref(),
+        );
+
+        send(
+            logger,
+            &webhook_url,
+            &payload(self.format, &message, report, inventory.as_ref()),
+            retry,
+        )
+    }
+}
@@ -0,0 +1,104 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Observing pipeline execution.
+
+Applications embedding tugger can implement `ExecutionObserver` to be
+notified as pipelines and steps execute, for example to render progress
+or collect metrics, instead of parsing log output.
+*/
+
+use crate::pipeline::{Pipeline, Step};
+use crate::report::{PipelineReport, StepReport};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Receives notifications about pipeline execution.
+///
+/// All methods do nothing by default.
+pub trait ExecutionObserver {
+    /// Called before the steps of a pipeline execute.
+    fn on_pipeline_start(&mut self, _pipeline: &Pipeline) {}
+
+    /// Called after the steps of a pipeline executed.
+    fn on_pipeline_end(&mut self, _pipeline: &Pipeline, _report: &PipelineReport) {}
+
+    /// Called before a step executes.
+    fn on_step_start(&mut self, _pipeline: &Pipeline, _index: usize, _step: &Step) {}
+
+    /// Called after a step executed, whether or not it succeeded.
+    fn on_step_end(&mut self, _pipeline: &Pipeline, _report: &StepReport) {}
+
+    /// Called for each file a step created or modified in the distribution
+    /// directory, before `on_step_end()`.
+    fn on_artifact(&mut self, _pipeline: &Pipeline, _path: &Path) {}
+}
+
+/// Observers notified in turn.
+impl ExecutionObserver for Vec<Box<dyn ExecutionObserver>> {
+    fn on_pipeline_start(&mut self, pipeline: &Pipeline) {
+        for observer in self.iter_mut() {
+            observer.on_pipeline_start(pipeline);
+        }
+    }
+
+    fn on_pipeline_end(&mut self, pipeline: &Pipeline, report: &PipelineReport) {
+        for observer in self.iter_mut() {
+            observer.on_pipeline_end(pipeline, report);
+        }
+    }
+
+    fn on_step_start(&mut self, pipeline: &Pipeline, index: usize, step: &Step) {
+        for observer in self.iter_mut() {
+            observer.on_step_start(pipeline, index, step);
+        }
+    }
+
+    fn on_step_end(&mut self, pipeline: &Pipeline, report: &StepReport) {
+        for observer in self.iter_mut() {
+            observer.on_step_end(pipeline, report);
+        }
+    }
+
+    fn on_artifact(&mut self, pipeline: &Pipeline, path: &Path) {
+        for observer in self.iter_mut() {
+            observer.on_artifact(pipeline, path);
+        }
+    }
+}
+
+/// The size and modification time of files in a directory.
+pub(crate) struct DirectorySnapshot(BTreeMap<PathBuf, (u64, Option<SystemTime>)>);
+
+impl DirectorySnapshot {
+    /// Record the files in a directory. A missing directory has no files.
+    pub fn new(dir: &Path) -> Self {
+        DirectorySnapshot(
+            walkdir::WalkDir::new(dir)
+                .into_iter()
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.file_type().is_file())
+                .filter_map(|entry| {
+                    let metadata = entry.metadata().ok()?;
+                    Some((
+                        entry.into_path(),
+                        (metadata.len(), metadata.modified().ok()),
+                    ))
+                })
+                .collect(),
+        )
+    }
+
+    /// Obtain files that are new or modified in a later snapshot.
+    pub fn changed(&self, later: &DirectorySnapshot) -> Vec<PathBuf> {
+        later
+            .0
+            .iter()
+            .filter(|(path, state)| self.0.get(*path) != Some(state))
+            .map(|(path, _)| path.clone())
+            .collect()
+    }
+}
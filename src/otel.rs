@@ -0,0 +1,349 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+OpenTelemetry tracing of pipeline execution.
+
+`OtlpObserver` is an `ExecutionObserver` recording a span for each
+pipeline and, nested in it, a span for each step. Step spans carry the
+step's kind and index and the artifacts it produced. Spans are exported
+with the OTLP/HTTP JSON protocol when their pipeline ends, so they can
+be analyzed in Jaeger, Tempo, or any other OTLP collector.
+
+The exporter is configured with the standard OpenTelemetry environment
+variables:
+
+* `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT` or `OTEL_EXPORTER_OTLP_ENDPOINT`
+  (to which `/v1/traces` is appended) define where spans are sent.
+* `OTEL_EXPORTER_OTLP_HEADERS` defines extra HTTP headers as
+  comma-separated `key=value` pairs, e.g. for authentication.
+* `OTEL_SERVICE_NAME` defines the service name (default `tugger`).
+
+If `TRACEPARENT` holds a W3C trace context, e.g. one set by a CI system,
+pipeline spans become children of its span, so releases show up within
+the trace of the CI job running them.
+*/
+
+use crate::observer::ExecutionObserver;
+use crate::pipeline::{Pipeline, Step};
+use crate::report::{PipelineReport, StepReport};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use slog::{warn, Logger};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Span status codes of successful and failed operations.
+const STATUS_CODE_OK: u8 = 1;
+const STATUS_CODE_ERROR: u8 = 2;
+
+/// Span kind of operations internal to an application.
+const SPAN_KIND_INTERNAL: u8 = 1;
+
+/// Where to send spans and how.
+#[derive(Clone, Debug)]
+pub struct OtlpConfig {
+    /// URL spans are posted to.
+    pub endpoint: String,
+
+    /// Extra HTTP headers sent with each request.
+    pub headers: Vec<(String, String)>,
+
+    pub service_name: String,
+
+    /// Trace and span ID of a parent span, from `TRACEPARENT`.
+    pub parent: Option<(String, String)>,
+}
+
+impl OtlpConfig {
+    /// Obtain configuration from the environment.
+    ///
+    /// Returns `None` if no OTLP endpoint is defined.
+    pub fn from_env() -> Option<Self> {
+        let endpoint = match std::env::var("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT") {
+            Ok(endpoint) if !endpoint.is_empty() => endpoint,
+            _ => match std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT") {
+                Ok(endpoint) if !endpoint.is_empty() => {
+                    format!("{}/v1/traces", endpoint.trim_end_matches('/'))
+                }
+                _ => return None,
+            },
+        };
+
+        let headers = std::env::var("OTEL_EXPORTER_OTLP_HEADERS")
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|pair| {
+                let pos = pair.find('=')?;
+                Some((
+                    pair[..pos].trim().to_string(),
+                    pair[pos + 1..].trim().to_string(),
+                ))
+            })
+            .collect();
+
+        let service_name = std::env::var("OTEL_SERVICE_NAME")
+            .ok()
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| "tugger".to_string());
+
+        let parent = std::env::var("TRACEPARENT")
+            .ok()
+            .and_then(|value| parse_traceparent(&value));
+
+        Some(OtlpConfig {
+            endpoint,
+            headers,
+            service_name,
+            parent,
+        })
+    }
+}
+
+/// Parse a W3C `traceparent` value into its trace and span IDs.
+fn parse_traceparent(value: &str) -> Option<(String, String)> {
+    let parts: Vec<&str> = value.trim().split('-').collect();
+
+    match parts.as_slice() {
+        [_version, trace_id, span_id, _flags]
+            if trace_id.len() == 32
+                && span_id.len() == 16
+                && trace_id.chars().all(|c| c.is_ascii_hexdigit())
+                && span_id.chars().all(|c| c.is_ascii_hexdigit()) =>
+        {
+            Some((trace_id.to_lowercase(), span_id.to_lowercase()))
+        }
+        _ => None,
+    }
+}
+
+/// Generate a hex encoded random ID of `len` bytes.
+fn new_id(len: usize) -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let mut hasher = Sha256::new();
+    hasher.input(unix_nanos().to_le_bytes());
+    hasher.input(std::process::id().to_le_bytes());
+    hasher.input(COUNTER.fetch_add(1, Ordering::Relaxed).to_le_bytes());
+
+    hasher.result()[..len]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn unix_nanos() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0)
+}
+
+/// A recorded span.
+struct Span {
+    span_id: String,
+    parent_span_id: Option<String>,
+    name: String,
+    start: u128,
+    end: u128,
+    attributes: Vec<(String, serde_json::Value)>,
+    error: Option<String>,
+}
+
+impl Span {
+    fn new(name: String, parent_span_id: Option<String>) -> Self {
+        Span {
+            span_id: new_id(8),
+            parent_span_id,
+            name,
+            start: unix_nanos(),
+            end: 0,
+            attributes: vec![],
+            error: None,
+        }
+    }
+
+    fn to_json(&self, trace_id: &str) -> serde_json::Value {
+        let mut span = json!({
+            "traceId": trace_id,
+            "spanId": self.span_id,
+            "name": self.name,
+            "kind": SPAN_KIND_INTERNAL,
+            "startTimeUnixNano": self.start.to_string(),
+            "endTimeUnixNano": self.end.to_string(),
+            "attributes": self
+                .attributes
+                .iter()
+                .map(|(key, value)| json!({"key": key, "value": value}))
+                .collect::<Vec<_>>(),
+            "status": match &self.error {
+                Some(message) => json!({"code": STATUS_CODE_ERROR, "message": message}),
+                None => json!({"code": STATUS_CODE_OK}),
+            },
+        });
+
+        if let Some(parent) = &self.parent_span_id {
+            span["parentSpanId"] = json!(parent);
+        }
+
+        span
+    }
+}
+
+fn string_value(value: &str) -> serde_json::Value {
+    json!({ "stringValue": value })
+}
+
+fn int_value(value: usize) -> serde_json::Value {
+    // OTLP JSON encodes 64-bit integers as strings.
+    json!({ "intValue": value.to_string() })
+}
+
+/// An `ExecutionObserver` exporting spans to an OTLP collector.
+pub struct OtlpObserver {
+    logger: Logger,
+    config: OtlpConfig,
+    trace_id: String,
+    pipeline: Option<Span>,
+    step: Option<Span>,
+    artifacts: Vec<serde_json::Value>,
+    finished: Vec<Span>,
+}
+
+impl OtlpObserver {
+    pub fn new(logger: Logger, config: OtlpConfig) -> Self {
+        let trace_id = match &config.parent {
+            Some((trace_id, _)) => trace_id.clone(),
+            None => new_id(16),
+        };
+
+        OtlpObserver {
+            logger,
+            config,
+            trace_id,
+            pipeline: None,
+            step: None,
+            artifacts: vec![],
+            finished: vec![],
+        }
+    }
+
+    /// Construct an observer configured by the environment, if enabled.
+    pub fn from_env(logger: Logger) -> Option<Self> {
+        OtlpConfig::from_env().map(|config| OtlpObserver::new(logger, config))
+    }
+
+    /// The ID of the trace spans are recorded in.
+    pub fn trace_id(&self) -> &str {
+        &self.trace_id
+    }
+
+    /// Send finished spans to the collector.
+    ///
+    /// Export failures are logged rather than failing pipelines.
+    fn export(&mut self) {
+        if self.finished.is_empty() {
+            return;
+        }
+
+        let spans = std::mem::take(&mut self.finished);
+
+        let body = json!({
+            "resourceSpans": [{
+                "resource": {
+                    "attributes": [
+                        {"key": "service.name", "value": string_value(&self.config.service_name)},
+                    ],
+                },
+                "scopeSpans": [{
+                    "scope": {"name": "tugger", "version": env!("CARGO_PKG_VERSION")},
+                    "spans": spans
+                        .iter()
+                        .map(|span| span.to_json(&self.trace_id))
+                        .collect::<Vec<_>>(),
+                }],
+            }],
+        });
+
+        let mut request = ureq::post(&self.config.endpoint).set("Content-Type", "application/json");
+        for (key, value) in &self.config.headers {
+            request = request.set(key, value);
+        }
+
+        if let Err(e) = request.send_string(&body.to_string()) {
+            warn!(
+                self.logger,
+                "unable to export trace to {}: {}",
+                self.config.endpoint,
+                crate::http::error_message(e)
+            );
+        }
+    }
+}
+
+impl ExecutionObserver for OtlpObserver {
+    fn on_pipeline_start(&mut self, pipeline: &Pipeline) {
+        let parent = self
+            .config
+            .parent
+            .as_ref()
+            .map(|(_, span_id)| span_id.clone());
+        let mut span = Span::new(format!("pipeline {}", pipeline.name), parent);
+        span.attributes
+            .push(("tugger.pipeline".to_string(), string_value(&pipeline.name)));
+        span.attributes.push((
+            "tugger.pipeline.steps".to_string(),
+            int_value(pipeline.steps.len()),
+        ));
+
+        self.pipeline = Some(span);
+    }
+
+    fn on_pipeline_end(&mut self, _pipeline: &Pipeline, report: &PipelineReport) {
+        if let Some(mut span) = self.pipeline.take() {
+            span.end = unix_nanos();
+            span.error = report.error.clone();
+            self.finished.push(span);
+        }
+
+        self.export();
+    }
+
+    fn on_step_start(&mut self, pipeline: &Pipeline, index: usize, step: &Step) {
+        let parent = self.pipeline.as_ref().map(|span| span.span_id.clone());
+        let mut span = Span::new(step.kind().to_string(), parent);
+        span.attributes
+            .push(("tugger.pipeline".to_string(), string_value(&pipeline.name)));
+        span.attributes
+            .push(("tugger.step.index".to_string(), int_value(index)));
+        span.attributes
+            .push(("tugger.step.kind".to_string(), string_value(step.kind())));
+
+        self.step = Some(span);
+        self.artifacts.clear();
+    }
+
+    fn on_step_end(&mut self, _pipeline: &Pipeline, report: &StepReport) {
+        if let Some(mut span) = self.step.take() {
+            span.end = unix_nanos();
+            span.error = report.error.clone();
+
+            if !self.artifacts.is_empty() {
+                span.attributes.push((
+                    "tugger.artifacts".to_string(),
+                    json!({ "arrayValue": { "values": std::mem::take(&mut self.artifacts) } }),
+                ));
+            }
+
+            self.finished.push(span);
+        }
+    }
+
+    fn on_artifact(&mut self, pipeline: &Pipeline, path: &Path) {
+        self.artifacts.push(string_value(
+            &pipeline.artifact_path(path).display().to_string(),
+        ));
+    }
+}
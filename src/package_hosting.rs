@@ -0,0 +1,323 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Uploading packages to hosted repository services.
+
+Services like [packagecloud](https://packagecloud.io/) and
+[Gemfury](https://gemfury.com/) host package repositories and expose
+HTTP APIs for uploading packages to them.
+*/
+
+use crate::retry::{AttemptError, FailureClass, RetryPolicy};
+use crate::secrets::Secret;
+use serde::Serialize;
+use slog::{warn, Logger};
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Default base URL of the packagecloud API.
+pub const PACKAGECLOUD_URL: &str = "https://packagecloud.io";
+
+/// Base URL of the Gemfury push API.
+const GEMFURY_PUSH_URL: &str = "https://push.fury.io";
+
+/// Insert credentials for HTTP basic authentication into a URL.
+fn url_with_credentials(url: &str, username: &str, password: &str) -> Result<String, String> {
+    let pos = url
+        .find("://")
+        .ok_or_else(|| format!("{} is not a valid URL", url))?;
+
+    Ok(format!(
+        "{}{}:{}@{}",
+        &url[..pos + 3],
+        crate::http::percent_encode(username),
+        crate::http::percent_encode(password),
+        &url[pos + 3..]
+    ))
+}
+
+/// A `multipart/form-data` request body.
+struct MultipartForm {
+    boundary: String,
+    body: Vec<u8>,
+}
+
+impl MultipartForm {
+    fn new() -> Self {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or(0);
+
+        MultipartForm {
+            boundary: format!("tugger-boundary-{:x}", nanos),
+            body: Vec::new(),
+        }
+    }
+
+    fn add_text(&mut self, name: &str, value: &str) {
+        self.body.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+                self.boundary, name, value
+            )
+            .as_bytes(),
+        );
+    }
+
+    fn add_file(&mut self, name: &str, path: &Path) -> Result<(), String> {
+        let filename = path
+            .file_name()
+            .map(|f| f.to_string_lossy().replace('"', "_"))
+            .ok_or_else(|| format!("{} has no filename", path.display()))?;
+        let data =
+            std::fs::read(path).map_err(|e| format!("unable to read {}: {}", path.display(), e))?;
+
+        self.body.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\nContent-Type: application/octet-stream\r\n\r\n",
+                self.boundary, name, filename
+            )
+            .as_bytes(),
+        );
+        self.body.extend_from_slice(&data);
+        self.body.extend_from_slice(b"\r\n");
+
+        Ok(())
+    }
+
+    /// POST the form to a URL.
+    ///
+    /// The form isn't consumed, so failed requests can be retried.
+    fn post(&self, url: &str) -> Result<ureq::Response, AttemptError> {
+        let trailer = format!("--{}--\r\n", self.boundary);
+
+        // The length is defined so the body isn't sent with chunked
+        // encoding, which some services reject.
+        ureq::post(url)
+            .set(
+                "Content-Type",
+                &format!("multipart/form-data; boundary={}", self.boundary),
+            )
+            .set(
+                "Content-Length",
+                &(self.body.len() + trailer.len()).to_string(),
+            )
+            .send(self.body.as_slice().chain(trailer.as_bytes()))
+            .map_err(AttemptError::http)
+    }
+}
+
+/// The packagecloud package type of a file, derived from its extension.
+fn packagecloud_package_type(path: &Path) -> Option<&'static str> {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("deb") => Some("deb"),
+        Some("dsc") => Some("dsc"),
+        Some("rpm") => Some("rpm"),
+        _ => None,
+    }
+}
+
+/// Resolve a distribution string like `ubuntu/focal` to a packagecloud ID.
+fn packagecloud_distro_version_id(
+    logger: &Logger,
+    retry: &RetryPolicy,
+    base_url: &str,
+    token: &str,
+    package_type: &str,
+    distro: &str,
+) -> Result<u64, String> {
+    let (name, version) = distro
+        .split_once('/')
+        .ok_or_else(|| format!("distro {} is not of the form name/version", distro))?;
+
+    let url = url_with_credentials(
+        &format!("{}/api/v1/distributions.json", base_url),
+        token,
+        "",
+    )?;
+
+    let body = retry.retry(logger, "packagecloud distribution lookup", || {
+        ureq::get(&url)
+            .call()
+            .map_err(AttemptError::http)?
+            .into_string()
+            .map_err(|e| AttemptError {
+                class: Some(FailureClass::Network),
+                message: format!("error reading packagecloud distributions: {}", e),
+            })
+    })?;
+    let distributions: serde_json::Value = serde_json::from_str(&body)
+        .map_err(|e| format!("unable to parse packagecloud distributions: {}", e))?;
+
+    for distribution in distributions[package_type].as_array().unwrap_or(&vec![]) {
+        if distribution["index_name"].as_str() != Some(name) {
+            continue;
+        }
+
+        for v in distribution["versions"].as_array().unwrap_or(&vec![]) {
+            if v["index_name"].as_str() == Some(version) {
+                if let Some(id) = v["id"].as_u64() {
+                    return Ok(id);
+                }
+            }
+        }
+    }
+
+    Err(format!(
+        "packagecloud does not know {} distro {}",
+        package_type, distro
+    ))
+}
+
+/// Upload packages to a packagecloud repository.
+///
+/// `repo` is of the form `user/repo`. `distro` is a distribution string
+/// like `ubuntu/focal` or `el/8`, required for Debian and RPM packages.
+/// Up to `concurrency` packages are uploaded at once. Failed requests are
+/// retried according to `retry`.
+#[allow(clippy::too_many_arguments)]
+pub fn packagecloud_push(
+    logger: &Logger,
+    base_url: &str,
+    repo: &str,
+    distro: Option<&str>,
+    artifacts: &[PathBuf],
+    token: &str,
+    concurrency: usize,
+    retry: &RetryPolicy,
+) -> Result<(), String> {
+    let base_url = base_url.trim_end_matches('/');
+    let url = url_with_credentials(
+        &format!("{}/api/v1/repos/{}/packages.json", base_url, repo),
+        token,
+        "",
+    )?;
+
+    // Resolve distro IDs before uploading so each package type is only
+    // looked up once.
+    let mut distro_ids = HashMap::new();
+
+    if let Some(distro) = distro {
+        for artifact in artifacts {
+            let package_type = packagecloud_package_type(artifact).ok_or_else(|| {
+                format!("unable to determine package type of {}", artifact.display())
+            })?;
+
+            if !distro_ids.contains_key(package_type) {
+                let id = packagecloud_distro_version_id(
+                    logger,
+                    retry,
+                    base_url,
+                    token,
+                    package_type,
+                    distro,
+                )?;
+                distro_ids.insert(package_type, id);
+            }
+        }
+    }
+
+    crate::upload::upload_concurrently(logger, artifacts, concurrency, |artifact| {
+        warn!(
+            logger,
+            "uploading {} to packagecloud repository {}",
+            artifact.display(),
+            repo
+        );
+
+        let mut form = MultipartForm::new();
+
+        if let Some(package_type) = packagecloud_package_type(artifact) {
+            if let Some(id) = distro_ids.get(package_type) {
+                form.add_text("package[distro_version_id]", &id.to_string());
+            }
+        }
+
+        form.add_file("package[package_file]", artifact)?;
+
+        retry.retry(logger, &format!("upload of {}", artifact.display()), || {
+            form.post(&url)
+        })?;
+
+        Ok(())
+    })
+}
+
+/// Upload packages to a Gemfury account.
+///
+/// Up to `concurrency` packages are uploaded at once. Failed requests are
+/// retried according to `retry`.
+pub fn gemfury_push(
+    logger: &Logger,
+    account: &str,
+    artifacts: &[PathBuf],
+    token: &str,
+    concurrency: usize,
+    retry: &RetryPolicy,
+) -> Result<(), String> {
+    let url = url_with_credentials(&format!("{}/{}/", GEMFURY_PUSH_URL, account), token, "")?;
+
+    crate::upload::upload_concurrently(logger, artifacts, concurrency, |artifact| {
+        warn!(
+            logger,
+            "uploading {} to Gemfury account {}",
+            artifact.display(),
+            account
+        );
+
+        let mut form = MultipartForm::new();
+        form.add_file("package", artifact)?;
+
+        retry.retry(logger, &format!("upload of {}", artifact.display()), || {
+            form.post(&url)
+        })?;
+
+        Ok(())
+    })
+}
+
+/// Represents a request to upload packages to packagecloud.
+#[derive(Debug, Clone, Serialize)]
+pub struct PackagecloudPush {
+    pub repo: String,
+
+    /// Paths to files relative to the distribution path.
+    pub artifacts: Vec<String>,
+
+    pub distro: Option<String>,
+
+    /// API token.
+    pub token: Secret,
+
+    pub url: String,
+
+    /// Maximum number of packages to upload at once.
+    pub concurrency: usize,
+
+    /// How failed operations are retried, if not the pipeline's policy.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry: Option<RetryPolicy>,
+}
+
+/// Represents a request to upload packages to Gemfury.
+#[derive(Debug, Clone, Serialize)]
+pub struct GemfuryPush {
+    pub account: String,
+
+    /// Paths to files relative to the distribution path.
+    pub artifacts: Vec<String>,
+
+    /// Push token.
+    pub token: Secret,
+
+    /// Maximum number of packages to upload at once.
+    pub concurrency: usize,
+
+    /// How failed operations are retried, if not the pipeline's policy.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry: Option<RetryPolicy>,
+}
@@ -0,0 +1,132 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Metadata shared by packaging formats.
+
+Every package format describes its package with the same handful of
+fields (name, version, description, license, and so on) under slightly
+different names and conventions. `PackageMetadata` holds these fields
+once, and provides them in the form each format expects.
+*/
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Describes a package independently of its format.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct PackageMetadata {
+    /// Name of the package.
+    pub name: String,
+
+    pub version: String,
+
+    /// Description of the package.
+    ///
+    /// May span multiple lines. Paragraphs are separated by empty lines.
+    pub description: String,
+
+    /// A one-line summary.
+    ///
+    /// Defaults to the first line of the description.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+
+    /// SPDX license expression, e.g. `MIT OR Apache-2.0`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+
+    /// URL of the project's website.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub homepage: Option<String>,
+
+    /// Person or organization maintaining the package, e.g.
+    /// `Jane Doe <jane@example.com>`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub maintainer: Option<String>,
+
+    /// Organization publishing the package.
+    ///
+    /// Defaults to the name of the maintainer.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vendor: Option<String>,
+}
+
+impl PackageMetadata {
+    /// Obtain the one-line summary of the package.
+    pub fn summary(&self) -> String {
+        match &self.summary {
+            Some(summary) => summary.clone(),
+            None => self
+                .description
+                .lines()
+                .next()
+                .unwrap_or("")
+                .trim()
+                .to_string(),
+        }
+    }
+
+    /// Obtain the organization publishing the package, if known.
+    ///
+    /// The email address of the maintainer isn't included.
+    pub fn vendor(&self) -> Option<String> {
+        self.vendor.clone().or_else(|| {
+            self.maintainer
+                .as_ref()
+                .map(|maintainer| match maintainer.find('<') {
+                    Some(pos) => maintainer[..pos].trim().to_string(),
+                    None => maintainer.trim().to_string(),
+                })
+        })
+    }
+
+    /// Obtain the description in the form of a Debian `Description` field.
+    ///
+    /// The first line is the summary. Unless the description only consists
+    /// of the summary, it follows as the extended description, with lines
+    /// indented and empty lines replaced by ` .`.
+    pub fn debian_description(&self) -> String {
+        let summary = self.summary();
+        let mut lines = self.description.trim().lines();
+
+        // Don't repeat a summary derived from the description.
+        if self.summary.is_none() {
+            lines.next();
+        }
+
+        let extended: Vec<String> = lines
+            .skip_while(|line| line.trim().is_empty())
+            .map(|line| {
+                if line.trim().is_empty() {
+                    " .".to_string()
+                } else {
+                    format!(" {}", line.trim_end())
+                }
+            })
+            .collect();
+
+        if extended.is_empty() {
+            summary
+        } else {
+            format!("{}\n{}", summary, extended.join("\n"))
+        }
+    }
+
+    /// Obtain the version strings of a Windows `VERSIONINFO` resource.
+    pub fn windows_version_info(&self) -> BTreeMap<String, String> {
+        let mut strings = BTreeMap::new();
+
+        strings.insert("ProductName".to_string(), self.name.clone());
+        strings.insert("ProductVersion".to_string(), self.version.clone());
+        strings.insert("FileVersion".to_string(), self.version.clone());
+        strings.insert("FileDescription".to_string(), self.summary());
+
+        if let Some(vendor) = self.vendor() {
+            strings.insert("CompanyName".to_string(), vendor);
+        }
+
+        strings
+    }
+}
@@ -0,0 +1,972 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Editing resources of Windows PE binaries.
+
+Windows Explorer shows the icon and version metadata of executables from
+their resources, stored in the `.rsrc` section. This module rewrites that
+section, like `rcedit`, without needing Windows or its SDK.
+
+Existing resources are read and kept, except those being replaced. The
+new resource section replaces the existing one if it is the last section
+of the binary and is appended as a new section otherwise. Authenticode
+signatures are removed, as modified binaries no longer match them, so
+binaries should be signed after their resources are edited.
+*/
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+/// Resource type of icon images.
+pub const RT_ICON: u16 = 3;
+/// Resource type of icon groups, which reference `RT_ICON` resources.
+pub const RT_GROUP_ICON: u16 = 14;
+/// Resource type of version information.
+pub const RT_VERSION: u16 = 16;
+
+/// Language ID of US English, used for added resources.
+const LANG_EN_US: u16 = 0x0409;
+
+/// Code page of UTF-16LE, which strings in version information use.
+const CODEPAGE_UTF16: u16 = 1200;
+
+const IMAGE_DIRECTORY_ENTRY_RESOURCE: usize = 2;
+const IMAGE_DIRECTORY_ENTRY_SECURITY: usize = 4;
+const SECTION_HEADER_SIZE: usize = 40;
+const RSRC_CHARACTERISTICS: u32 = 0x4000_0040;
+
+/// Identifies a resource type or name.
+///
+/// Named entries sort before numeric ones, as the PE format requires.
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum ResourceId {
+    Name(String),
+    Id(u16),
+}
+
+/// A resource's data.
+#[derive(Clone, Debug)]
+pub struct Resource {
+    pub data: Vec<u8>,
+    pub codepage: u32,
+}
+
+/// Resources by type, name, and language.
+pub type ResourceTree = BTreeMap<ResourceId, BTreeMap<ResourceId, BTreeMap<u16, Resource>>>;
+
+/// Obtain `len` bytes of `data` at `offset`.
+fn slice(data: &[u8], offset: usize, len: usize) -> Option<&[u8]> {
+    data.get(offset..offset.checked_add(len)?)
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, String> {
+    slice(data, offset, 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or_else(|| "truncated PE file".to_string())
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, String> {
+    slice(data, offset, 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| "truncated PE file".to_string())
+}
+
+fn write_u16(data: &mut [u8], offset: usize, value: u16) {
+    data[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn write_u32(data: &mut [u8], offset: usize, value: u32) {
+    data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn align(value: usize, alignment: usize) -> usize {
+    value.div_ceil(alignment) * alignment
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Section {
+    /// Offset of the section header in the file.
+    header: usize,
+    virtual_size: u32,
+    virtual_address: u32,
+    raw_size: u32,
+    raw_pointer: u32,
+}
+
+impl Section {
+    /// Offset of the end of the section's data in the file.
+    fn raw_end(&self) -> Result<usize, String> {
+        self.raw_pointer
+            .checked_add(self.raw_size)
+            .map(|end| end as usize)
+            .ok_or_else(|| "PE section extends past the end of the file".to_string())
+    }
+
+    /// RVA of the end of the section.
+    fn virtual_end(&self) -> Result<u32, String> {
+        self.virtual_address
+            .checked_add(self.virtual_size.max(self.raw_size))
+            .ok_or_else(|| "PE section extends past the end of the address space".to_string())
+    }
+
+    fn contains_rva(&self, rva: u32) -> Result<bool, String> {
+        Ok(rva >= self.virtual_address && rva < self.virtual_end()?)
+    }
+}
+
+/// Locations of PE headers needed to edit resources.
+struct Headers {
+    coff: usize,
+    optional: usize,
+    data_directories: usize,
+    section_table: usize,
+    sections: Vec<Section>,
+}
+
+impl Headers {
+    fn parse(data: &[u8]) -> Result<Self, String> {
+        if data.get(0..2) != Some(b"MZ") {
+            return Err("not a PE file".to_string());
+        }
+
+        let pe = read_u32(data, 0x3c)? as usize;
+        if slice(data, pe, 4) != Some(b"PE\0\0") {
+            return Err("not a PE file".to_string());
+        }
+
+        let coff = pe + 4;
+        let section_count = read_u16(data, coff + 2)? as usize;
+        let optional = coff + 20;
+        let optional_size = read_u16(data, coff + 16)? as usize;
+
+        let (data_directories, data_directory_count) = match read_u16(data, optional)? {
+            0x10b => (optional + 96, read_u32(data, optional + 92)? as usize),
+            0x20b => (optional + 112, read_u32(data, optional + 108)? as usize),
+            magic => return Err(format!("unknown optional header magic {:#x}", magic)),
+        };
+
+        if data_directory_count <= IMAGE_DIRECTORY_ENTRY_SECURITY {
+            return Err("PE file has too few data directories".to_string());
+        }
+
+        let section_table = optional + optional_size;
+        let mut sections = Vec::new();
+
+        for i in 0..section_count {
+            let header = section_table + i * SECTION_HEADER_SIZE;
+            sections.push(Section {
+                header,
+                virtual_size: read_u32(data, header + 8)?,
+                virtual_address: read_u32(data, header + 12)?,
+                raw_size: read_u32(data, header + 16)?,
+                raw_pointer: read_u32(data, header + 20)?,
+            });
+        }
+
+        Ok(Headers {
+            coff,
+            optional,
+            data_directories,
+            section_table,
+            sections,
+        })
+    }
+
+    fn data_directory(&self, data: &[u8], index: usize) -> Result<(u32, u32), String> {
+        let offset = self.data_directories + index * 8;
+        Ok((read_u32(data, offset)?, read_u32(data, offset + 4)?))
+    }
+
+    fn rva_to_offset(&self, rva: u32) -> Result<usize, String> {
+        for section in &self.sections {
+            if section.contains_rva(rva)? {
+                return (rva - section.virtual_address)
+                    .checked_add(section.raw_pointer)
+                    .map(|offset| offset as usize)
+                    .ok_or_else(|| format!("RVA {:#x} is outside the file", rva));
+            }
+        }
+
+        Err(format!("RVA {:#x} is not in any section", rva))
+    }
+}
+
+/// Read the resources of a PE binary.
+pub fn read_resources(data: &[u8]) -> Result<ResourceTree, String> {
+    let headers = Headers::parse(data)?;
+    let (rva, size) = headers.data_directory(data, IMAGE_DIRECTORY_ENTRY_RESOURCE)?;
+    let mut tree = ResourceTree::new();
+
+    if rva == 0 || size == 0 {
+        return Ok(tree);
+    }
+
+    let base = headers.rva_to_offset(rva)?;
+
+    let read_id = |value: u32| -> Result<ResourceId, String> {
+        if value & 0x8000_0000 != 0 {
+            let offset = base + (value & 0x7fff_ffff) as usize;
+            let len = read_u16(data, offset)? as usize;
+            let units = (0..len)
+                .map(|i| read_u16(data, offset + 2 + i * 2))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(ResourceId::Name(String::from_utf16_lossy(&units)))
+        } else {
+            Ok(ResourceId::Id(value as u16))
+        }
+    };
+
+    // Returns (id, offset of subdirectory or data entry, is subdirectory).
+    let read_dir = |offset: u32| -> Result<Vec<(ResourceId, u32, bool)>, String> {
+        let dir = base + offset as usize;
+        let count = read_u16(data, dir + 12)? as usize + read_u16(data, dir + 14)? as usize;
+
+        (0..count)
+            .map(|i| {
+                let entry = dir + 16 + i * 8;
+                let target = read_u32(data, entry + 4)?;
+                Ok((
+                    read_id(read_u32(data, entry)?)?,
+                    target & 0x7fff_ffff,
+                    target & 0x8000_0000 != 0,
+                ))
+            })
+            .collect()
+    };
+
+    for (type_id, type_offset, is_dir) in read_dir(0)? {
+        if !is_dir {
+            return Err("malformed resource directory".to_string());
+        }
+
+        for (name_id, name_offset, is_dir) in read_dir(type_offset)? {
+            if !is_dir {
+                return Err("malformed resource directory".to_string());
+            }
+
+            for (lang, entry_offset, is_dir) in read_dir(name_offset)? {
+                let lang = match (lang, is_dir) {
+                    (ResourceId::Id(lang), false) => lang,
+                    _ => return Err("malformed resource directory".to_string()),
+                };
+
+                let entry = base + entry_offset as usize;
+                let data_offset = headers.rva_to_offset(read_u32(data, entry)?)?;
+                let data_size = read_u32(data, entry + 4)? as usize;
+
+                let resource = Resource {
+                    data: slice(data, data_offset, data_size)
+                        .ok_or_else(|| "truncated resource data".to_string())?
+                        .to_vec(),
+                    codepage: read_u32(data, entry + 8)?,
+                };
+
+                tree.entry(type_id.clone())
+                    .or_default()
+                    .entry(name_id.clone())
+                    .or_default()
+                    .insert(lang, resource);
+            }
+        }
+    }
+
+    Ok(tree)
+}
+
+fn named_count<'a>(ids: impl Iterator<Item = &'a ResourceId>) -> usize {
+    ids.filter(|id| matches!(id, ResourceId::Name(_))).count()
+}
+
+/// Serialize resources into the content of a `.rsrc` section at `rva`.
+fn build_section(tree: &ResourceTree, rva: u32) -> Vec<u8> {
+    // Directories come first, then name strings, then data entries, then
+    // data. Offsets are assigned in the order things are written.
+    let mut dirs_size = 16 + 8 * tree.len();
+    for names in tree.values() {
+        dirs_size += 16 + 8 * names.len();
+        for langs in names.values() {
+            dirs_size += 16 + 8 * langs.len();
+        }
+    }
+
+    let mut strings = BTreeMap::new();
+    let mut strings_size = 0;
+    for (type_id, names) in tree {
+        for id in std::iter::once(type_id).chain(names.keys()) {
+            if let ResourceId::Name(name) = id {
+                if !strings.contains_key(name) {
+                    strings.insert(name.clone(), dirs_size + strings_size);
+                    strings_size += 2 + 2 * name.encode_utf16().count();
+                }
+            }
+        }
+    }
+
+    let entries_offset = align(dirs_size + strings_size, 4);
+    let entry_count: usize = tree
+        .values()
+        .flat_map(|names| names.values())
+        .map(|langs| langs.len())
+        .sum();
+    let data_offset = align(entries_offset + 16 * entry_count, 8);
+
+    let mut out = vec![0; data_offset];
+
+    for (name, offset) in &strings {
+        let units = name.encode_utf16().collect::<Vec<_>>();
+        write_u16(&mut out, *offset, units.len() as u16);
+        for (i, unit) in units.iter().enumerate() {
+            write_u16(&mut out, offset + 2 + i * 2, *unit);
+        }
+    }
+
+    let id_value = |id: &ResourceId| match id {
+        ResourceId::Name(name) => 0x8000_0000 | strings[name] as u32,
+        ResourceId::Id(id) => u32::from(*id),
+    };
+
+    let write_dir_header = |out: &mut Vec<u8>, offset: usize, named: usize, count: usize| {
+        write_u16(out, offset + 12, named as u16);
+        write_u16(out, offset + 14, (count - named) as u16);
+    };
+
+    write_dir_header(&mut out, 0, named_count(tree.keys()), tree.len());
+
+    let mut next_dir = 16 + 8 * tree.len();
+    let mut next_entry = entries_offset;
+
+    // Type directories are written after the root and name directories
+    // after all type directories.
+    let mut name_dirs = next_dir;
+    for names in tree.values() {
+        name_dirs += 16 + 8 * names.len();
+    }
+
+    for (i, (type_id, names)) in tree.iter().enumerate() {
+        let type_dir = next_dir;
+        next_dir += 16 + 8 * names.len();
+
+        write_u32(&mut out, 16 + i * 8, id_value(type_id));
+        write_u32(&mut out, 16 + i * 8 + 4, 0x8000_0000 | type_dir as u32);
+        write_dir_header(&mut out, type_dir, named_count(names.keys()), names.len());
+
+        for (j, (name_id, langs)) in names.iter().enumerate() {
+            let name_dir = name_dirs;
+            name_dirs += 16 + 8 * langs.len();
+
+            write_u32(&mut out, type_dir + 16 + j * 8, id_value(name_id));
+            write_u32(
+                &mut out,
+                type_dir + 16 + j * 8 + 4,
+                0x8000_0000 | name_dir as u32,
+            );
+            write_dir_header(&mut out, name_dir, 0, langs.len());
+
+            for (k, (lang, resource)) in langs.iter().enumerate() {
+                write_u32(&mut out, name_dir + 16 + k * 8, u32::from(*lang));
+                write_u32(&mut out, name_dir + 16 + k * 8 + 4, next_entry as u32);
+
+                let offset = out.len();
+                out.extend_from_slice(&resource.data);
+                out.resize(align(out.len(), 8), 0);
+
+                write_u32(&mut out, next_entry, rva + offset as u32);
+                write_u32(&mut out, next_entry + 4, resource.data.len() as u32);
+                write_u32(&mut out, next_entry + 8, resource.codepage);
+                next_entry += 16;
+            }
+        }
+    }
+
+    out
+}
+
+/// Compute the checksum of a PE file, as `CheckSumMappedFile` does.
+fn checksum(data: &[u8], checksum_offset: usize) -> u32 {
+    let mut sum: u64 = 0;
+
+    for (i, chunk) in data.chunks(2).enumerate() {
+        if i * 2 == checksum_offset || i * 2 == checksum_offset + 2 {
+            continue;
+        }
+
+        let word = if chunk.len() == 2 {
+            u16::from_le_bytes([chunk[0], chunk[1]])
+        } else {
+            u16::from(chunk[0])
+        };
+
+        sum += u64::from(word);
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+
+    sum = (sum & 0xffff) + (sum >> 16);
+
+    (sum as u32).wrapping_add(data.len() as u32)
+}
+
+/// Replace the resources of a PE binary.
+pub fn write_resources(data: &[u8], tree: &ResourceTree) -> Result<Vec<u8>, String> {
+    let headers = Headers::parse(data)?;
+    let section_alignment = read_u32(data, headers.optional + 32)? as usize;
+    let file_alignment = read_u32(data, headers.optional + 36)? as usize;
+    let headers_size = read_u32(data, headers.optional + 60)? as usize;
+
+    if section_alignment == 0 || file_alignment == 0 {
+        return Err("invalid PE alignment".to_string());
+    }
+
+    let sections_end = headers
+        .sections
+        .iter()
+        .map(|s| s.raw_end())
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .max()
+        .unwrap_or(headers_size);
+
+    // The certificate table is addressed by file offset and is always
+    // at the end of the file. Anything else after the sections is data
+    // we don't know how to preserve.
+    let (cert_offset, cert_size) = headers.data_directory(data, IMAGE_DIRECTORY_ENTRY_SECURITY)?;
+    let content_end = if cert_size != 0 {
+        cert_offset as usize
+    } else {
+        data.len()
+    };
+
+    if content_end > sections_end {
+        let appended = data
+            .get(sections_end..content_end)
+            .ok_or_else(|| "truncated PE file".to_string())?;
+
+        if appended.iter().any(|b| *b != 0) {
+            return Err("PE file has data appended after its sections".to_string());
+        }
+    }
+
+    let (resource_rva, _) = headers.data_directory(data, IMAGE_DIRECTORY_ENTRY_RESOURCE)?;
+    let last = headers
+        .sections
+        .iter()
+        .max_by_key(|s| s.virtual_address)
+        .ok_or_else(|| "PE file has no sections".to_string())?;
+
+    let reuse = resource_rva != 0
+        && last.contains_rva(resource_rva)?
+        && last.virtual_address == resource_rva
+        && last.raw_end()? == sections_end;
+
+    let mut out;
+    let header;
+    let rva;
+    let raw_pointer;
+    let old_raw_size;
+
+    if reuse {
+        header = last.header;
+        rva = last.virtual_address;
+        raw_pointer = last.raw_pointer as usize;
+        old_raw_size = last.raw_size as usize;
+        out = data
+            .get(..raw_pointer)
+            .ok_or_else(|| "truncated PE file".to_string())?
+            .to_vec();
+    } else {
+        header = headers.section_table + headers.sections.len() * SECTION_HEADER_SIZE;
+        let first_raw = headers
+            .sections
+            .iter()
+            .filter(|s| s.raw_size != 0)
+            .map(|s| s.raw_pointer as usize)
+            .min()
+            .unwrap_or(headers_size);
+
+        if header + SECTION_HEADER_SIZE > headers_size.min(first_raw) {
+            return Err("no room in PE headers for a new section".to_string());
+        }
+
+        rva = u32::try_from(align(last.virtual_end()? as usize, section_alignment))
+            .map_err(|_| "PE file has no room for a new section".to_string())?;
+        raw_pointer = align(sections_end, file_alignment);
+        old_raw_size = 0;
+        out = data
+            .get(..sections_end)
+            .ok_or_else(|| "truncated PE file".to_string())?
+            .to_vec();
+        out.resize(raw_pointer, 0);
+
+        let count = read_u16(data, headers.coff + 2)?
+            .checked_add(1)
+            .ok_or_else(|| "PE file has too many sections".to_string())?;
+        write_u16(&mut out, headers.coff + 2, count);
+    }
+
+    // Headers are rewritten in place, so they must precede the section.
+    let checksum_offset = headers.optional + 64;
+    let headers_end = [
+        header + SECTION_HEADER_SIZE,
+        headers.data_directories + (IMAGE_DIRECTORY_ENTRY_SECURITY + 1) * 8,
+        checksum_offset + 4,
+    ]
+    .iter()
+    .copied()
+    .max()
+    .unwrap_or_default();
+
+    if headers_end > raw_pointer {
+        return Err("PE resource section overlaps the PE headers".to_string());
+    }
+
+    let section = build_section(tree, rva);
+    let raw_size = align(section.len(), file_alignment);
+    out.extend_from_slice(&section);
+    out.resize(raw_pointer + raw_size, 0);
+
+    out[header..header + 8].copy_from_slice(b".rsrc\0\0\0");
+    write_u32(&mut out, header + 8, section.len() as u32);
+    write_u32(&mut out, header + 12, rva);
+    write_u32(&mut out, header + 16, raw_size as u32);
+    write_u32(&mut out, header + 20, raw_pointer as u32);
+    out[header + 24..header + 36]
+        .iter_mut()
+        .for_each(|b| *b = 0);
+    write_u32(&mut out, header + 36, RSRC_CHARACTERISTICS);
+
+    let size_of_image = u32::try_from(align(rva as usize + section.len(), section_alignment))
+        .map_err(|_| "resources don't fit in the PE address space".to_string())?;
+    write_u32(&mut out, headers.optional + 56, size_of_image);
+
+    let initialized = read_u32(&out, headers.optional + 8)? as usize;
+    write_u32(
+        &mut out,
+        headers.optional + 8,
+        (initialized + raw_size).saturating_sub(old_raw_size) as u32,
+    );
+
+    let resource_dir = headers.data_directories + IMAGE_DIRECTORY_ENTRY_RESOURCE * 8;
+    write_u32(&mut out, resource_dir, rva);
+    write_u32(&mut out, resource_dir + 4, section.len() as u32);
+
+    let security_dir = headers.data_directories + IMAGE_DIRECTORY_ENTRY_SECURITY * 8;
+    write_u32(&mut out, security_dir, 0);
+    write_u32(&mut out, security_dir + 4, 0);
+
+    write_u32(&mut out, checksum_offset, 0);
+    let sum = checksum(&out, checksum_offset);
+    write_u32(&mut out, checksum_offset, sum);
+
+    Ok(out)
+}
+
+fn utf16z(s: &str) -> Vec<u8> {
+    s.encode_utf16()
+        .chain(std::iter::once(0))
+        .flat_map(|u| u.to_le_bytes())
+        .collect()
+}
+
+fn pad4(data: &mut Vec<u8>) {
+    data.resize(align(data.len(), 4), 0);
+}
+
+/// Serialize a block of version information.
+///
+/// Every structure in version information has this shape: a header with
+/// its length, the length of its value, and its type, then a key, the
+/// value, and child blocks, each aligned to 32 bits.
+fn version_block(
+    key: &str,
+    value: &[u8],
+    value_length: u16,
+    text: bool,
+    children: &[Vec<u8>],
+) -> Vec<u8> {
+    let mut out = vec![0; 6];
+    write_u16(&mut out, 2, value_length);
+    write_u16(&mut out, 4, if text { 1 } else { 0 });
+    out.extend(utf16z(key));
+    pad4(&mut out);
+    out.extend_from_slice(value);
+
+    for child in children {
+        pad4(&mut out);
+        out.extend_from_slice(child);
+    }
+
+    let len = out.len() as u16;
+    write_u16(&mut out, 0, len);
+    out
+}
+
+/// Parse a version string into the 64-bit representation of fixed file info.
+///
+/// Up to 4 leading numeric components are used, e.g. `1.2.3-beta` is
+/// `1.2.3.0`.
+fn parse_fixed_version(version: &str) -> u64 {
+    let mut parts = [0u16; 4];
+
+    for (i, component) in version.split('.').take(4).enumerate() {
+        let digits = component
+            .chars()
+            .take_while(|c| c.is_ascii_digit())
+            .collect::<String>();
+
+        match digits.parse() {
+            Ok(value) => parts[i] = value,
+            Err(_) => break,
+        }
+
+        if digits.len() != component.len() {
+            break;
+        }
+    }
+
+    parts
+        .iter()
+        .fold(0u64, |acc, part| (acc << 16) | u64::from(*part))
+}
+
+/// Build an `RT_VERSION` resource from version strings.
+///
+/// `strings` are entries of the string table, such as `FileVersion` and
+/// `ProductName`. The numeric versions shown in file properties come from
+/// `FileVersion` and `ProductVersion`, each defaulting to the other.
+pub fn version_info(strings: &BTreeMap<String, String>, dll: bool) -> Vec<u8> {
+    let file_version = strings
+        .get("FileVersion")
+        .or_else(|| strings.get("ProductVersion"))
+        .map(|v| parse_fixed_version(v))
+        .unwrap_or(0);
+    let product_version = strings
+        .get("ProductVersion")
+        .map(|v| parse_fixed_version(v))
+        .unwrap_or(file_version);
+
+    let mut fixed = Vec::new();
+    for value in [
+        0xfeef_04bd_u32,
+        0x0001_0000,
+        (file_version >> 32) as u32,
+        file_version as u32,
+        (product_version >> 32) as u32,
+        product_version as u32,
+        0x3f,
+        0,
+        0x0004_0004,
+        if dll { 2 } else { 1 },
+        0,
+        0,
+        0,
+    ] {
+        fixed.extend_from_slice(&value.to_le_bytes());
+    }
+
+    let string_blocks = strings
+        .iter()
+        .map(|(key, value)| {
+            version_block(
+                key,
+                &utf16z(value),
+                value.encode_utf16().count() as u16 + 1,
+                true,
+                &[],
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let table = version_block(
+        &format!("{:04X}{:04X}", LANG_EN_US, CODEPAGE_UTF16),
+        &[],
+        0,
+        true,
+        &string_blocks,
+    );
+    let string_file_info = version_block("StringFileInfo", &[], 0, true, &[table]);
+
+    let mut translation = Vec::new();
+    translation.extend_from_slice(&LANG_EN_US.to_le_bytes());
+    translation.extend_from_slice(&CODEPAGE_UTF16.to_le_bytes());
+    let var = version_block("Translation", &translation, 4, false, &[]);
+    let var_file_info = version_block("VarFileInfo", &[], 0, true, &[var]);
+
+    version_block(
+        "VS_VERSION_INFO",
+        &fixed,
+        fixed.len() as u16,
+        false,
+        &[string_file_info, var_file_info],
+    )
+}
+
+/// Replace the version information of a resource tree.
+pub fn set_version_info(tree: &mut ResourceTree, data: Vec<u8>) {
+    let mut langs = BTreeMap::new();
+    langs.insert(LANG_EN_US, Resource { data, codepage: 0 });
+
+    let mut names = BTreeMap::new();
+    names.insert(ResourceId::Id(1), langs);
+
+    tree.insert(ResourceId::Id(RT_VERSION), names);
+}
+
+/// Replace the icons of a resource tree with those of a `.ico` file.
+///
+/// All existing icons are removed, so the new icon group is the one
+/// Explorer shows.
+pub fn set_icon(tree: &mut ResourceTree, ico: &[u8]) -> Result<(), String> {
+    if read_u16(ico, 0)? != 0 || read_u16(ico, 2)? != 1 {
+        return Err("not a .ico file".to_string());
+    }
+
+    let count = read_u16(ico, 4)? as usize;
+    if count == 0 {
+        return Err(".ico file contains no images".to_string());
+    }
+
+    let mut icons = BTreeMap::new();
+    let mut group = slice(ico, 0, 6)
+        .ok_or_else(|| "truncated .ico file".to_string())?
+        .to_vec();
+
+    for i in 0..count {
+        let entry = 6 + i * 16;
+        let size = read_u32(ico, entry + 8)? as usize;
+        let offset = read_u32(ico, entry + 12)? as usize;
+        let image = slice(ico, offset, size).ok_or_else(|| "truncated .ico file".to_string())?;
+
+        let id = i as u16 + 1;
+        let mut langs = BTreeMap::new();
+        langs.insert(
+            LANG_EN_US,
+            Resource {
+                data: image.to_vec(),
+                codepage: 0,
+            },
+        );
+        icons.insert(ResourceId::Id(id), langs);
+
+        // Group entries are icon directory entries with the image offset
+        // replaced by the 16-bit ID of the RT_ICON resource.
+        group.extend_from_slice(
+            slice(ico, entry, 12).ok_or_else(|| "truncated .ico file".to_string())?,
+        );
+        group.extend_from_slice(&id.to_le_bytes());
+    }
+
+    let mut group_langs = BTreeMap::new();
+    group_langs.insert(
+        LANG_EN_US,
+        Resource {
+            data: group,
+            codepage: 0,
+        },
+    );
+    let mut groups = BTreeMap::new();
+    groups.insert(ResourceId::Id(1), group_langs);
+
+    tree.insert(ResourceId::Id(RT_ICON), icons);
+    tree.insert(ResourceId::Id(RT_GROUP_ICON), groups);
+
+    Ok(())
+}
+
+/// Whether a PE binary is a DLL.
+pub fn is_dll(data: &[u8]) -> Result<bool, String> {
+    let headers = Headers::parse(data)?;
+    Ok(read_u16(data, headers.coff + 18)? & 0x2000 != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT_HEADER: usize = 0x148;
+
+    /// A minimal PE32+ executable with one `.text` section.
+    fn fixture() -> Vec<u8> {
+        let mut data = vec![0; 0x400];
+        data[0..2].copy_from_slice(b"MZ");
+        write_u32(&mut data, 0x3c, 0x40);
+        data[0x40..0x44].copy_from_slice(b"PE\0\0");
+
+        // COFF header: x86-64, 1 section, executable.
+        write_u16(&mut data, 0x44, 0x8664);
+        write_u16(&mut data, 0x46, 1);
+        write_u16(&mut data, 0x54, 240);
+        write_u16(&mut data, 0x56, 0x22);
+
+        // Optional header.
+        write_u16(&mut data, 0x58, 0x20b);
+        write_u32(&mut data, 0x58 + 32, 0x1000);
+        write_u32(&mut data, 0x58 + 36, 0x200);
+        write_u32(&mut data, 0x58 + 56, 0x2000);
+        write_u32(&mut data, 0x58 + 60, 0x200);
+        write_u32(&mut data, 0x58 + 108, 16);
+
+        data[TEXT_HEADER..TEXT_HEADER + 8].copy_from_slice(b".text\0\0\0");
+        write_u32(&mut data, TEXT_HEADER + 8, 0x10);
+        write_u32(&mut data, TEXT_HEADER + 12, 0x1000);
+        write_u32(&mut data, TEXT_HEADER + 16, 0x200);
+        write_u32(&mut data, TEXT_HEADER + 20, 0x200);
+        write_u32(&mut data, TEXT_HEADER + 36, 0x6000_0020);
+        data[0x200..0x210].copy_from_slice(&[0xc3; 16]);
+
+        data
+    }
+
+    /// A `.ico` file with one 16x16 image.
+    fn ico() -> Vec<u8> {
+        let image = b"\x89PNG image data";
+
+        let mut data = vec![0; 22];
+        write_u16(&mut data, 2, 1);
+        write_u16(&mut data, 4, 1);
+        data[6] = 16;
+        data[7] = 16;
+        write_u16(&mut data, 10, 1);
+        write_u16(&mut data, 12, 32);
+        write_u32(&mut data, 14, image.len() as u32);
+        write_u32(&mut data, 18, 22);
+        data.extend_from_slice(image);
+
+        data
+    }
+
+    fn flatten(tree: &ResourceTree) -> Vec<(ResourceId, ResourceId, u16, Vec<u8>, u32)> {
+        let mut res = Vec::new();
+
+        for (type_id, names) in tree {
+            for (name_id, langs) in names {
+                for (lang, resource) in langs {
+                    res.push((
+                        type_id.clone(),
+                        name_id.clone(),
+                        *lang,
+                        resource.data.clone(),
+                        resource.codepage,
+                    ));
+                }
+            }
+        }
+
+        res
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let data = fixture();
+        assert!(read_resources(&data).unwrap().is_empty());
+        assert!(!is_dll(&data).unwrap());
+
+        let mut strings = BTreeMap::new();
+        strings.insert("FileVersion".to_string(), "1.2.3".to_string());
+        strings.insert("ProductName".to_string(), "Demo".to_string());
+
+        let mut tree = ResourceTree::new();
+        set_version_info(&mut tree, version_info(&strings, false));
+        set_icon(&mut tree, &ico()).unwrap();
+
+        // The resource section is appended as a new section.
+        let out = write_resources(&data, &tree).unwrap();
+        let headers = Headers::parse(&out).unwrap();
+        assert_eq!(headers.sections.len(), 2);
+        assert_eq!(&out[..8], &data[..8]);
+        assert_eq!(&out[0x200..0x400], &data[0x200..0x400]);
+        assert_eq!(
+            read_u32(&out, headers.optional + 64).unwrap(),
+            checksum(&out, headers.optional + 64)
+        );
+        assert_eq!(flatten(&read_resources(&out).unwrap()), flatten(&tree));
+
+        let group = &tree[&ResourceId::Id(RT_GROUP_ICON)][&ResourceId::Id(1)][&LANG_EN_US];
+        assert_eq!(&group.data[6..18], &ico()[6..18]);
+        assert_eq!(read_u16(&group.data, 18).unwrap(), 1);
+
+        // Editing again replaces the resource section in place.
+        tree.entry(ResourceId::Name("CUSTOM".to_string()))
+            .or_default()
+            .entry(ResourceId::Name("DATA".to_string()))
+            .or_default()
+            .insert(
+                0,
+                Resource {
+                    data: b"custom".to_vec(),
+                    codepage: 0,
+                },
+            );
+
+        let again = write_resources(&out, &tree).unwrap();
+        let headers = Headers::parse(&again).unwrap();
+        assert_eq!(headers.sections.len(), 2);
+        assert_eq!(headers.sections[1].raw_pointer, 0x400);
+        assert_eq!(flatten(&read_resources(&again).unwrap()), flatten(&tree));
+    }
+
+    #[test]
+    fn test_truncated() {
+        let data = fixture();
+
+        assert_eq!(read_resources(&data[..0]).unwrap_err(), "not a PE file");
+        for len in &[0x3c, 0x50, 0x100, 0x150] {
+            assert_eq!(
+                read_resources(&data[..*len]).unwrap_err(),
+                "truncated PE file"
+            );
+        }
+
+        // Resources in a section past the end of the file.
+        let mut data = fixture();
+        write_u32(&mut data, TEXT_HEADER + 20, 0x1000);
+        write_u32(&mut data, 0x58 + 112 + 16, 0x1000);
+        write_u32(&mut data, 0x58 + 112 + 20, 0x10);
+        assert_eq!(read_resources(&data).unwrap_err(), "truncated PE file");
+        assert_eq!(
+            write_resources(&data, &ResourceTree::new()).unwrap_err(),
+            "truncated PE file"
+        );
+    }
+
+    #[test]
+    fn test_overflowing_sections() {
+        let mut data = fixture();
+        write_u32(&mut data, TEXT_HEADER + 20, 0xffff_ff00);
+        assert_eq!(
+            write_resources(&data, &ResourceTree::new()).unwrap_err(),
+            "PE section extends past the end of the file"
+        );
+
+        let mut data = fixture();
+        write_u32(&mut data, TEXT_HEADER + 8, 0x2000);
+        write_u32(&mut data, TEXT_HEADER + 12, 0xffff_f000);
+        write_u32(&mut data, 0x58 + 112 + 16, 0xffff_f000);
+        write_u32(&mut data, 0x58 + 112 + 20, 0x10);
+        assert_eq!(
+            read_resources(&data).unwrap_err(),
+            "PE section extends past the end of the address space"
+        );
+        assert_eq!(
+            write_resources(&data, &ResourceTree::new()).unwrap_err(),
+            "PE section extends past the end of the address space"
+        );
+    }
+
+    #[test]
+    fn test_invalid_ico() {
+        let mut tree = ResourceTree::new();
+
+        let mut data = ico();
+        write_u32(&mut data, 14, 0xffff_ffff);
+        assert_eq!(
+            set_icon(&mut tree, &data).unwrap_err(),
+            "truncated .ico file"
+        );
+        assert_eq!(
+            set_icon(&mut tree, &ico()[..10]).unwrap_err(),
+            "truncated PE file"
+        );
+        assert!(tree.is_empty());
+    }
+}
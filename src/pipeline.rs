@@ -0,0 +1,1193 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Pipelines and their execution.
+
+A `Pipeline` is an ordered series of `Step`s. Pipelines are usually
+defined by configuration files, but can also be defined from Rust with
+`Pipeline::builder()`, and are executed in an `EnvironmentContext`.
+*/
+
+use crate::archive::{Archive, TarArchive};
+use crate::channel::Channel;
+use crate::compression::{Compression, CompressionSettings};
+use crate::error::TuggerError;
+use crate::extract::ArchiveFormat;
+use crate::mode_policy::ModePolicy;
+use crate::observer::{DirectorySnapshot, ExecutionObserver};
+use crate::report::{duration_secs, OutputLine, PipelineReport, StepReport};
+use crate::retry::RetryPolicy;
+use crate::symbols::SymbolFiles;
+use crate::tools::ToolSpec;
+use serde::Serialize;
+use slog::{o, warn, Logger};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// Holds state for evaluating configuration and executing pipelines.
+#[derive(Debug, Clone)]
+pub struct EnvironmentContext {
+    /// Directory the environment should be evaluated from.
+    ///
+    /// Typically used to resolve absolute paths to relative filenames.
+    pub cwd: PathBuf,
+
+    /// Logger to use for logging execution.
+    pub logger: slog::Logger,
+
+    /// Path to write distribution artifacts.
+    pub dist_path: PathBuf,
+
+    /// Settings for producing reproducible artifacts.
+    pub reproducibility: crate::reproducibility::Reproducibility,
+
+    /// Release channel being built.
+    pub channel: crate::channel::Channel,
+
+    /// Path of the configuration file being evaluated, if any.
+    pub config_path: Option<PathBuf>,
+
+    /// How failed network operations are retried, unless a pipeline or
+    /// step defines its own policy.
+    pub retry: RetryPolicy,
+
+    /// Pins and records downloaded resources.
+    pub lock: crate::lock::Lock,
+
+    /// How many steps may hold a resource at once, for resources that
+    /// allow more than one.
+    pub resource_limits: BTreeMap<String, usize>,
+}
+
+impl EnvironmentContext {
+    /// Construct a context for a working directory.
+    ///
+    /// Artifacts are written to `dist` under `cwd`, for the stable channel.
+    /// Reproducible mode is enabled if `SOURCE_DATE_EPOCH` is set.
+    pub fn new(logger: slog::Logger, cwd: &Path) -> Result<Self, crate::error::TuggerError> {
+        Ok(EnvironmentContext {
+            reproducibility: crate::reproducibility::Reproducibility::resolve(false, cwd)?,
+            channel: crate::channel::Channel::default(),
+            config_path: None,
+            retry: RetryPolicy::default(),
+            lock: crate::lock::Lock::default(),
+            resource_limits: BTreeMap::new(),
+            cwd: cwd.to_path_buf(),
+            logger,
+            dist_path: cwd.join("dist"),
+        })
+    }
+}
+
+/// Represents a generic step.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type")]
+pub enum Step {
+    Appcast(crate::appcast::Appcast),
+    Archive(Archive),
+    AptRepositoryPublish(crate::deploy::AptRepositoryPublish),
+    BreakpadSymbols(crate::breakpad::BreakpadSymbols),
+    BuildInfo(crate::build_info::BuildInfoFile),
+    CargoPublish(crate::cargo::CargoPublish),
+    CodesignSign(crate::signing::CodesignSign),
+    CondaPackage(crate::conda::CondaPackage),
+    CosignSign(crate::signing::CosignSign),
+    #[cfg(feature = "deb")]
+    DebianDebArchive(crate::debian::DebianDebArchive),
+    DeltaUpdate(crate::delta::DeltaUpdate),
+    GemfuryPush(crate::package_hosting::GemfuryPush),
+    MinisignSign(crate::signing::MinisignSign),
+    Mirror(crate::mirror::Mirror),
+    Notify(crate::notify::Notify),
+    PackagecloudPush(crate::package_hosting::PackagecloudPush),
+    ReleaseBundle(crate::bundle::ReleaseBundle),
+    ReleaseNotes(crate::release_notes::ReleaseNotes),
+    RemoteCopy(crate::deploy::RemoteCopy),
+    RpmBuild(Box<crate::rpm::RpmBuild>),
+    RpmSign(crate::signing::RpmSign),
+    #[cfg(feature = "snap")]
+    Snapcraft(Box<crate::snap::Snapcraft>),
+    SquirrelRelease(crate::squirrel::SquirrelRelease),
+    TarArchive(TarArchive),
+    TestInstall(crate::container::TestInstall),
+    UploadSymbols(crate::symbols::UploadSymbols),
+}
+
+impl Step {
+    /// The name of the Starlark function used to define this type of step.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Step::Appcast(_) => "appcast",
+            Step::Archive(_) => "archive",
+            Step::AptRepositoryPublish(_) => "apt_repository_publish",
+            Step::BreakpadSymbols(_) => "breakpad_symbols",
+            Step::BuildInfo(_) => "build_info",
+            Step::CargoPublish(_) => "cargo_publish",
+            Step::CodesignSign(_) => "codesign_sign",
+            Step::CondaPackage(_) => "conda_package",
+            Step::CosignSign(_) => "cosign_sign",
+            #[cfg(feature = "deb")]
+            Step::DebianDebArchive(_) => "debian_deb_archive",
+            Step::DeltaUpdate(_) => "delta_update",
+            Step::GemfuryPush(_) => "gemfury_push",
+            Step::MinisignSign(_) => "minisign_sign",
+            Step::Mirror(_) => "mirror",
+            Step::Notify(_) => "notify",
+            Step::PackagecloudPush(_) => "packagecloud_push",
+            Step::ReleaseBundle(_) => "release_bundle",
+            Step::ReleaseNotes(_) => "release_notes",
+            Step::RemoteCopy(_) => "remote_copy",
+            Step::RpmBuild(_) => "rpm_build",
+            Step::RpmSign(_) => "rpm_sign",
+            #[cfg(feature = "snap")]
+            Step::Snapcraft(_) => "snapcraft",
+            Step::SquirrelRelease(_) => "squirrel_release",
+            Step::TarArchive(_) => "tar_archive",
+            Step::TestInstall(_) => "test_install",
+            Step::UploadSymbols(_) => "upload_symbols",
+        }
+    }
+
+    /// Name the artifact of this step with a name template.
+    ///
+    /// `template` is the template of the pipeline, which applies unless
+    /// the step defines its own. Steps whose artifact doesn't belong to a
+    /// package keep their name.
+    pub fn apply_name_template(
+        &mut self,
+        template: Option<&str>,
+        target: &str,
+        channel: Channel,
+    ) -> Result<(), String> {
+        let (artifact_name, dest_name) = match self {
+            Step::Archive(Archive {
+                artifact_name: Some(artifact_name),
+                dest_name,
+                ..
+            })
+            | Step::TarArchive(TarArchive {
+                artifact_name: Some(artifact_name),
+                dest_name,
+                ..
+            }) => (&*artifact_name, dest_name),
+            #[cfg(feature = "deb")]
+            Step::DebianDebArchive(deb) => (&deb.artifact_name, &mut deb.dest_name),
+            _ => return Ok(()),
+        };
+
+        if let Some(name) = artifact_name.resolve(template, target, channel)? {
+            *dest_name = name;
+        }
+
+        Ok(())
+    }
+
+    /// Ensure the compression settings in effect for this step are valid.
+    ///
+    /// The settings of the step are combined with `pipeline`, the settings
+    /// of its pipeline, and validated against the algorithm the step
+    /// compresses with, so levels invalid for it are reported before any
+    /// step executes.
+    pub fn validate_compression(
+        &self,
+        pipeline: Option<&CompressionSettings>,
+    ) -> Result<(), String> {
+        let (name, compression, algorithm) = match self {
+            // zip archives are deflated, which uses the levels of gzip.
+            Step::Archive(archive) => (
+                &archive.dest_name,
+                &archive.compression,
+                match archive.format {
+                    ArchiveFormat::Zip => Some(Compression::Gzip),
+                    _ => Compression::from_filename(&archive.dest_name),
+                },
+            ),
+            Step::TarArchive(tar) => (
+                &tar.dest_name,
+                &tar.compression,
+                Compression::from_filename(&tar.dest_name),
+            ),
+            #[cfg(feature = "deb")]
+            Step::DebianDebArchive(deb) => (
+                &deb.dest_name,
+                &deb.compression,
+                deb.compression.or(pipeline).algorithm,
+            ),
+            _ => return Ok(()),
+        };
+
+        match algorithm {
+            Some(algorithm) => compression
+                .or(pipeline)
+                .level(algorithm)
+                .map(|_| ())
+                .map_err(|e| format!("invalid compression of {}: {}", name, e)),
+            None => Ok(()),
+        }
+    }
+
+    /// Artifacts in the distribution directory this step consumes.
+    ///
+    /// Names are relative to the distribution directory of the pipeline.
+    pub fn inputs(&self) -> Vec<&str> {
+        match self {
+            Step::Appcast(crate::appcast::Appcast { artifacts, .. })
+            | Step::CodesignSign(crate::signing::CodesignSign { artifacts, .. })
+            | Step::DeltaUpdate(crate::delta::DeltaUpdate { artifacts, .. })
+            | Step::GemfuryPush(crate::package_hosting::GemfuryPush { artifacts, .. })
+            | Step::MinisignSign(crate::signing::MinisignSign { artifacts, .. })
+            | Step::Mirror(crate::mirror::Mirror { artifacts, .. })
+            | Step::PackagecloudPush(crate::package_hosting::PackagecloudPush {
+                artifacts, ..
+            })
+            | Step::RemoteCopy(crate::deploy::RemoteCopy { artifacts, .. }) => {
+                artifacts.iter().map(String::as_str).collect()
+            }
+            Step::CosignSign(sign) if !sign.image => vec![sign.artifact.as_str()],
+            Step::ReleaseBundle(bundle) => {
+                bundle.artifacts.iter().map(|(a, _)| a.as_str()).collect()
+            }
+            Step::RpmSign(sign) => vec![sign.rpm.as_str()],
+            Step::TestInstall(test) => vec![test.artifact.as_str()],
+            _ => vec![],
+        }
+    }
+
+    /// Whether this step executes after an earlier step of its pipeline failed.
+    pub fn runs_after_failure(&self) -> bool {
+        match self {
+            Step::Notify(notify) => notify.when != crate::notify::NotifyWhen::Success,
+            _ => false,
+        }
+    }
+}
+
+macro_rules! step_from {
+    ($($(#[$attr:meta])* $variant:ident($ty:ty)),* $(,)?) => {
+        $(
+            $(#[$attr])*
+            impl From<$ty> for Step {
+                fn from(step: $ty) -> Self {
+                    Step::$variant(step.into())
+                }
+            }
+        )*
+    };
+}
+
+step_from!(
+    Appcast(crate::appcast::Appcast),
+    Archive(Archive),
+    AptRepositoryPublish(crate::deploy::AptRepositoryPublish),
+    BreakpadSymbols(crate::breakpad::BreakpadSymbols),
+    BuildInfo(crate::build_info::BuildInfoFile),
+    CargoPublish(crate::cargo::CargoPublish),
+    CodesignSign(crate::signing::CodesignSign),
+    CondaPackage(crate::conda::CondaPackage),
+    CosignSign(crate::signing::CosignSign),
+    #[cfg(feature = "deb")]
+    DebianDebArchive(crate::debian::DebianDebArchive),
+    DeltaUpdate(crate::delta::DeltaUpdate),
+    GemfuryPush(crate::package_hosting::GemfuryPush),
+    MinisignSign(crate::signing::MinisignSign),
+    Mirror(crate::mirror::Mirror),
+    Notify(crate::notify::Notify),
+    PackagecloudPush(crate::package_hosting::PackagecloudPush),
+    ReleaseBundle(crate::bundle::ReleaseBundle),
+    ReleaseNotes(crate::release_notes::ReleaseNotes),
+    RemoteCopy(crate::deploy::RemoteCopy),
+    RpmBuild(crate::rpm::RpmBuild),
+    RpmSign(crate::signing::RpmSign),
+    #[cfg(feature = "snap")]
+    Snapcraft(crate::snap::Snapcraft),
+    SquirrelRelease(crate::squirrel::SquirrelRelease),
+    TarArchive(TarArchive),
+    TestInstall(crate::container::TestInstall),
+    UploadSymbols(crate::symbols::UploadSymbols),
+);
+
+/// Represents a series of `Step`s to execute.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct Pipeline {
+    /// The name of this pipeline.
+    pub name: String,
+
+    /// Path to write distribution files.
+    pub dist_path: PathBuf,
+
+    /// Directory of `dist_path` relative to the distribution path, if the
+    /// pipeline has a distribution layout.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dist_dir: Option<String>,
+
+    /// Target triple the pipeline builds for.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+
+    /// The series of steps to execute.
+    pub steps: Vec<Step>,
+
+    /// Environment variables set while the steps execute.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+
+    /// How failed network operations of steps are retried.
+    ///
+    /// Steps may define their own policy. If not defined, the policy of
+    /// the environment context is used.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry: Option<RetryPolicy>,
+
+    /// External tools put on `PATH` while the steps execute.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<ToolSpec>,
+
+    /// How archives of steps are compressed.
+    ///
+    /// Steps may define their own settings, which take precedence.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compression: Option<CompressionSettings>,
+
+    /// Permissions of files written by steps.
+    ///
+    /// Defaults to `ModePolicy::default()`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode_policy: Option<ModePolicy>,
+
+    /// Resources every step holds while it executes.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub requires: Vec<String>,
+
+    /// Template of the names of artifacts of steps.
+    ///
+    /// It has already been applied to the steps. See `crate::naming`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name_template: Option<String>,
+
+    /// Resources held by individual steps, by step index.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub step_requires: BTreeMap<usize, Vec<String>>,
+}
+
+impl Pipeline {
+    /// Obtain a builder for defining a pipeline from Rust.
+    pub fn builder() -> PipelineBuilder {
+        PipelineBuilder::default()
+    }
+
+    /// Expand a distribution layout into a directory relative to the
+    /// distribution path.
+    ///
+    /// `{pipeline}`, `{target}`, and `{channel}` in `layout` are replaced
+    /// by the pipeline name, target triple, and release channel.
+    pub fn expand_dist_layout(
+        layout: &str,
+        name: &str,
+        target: &str,
+        channel: Channel,
+    ) -> Result<String, String> {
+        let dir = channel
+            .expand(layout)
+            .replace("{pipeline}", name)
+            .replace("{target}", target);
+
+        if dir.contains(['{', '}']) {
+            return Err(format!(
+                "unknown placeholder in distribution layout {}; expected {{pipeline}}, {{target}}, or {{channel}}",
+                layout
+            ));
+        }
+
+        let components = dir.trim_end_matches('/').split('/').collect::<Vec<_>>();
+        if components
+            .iter()
+            .any(|c| c.is_empty() || *c == "." || *c == ".." || c.contains('\\'))
+        {
+            return Err(format!(
+                "distribution layout {} must expand to a relative path without . or .. components; got {}",
+                layout, dir
+            ));
+        }
+
+        Ok(components.join("/"))
+    }
+
+    /// Obtain the path of an artifact relative to the distribution path.
+    ///
+    /// `path` is a path in `dist_path`. Artifacts of pipelines with a
+    /// distribution layout keep their directory, so identically named
+    /// artifacts of different pipelines remain distinct.
+    pub fn artifact_path(&self, path: &Path) -> PathBuf {
+        let path = path.strip_prefix(&self.dist_path).unwrap_or(path);
+
+        match &self.dist_dir {
+            Some(dir) => Path::new(dir).join(path),
+            None => path.to_path_buf(),
+        }
+    }
+
+    /// Obtain the resources a step holds while it executes.
+    pub fn step_resources(&self, index: usize) -> Vec<String> {
+        let mut resources = self.requires.clone();
+        if let Some(step_requires) = self.step_requires.get(&index) {
+            resources.extend(step_requires.iter().cloned());
+        }
+        resources.sort();
+        resources.dedup();
+
+        resources
+    }
+
+    /// Execute the steps of this pipeline.
+    pub fn execute(&self, context: &EnvironmentContext) -> Result<PipelineReport, TuggerError> {
+        let (report, res) = execute_pipeline(context, self, None);
+
+        res.map(|_| report)
+    }
+
+    /// Execute the steps of this pipeline, notifying an observer.
+    pub fn execute_observed(
+        &self,
+        context: &EnvironmentContext,
+        observer: &mut dyn ExecutionObserver,
+    ) -> Result<PipelineReport, TuggerError> {
+        let (report, res) = execute_pipeline(context, self, Some(observer));
+
+        res.map(|_| report)
+    }
+}
+
+/// Defines a `Pipeline` without evaluating a configuration file.
+///
+/// ```ignore
+/// let report = Pipeline::builder()
+///     .name("release")
+///     .step(TarArchive { .. })
+///     .execute(&context)?;
+/// ```
+#[derive(Debug, Default)]
+pub struct PipelineBuilder {
+    name: Option<String>,
+    dist_path: Option<PathBuf>,
+    dist_layout: Option<String>,
+    target: Option<String>,
+    steps: Vec<Step>,
+    env: BTreeMap<String, String>,
+    retry: Option<RetryPolicy>,
+    tools: Vec<ToolSpec>,
+    compression: Option<CompressionSettings>,
+    mode_policy: Option<ModePolicy>,
+    requires: Vec<String>,
+    step_requires: BTreeMap<usize, Vec<String>>,
+    name_template: Option<String>,
+}
+
+impl PipelineBuilder {
+    /// Set the name of the pipeline.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Set the path to write distribution files.
+    ///
+    /// Defaults to the distribution path of the context the pipeline is
+    /// executed in.
+    pub fn dist_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.dist_path = Some(path.into());
+        self
+    }
+
+    /// Set the layout of the pipeline's directory in the distribution path.
+    ///
+    /// See `Pipeline::expand_dist_layout()`.
+    pub fn dist_layout(mut self, layout: impl Into<String>) -> Self {
+        self.dist_layout = Some(layout.into());
+        self
+    }
+
+    /// Set the target triple the pipeline builds for.
+    ///
+    /// Defaults to the host target in distribution layouts.
+    pub fn target(mut self, target: impl Into<String>) -> Self {
+        self.target = Some(target.into());
+        self
+    }
+
+    /// Set an environment variable while the steps execute.
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.insert(key.into(), value.into());
+        self
+    }
+
+    /// Set how failed network operations of steps are retried.
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    /// Provision an external tool while the steps execute.
+    pub fn tool(mut self, tool: ToolSpec) -> Self {
+        self.tools.push(tool);
+        self
+    }
+
+    /// Set how archives of steps are compressed.
+    pub fn compression(mut self, compression: CompressionSettings) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    /// Set the permissions of files written by steps.
+    pub fn mode_policy(mut self, mode_policy: ModePolicy) -> Self {
+        self.mode_policy = Some(mode_policy);
+        self
+    }
+
+    /// Set the template of the names of artifacts of steps.
+    ///
+    /// See `crate::naming`.
+    pub fn name_template(mut self, template: impl Into<String>) -> Self {
+        self.name_template = Some(template.into());
+        self
+    }
+
+    /// Require a resource while each step executes.
+    ///
+    /// See `crate::resources`.
+    pub fn requires(mut self, resource: impl Into<String>) -> Self {
+        self.requires.push(resource.into());
+        self
+    }
+
+    /// Append a step to the pipeline.
+    pub fn step(mut self, step: impl Into<Step>) -> Self {
+        self.steps.push(step.into());
+        self
+    }
+
+    /// Append a step holding resources while it executes.
+    pub fn step_requiring(mut self, step: impl Into<Step>, resources: &[&str]) -> Self {
+        self.step_requires.insert(
+            self.steps.len(),
+            resources.iter().map(|r| r.to_string()).collect(),
+        );
+        self.step(step)
+    }
+
+    /// Construct the pipeline.
+    pub fn build(self, context: &EnvironmentContext) -> Result<Pipeline, TuggerError> {
+        let name = self
+            .name
+            .ok_or_else(|| TuggerError::from("pipeline name is not defined"))?;
+
+        for resource in self
+            .requires
+            .iter()
+            .chain(self.step_requires.values().flatten())
+        {
+            crate::resources::validate_name(resource)?;
+        }
+
+        let dist_path = self.dist_path.unwrap_or_else(|| context.dist_path.clone());
+        let target = self
+            .target
+            .clone()
+            .unwrap_or_else(crate::cargo::host_target);
+
+        let dist_dir = match &self.dist_layout {
+            Some(layout) => Some(Pipeline::expand_dist_layout(
+                layout,
+                &name,
+                &target,
+                context.channel,
+            )?),
+            None => None,
+        };
+
+        let mut steps = self.steps;
+        for step in &mut steps {
+            step.apply_name_template(self.name_template.as_deref(), &target, context.channel)?;
+            step.validate_compression(self.compression.as_ref())?;
+        }
+
+        Ok(Pipeline {
+            name,
+            dist_path: match &dist_dir {
+                Some(dir) => dist_path.join(dir),
+                None => dist_path,
+            },
+            dist_dir,
+            target: self.target,
+            steps,
+            env: self.env,
+            retry: self.retry,
+            tools: self.tools,
+            compression: self.compression,
+            mode_policy: self.mode_policy,
+            requires: self.requires,
+            step_requires: self.step_requires,
+            name_template: self.name_template,
+        })
+    }
+
+    /// Construct the pipeline and execute it.
+    pub fn execute(self, context: &EnvironmentContext) -> Result<PipelineReport, TuggerError> {
+        self.build(context)?.execute(context)
+    }
+}
+
+/// Execute the steps of a pipeline.
+///
+/// After a step fails, only steps reacting to failure are executed. Returns
+/// a report of execution and the first failure, if any. `observer` is
+/// notified of execution.
+pub fn execute_pipeline(
+    context: &EnvironmentContext,
+    pipeline: &Pipeline,
+    mut observer: Option<&mut (dyn ExecutionObserver + '_)>,
+) -> (PipelineReport, Result<(), TuggerError>) {
+    let logger = context.logger.new(o!("pipeline" => pipeline.name.clone()));
+
+    warn!(logger, "executing pipeline: {}", pipeline.name);
+
+    let _env = crate::process::EnvironmentOverride::new(&pipeline.env);
+
+    if let Some(observer) = observer.as_deref_mut() {
+        observer.on_pipeline_start(pipeline);
+    }
+
+    let start = Instant::now();
+    let mut steps = Vec::new();
+    let mut error = None;
+    let mut failure = None;
+
+    // Steps reacting to failure still execute if tools can't be provisioned.
+    let _tools = match provision_tools(&logger, context, pipeline) {
+        Ok(tools) => Some(tools),
+        Err(e) => {
+            warn!(logger, "{}", e);
+            error = Some(e.clone());
+            failure = Some(TuggerError::from(e));
+            None
+        }
+    };
+
+    for index in 0..pipeline.steps.len() {
+        // After a failure, only steps reacting to failure are executed.
+        if error.is_some() && !pipeline.steps[index].runs_after_failure() {
+            continue;
+        }
+
+        let progress = PipelineReport {
+            name: pipeline.name.clone(),
+            target: pipeline.target.clone(),
+            dist_dir: pipeline.dist_dir.clone(),
+            duration_secs: duration_secs(start.elapsed()),
+            steps: steps.clone(),
+            error: error.clone(),
+        };
+
+        let (step_report, res) = execute_step_timed(
+            &logger,
+            context,
+            pipeline,
+            index,
+            &progress,
+            observer.as_deref_mut(),
+        );
+
+        if let Err(e) = res {
+            if failure.is_none() {
+                error = step_report.error.clone();
+                failure = Some(e);
+            }
+        }
+
+        steps.push(step_report);
+    }
+
+    let report = PipelineReport {
+        name: pipeline.name.clone(),
+        target: pipeline.target.clone(),
+        dist_dir: pipeline.dist_dir.clone(),
+        duration_secs: duration_secs(start.elapsed()),
+        steps,
+        error,
+    };
+
+    if let Some(observer) = observer {
+        observer.on_pipeline_end(pipeline, &report);
+    }
+
+    match failure {
+        Some(e) => (report, Err(e)),
+        None => (report, Ok(())),
+    }
+}
+
+pub(crate) fn execute_step_timed(
+    logger: &Logger,
+    context: &EnvironmentContext,
+    pipeline: &Pipeline,
+    index: usize,
+    progress: &PipelineReport,
+    mut observer: Option<&mut (dyn ExecutionObserver + '_)>,
+) -> (StepReport, Result<(), TuggerError>) {
+    let step = &pipeline.steps[index];
+    let logger = logger.new(o!("step" => step.kind(), "step_index" => index));
+
+    if let Some(observer) = observer.as_deref_mut() {
+        observer.on_step_start(pipeline, index, step);
+    }
+
+    // Artifacts are found by comparing the distribution directory before
+    // and after the step.
+    let before = DirectorySnapshot::new(&pipeline.dist_path);
+
+    // Time spent waiting for resources isn't part of the step's duration.
+    let resources = crate::resources::acquire(
+        &logger,
+        &pipeline.step_resources(index),
+        &context.resource_limits,
+    );
+
+    let start = Instant::now();
+    let mut output = Vec::new();
+    let res = resources
+        .map_err(TuggerError::from)
+        .and_then(|_resources| {
+            execute_step(&logger, context, pipeline, step, progress, &mut output)
+        })
+        .map_err(|e| TuggerError::Step {
+            pipeline: pipeline.name.clone(),
+            index,
+            kind: step.kind().to_string(),
+            source: Box::new(e),
+        });
+
+    let duration = start.elapsed();
+    let artifacts = before.changed(&DirectorySnapshot::new(&pipeline.dist_path));
+
+    let report = StepReport {
+        index,
+        kind: step.kind().to_string(),
+        duration_secs: duration_secs(duration),
+        error: res
+            .as_ref()
+            .err()
+            .map(|e| crate::secrets::redact(&e.render())),
+        output,
+        artifacts: artifacts
+            .iter()
+            .map(|path| crate::runs::artifact_name(&pipeline.dist_path, path))
+            .collect(),
+    };
+
+    if let Some(observer) = observer {
+        for path in &artifacts {
+            observer.on_artifact(pipeline, path);
+        }
+
+        observer.on_step_end(pipeline, &report);
+    }
+
+    (report, res)
+}
+
+/// Provision the tools of a pipeline and put them on `PATH`.
+///
+/// `PATH` is restored when the returned value is dropped. Tools are
+/// provisioned after the pipeline's environment is set, so they precede
+/// any `PATH` it defines.
+pub(crate) fn provision_tools(
+    logger: &Logger,
+    context: &EnvironmentContext,
+    pipeline: &Pipeline,
+) -> Result<crate::process::EnvironmentOverride, String> {
+    let env = crate::tools::provision_all(
+        logger,
+        &pipeline.tools,
+        &context.lock,
+        pipeline.retry.as_ref().unwrap_or(&context.retry),
+    )
+    .map_err(|e| format!("unable to provision tools: {}", e))?;
+
+    Ok(crate::process::EnvironmentOverride::new(&env))
+}
+
+/// Obtain the retry policy of a step.
+///
+/// The step's own policy takes precedence over the pipeline's, which takes
+/// precedence over the context's.
+fn retry_policy<'a>(
+    context: &'a EnvironmentContext,
+    pipeline: &'a Pipeline,
+    step: &'a Option<RetryPolicy>,
+) -> &'a RetryPolicy {
+    step.as_ref()
+        .or(pipeline.retry.as_ref())
+        .unwrap_or(&context.retry)
+}
+
+/// Obtain the compression settings of a step.
+///
+/// Settings the step doesn't define are those of its pipeline.
+fn compression_settings(pipeline: &Pipeline, step: &CompressionSettings) -> CompressionSettings {
+    step.or(pipeline.compression.as_ref())
+}
+
+/// Execute a single pipeline step.
+///
+/// `progress` describes execution of the pipeline before this step. Output
+/// of external processes run by the step is appended to `output`.
+fn execute_step(
+    logger: &Logger,
+    context: &EnvironmentContext,
+    pipeline: &Pipeline,
+    step: &Step,
+    progress: &PipelineReport,
+    #[cfg_attr(not(feature = "snap"), allow(unused_variables))] output: &mut Vec<OutputLine>,
+) -> Result<(), TuggerError> {
+    let modes = pipeline.mode_policy.unwrap_or_default();
+
+    match step {
+        Step::Appcast(appcast) => {
+            let artifacts: Vec<PathBuf> = appcast
+                .artifacts
+                .iter()
+                .map(|a| pipeline.dist_path.join(a))
+                .collect();
+            let ed_key = match &appcast.ed_key {
+                Some(key) => Some(key.resolve()?),
+                None => None,
+            };
+
+            crate::appcast::write_appcast(
+                logger,
+                &pipeline.dist_path,
+                &artifacts,
+                &appcast.feed,
+                ed_key.as_deref(),
+                context.reproducibility.mtime(),
+                retry_policy(context, pipeline, &appcast.retry),
+            )?;
+        }
+        Step::Archive(archive) => {
+            archive.execute(
+                logger,
+                &pipeline.dist_path,
+                &compression_settings(pipeline, &archive.compression),
+                &context.reproducibility,
+                &modes,
+            )?;
+        }
+        Step::BreakpadSymbols(symbols) => {
+            crate::breakpad::write_breakpad_symbols(
+                logger,
+                &pipeline.dist_path,
+                &symbols.directory,
+                &symbols.files,
+            )?;
+        }
+        Step::BuildInfo(info) => {
+            info.execute(logger, &pipeline.dist_path)?;
+        }
+        Step::AptRepositoryPublish(publish) => {
+            crate::deploy::publish_apt_repository(
+                logger,
+                &pipeline.dist_path.join(&publish.repo_dir),
+                &publish.destination,
+                &publish.ssh,
+                publish.cloudfront_distribution.as_deref(),
+                retry_policy(context, pipeline, &publish.retry),
+            )?;
+        }
+        Step::CargoPublish(publish) => {
+            crate::cargo::cargo_publish(
+                logger,
+                &publish.manifest_path,
+                publish.dry_run,
+                publish.token.as_ref(),
+                std::time::Duration::from_secs(publish.wait_timeout),
+                retry_policy(context, pipeline, &publish.retry),
+            )?;
+        }
+        Step::CodesignSign(sign) => {
+            let artifacts: Vec<PathBuf> = sign
+                .artifacts
+                .iter()
+                .map(|a| pipeline.dist_path.join(a))
+                .collect();
+
+            crate::signing::codesign_sign(logger, &artifacts, &sign.options)?;
+        }
+        Step::CondaPackage(package) => {
+            crate::conda::write_conda_package(
+                logger,
+                &pipeline.dist_path,
+                &package.package,
+                &package.files,
+                &context.reproducibility,
+                &modes,
+            )?;
+        }
+        Step::CosignSign(sign) => {
+            let password = match &sign.password {
+                Some(password) => Some(password.resolve()?),
+                None => None,
+            };
+
+            if sign.image {
+                crate::signing::cosign_sign_image(
+                    logger,
+                    &sign.artifact,
+                    sign.key.as_deref(),
+                    password.as_deref(),
+                )?;
+            } else {
+                crate::signing::cosign_sign_blob(
+                    logger,
+                    &pipeline.dist_path.join(&sign.artifact),
+                    sign.key.as_deref(),
+                    password.as_deref(),
+                )?;
+            }
+        }
+        #[cfg(feature = "deb")]
+        Step::DebianDebArchive(deb) => {
+            crate::debian::execute_deb_archive(
+                logger,
+                &pipeline.dist_path,
+                &deb.dest_name,
+                &deb.control_file,
+                &deb.files,
+                deb.systemd,
+                &compression_settings(pipeline, &deb.compression),
+                &context.reproducibility,
+                &modes,
+            )?;
+        }
+        Step::DeltaUpdate(delta) => {
+            for artifact in &delta.artifacts {
+                let path = pipeline.dist_path.join(artifact);
+
+                if !path.is_file() {
+                    return Err(format!("{} is not a file", path.display()).into());
+                }
+
+                if delta.zsync {
+                    crate::delta::zsync_make(logger, &path, delta.url.as_deref())?;
+                }
+
+                if let Some(previous) = delta.previous.get(artifact) {
+                    crate::delta::bsdiff(logger, previous, &path)?;
+                }
+            }
+        }
+        Step::GemfuryPush(push) => {
+            let artifacts: Vec<PathBuf> = push
+                .artifacts
+                .iter()
+                .map(|a| pipeline.dist_path.join(a))
+                .collect();
+            let token = push.token.resolve()?;
+
+            crate::package_hosting::gemfury_push(
+                logger,
+                &push.account,
+                &artifacts,
+                &token,
+                push.concurrency,
+                retry_policy(context, pipeline, &push.retry),
+            )?;
+        }
+        Step::MinisignSign(sign) => {
+            let artifacts: Vec<PathBuf> = sign
+                .artifacts
+                .iter()
+                .map(|a| pipeline.dist_path.join(a))
+                .collect();
+
+            crate::signing::minisign_sign(
+                logger,
+                &artifacts,
+                &sign.secret_key,
+                sign.trusted_comment.as_deref(),
+            )?;
+        }
+        Step::Mirror(mirror) => {
+            let artifacts: Vec<PathBuf> = mirror
+                .artifacts
+                .iter()
+                .map(|a| pipeline.dist_path.join(a))
+                .collect();
+
+            crate::mirror::mirror(
+                logger,
+                &artifacts,
+                &mirror.destinations,
+                mirror.require,
+                mirror.concurrency,
+                retry_policy(context, pipeline, &mirror.retry),
+            )?;
+        }
+        Step::Notify(notify) => {
+            notify.execute(
+                logger,
+                &pipeline.dist_path,
+                progress,
+                retry_policy(context, pipeline, &notify.retry),
+            )?;
+        }
+        Step::PackagecloudPush(push) => {
+            let artifacts: Vec<PathBuf> = push
+                .artifacts
+                .iter()
+                .map(|a| pipeline.dist_path.join(a))
+                .collect();
+            let token = push.token.resolve()?;
+
+            crate::package_hosting::packagecloud_push(
+                logger,
+                &push.url,
+                &push.repo,
+                push.distro.as_deref(),
+                &artifacts,
+                &token,
+                push.concurrency,
+                retry_policy(context, pipeline, &push.retry),
+            )?;
+        }
+        Step::ReleaseBundle(bundle) => {
+            crate::bundle::write_release_bundle(
+                logger,
+                &pipeline.dist_path,
+                &bundle.directory,
+                &bundle.artifacts,
+                bundle.layout,
+                bundle.index,
+            )?;
+        }
+        Step::ReleaseNotes(notes) => {
+            notes.execute(logger, &pipeline.dist_path)?;
+        }
+        Step::RemoteCopy(copy) => {
+            let artifacts: Vec<PathBuf> = copy
+                .artifacts
+                .iter()
+                .map(|a| pipeline.dist_path.join(a))
+                .collect();
+
+            crate::deploy::remote_copy(
+                logger,
+                &copy.host,
+                &copy.dest,
+                &artifacts,
+                copy.protocol,
+                &copy.ssh,
+                copy.concurrency,
+                retry_policy(context, pipeline, &copy.retry),
+            )?;
+        }
+        Step::RpmBuild(build) => {
+            crate::rpm::build_rpm(
+                logger,
+                &pipeline.dist_path,
+                &build.package,
+                &build.files,
+                &context.reproducibility,
+                &modes,
+            )?;
+        }
+        Step::RpmSign(sign) => {
+            let passphrase = match &sign.passphrase {
+                Some(passphrase) => Some(passphrase.resolve()?),
+                None => None,
+            };
+
+            crate::signing::rpm_sign(
+                logger,
+                &pipeline.dist_path.join(&sign.rpm),
+                &sign.key,
+                passphrase.as_deref(),
+            )?;
+        }
+        #[cfg(feature = "snap")]
+        Step::Snapcraft(snapcraft) => {
+            let mut args = snapcraft.args.clone();
+            if snapcraft.verbose {
+                args.push("--verbose".to_string());
+            }
+
+            crate::snap::execute_snapcraft(
+                logger,
+                &args,
+                &snapcraft.snap,
+                &snapcraft.build_path,
+                &snapcraft.manifest,
+                snapcraft.purge_build,
+                snapcraft.install_mode,
+                &modes,
+                output,
+            )?;
+        }
+        Step::SquirrelRelease(release) => {
+            crate::squirrel::squirrel_release(
+                logger,
+                &pipeline.dist_path.join(&release.dest),
+                &release.metadata,
+                &release.files,
+                release.previous_dir.as_deref(),
+                &context.reproducibility,
+            )?;
+        }
+        Step::TarArchive(ta) => {
+            ta.execute(
+                logger,
+                &pipeline.dist_path,
+                &compression_settings(pipeline, &ta.compression),
+                &context.reproducibility,
+                &modes,
+            )?;
+        }
+        Step::TestInstall(test) => {
+            crate::container::test_install(
+                logger,
+                &pipeline.dist_path.join(&test.artifact),
+                &test.image,
+                &test.command,
+            )?;
+        }
+        Step::UploadSymbols(upload) => {
+            let token = match &upload.credentials {
+                Some(credentials) => Some(credentials.resolve()?),
+                None => None,
+            };
+
+            let retry = retry_policy(context, pipeline, &upload.retry);
+
+            match &upload.files {
+                SymbolFiles::Manifest(files) => crate::symbols::upload_symbols(
+                    logger,
+                    files,
+                    &upload.store,
+                    token.as_deref(),
+                    retry,
+                )?,
+                SymbolFiles::Directory(dir) => crate::symbols::upload_symbols_dir(
+                    logger,
+                    &pipeline.dist_path.join(dir),
+                    &upload.store,
+                    token.as_deref(),
+                    retry,
+                )?,
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,153 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Functionality for running external processes.
+
+Many steps are implemented by invoking an external tool. The functions in
+this module run a `Command`, forward its output to a logger, and turn a
+non-zero exit into an error.
+*/
+
+use crate::report::{OutputLine, OutputStream};
+use slog::{warn, Logger};
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::{OsStr, OsString};
+use std::io::{BufRead, BufReader, Read};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::channel;
+use std::sync::Mutex;
+
+/// Programs run by this process.
+static PROGRAMS: Mutex<BTreeSet<OsString>> = Mutex::new(BTreeSet::new());
+
+/// Record that an external program is run.
+///
+/// Commands run with `run_captured()` are recorded automatically. Others
+/// must be recorded explicitly, so the build environment can be audited.
+pub fn record_program(program: impl AsRef<OsStr>) {
+    PROGRAMS
+        .lock()
+        .unwrap()
+        .insert(program.as_ref().to_os_string());
+}
+
+/// Obtain the external programs run so far, in sorted order.
+pub fn programs_run() -> Vec<OsString> {
+    PROGRAMS.lock().unwrap().iter().cloned().collect()
+}
+
+/// Run a command to completion, logging its stdout and stderr.
+///
+/// Returns an error if the process could not be started or exited
+/// with a non-zero status.
+pub fn run_logged(logger: &Logger, command: &mut Command) -> Result<(), String> {
+    run_captured(logger, command, &mut Vec::new())
+}
+
+/// Run a command to completion, logging and capturing its output.
+///
+/// Lines of stdout and stderr are interleaved in the order they are
+/// received and appended to `output`, labeled with their stream. Output
+/// is captured even if the process fails.
+pub fn run_captured(
+    logger: &Logger,
+    command: &mut Command,
+    output: &mut Vec<OutputLine>,
+) -> Result<(), String> {
+    let program = format!("{:?}", command);
+    record_program(command.get_program());
+
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("error running {}: {}", program, e))?;
+
+    let (sender, receiver) = channel();
+
+    let threads: Vec<_> = vec![
+        forward_lines(
+            child.stdout.take().unwrap(),
+            OutputStream::Stdout,
+            sender.clone(),
+        ),
+        forward_lines(child.stderr.take().unwrap(), OutputStream::Stderr, sender),
+    ];
+
+    for mut line in receiver {
+        line.line = crate::secrets::redact(&line.line);
+        warn!(logger, "{}", line.line; "stream" => line.stream.as_str());
+        output.push(line);
+    }
+
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    let status = child
+        .wait()
+        .map_err(|e| format!("error waiting on {}: {}", program, e))?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("{} exited with {}", program, status))
+    }
+}
+
+fn forward_lines<R: Read + Send + 'static>(
+    reader: R,
+    stream: OutputStream,
+    sender: std::sync::mpsc::Sender<OutputLine>,
+) -> std::thread::JoinHandle<()> {
+    std::thread::spawn(move || {
+        for line in BufReader::new(reader).lines() {
+            match line {
+                Ok(line) => {
+                    if sender.send(OutputLine { stream, line }).is_err() {
+                        break;
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+    })
+}
+
+/// Environment variables set for the lifetime of a value.
+///
+/// Processes spawned while the value is alive inherit the variables.
+/// Previous values are restored when it is dropped.
+pub struct EnvironmentOverride {
+    previous: Vec<(String, Option<OsString>)>,
+}
+
+impl EnvironmentOverride {
+    pub fn new(vars: &BTreeMap<String, String>) -> Self {
+        let previous = vars
+            .iter()
+            .map(|(key, value)| {
+                let previous = std::env::var_os(key);
+                std::env::set_var(key, value);
+
+                (key.clone(), previous)
+            })
+            .collect();
+
+        EnvironmentOverride { previous }
+    }
+}
+
+impl Drop for EnvironmentOverride {
+    fn drop(&mut self) {
+        for (key, value) in self.previous.drain(..) {
+            match value {
+                Some(value) => std::env::set_var(&key, value),
+                None => std::env::remove_var(&key),
+            }
+        }
+    }
+}
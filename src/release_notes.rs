@@ -0,0 +1,250 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Generation of release notes.
+
+Release notes are Markdown documents combining a description of changes,
+obtained from Git history or a changelog file, with a table of the
+artifacts being released.
+*/
+
+use crate::inventory::{Inventory, INVENTORY_FILENAME};
+use serde::Serialize;
+use slog::{warn, Logger};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Template used when one isn't provided.
+pub const DEFAULT_TEMPLATE: &str = "## Changes
+
+{{changes}}
+
+## Artifacts
+
+{{artifacts}}
+";
+
+/// Describe commits since a tag as a Markdown list.
+///
+/// If `since_tag` is not defined, the most recent tag reachable from
+/// `HEAD` that doesn't point at `HEAD` is used. If there is no such tag,
+/// all history is described.
+pub fn git_changes(repo_path: &Path, since_tag: Option<&str>) -> Result<String, String> {
+    let repo = git2::Repository::discover(repo_path)
+        .map_err(|e| format!("unable to open Git repository: {}", e))?;
+
+    let head = repo
+        .head()
+        .and_then(|r| r.peel_to_commit())
+        .map_err(|e| format!("unable to resolve HEAD: {}", e))?;
+
+    let mut walk = repo.revwalk().map_err(|e| e.to_string())?;
+    walk.push(head.id()).map_err(|e| e.to_string())?;
+
+    let since = match since_tag {
+        Some(tag) => Some(
+            repo.revparse_single(&format!("refs/tags/{}", tag))
+                .and_then(|o| o.peel_to_commit())
+                .map_err(|e| format!("unable to resolve tag {}: {}", tag, e))?
+                .id(),
+        ),
+        None => {
+            let mut tagged = HashSet::new();
+
+            for name in repo
+                .tag_names(None)
+                .map_err(|e| e.to_string())?
+                .iter()
+                .flatten()
+            {
+                if let Ok(commit) = repo
+                    .revparse_single(&format!("refs/tags/{}", name))
+                    .and_then(|o| o.peel_to_commit())
+                {
+                    tagged.insert(commit.id());
+                }
+            }
+
+            let mut tag_walk = repo.revwalk().map_err(|e| e.to_string())?;
+            tag_walk.push(head.id()).map_err(|e| e.to_string())?;
+
+            tag_walk
+                .filter_map(|id| id.ok())
+                .filter(|id| *id != head.id())
+                .find(|id| tagged.contains(id))
+        }
+    };
+
+    if let Some(since) = since {
+        walk.hide(since).map_err(|e| e.to_string())?;
+    }
+
+    let mut lines = Vec::new();
+
+    for id in walk {
+        let id = id.map_err(|e| e.to_string())?;
+        let commit = repo.find_commit(id).map_err(|e| e.to_string())?;
+
+        // Merge commits don't describe changes themselves.
+        if commit.parent_count() > 1 {
+            continue;
+        }
+
+        let short_id = id.to_string()[0..12].to_string();
+        lines.push(format!(
+            "- {} ({})",
+            commit.summary().unwrap_or("").trim(),
+            short_id
+        ));
+    }
+
+    if lines.is_empty() {
+        Ok("No changes.".to_string())
+    } else {
+        Ok(lines.join("\n"))
+    }
+}
+
+/// Extract the most recent section of a Markdown changelog.
+///
+/// The section starts at the first heading and ends before the next
+/// heading of the same or a higher level. The heading itself is omitted.
+pub fn changelog_section(path: &Path) -> Result<String, String> {
+    let data = std::fs::read_to_string(path)
+        .map_err(|e| format!("unable to read {}: {}", path.display(), e))?;
+
+    let heading_level = |line: &str| {
+        let level = line.chars().take_while(|c| *c == '#').count();
+
+        if level > 0 && line[level..].starts_with(' ') {
+            Some(level)
+        } else {
+            None
+        }
+    };
+
+    let mut section_level = None;
+    let mut lines = Vec::new();
+
+    for line in data.lines() {
+        match (section_level, heading_level(line)) {
+            (None, Some(level)) => section_level = Some(level),
+            (None, None) => {}
+            (Some(section), Some(level)) if level <= section => break,
+            (Some(_), _) => lines.push(line),
+        }
+    }
+
+    if section_level.is_none() {
+        return Err(format!("{} does not contain any sections", path.display()));
+    }
+
+    Ok(lines.join("\n").trim().to_string())
+}
+
+/// Render a byte count in human friendly units.
+pub fn format_size(size: u64) -> String {
+    const UNITS: &[&str] = &["KiB", "MiB", "GiB"];
+
+    if size < 1024 {
+        return format!("{} B", size);
+    }
+
+    let mut value = size as f64 / 1024.0;
+    let mut unit = 0;
+
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+/// Render an inventory as a Markdown table.
+pub fn artifacts_table(inventory: &Inventory) -> String {
+    if inventory.artifacts.is_empty() {
+        return "No artifacts.".to_string();
+    }
+
+    let mut s = "| File | Size | SHA-256 |\n| --- | --- | --- |\n".to_string();
+
+    for artifact in &inventory.artifacts {
+        let name = match &artifact.url {
+            Some(url) => format!("[{}]({})", artifact.name, url),
+            None => format!("`{}`", artifact.name),
+        };
+
+        s.push_str(&format!(
+            "| {} | {} | `{}` |\n",
+            name,
+            format_size(artifact.size),
+            artifact.sha256
+        ));
+    }
+
+    s.trim_end().to_string()
+}
+
+/// Render release notes from a template.
+///
+/// `{{changes}}` and `{{artifacts}}` in the template are replaced with
+/// the description of changes and a table of artifacts, respectively.
+pub fn render(template: &str, changes: &str, inventory: &Inventory) -> String {
+    template
+        .replace("{{changes}}", changes)
+        .replace("{{artifacts}}", &artifacts_table(inventory))
+}
+
+/// Represents a request to write release notes.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReleaseNotes {
+    /// Path within the Git repository to describe history of.
+    pub repo_path: PathBuf,
+
+    pub template: Option<PathBuf>,
+    pub since_tag: Option<String>,
+    pub changelog: Option<PathBuf>,
+
+    /// Base URL artifacts can be downloaded from.
+    pub download_url: Option<String>,
+
+    /// Filename of the notes relative to the distribution path.
+    pub filename: String,
+}
+
+impl ReleaseNotes {
+    /// Write release notes and the artifact inventory into `dist_path`.
+    pub fn execute(&self, logger: &Logger, dist_path: &Path) -> Result<(), String> {
+        let inventory = Inventory::scan(
+            dist_path,
+            &[self.filename.as_str()],
+            self.download_url.as_deref(),
+        )?;
+        inventory.write(&dist_path.join(INVENTORY_FILENAME))?;
+
+        let changes = match &self.changelog {
+            Some(path) => changelog_section(path)?,
+            None => git_changes(&self.repo_path, self.since_tag.as_deref())?,
+        };
+
+        let template = match &self.template {
+            Some(path) => std::fs::read_to_string(path)
+                .map_err(|e| format!("unable to read {}: {}", path.display(), e))?,
+            None => DEFAULT_TEMPLATE.to_string(),
+        };
+
+        let path = dist_path.join(&self.filename);
+        warn!(
+            logger,
+            "writing release notes describing {} artifacts to {}",
+            inventory.artifacts.len(),
+            path.display()
+        );
+
+        std::fs::write(&path, render(&template, &changes, &inventory))
+            .map_err(|e| format!("unable to write {}: {}", path.display(), e))
+    }
+}
@@ -0,0 +1,167 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Interactive Read-Eval-Print Loop for the tugger Starlark dialect.
+
+This is modeled after the REPL in the `starlark-repl` crate but adds
+persistent history and tab completion of tugger builtins.
+*/
+
+use codemap_diagnostic::{ColorConfig, Emitter};
+use linefeed::{Completer, Completion, Interface, Prompter, ReadResult, Terminal};
+use starlark::environment::Environment;
+use starlark::eval::eval_lexer;
+use starlark::eval::simple::SimpleFileLoader;
+use starlark::syntax::lexer::BufferedLexer;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// Maximum number of entries retained in the history file.
+const HISTORY_SIZE: usize = 1000;
+
+/// Completes identifiers from tugger builtins and names seen in the session.
+struct NameCompleter {
+    names: Mutex<Vec<String>>,
+}
+
+impl NameCompleter {
+    fn new() -> Self {
+        Self {
+            names: Mutex::new(
+                crate::starlark::BUILTIN_NAMES
+                    .iter()
+                    .map(|s| s.to_string())
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Record identifiers appearing in evaluated input.
+    fn learn(&self, input: &str) {
+        let mut names = self.names.lock().unwrap();
+
+        for word in input.split(|c: char| !(c.is_alphanumeric() || c == '_')) {
+            if word.is_empty() || word.starts_with(|c: char| c.is_ascii_digit()) {
+                continue;
+            }
+
+            if !names.iter().any(|n| n == word) {
+                names.push(word.to_string());
+            }
+        }
+    }
+}
+
+impl<Term: Terminal> Completer<Term> for NameCompleter {
+    fn complete(
+        &self,
+        word: &str,
+        _prompter: &Prompter<Term>,
+        _start: usize,
+        _end: usize,
+    ) -> Option<Vec<Completion>> {
+        let names = self.names.lock().unwrap();
+
+        let mut matches: Vec<&String> = names.iter().filter(|n| n.starts_with(word)).collect();
+        matches.sort();
+
+        Some(
+            matches
+                .into_iter()
+                .map(|n| Completion::simple(n.clone()))
+                .collect(),
+        )
+    }
+}
+
+/// Path to the file holding REPL history.
+fn history_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".tugger_history"))
+}
+
+/// Run an interactive REPL until the user hits Ctrl+D.
+pub fn repl(global_environment: &Environment) -> Result<(), String> {
+    let map = Arc::new(Mutex::new(codemap::CodeMap::new()));
+    let reader =
+        Interface::new("tugger").map_err(|e| format!("unable to initialize terminal: {}", e))?;
+    let mut env = global_environment.child("repl");
+    let completer = Arc::new(NameCompleter::new());
+    let mut n = 0;
+
+    reader.set_completer(completer.clone());
+    reader.set_history_size(HISTORY_SIZE);
+
+    let history = history_path();
+    if let Some(path) = &history {
+        // A missing history file is expected on first use.
+        if path.exists() {
+            if let Err(e) = reader.load_history(path) {
+                eprintln!("unable to load history from {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    let set_prompt = |prompt: &str| {
+        reader
+            .set_prompt(prompt)
+            .map_err(|e| format!("unable to set prompt: {}", e))
+    };
+
+    set_prompt(">>> ")?;
+
+    while let Ok(ReadResult::Input(input)) = reader.read_line() {
+        if !input.is_empty() {
+            set_prompt("... ")?;
+            n += 1;
+            let input = input + "\n";
+            let mut lexer = BufferedLexer::new(&input);
+            let mut content = input;
+            while lexer.need_more() {
+                if let Ok(ReadResult::Input(input)) = reader.read_line() {
+                    let input = input + "\n";
+                    content += &input;
+                    lexer.input(&input);
+                } else {
+                    break;
+                }
+            }
+
+            let mut hist = content.clone();
+            hist.pop();
+            reader.add_history_unique(hist);
+            completer.learn(&content);
+
+            match eval_lexer(
+                &map,
+                &format!("<{}>", n),
+                &content,
+                false,
+                lexer,
+                &mut env,
+                SimpleFileLoader::new(&map.clone()),
+            ) {
+                Ok(v) => {
+                    if v.get_type() != "NoneType" {
+                        println!("{}", v.to_repr())
+                    }
+                }
+                Err(p) => {
+                    Emitter::stderr(ColorConfig::Always, Some(&map.lock().unwrap())).emit(&[p])
+                }
+            }
+        }
+        set_prompt(">>> ")?;
+    }
+
+    if let Some(path) = &history {
+        if let Err(e) = reader.save_history(path) {
+            eprintln!("unable to save history to {}: {}", path.display(), e);
+        }
+    }
+
+    println!("\nGoodbye!");
+
+    Ok(())
+}
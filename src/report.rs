@@ -0,0 +1,281 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Reporting on pipeline execution.
+
+An `ExecutionReport` records what happened when pipelines were executed:
+which pipelines and steps ran, how long they took, and whether they
+failed. Reports can be rendered as a human-readable timing table or
+serialized to JSON for consumption by other tools.
+
+Reports can also be rendered as JUnit XML, which CI systems display
+natively. Pipelines map to test suites and steps to test cases.
+*/
+
+use crate::build_environment::BuildEnvironment;
+use crate::channel::Channel;
+use crate::xml::xml_escape;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Describes the execution of a single pipeline step.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct StepReport {
+    /// Index of the step within its pipeline.
+    pub index: usize,
+
+    /// The kind of step (e.g. `tar_archive`).
+    pub kind: String,
+
+    /// Wall time spent executing the step, in seconds.
+    pub duration_secs: f64,
+
+    /// Error message if the step failed.
+    pub error: Option<String>,
+
+    /// Output of external processes run by the step.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub output: Vec<OutputLine>,
+
+    /// Artifacts the step created or modified, relative to the
+    /// distribution directory.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub artifacts: Vec<String>,
+}
+
+/// A stream of output of a process.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+impl OutputStream {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            OutputStream::Stdout => "stdout",
+            OutputStream::Stderr => "stderr",
+        }
+    }
+}
+
+/// A line of output of a process.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct OutputLine {
+    pub stream: OutputStream,
+    pub line: String,
+}
+
+/// Describes the execution of a pipeline.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PipelineReport {
+    /// The name of the pipeline.
+    pub name: String,
+
+    /// Target triple the pipeline built for.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+
+    /// Directory of the pipeline's artifacts relative to the distribution
+    /// path, if the pipeline has a distribution layout.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dist_dir: Option<String>,
+
+    /// Wall time spent executing the pipeline, in seconds.
+    pub duration_secs: f64,
+
+    /// Steps that were executed.
+    pub steps: Vec<StepReport>,
+
+    /// Error message if the pipeline failed.
+    pub error: Option<String>,
+}
+
+/// Describes the execution of pipelines.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ExecutionReport {
+    /// Release channel that was built.
+    pub channel: Channel,
+
+    pub pipelines: Vec<PipelineReport>,
+
+    /// The environment of the build, if recorded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub environment: Option<BuildEnvironment>,
+}
+
+impl ExecutionReport {
+    /// Whether any executed pipeline failed.
+    pub fn has_failures(&self) -> bool {
+        self.pipelines.iter().any(|p| p.error.is_some())
+    }
+
+    /// Render a table of per-pipeline and per-step durations.
+    pub fn timing_table(&self) -> Vec<String> {
+        let mut rows: Vec<(String, f64, &str)> = Vec::new();
+
+        for pipeline in &self.pipelines {
+            rows.push((
+                pipeline.name.clone(),
+                pipeline.duration_secs,
+                status(&pipeline.error),
+            ));
+
+            for step in &pipeline.steps {
+                rows.push((
+                    format!("  {}: {}", step.index, step.kind),
+                    step.duration_secs,
+                    status(&step.error),
+                ));
+            }
+        }
+
+        let width = rows
+            .iter()
+            .map(|(name, _, _)| name.len())
+            .max()
+            .unwrap_or(0)
+            .max("pipeline / step".len());
+
+        let mut lines = vec![format!(
+            "{:width$}  {:>10}  status",
+            "pipeline / step",
+            "duration",
+            width = width
+        )];
+
+        for (name, duration, status) in rows {
+            lines.push(format!(
+                "{:width$}  {:>9.2}s  {}",
+                name,
+                duration,
+                status,
+                width = width
+            ));
+        }
+
+        lines
+    }
+
+    /// Render the report as a JUnit XML document.
+    ///
+    /// Each pipeline is a test suite and each executed step a test case,
+    /// with failed steps carrying their error message and process output.
+    /// A pipeline that failed without a failing step, e.g. because it
+    /// wasn't found, gets a test case named `pipeline` for the failure.
+    pub fn to_junit_xml(&self) -> String {
+        let mut cases = Vec::new();
+
+        for pipeline in &self.pipelines {
+            let mut suite_cases: Vec<(String, f64, Option<&str>, &[OutputLine])> = pipeline
+                .steps
+                .iter()
+                .map(|step| {
+                    (
+                        format!("{}: {}", step.index, step.kind),
+                        step.duration_secs,
+                        step.error.as_deref(),
+                        &step.output[..],
+                    )
+                })
+                .collect();
+
+            if pipeline.error.is_some() && pipeline.steps.iter().all(|s| s.error.is_none()) {
+                suite_cases.push(("pipeline".to_string(), 0.0, pipeline.error.as_deref(), &[]));
+            }
+
+            cases.push((pipeline, suite_cases));
+        }
+
+        let tests: usize = cases.iter().map(|(_, c)| c.len()).sum();
+        let failures = cases
+            .iter()
+            .flat_map(|(_, c)| c.iter())
+            .filter(|(_, _, error, _)| error.is_some())
+            .count();
+        let time: f64 = self.pipelines.iter().map(|p| p.duration_secs).sum();
+
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        xml.push_str(&format!(
+            "<testsuites name=\"tugger\" tests=\"{}\" failures=\"{}\" time=\"{:.3}\">\n",
+            tests, failures, time
+        ));
+
+        for (pipeline, suite_cases) in &cases {
+            xml.push_str(&format!(
+                "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" time=\"{:.3}\">\n",
+                xml_escape(&pipeline.name),
+                suite_cases.len(),
+                suite_cases
+                    .iter()
+                    .filter(|(_, _, e, _)| e.is_some())
+                    .count(),
+                pipeline.duration_secs
+            ));
+
+            for (name, duration, error, output) in suite_cases {
+                xml.push_str(&format!(
+                    "    <testcase classname=\"{}\" name=\"{}\" time=\"{:.3}\"",
+                    xml_escape(&pipeline.name),
+                    xml_escape(name),
+                    duration
+                ));
+
+                if error.is_none() && output.is_empty() {
+                    xml.push_str("/>\n");
+                    continue;
+                }
+
+                xml.push_str(">\n");
+
+                if let Some(error) = error {
+                    let message = error.lines().next().unwrap_or("");
+                    xml.push_str(&format!(
+                        "      <failure message=\"{}\">{}</failure>\n",
+                        xml_escape(message),
+                        xml_escape(error)
+                    ));
+                }
+
+                for (stream, tag) in &[
+                    (OutputStream::Stdout, "system-out"),
+                    (OutputStream::Stderr, "system-err"),
+                ] {
+                    let lines = output
+                        .iter()
+                        .filter(|l| l.stream == *stream)
+                        .map(|l| xml_escape(&l.line))
+                        .collect::<Vec<_>>();
+
+                    if !lines.is_empty() {
+                        xml.push_str(&format!("      <{0}>{1}</{0}>\n", tag, lines.join("\n")));
+                    }
+                }
+
+                xml.push_str("    </testcase>\n");
+            }
+
+            xml.push_str("  </testsuite>\n");
+        }
+
+        xml.push_str("</testsuites>\n");
+
+        xml
+    }
+}
+
+fn status(error: &Option<String>) -> &'static str {
+    if error.is_some() {
+        "failed"
+    } else {
+        "ok"
+    }
+}
+
+/// Convert a `Duration` to fractional seconds.
+pub fn duration_secs(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) / 1_000_000_000.0
+}
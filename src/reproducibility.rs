@@ -0,0 +1,112 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Settings for producing reproducible artifacts.
+
+When reproducibility mode is enabled, archive writers must produce
+byte-identical output given identical inputs. This means entries are
+written in sorted order, owners are zeroed, compression parameters are
+fixed, and modification times are set to a fixed timestamp.
+
+The timestamp follows the
+[`SOURCE_DATE_EPOCH`](https://reproducible-builds.org/specs/source-date-epoch/)
+specification. If the environment variable is set, reproducibility mode
+is enabled automatically.
+*/
+
+use crate::filemanifest::FileContent;
+use serde::Serialize;
+use std::path::Path;
+
+/// Name of environment variable defining the reproducible timestamp.
+pub const SOURCE_DATE_EPOCH: &str = "SOURCE_DATE_EPOCH";
+
+/// Describes how to produce reproducible artifacts.
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct Reproducibility {
+    /// Timestamp to clamp file modification times to, in seconds since
+    /// the UNIX epoch.
+    ///
+    /// `None` means reproducibility mode is disabled.
+    pub source_date_epoch: Option<u64>,
+}
+
+impl Reproducibility {
+    /// Resolve settings from the environment.
+    ///
+    /// `SOURCE_DATE_EPOCH` is honored if set. Otherwise, if `enabled` is
+    /// true the timestamp of the Git commit `cwd` is based on is used,
+    /// falling back to the epoch.
+    pub fn resolve(enabled: bool, cwd: &Path) -> Result<Self, String> {
+        if let Ok(value) = std::env::var(SOURCE_DATE_EPOCH) {
+            let epoch = value
+                .trim()
+                .parse::<u64>()
+                .map_err(|e| format!("invalid {} value {}: {}", SOURCE_DATE_EPOCH, value, e))?;
+
+            return Ok(Reproducibility {
+                source_date_epoch: Some(epoch),
+            });
+        }
+
+        if !enabled {
+            return Ok(Reproducibility::default());
+        }
+
+        Ok(Reproducibility {
+            source_date_epoch: Some(git_commit_time(cwd).unwrap_or(0)),
+        })
+    }
+
+    /// Whether reproducibility mode is enabled.
+    pub fn is_enabled(&self) -> bool {
+        self.source_date_epoch.is_some()
+    }
+
+    /// The modification time to use for generated content.
+    ///
+    /// This is the reproducible timestamp or the current time.
+    pub fn mtime(&self) -> u64 {
+        match self.source_date_epoch {
+            Some(epoch) => epoch,
+            None => std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+        }
+    }
+
+    /// The modification time of file content.
+    ///
+    /// This is the reproducible timestamp if enabled, as modification
+    /// times of source files vary between checkouts. Otherwise it is the
+    /// modification time of files on the filesystem and the current time
+    /// for other content.
+    pub fn content_mtime(&self, content: &FileContent) -> Result<u64, String> {
+        match (self.source_date_epoch, content) {
+            (Some(epoch), _) => Ok(epoch),
+            (None, FileContent::Path(path)) => Ok(std::fs::metadata(path)
+                .map_err(|e| format!("unable to stat {}: {}", path.display(), e))?
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|d| d.as_secs())
+                .unwrap_or(0)),
+            (None, _) => Ok(self.mtime()),
+        }
+    }
+}
+
+fn git_commit_time(cwd: &Path) -> Option<u64> {
+    let repo = git2::Repository::discover(cwd).ok()?;
+    let commit = repo.head().ok()?.peel_to_commit().ok()?;
+    let seconds = commit.time().seconds();
+
+    if seconds >= 0 {
+        Some(seconds as u64)
+    } else {
+        None
+    }
+}
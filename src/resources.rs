@@ -0,0 +1,151 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Named resources steps hold while they execute.
+
+Some steps contend for something on the build host, like the VM
+snapcraft builds in or the Docker daemon. Steps declaring that they
+require a resource acquire it before they execute, so steps requiring
+the same resource don't run at the same time, even in separate tugger
+processes.
+
+Each resource has a limit of how many steps may hold it at once, which
+defaults to 1. A resource with a limit of `n` has `n` slots, each a file
+in the `locks` directory of the cache locked with an advisory lock.
+Locks are released when the process exits, so a killed process never
+leaves a resource held.
+*/
+
+use slog::{warn, Logger};
+use std::collections::BTreeMap;
+use std::fs::{File, TryLockError};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Directory of the cache holding lock files.
+pub const LOCKS_DIR: &str = "locks";
+
+/// How long to wait before checking again whether a resource is free.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Validate the name of a resource.
+///
+/// Names become filenames, so they are limited to ASCII letters, digits,
+/// `-`, `_`, and `.` and can't start with `.`.
+pub fn validate_name(name: &str) -> Result<(), String> {
+    if name.is_empty()
+        || name.starts_with('.')
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+    {
+        return Err(format!(
+            "invalid resource name {:?}; expected ASCII letters, digits, -, _, and .",
+            name
+        ));
+    }
+
+    Ok(())
+}
+
+/// Parse a resource limit such as `docker=2`.
+pub fn parse_limit(value: &str) -> Result<(String, usize), String> {
+    let (name, limit) = value
+        .split_once('=')
+        .ok_or_else(|| format!("invalid resource limit {}; expected NAME=LIMIT", value))?;
+
+    validate_name(name)?;
+
+    match limit.parse::<usize>() {
+        Ok(limit) if limit > 0 => Ok((name.to_string(), limit)),
+        _ => Err(format!(
+            "invalid limit of resource {}: {}; expected a positive integer",
+            name, limit
+        )),
+    }
+}
+
+/// Resources held by a step.
+///
+/// The resources are released when this is dropped.
+#[derive(Debug)]
+pub struct ResourceLocks {
+    _files: Vec<File>,
+}
+
+fn lock_path(name: &str, slot: usize) -> Result<PathBuf, String> {
+    Ok(crate::cache::cache_dir()?
+        .join(LOCKS_DIR)
+        .join(format!("{}.{}.lock", name, slot)))
+}
+
+/// Try to lock any free slot of a resource.
+fn try_acquire(name: &str, limit: usize) -> Result<Option<File>, String> {
+    for slot in 0..limit {
+        let path = lock_path(name, slot)?;
+        std::fs::create_dir_all(path.parent().unwrap())
+            .map_err(|e| format!("unable to create {}: {}", path.display(), e))?;
+
+        let fh = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .map_err(|e| format!("unable to open {}: {}", path.display(), e))?;
+
+        match fh.try_lock() {
+            Ok(()) => return Ok(Some(fh)),
+            Err(TryLockError::WouldBlock) => {}
+            Err(TryLockError::Error(e)) => {
+                return Err(format!("unable to lock {}: {}", path.display(), e))
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Acquire resources, waiting until they are free.
+///
+/// `limits` defines how many steps may hold a resource at once.
+/// Resources without a limit can be held by one step at a time.
+/// Resources are acquired in the order of their names, so steps
+/// requiring several of the same resources don't deadlock.
+pub fn acquire(
+    logger: &Logger,
+    resources: &[String],
+    limits: &BTreeMap<String, usize>,
+) -> Result<ResourceLocks, String> {
+    let mut names = resources.to_vec();
+    names.sort();
+    names.dedup();
+
+    let mut files = Vec::with_capacity(names.len());
+
+    for name in &names {
+        validate_name(name)?;
+        let limit = limits.get(name).copied().unwrap_or(1).max(1);
+
+        let mut waiting = false;
+        loop {
+            if let Some(fh) = try_acquire(name, limit)? {
+                files.push(fh);
+                break;
+            }
+
+            if !waiting {
+                warn!(
+                    logger,
+                    "waiting for resource {} held by {} other step(s)", name, limit
+                );
+                waiting = true;
+            }
+
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    Ok(ResourceLocks { _files: files })
+}
@@ -0,0 +1,481 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Resumable uploads over HTTP.
+
+Google Cloud Storage and [tus](https://tus.io/) servers accept uploads
+in sessions: a request starts a session and returns its URL, content is
+sent to the URL in chunks, and the server reports how many bytes it has
+received. The session URL is persisted in the `uploads` namespace of the
+cache, like the state of multipart uploads to S3, so running the upload
+again after an interruption asks the server for its offset and only
+sends the remaining content.
+
+Sessions are only resumed if the artifact is unchanged since the upload
+started. Sessions the server no longer knows, e.g. because they expired,
+are replaced by new ones.
+*/
+
+use crate::multipart::{file_version, read_state, state_path, write_state};
+use crate::retry::{AttemptError, RetryPolicy};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use slog::{warn, Logger};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::process::Command;
+
+/// Default size of chunks of resumable uploads.
+pub const DEFAULT_CHUNK_SIZE: u64 = 64 << 20;
+
+/// Google Cloud Storage requires chunks to be a multiple of this size.
+pub const GCS_CHUNK_ALIGNMENT: u64 = 256 << 10;
+
+/// Base URL of the Google Cloud Storage JSON API.
+pub const GCS_API_URL: &str = "https://storage.googleapis.com";
+
+/// Version of the tus protocol spoken.
+const TUS_VERSION: &str = "1.0.0";
+
+/// State of an unfinished upload session, persisted between runs.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+struct SessionState {
+    /// URL content of the upload is sent to.
+    session_url: String,
+
+    /// Size and modification time of the artifact when the upload started.
+    size: u64,
+    modified: u64,
+}
+
+/// A protocol of upload sessions.
+trait SessionProtocol {
+    /// Start a session uploading `size` bytes and obtain its URL.
+    fn create(&self, size: u64) -> Result<String, AttemptError>;
+
+    /// Obtain how many bytes of an upload the server has.
+    ///
+    /// Returns `None` if the server doesn't know the session.
+    fn offset(&self, session_url: &str, size: u64) -> Result<Option<u64>, AttemptError>;
+
+    /// Send `len` bytes at `offset` and obtain the offset the server is at.
+    fn send(
+        &self,
+        session_url: &str,
+        offset: u64,
+        len: u64,
+        size: u64,
+        data: &mut dyn Read,
+    ) -> Result<u64, AttemptError>;
+}
+
+/// An HTTP agent that doesn't follow redirects.
+///
+/// Google Cloud Storage reports progress with `308` responses.
+fn agent() -> ureq::Agent {
+    ureq::AgentBuilder::new().redirects(0).build()
+}
+
+/// Resolve a `Location` header against the URL of a request.
+fn resolve_location(url: &str, location: &str) -> String {
+    if location.contains("://") {
+        return location.to_string();
+    }
+
+    let origin_end = url
+        .find("://")
+        .and_then(|i| url[i + 3..].find('/').map(|j| i + 3 + j))
+        .unwrap_or(url.len());
+
+    if location.starts_with('/') {
+        format!("{}{}", &url[..origin_end], location)
+    } else {
+        format!("{}/{}", url.trim_end_matches('/'), location)
+    }
+}
+
+/// Obtain the `Location` header of a response starting a session.
+fn session_location(url: &str, response: &ureq::Response) -> Result<String, AttemptError> {
+    response
+        .header("Location")
+        .map(|location| resolve_location(url, location))
+        .ok_or_else(|| AttemptError::permanent(format!("{} didn't return an upload URL", url)))
+}
+
+/// Upload a file in a resumable session, resuming an unfinished upload of it.
+///
+/// `url` identifies the destination of the upload, for keying its state.
+fn session_upload(
+    logger: &Logger,
+    artifact: &Path,
+    url: &str,
+    chunk_size: u64,
+    retry: &RetryPolicy,
+    protocol: &dyn SessionProtocol,
+) -> Result<(), String> {
+    let (size, modified) = file_version(artifact)?;
+    let state_path = state_path(url, artifact)?;
+
+    let previous = read_state::<SessionState>(&state_path)
+        .filter(|state| state.size == size && state.modified == modified);
+
+    let resumed = previous.and_then(|state| match protocol.offset(&state.session_url, size) {
+        Ok(Some(offset)) => Some((state, offset)),
+        Ok(None) => None,
+        Err(e) => {
+            warn!(
+                logger,
+                "unable to resume upload of {} to {}: {}",
+                artifact.display(),
+                url,
+                e.message
+            );
+            None
+        }
+    });
+
+    let (state, mut offset) = match resumed {
+        Some((state, offset)) => {
+            warn!(
+                logger,
+                "resuming upload of {} to {} at byte {} of {}",
+                artifact.display(),
+                url,
+                offset,
+                size
+            );
+            (state, offset)
+        }
+        None => {
+            let session_url = retry.retry(logger, &format!("upload to {}", url), || {
+                protocol.create(size)
+            })?;
+
+            let state = SessionState {
+                session_url,
+                size,
+                modified,
+            };
+            write_state(&state_path, &state)?;
+
+            (state, 0)
+        }
+    };
+
+    let mut fh = std::fs::File::open(artifact)
+        .map_err(|e| format!("unable to open {}: {}", artifact.display(), e))?;
+
+    while offset < size {
+        let mut first_attempt = true;
+
+        let received = retry.retry(
+            logger,
+            &format!("upload of {} at byte {}", artifact.display(), offset),
+            || {
+                // A failed attempt may have sent part of the chunk.
+                let offset = if first_attempt {
+                    first_attempt = false;
+                    offset
+                } else {
+                    protocol
+                        .offset(&state.session_url, size)?
+                        .ok_or_else(|| AttemptError::permanent("upload session expired"))?
+                };
+                if offset >= size {
+                    return Ok(offset);
+                }
+
+                let len = chunk_size.min(size - offset);
+                fh.seek(SeekFrom::Start(offset))
+                    .map_err(|e| AttemptError::permanent(format!("unable to seek: {}", e)))?;
+
+                protocol.send(&state.session_url, offset, len, size, &mut (&fh).take(len))
+            },
+        )?;
+
+        if received <= offset {
+            return Err(format!(
+                "{} didn't accept content of {} at byte {}",
+                url,
+                artifact.display(),
+                offset
+            ));
+        }
+        offset = received;
+
+        warn!(
+            logger,
+            "uploaded {} of {} bytes of {}",
+            offset,
+            size,
+            artifact.display()
+        );
+    }
+
+    let _ = std::fs::remove_file(&state_path);
+
+    Ok(())
+}
+
+/// Obtain an access token of the active `gcloud` account.
+fn gcloud_access_token() -> Result<String, AttemptError> {
+    crate::process::record_program("gcloud");
+
+    // The output is a credential, so it isn't logged.
+    let output = Command::new("gcloud")
+        .arg("auth")
+        .arg("print-access-token")
+        .output()
+        .map_err(|e| AttemptError::process(format!("error running gcloud: {}", e)))?;
+
+    if !output.status.success() {
+        return Err(AttemptError::process(format!(
+            "gcloud failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Resumable uploads of the Google Cloud Storage JSON API.
+struct GcsProtocol {
+    api_url: String,
+    bucket: String,
+    object: String,
+}
+
+impl GcsProtocol {
+    /// Obtain the offset from the `Range` header of a `308` response.
+    fn received(response: &ureq::Response) -> u64 {
+        response
+            .header("Range")
+            .and_then(|range| range.rsplit('-').next())
+            .and_then(|end| end.parse::<u64>().ok())
+            .map(|end| end + 1)
+            .unwrap_or(0)
+    }
+}
+
+impl SessionProtocol for GcsProtocol {
+    fn create(&self, size: u64) -> Result<String, AttemptError> {
+        let url = format!(
+            "{}/upload/storage/v1/b/{}/o?uploadType=resumable&name={}",
+            self.api_url,
+            crate::http::percent_encode(&self.bucket),
+            crate::http::percent_encode(&self.object)
+        );
+
+        let response = agent()
+            .post(&url)
+            .set(
+                "Authorization",
+                &format!("Bearer {}", gcloud_access_token()?),
+            )
+            .set("X-Upload-Content-Type", "application/octet-stream")
+            .set("X-Upload-Content-Length", &size.to_string())
+            .send_bytes(&[])
+            .map_err(AttemptError::http)?;
+
+        session_location(&url, &response)
+    }
+
+    fn offset(&self, session_url: &str, size: u64) -> Result<Option<u64>, AttemptError> {
+        match agent()
+            .put(session_url)
+            .set("Content-Range", &format!("bytes */{}", size))
+            .send_bytes(&[])
+        {
+            Ok(response) if response.status() == 308 => Ok(Some(Self::received(&response))),
+            Ok(_) => Ok(Some(size)),
+            Err(ureq::Error::Status(404, _)) | Err(ureq::Error::Status(410, _)) => Ok(None),
+            Err(e) => Err(AttemptError::http(e)),
+        }
+    }
+
+    fn send(
+        &self,
+        session_url: &str,
+        offset: u64,
+        len: u64,
+        size: u64,
+        data: &mut dyn Read,
+    ) -> Result<u64, AttemptError> {
+        let response = agent()
+            .put(session_url)
+            .set("Content-Length", &len.to_string())
+            .set(
+                "Content-Range",
+                &format!("bytes {}-{}/{}", offset, offset + len - 1, size),
+            )
+            .send(data)
+            .map_err(AttemptError::http)?;
+
+        Ok(match response.status() {
+            308 => Self::received(&response),
+            _ => size,
+        })
+    }
+}
+
+/// Upload a file to Google Cloud Storage in a resumable session.
+///
+/// `url` is the `gs://bucket/object` URL of the object to write and
+/// `api_url` the base URL of the JSON API. Authentication uses the active
+/// `gcloud` account. `chunk_size` is rounded down to a multiple of 256KiB.
+pub fn gcs_resumable_upload(
+    logger: &Logger,
+    artifact: &Path,
+    url: &str,
+    api_url: &str,
+    chunk_size: u64,
+    retry: &RetryPolicy,
+) -> Result<(), String> {
+    let path = url
+        .strip_prefix("gs://")
+        .ok_or_else(|| format!("{} is not a gs:// URL", url))?;
+    let (bucket, object) = match path.split_once('/') {
+        Some((bucket, object)) if !bucket.is_empty() && !object.is_empty() => (bucket, object),
+        _ => return Err(format!("{} doesn't name an object in a bucket", url)),
+    };
+
+    let protocol = GcsProtocol {
+        api_url: api_url.trim_end_matches('/').to_string(),
+        bucket: bucket.to_string(),
+        object: object.to_string(),
+    };
+
+    let chunk_size = (chunk_size / GCS_CHUNK_ALIGNMENT).max(1) * GCS_CHUNK_ALIGNMENT;
+
+    session_upload(logger, artifact, url, chunk_size, retry, &protocol)
+}
+
+/// The [tus](https://tus.io/protocols/resumable-upload) resumable upload protocol.
+struct TusProtocol<'a> {
+    /// URL uploads are created at.
+    endpoint: &'a str,
+
+    filename: String,
+    token: Option<&'a str>,
+}
+
+impl TusProtocol<'_> {
+    fn request(&self, method: &str, url: &str) -> ureq::Request {
+        let request = agent()
+            .request(method, url)
+            .set("Tus-Resumable", TUS_VERSION);
+
+        match self.token {
+            Some(token) => request.set("Authorization", &format!("Bearer {}", token)),
+            None => request,
+        }
+    }
+
+    fn upload_offset(response: &ureq::Response) -> Result<u64, AttemptError> {
+        response
+            .header("Upload-Offset")
+            .and_then(|offset| offset.parse().ok())
+            .ok_or_else(|| AttemptError::permanent("server didn't report the upload offset"))
+    }
+}
+
+impl SessionProtocol for TusProtocol<'_> {
+    fn create(&self, size: u64) -> Result<String, AttemptError> {
+        let metadata = format!(
+            "filename {}",
+            base64::engine::general_purpose::STANDARD.encode(&self.filename)
+        );
+
+        let response = self
+            .request("POST", self.endpoint)
+            .set("Upload-Length", &size.to_string())
+            .set("Upload-Metadata", &metadata)
+            .send_bytes(&[])
+            .map_err(AttemptError::http)?;
+
+        session_location(self.endpoint, &response)
+    }
+
+    fn offset(&self, session_url: &str, _size: u64) -> Result<Option<u64>, AttemptError> {
+        match self.request("HEAD", session_url).call() {
+            Ok(response) => Ok(Some(Self::upload_offset(&response)?)),
+            Err(ureq::Error::Status(403, _))
+            | Err(ureq::Error::Status(404, _))
+            | Err(ureq::Error::Status(410, _)) => Ok(None),
+            Err(e) => Err(AttemptError::http(e)),
+        }
+    }
+
+    fn send(
+        &self,
+        session_url: &str,
+        offset: u64,
+        len: u64,
+        _size: u64,
+        data: &mut dyn Read,
+    ) -> Result<u64, AttemptError> {
+        let response = self
+            .request("PATCH", session_url)
+            .set("Content-Type", "application/offset+octet-stream")
+            .set("Content-Length", &len.to_string())
+            .set("Upload-Offset", &offset.to_string())
+            .send(data)
+            .map_err(AttemptError::http)?;
+
+        Self::upload_offset(&response)
+    }
+}
+
+/// Upload a file to a tus server, resuming an unfinished upload of it.
+///
+/// `endpoint` is the URL uploads are created at. The filename of the
+/// artifact is sent as `filename` metadata. `token` is sent as bearer
+/// token if defined.
+pub fn tus_upload(
+    logger: &Logger,
+    artifact: &Path,
+    endpoint: &str,
+    token: Option<&str>,
+    chunk_size: u64,
+    retry: &RetryPolicy,
+) -> Result<(), String> {
+    let filename = artifact
+        .file_name()
+        .map(|f| f.to_string_lossy().to_string())
+        .ok_or_else(|| format!("{} has no filename", artifact.display()))?;
+
+    let protocol = TusProtocol {
+        endpoint,
+        filename,
+        token,
+    };
+
+    session_upload(logger, artifact, endpoint, chunk_size, retry, &protocol)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_location() {
+        assert_eq!(
+            resolve_location("https://example.com/files/", "https://other.com/x"),
+            "https://other.com/x"
+        );
+        assert_eq!(
+            resolve_location("https://example.com/files/", "/files/abc"),
+            "https://example.com/files/abc"
+        );
+        assert_eq!(
+            resolve_location("https://example.com/files", "abc"),
+            "https://example.com/files/abc"
+        );
+        assert_eq!(
+            resolve_location("http://localhost:1080", "/files/abc"),
+            "http://localhost:1080/files/abc"
+        );
+    }
+}
@@ -0,0 +1,204 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Retrying operations that fail transiently.
+
+Network operations like uploads fail for reasons that go away on their
+own: dropped connections, timeouts, rate limiting, and overloaded
+servers. A `RetryPolicy` defines how often such operations are attempted
+and how long to wait between attempts. Failures are classified so that
+only failures likely to be transient are retried.
+*/
+
+use serde::Serialize;
+use slog::{warn, Logger};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Kinds of failures that may be retried.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureClass {
+    /// A connection couldn't be established or was interrupted.
+    Network,
+
+    /// A server responded with a status indicating a temporary problem,
+    /// like HTTP 429 or 503.
+    Server,
+
+    /// An external program, e.g. `rsync` or `cargo`, failed.
+    Process,
+}
+
+impl FailureClass {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FailureClass::Network => "network",
+            FailureClass::Server => "server",
+            FailureClass::Process => "process",
+        }
+    }
+}
+
+impl FromStr for FailureClass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "network" => Ok(FailureClass::Network),
+            "server" => Ok(FailureClass::Server),
+            "process" => Ok(FailureClass::Process),
+            _ => Err(format!(
+                "unknown failure class {}; expected network, server, or process",
+                s
+            )),
+        }
+    }
+}
+
+/// An error of a single attempt of an operation.
+#[derive(Clone, Debug)]
+pub struct AttemptError {
+    /// Class of the failure, if it may be retried.
+    pub class: Option<FailureClass>,
+
+    pub message: String,
+}
+
+impl AttemptError {
+    /// An error that retrying won't fix.
+    pub fn permanent(message: impl Into<String>) -> Self {
+        AttemptError {
+            class: None,
+            message: message.into(),
+        }
+    }
+
+    /// An error of an external program.
+    pub fn process(message: impl Into<String>) -> Self {
+        AttemptError {
+            class: Some(FailureClass::Process),
+            message: message.into(),
+        }
+    }
+
+    /// Classify an HTTP error.
+    ///
+    /// Transport errors are network failures. Request timeouts, rate
+    /// limiting, and 5xx responses are server failures. Other responses,
+    /// e.g. authentication failures, are permanent.
+    pub fn http(e: ureq::Error) -> Self {
+        let class = match &e {
+            ureq::Error::Transport(_) => Some(FailureClass::Network),
+            ureq::Error::Status(408, _)
+            | ureq::Error::Status(425, _)
+            | ureq::Error::Status(429, _)
+            | ureq::Error::Status(500..=599, _) => Some(FailureClass::Server),
+            ureq::Error::Status(_, _) => None,
+        };
+
+        AttemptError {
+            class,
+            message: crate::http::error_message(e),
+        }
+    }
+}
+
+impl From<String> for AttemptError {
+    fn from(message: String) -> Self {
+        AttemptError::permanent(message)
+    }
+}
+
+/// Defines how operations are retried.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct RetryPolicy {
+    /// Maximum number of attempts, including the first.
+    pub max_attempts: u32,
+
+    /// Seconds to wait before the first retry.
+    ///
+    /// The delay doubles with every further retry.
+    pub initial_delay: u64,
+
+    /// Maximum number of seconds to wait between attempts.
+    pub max_delay: u64,
+
+    /// Classes of failures that are retried.
+    pub retry_on: Vec<FailureClass>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_delay: 2,
+            max_delay: 60,
+            // External programs also fail for reasons retrying won't fix,
+            // so their failures are only retried if requested.
+            retry_on: vec![FailureClass::Network, FailureClass::Server],
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Obtain the delay before the attempt following attempt `attempt`.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 2u64.saturating_pow(attempt.saturating_sub(1));
+
+        Duration::from_secs(
+            self.initial_delay
+                .saturating_mul(factor)
+                .min(self.max_delay),
+        )
+    }
+
+    /// Perform an operation, retrying it according to this policy.
+    ///
+    /// `what` describes the operation in log messages. The operation is
+    /// attempted again after a delay if it fails with an error of a class
+    /// in `retry_on`, until `max_attempts` attempts were made.
+    pub fn retry<T, F>(&self, logger: &Logger, what: &str, mut operation: F) -> Result<T, String>
+    where
+        F: FnMut() -> Result<T, AttemptError>,
+    {
+        let mut attempt = 1;
+
+        loop {
+            let e = match operation() {
+                Ok(value) => return Ok(value),
+                Err(e) => e,
+            };
+
+            let retryable = e
+                .class
+                .map(|class| self.retry_on.contains(&class))
+                .unwrap_or(false);
+
+            if !retryable || attempt >= self.max_attempts {
+                return Err(if attempt > 1 {
+                    format!("{} (gave up after {} attempts)", e.message, attempt)
+                } else {
+                    e.message
+                });
+            }
+
+            let delay = self.delay(attempt);
+
+            warn!(
+                logger,
+                "{} failed (attempt {} of {}): {}; retrying in {}s",
+                what,
+                attempt,
+                self.max_attempts,
+                e.message,
+                delay.as_secs()
+            );
+
+            std::thread::sleep(delay);
+            attempt += 1;
+        }
+    }
+}
@@ -0,0 +1,350 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Building RPMs with `rpmbuild` and `mock`.
+
+Rather than writing RPMs itself, tugger generates a `.spec` file and a
+source tarball from package metadata and a file manifest, and has
+`rpmbuild` build them. The spec installs the files as they are: nothing
+is compiled, stripped, or split into debug packages.
+
+With the `mock` backend, `rpmbuild` only builds a source RPM, which
+`mock` rebuilds in a clean chroot of the target distribution. This
+produces RPMs whose automatic dependencies are computed against that
+distribution rather than the build machine.
+*/
+
+use crate::filemanifest::{FileContent, FileManifest};
+use crate::mode_policy::ModePolicy;
+use crate::package_metadata::PackageMetadata;
+use crate::reproducibility::{Reproducibility, SOURCE_DATE_EPOCH};
+use serde::Serialize;
+use slog::{warn, Logger};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+
+/// Tools building RPMs.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RpmBackend {
+    /// Build on the build machine with `rpmbuild`.
+    Rpmbuild,
+
+    /// Rebuild a source RPM in a chroot with `mock`.
+    Mock,
+}
+
+impl FromStr for RpmBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "rpmbuild" => Ok(RpmBackend::Rpmbuild),
+            "mock" => Ok(RpmBackend::Mock),
+            _ => Err(format!(
+                "unknown RPM backend {}; expected rpmbuild or mock",
+                s
+            )),
+        }
+    }
+}
+
+/// Describes an RPM to build.
+#[derive(Clone, Debug, Serialize)]
+pub struct RpmPackageInfo {
+    pub metadata: PackageMetadata,
+
+    /// The `Release` of the package.
+    pub release: String,
+
+    /// The `BuildArch` of the package, e.g. `noarch`.
+    ///
+    /// Defaults to the architecture of the build machine or mock chroot.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arch: Option<String>,
+
+    /// `Requires` of the package, e.g. `openssl-libs >= 3`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub requires: Vec<String>,
+
+    pub backend: RpmBackend,
+
+    /// Name of the mock chroot configuration, e.g. `fedora-39-x86_64`.
+    ///
+    /// Defaults to mock's default configuration.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mock_root: Option<String>,
+}
+
+impl RpmPackageInfo {
+    /// The name of the directory in the source tarball.
+    fn source_dir(&self) -> String {
+        format!("{}-{}", self.metadata.name, self.metadata.version)
+    }
+
+    /// Obtain the `.spec` file building the package from its source tarball.
+    pub fn spec(&self, files: &FileManifest, reproducibility: &Reproducibility) -> String {
+        let metadata = &self.metadata;
+        let mut lines = vec![
+            // The files are installed as they are.
+            "%global debug_package %{nil}".to_string(),
+            "%global __os_install_post %{nil}".to_string(),
+            "%global _build_id_links none".to_string(),
+        ];
+
+        if reproducibility.is_enabled() {
+            lines.push("%global use_source_date_epoch_as_buildtime 1".to_string());
+            lines.push("%global clamp_mtime_to_source_date_epoch 1".to_string());
+        }
+
+        lines.push(String::new());
+        lines.push(format!("Name: {}", metadata.name));
+        lines.push(format!("Version: {}", metadata.version));
+        lines.push(format!("Release: {}", self.release));
+        lines.push(format!("Summary: {}", spec_escape(&metadata.summary())));
+        lines.push(format!(
+            "License: {}",
+            spec_escape(metadata.license.as_deref().unwrap_or("Unknown"))
+        ));
+
+        if let Some(homepage) = &metadata.homepage {
+            lines.push(format!("URL: {}", spec_escape(homepage)));
+        }
+        if let Some(vendor) = metadata.vendor() {
+            lines.push(format!("Vendor: {}", spec_escape(&vendor)));
+        }
+        if let Some(maintainer) = &metadata.maintainer {
+            lines.push(format!("Packager: {}", spec_escape(maintainer)));
+        }
+        if let Some(arch) = &self.arch {
+            lines.push(format!("BuildArch: {}", arch));
+        }
+        for require in &self.requires {
+            lines.push(format!("Requires: {}", spec_escape(require)));
+        }
+
+        lines.push(format!("Source0: {}.tar.gz", self.source_dir()));
+        lines.push(String::new());
+        lines.push("%description".to_string());
+        lines.push(spec_escape(metadata.description.trim()));
+        lines.push(String::new());
+        lines.push("%prep".to_string());
+        lines.push("%setup -q".to_string());
+        lines.push(String::new());
+        lines.push("%install".to_string());
+        lines.push("mkdir -p %{buildroot}".to_string());
+        lines.push("cp -a . %{buildroot}/".to_string());
+        lines.push(String::new());
+        lines.push("%files".to_string());
+        lines.push("%defattr(-,root,root,-)".to_string());
+
+        for rel_path in files.keys() {
+            lines.push(format!("\"/{}\"", spec_escape(rel_path)));
+        }
+
+        lines.join("\n") + "\n"
+    }
+}
+
+/// Escape text for use in a `.spec` file, where `%` starts a macro.
+fn spec_escape(s: &str) -> String {
+    s.replace('%', "%%")
+}
+
+/// Write a gzipped source tarball with files under a directory.
+fn write_source_tarball(
+    logger: &Logger,
+    path: &Path,
+    dir: &str,
+    files: &FileManifest,
+    reproducibility: &Reproducibility,
+    modes: &ModePolicy,
+) -> Result<(), String> {
+    let fh = std::fs::File::create(path)
+        .map_err(|e| format!("unable to open {} for writing: {}", path.display(), e))?;
+    let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+        fh,
+        flate2::Compression::default(),
+    ));
+
+    for (rel_path, content) in files {
+        warn!(logger, "adding {} as {}", content.describe(), rel_path);
+
+        let name = format!("{}/{}", dir, rel_path);
+        let mut header = tar::Header::new_gnu();
+        header.set_mode(modes.file_mode(content));
+        header.set_size(content.size()?);
+        header.set_mtime(reproducibility.content_mtime(content)?);
+        header.set_uid(0);
+        header.set_gid(0);
+
+        if let FileContent::Symlink(target) = content {
+            header.set_entry_type(tar::EntryType::Symlink);
+            builder
+                .append_link(&mut header, &name, target)
+                .map_err(|e| format!("unable to add {} to archive: {}", rel_path, e))?;
+
+            continue;
+        }
+
+        builder
+            .append_data(&mut header, &name, content.open()?)
+            .map_err(|e| format!("unable to add {} to archive: {}", rel_path, e))?;
+    }
+
+    builder
+        .into_inner()
+        .and_then(|encoder| encoder.finish())
+        .map(|_| ())
+        .map_err(|e| format!("unable to write {}: {}", path.display(), e))
+}
+
+/// Find RPMs under a directory.
+///
+/// `source` selects source RPMs instead of binary RPMs.
+fn find_rpms(dir: &Path, source: bool) -> Result<Vec<PathBuf>, String> {
+    let mut rpms = Vec::new();
+
+    for entry in walkdir::WalkDir::new(dir).sort_by(|a, b| a.file_name().cmp(b.file_name())) {
+        let entry = entry.map_err(|e| e.to_string())?;
+        let name = entry.file_name().to_string_lossy();
+
+        if entry.file_type().is_file()
+            && name.ends_with(".rpm")
+            && name.ends_with(".src.rpm") == source
+        {
+            rpms.push(entry.path().to_path_buf());
+        }
+    }
+
+    Ok(rpms)
+}
+
+/// Build RPMs of files and copy them to the distribution directory.
+///
+/// Returns the paths of the RPMs written.
+pub fn build_rpm(
+    logger: &Logger,
+    dist_path: &Path,
+    info: &RpmPackageInfo,
+    files: &FileManifest,
+    reproducibility: &Reproducibility,
+    modes: &ModePolicy,
+) -> Result<Vec<PathBuf>, String> {
+    let temp_dir = tempfile::Builder::new()
+        .prefix("tugger-rpm-")
+        .tempdir()
+        .map_err(|e| format!("unable to create temp directory: {}", e))?;
+    let top_dir = temp_dir.path();
+
+    for dir in &["SOURCES", "SPECS"] {
+        std::fs::create_dir_all(top_dir.join(dir))
+            .map_err(|e| format!("unable to create {}: {}", top_dir.join(dir).display(), e))?;
+    }
+
+    write_source_tarball(
+        logger,
+        &top_dir
+            .join("SOURCES")
+            .join(format!("{}.tar.gz", info.source_dir())),
+        &info.source_dir(),
+        files,
+        reproducibility,
+        modes,
+    )?;
+
+    let spec_path = top_dir
+        .join("SPECS")
+        .join(format!("{}.spec", info.metadata.name));
+    std::fs::write(&spec_path, info.spec(files, reproducibility))
+        .map_err(|e| format!("unable to write {}: {}", spec_path.display(), e))?;
+
+    let rpmbuild = |mode: &str| {
+        let mut command = Command::new("rpmbuild");
+        command
+            .arg(mode)
+            .arg("--define")
+            .arg(format!("_topdir {}", top_dir.display()))
+            .arg(&spec_path);
+
+        if let Some(epoch) = reproducibility.source_date_epoch {
+            command.env(SOURCE_DATE_EPOCH, epoch.to_string());
+        }
+
+        command
+    };
+
+    let rpms = match info.backend {
+        RpmBackend::Rpmbuild => {
+            warn!(
+                logger,
+                "building RPM of {} with rpmbuild", info.metadata.name
+            );
+            crate::process::run_logged(logger, &mut rpmbuild("-bb"))?;
+
+            find_rpms(&top_dir.join("RPMS"), false)?
+        }
+        RpmBackend::Mock => {
+            crate::process::run_logged(logger, &mut rpmbuild("-bs"))?;
+
+            let srpm = find_rpms(&top_dir.join("SRPMS"), true)?
+                .into_iter()
+                .next()
+                .ok_or_else(|| "rpmbuild didn't produce a source RPM".to_string())?;
+            let result_dir = top_dir.join("mock");
+
+            warn!(
+                logger,
+                "building RPM of {} with mock in {}",
+                info.metadata.name,
+                info.mock_root.as_deref().unwrap_or("the default chroot")
+            );
+
+            let mut command = Command::new("mock");
+            if let Some(root) = &info.mock_root {
+                command.arg("--root").arg(root);
+            }
+            command
+                .arg("--resultdir")
+                .arg(&result_dir)
+                .arg("--rebuild")
+                .arg(&srpm);
+
+            if let Some(epoch) = reproducibility.source_date_epoch {
+                command.env(SOURCE_DATE_EPOCH, epoch.to_string());
+            }
+
+            crate::process::run_logged(logger, &mut command)?;
+
+            find_rpms(&result_dir, false)?
+        }
+    };
+
+    if rpms.is_empty() {
+        return Err(format!("no RPMs of {} were built", info.metadata.name));
+    }
+
+    std::fs::create_dir_all(dist_path)
+        .map_err(|e| format!("unable to create {}: {}", dist_path.display(), e))?;
+
+    rpms.iter()
+        .map(|rpm| {
+            let dest = dist_path.join(rpm.file_name().unwrap());
+            warn!(logger, "copying {} to {}", rpm.display(), dest.display());
+
+            std::fs::copy(rpm, &dest)
+                .map(|_| dest)
+                .map_err(|e| format!("unable to copy {}: {}", rpm.display(), e))
+        })
+        .collect()
+}
+
+/// Represents a request to build RPMs with `rpmbuild` or `mock`.
+#[derive(Debug, Clone, Serialize)]
+pub struct RpmBuild {
+    pub package: RpmPackageInfo,
+    pub files: FileManifest,
+}
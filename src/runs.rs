@@ -0,0 +1,70 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Records of the last execution of pipelines.
+
+After a pipeline executes, its report, including the artifacts each step
+created in the distribution directory, is persisted in the `runs`
+namespace of the cache. `tugger run --step` executes a single step in
+isolation, so it reads the record to find the artifacts earlier steps
+produced and to describe their execution to the step.
+
+Records are keyed by the distribution directory and the name of the
+pipeline, so projects don't share records.
+*/
+
+use crate::pipeline::Pipeline;
+use crate::report::PipelineReport;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+/// Cache namespace holding records of pipeline execution.
+const RUNS_NAMESPACE: &str = "runs";
+
+/// Obtain the path of the record of a pipeline.
+fn record_path(pipeline: &Pipeline) -> Result<PathBuf, String> {
+    let dist_path = pipeline
+        .dist_path
+        .canonicalize()
+        .unwrap_or_else(|_| pipeline.dist_path.clone());
+
+    let mut hasher = Sha256::new();
+    hasher.input(dist_path.to_string_lossy().as_bytes());
+    hasher.input(b"\0");
+    hasher.input(pipeline.name.as_bytes());
+
+    Ok(crate::cache::cache_dir()?
+        .join(RUNS_NAMESPACE)
+        .join(format!("{:x}.json", hasher.result())))
+}
+
+/// Obtain the record of the last execution of a pipeline, if any.
+pub fn read(pipeline: &Pipeline) -> Option<PipelineReport> {
+    let data = std::fs::read(record_path(pipeline).ok()?).ok()?;
+
+    serde_json::from_slice(&data).ok()
+}
+
+/// Record the execution of a pipeline.
+pub fn write(pipeline: &Pipeline, report: &PipelineReport) -> Result<(), String> {
+    let path = record_path(pipeline)?;
+    std::fs::create_dir_all(path.parent().unwrap())
+        .map_err(|e| format!("unable to create {}: {}", path.display(), e))?;
+
+    let data = serde_json::to_vec_pretty(report).map_err(|e| e.to_string())?;
+    std::fs::write(&path, data).map_err(|e| format!("unable to write {}: {}", path.display(), e))
+}
+
+/// Obtain the name of an artifact relative to a distribution directory.
+///
+/// Names always use `/` as the directory separator.
+pub fn artifact_name(dist_path: &Path, path: &Path) -> String {
+    path.strip_prefix(dist_path)
+        .unwrap_or(path)
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
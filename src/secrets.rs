@@ -0,0 +1,371 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Secrets such as API tokens and passwords.
+
+A `Secret` names a value that is only resolved when a step using it
+executes, so it never appears in evaluated configuration. Values are
+read from an environment variable of the same name or, if it isn't set,
+from the system keyring under the `tugger` service.
+
+With the `keyring` feature, the platform credential store (Secret
+Service, macOS Keychain, or Windows Credential Manager) is accessed
+directly and secrets can be stored in it with `store()`. Otherwise
+secrets are looked up with `security find-generic-password` on macOS
+and `secret-tool` elsewhere.
+
+When tugger runs interactively, secrets found in neither place are
+prompted for on the terminal, without echoing the value. Prompting is
+disabled with `set_interactive(false)`, or when stdin isn't a terminal
+or the `CI` environment variable is set, so unattended builds fail
+instead of waiting for input.
+
+Resolved values are remembered so `redact()` can remove them from log
+output, process output, and reports.
+*/
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::IsTerminal;
+#[cfg(not(feature = "keyring"))]
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+/// Keyring service secrets are stored under.
+pub const KEYRING_SERVICE: &str = "tugger";
+
+/// Replacement for secret values in redacted text.
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Environment variable set by CI systems.
+const CI_ENV: &str = "CI";
+
+/// Values of resolved secrets.
+static RESOLVED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Whether secrets may be prompted for.
+static INTERACTIVE: AtomicBool = AtomicBool::new(true);
+
+/// Values of secrets entered at prompts, by name.
+static PROMPTED: Mutex<BTreeMap<String, String>> = Mutex::new(BTreeMap::new());
+
+/// Allow or forbid prompting for secrets.
+pub fn set_interactive(interactive: bool) {
+    INTERACTIVE.store(interactive, Ordering::SeqCst);
+}
+
+/// Whether secrets that aren't set are prompted for.
+pub fn is_interactive() -> bool {
+    cfg!(unix)
+        && INTERACTIVE.load(Ordering::SeqCst)
+        && std::env::var_os(CI_ENV).is_none()
+        && std::io::stdin().is_terminal()
+}
+
+/// A reference to a secret value.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Secret {
+    /// Name of the environment variable or keyring entry holding the value.
+    pub name: String,
+}
+
+impl Secret {
+    pub fn new(name: impl Into<String>) -> Self {
+        Secret { name: name.into() }
+    }
+
+    /// Obtain the value of the secret.
+    ///
+    /// If the secret isn't set and tugger runs interactively, it is
+    /// prompted for. The value is registered for redaction.
+    pub fn resolve(&self) -> Result<String, String> {
+        let value = match std::env::var(&self.name) {
+            Ok(value) => value,
+            Err(_) => match keyring_lookup(&self.name) {
+                Some(value) => value,
+                None => self.prompt()?,
+            },
+        };
+
+        if !value.is_empty() {
+            let mut resolved = RESOLVED.lock().unwrap();
+
+            // Values embedded in URLs, e.g. tokens in query strings, appear
+            // percent-encoded in error messages.
+            for value in [value.clone(), crate::http::percent_encode(&value)] {
+                if !resolved.contains(&value) {
+                    resolved.push(value);
+                }
+            }
+        }
+
+        Ok(value)
+    }
+
+    /// Prompt for the value of the secret.
+    ///
+    /// Values are only prompted for once per process.
+    fn prompt(&self) -> Result<String, String> {
+        if let Some(value) = PROMPTED.lock().unwrap().get(&self.name) {
+            return Ok(value.clone());
+        }
+
+        if !is_interactive() {
+            return Err(format!(
+                "secret {} is not set in the environment or the {} keyring",
+                self.name, KEYRING_SERVICE
+            ));
+        }
+
+        let value = prompt_hidden(&format!("Enter secret {}: ", self.name))?;
+        if value.is_empty() {
+            return Err(format!("no value entered for secret {}", self.name));
+        }
+
+        PROMPTED
+            .lock()
+            .unwrap()
+            .insert(self.name.clone(), value.clone());
+
+        Ok(value)
+    }
+}
+
+/// Read a line from the terminal without echoing it.
+#[cfg(unix)]
+pub fn prompt_hidden(prompt: &str) -> Result<String, String> {
+    use std::io::{BufRead, Write};
+
+    let mut tty = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .map_err(|e| format!("unable to open terminal: {}", e))?;
+
+    tty.write_all(prompt.as_bytes())
+        .and_then(|_| tty.flush())
+        .map_err(|e| format!("unable to write to terminal: {}", e))?;
+
+    set_echo(&tty, false)?;
+    let mut line = String::new();
+    let res = std::io::BufReader::new(&tty).read_line(&mut line);
+    // Echo is restored even if reading failed.
+    set_echo(&tty, true)?;
+    let _ = tty.write_all(b"\n");
+
+    res.map_err(|e| format!("unable to read from terminal: {}", e))?;
+
+    Ok(line.trim_end_matches(&['\r', '\n'][..]).to_string())
+}
+
+/// Read a line from the terminal without echoing it.
+#[cfg(not(unix))]
+pub fn prompt_hidden(_prompt: &str) -> Result<String, String> {
+    Err("prompting for secrets is only supported on Unix".to_string())
+}
+
+/// Turn echoing of the terminal on or off with `stty`.
+#[cfg(unix)]
+fn set_echo(tty: &std::fs::File, echo: bool) -> Result<(), String> {
+    let tty = tty
+        .try_clone()
+        .map_err(|e| format!("unable to open terminal: {}", e))?;
+
+    let status = std::process::Command::new("stty")
+        .arg(if echo { "echo" } else { "-echo" })
+        .stdin(tty)
+        .status()
+        .map_err(|e| format!("error running stty: {}", e))?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("stty failed: {}", status))
+    }
+}
+
+/// A value given either literally or by a secret.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum MaybeSecret {
+    Value(String),
+    Secret(Secret),
+}
+
+impl MaybeSecret {
+    /// Obtain the value, resolving it if it is a secret.
+    pub fn resolve(&self) -> Result<String, String> {
+        match self {
+            MaybeSecret::Value(value) => Ok(value.clone()),
+            MaybeSecret::Secret(secret) => secret.resolve(),
+        }
+    }
+}
+
+/// Look up a secret in the system keyring.
+#[cfg(feature = "keyring")]
+fn keyring_lookup(name: &str) -> Option<String> {
+    keyring::Entry::new(KEYRING_SERVICE, name)
+        .and_then(|entry| entry.get_password())
+        .ok()
+        .filter(|value| !value.is_empty())
+}
+
+/// Look up a secret in the system keyring.
+#[cfg(not(feature = "keyring"))]
+fn keyring_lookup(name: &str) -> Option<String> {
+    let mut command = if cfg!(target_os = "macos") {
+        let mut command = Command::new("security");
+        command
+            .arg("find-generic-password")
+            .arg("-s")
+            .arg(KEYRING_SERVICE)
+            .arg("-a")
+            .arg(name)
+            .arg("-w");
+        command
+    } else {
+        let mut command = Command::new("secret-tool");
+        command
+            .arg("lookup")
+            .arg("service")
+            .arg(KEYRING_SERVICE)
+            .arg("account")
+            .arg(name);
+        command
+    };
+
+    crate::process::record_program(command.get_program());
+
+    let output = command.output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    let value = String::from_utf8(output.stdout).ok()?;
+    let value = value.trim_end_matches(&['\r', '\n'][..]);
+
+    if value.is_empty() {
+        None
+    } else {
+        Some(value.to_string())
+    }
+}
+
+/// Store a secret in the system keyring, replacing any existing value.
+#[cfg(feature = "keyring")]
+pub fn store(name: &str, value: &str) -> Result<(), String> {
+    keyring::Entry::new(KEYRING_SERVICE, name)
+        .and_then(|entry| entry.set_password(value))
+        .map_err(|e| format!("unable to store secret {}: {}", name, e))
+}
+
+/// Store a secret in the system keyring, replacing any existing value.
+#[cfg(not(feature = "keyring"))]
+pub fn store(_name: &str, _value: &str) -> Result<(), String> {
+    Err(NO_KEYRING.to_string())
+}
+
+/// Remove a secret from the system keyring.
+#[cfg(feature = "keyring")]
+pub fn delete(name: &str) -> Result<(), String> {
+    keyring::Entry::new(KEYRING_SERVICE, name)
+        .and_then(|entry| entry.delete_credential())
+        .map_err(|e| format!("unable to delete secret {}: {}", name, e))
+}
+
+/// Remove a secret from the system keyring.
+#[cfg(not(feature = "keyring"))]
+pub fn delete(_name: &str) -> Result<(), String> {
+    Err(NO_KEYRING.to_string())
+}
+
+#[cfg(not(feature = "keyring"))]
+const NO_KEYRING: &str = "storing secrets requires tugger to be built with the keyring feature";
+
+/// Replace values of resolved secrets in text.
+pub fn redact(text: &str) -> String {
+    redact_values(text, &RESOLVED.lock().unwrap())
+}
+
+/// Replace occurrences of `values` in text.
+///
+/// Occurrences are found before any is replaced, so values that contain
+/// or overlap others are redacted entirely. Adjacent and overlapping
+/// occurrences are replaced by a single `REDACTED`.
+fn redact_values(text: &str, values: &[String]) -> String {
+    let mut redacted = vec![false; text.len()];
+
+    for value in values.iter().filter(|value| !value.is_empty()) {
+        let mut start = 0;
+
+        while let Some(offset) = text[start..].find(value.as_str()) {
+            let pos = start + offset;
+            redacted[pos..pos + value.len()].fill(true);
+
+            // Occurrences may overlap, so the search resumes after the
+            // first character of this one.
+            start = pos + value.chars().next().unwrap().len_utf8();
+        }
+    }
+
+    let mut res = String::with_capacity(text.len());
+
+    for (index, c) in text.char_indices() {
+        if !redacted[index] {
+            res.push(c);
+        } else if index == 0 || !redacted[index - 1] {
+            res.push_str(REDACTED);
+        }
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn test_redact_values() {
+        assert_eq!(
+            redact_values("token=abc, again abc", &values(&["abc"])),
+            "token=[REDACTED], again [REDACTED]"
+        );
+        assert_eq!(
+            redact_values("nothing here", &values(&["", "abc"])),
+            "nothing here"
+        );
+    }
+
+    #[test]
+    fn test_redact_overlapping_values() {
+        // A shorter value contained in a longer one, registered first.
+        assert_eq!(
+            redact_values("key: secret-token.", &values(&["token", "secret-token"])),
+            "key: [REDACTED]."
+        );
+
+        // Values overlapping each other.
+        assert_eq!(
+            redact_values("[abcdef]", &values(&["abcd", "cdef"])),
+            "[[REDACTED]]"
+        );
+
+        // Overlapping occurrences of one value.
+        assert_eq!(redact_values("xaaay", &values(&["aa"])), "x[REDACTED]y");
+
+        // Non-ASCII values.
+        assert_eq!(
+            redact_values("mot de passe: été!", &values(&["té", "été"])),
+            "mot de passe: [REDACTED]!"
+        );
+    }
+}
@@ -0,0 +1,514 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Bundling of shared library dependencies.
+
+Binaries are analyzed to find the shared libraries they depend on. Those
+libraries are copied alongside the binaries and the binaries are modified
+to load them from there, making the set of files relocatable.
+
+ELF binaries have their dependencies resolved by `ldd` and their run-time
+search path rewritten by `patchelf`. Mach-O binaries have their install
+names rewritten by `install_name_tool` and are then ad-hoc signed by
+`codesign`. PE binaries load DLLs from their own directory, so they
+aren't modified.
+*/
+
+use crate::filemanifest::{FileContent, FileManifest};
+use goblin::Object;
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// ELF shared libraries assumed to be present on any Linux system.
+///
+/// These are the libraries manylinux Python wheels may link against.
+const ELF_SYSTEM_LIBRARIES: &[&str] = &[
+    "libc.so.6",
+    "libcrypt.so.1",
+    "libdl.so.2",
+    "libgcc_s.so.1",
+    "libGL.so.1",
+    "libglib-2.0.so.0",
+    "libgobject-2.0.so.0",
+    "libgthread-2.0.so.0",
+    "libICE.so.6",
+    "libm.so.6",
+    "libnsl.so.1",
+    "libpthread.so.0",
+    "libresolv.so.2",
+    "librt.so.1",
+    "libSM.so.6",
+    "libstdc++.so.6",
+    "libutil.so.1",
+    "libX11.so.6",
+    "libXext.so.6",
+    "libXrender.so.1",
+];
+
+/// Manifest directory bundled ELF and Mach-O libraries are placed in.
+const LIB_DIR: &str = "lib";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum BinaryFormat {
+    Elf,
+    MachO,
+    Pe,
+}
+
+/// A dependency of a binary on a shared library.
+#[derive(Clone, Debug)]
+struct Dependency {
+    /// How the binary refers to the library.
+    reference: String,
+
+    /// Path of the library on the filesystem.
+    path: PathBuf,
+}
+
+impl Dependency {
+    fn filename(&self) -> Result<String, String> {
+        self.path
+            .file_name()
+            .map(|f| f.to_string_lossy().to_string())
+            .ok_or_else(|| format!("{} has no filename", self.path.display()))
+    }
+}
+
+/// Whether an ELF library is the dynamic loader.
+fn is_elf_loader(name: &str) -> bool {
+    name.starts_with("ld-linux") || name.starts_with("ld64.so") || name.starts_with("ld-musl")
+}
+
+/// Resolve the dependencies of an ELF binary with `ldd`.
+///
+/// `ldd` reports the transitive closure of dependencies.
+fn elf_dependencies(path: &Path, exclude_system: bool) -> Result<Vec<Dependency>, String> {
+    crate::process::record_program("ldd");
+
+    let output = Command::new("ldd")
+        .arg(path)
+        .output()
+        .map_err(|e| format!("error running ldd: {}", e))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "ldd failed for {}: {}",
+            path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let mut deps = Vec::new();
+
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let (name, rest) = match line.trim().split_once(" => ") {
+            Some(v) => v,
+            None => continue,
+        };
+
+        if rest.starts_with("not found") {
+            return Err(format!(
+                "{} depends on {}, which could not be found",
+                path.display(),
+                name
+            ));
+        }
+
+        let lib_path = rest.split(" (").next().unwrap_or("").trim();
+
+        // The vDSO has no path.
+        if lib_path.is_empty() || is_elf_loader(name) {
+            continue;
+        }
+
+        if exclude_system && ELF_SYSTEM_LIBRARIES.contains(&name) {
+            continue;
+        }
+
+        deps.push(Dependency {
+            reference: name.to_string(),
+            path: PathBuf::from(lib_path),
+        });
+    }
+
+    Ok(deps)
+}
+
+/// Resolve a Mach-O install name to a path.
+fn resolve_macho_install_name(name: &str, loader_dir: &Path, rpaths: &[&str]) -> Option<PathBuf> {
+    let expand = |s: &str| {
+        for prefix in &["@loader_path", "@executable_path"] {
+            if let Some(rest) = s.strip_prefix(prefix) {
+                return loader_dir.join(rest.trim_start_matches('/'));
+            }
+        }
+
+        PathBuf::from(s)
+    };
+
+    let candidates = match name.strip_prefix("@rpath/") {
+        Some(rest) => rpaths
+            .iter()
+            .map(|rpath| expand(rpath).join(rest))
+            .collect(),
+        None => vec![expand(name)],
+    };
+
+    candidates.into_iter().find(|path| path.is_file())
+}
+
+fn macho_dependencies(
+    machos: &[goblin::mach::MachO],
+    path: &Path,
+    exclude_system: bool,
+) -> Result<Vec<Dependency>, String> {
+    let loader_dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let mut seen = HashSet::new();
+    let mut deps = Vec::new();
+
+    for macho in machos {
+        // The first library is a placeholder for the binary itself.
+        for name in macho.libs.iter().skip(1) {
+            if !seen.insert(name.to_string()) {
+                continue;
+            }
+
+            if exclude_system && (name.starts_with("/usr/lib/") || name.starts_with("/System/")) {
+                continue;
+            }
+
+            let lib_path =
+                resolve_macho_install_name(name, loader_dir, &macho.rpaths).ok_or_else(|| {
+                    format!(
+                        "{} depends on {}, which could not be found",
+                        path.display(),
+                        name
+                    )
+                })?;
+
+            deps.push(Dependency {
+                reference: name.to_string(),
+                path: lib_path,
+            });
+        }
+    }
+
+    Ok(deps)
+}
+
+/// Resolve the DLL dependencies of a PE binary.
+///
+/// DLLs are searched for in the binary's directory and then in `PATH`.
+/// DLLs that can't be found are assumed to be provided by Windows.
+fn pe_dependencies(
+    libraries: &[&str],
+    path: &Path,
+    exclude_system: bool,
+) -> Result<Vec<Dependency>, String> {
+    let mut search_dirs = vec![path
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .to_path_buf()];
+    if let Some(paths) = std::env::var_os("PATH") {
+        search_dirs.extend(std::env::split_paths(&paths));
+    }
+
+    let system_root = std::env::var_os("SystemRoot").map(PathBuf::from);
+    let mut deps = Vec::new();
+
+    for name in libraries {
+        let lower = name.to_lowercase();
+
+        // API sets are virtual DLLs resolved by the Windows loader.
+        if lower.starts_with("api-ms-win-") || lower.starts_with("ext-ms-") {
+            continue;
+        }
+
+        let lib_path = match search_dirs
+            .iter()
+            .map(|dir| dir.join(name))
+            .find(|p| p.is_file())
+        {
+            Some(p) => p,
+            None => continue,
+        };
+
+        if exclude_system {
+            if let Some(root) = &system_root {
+                if lib_path.starts_with(root) {
+                    continue;
+                }
+            }
+        }
+
+        deps.push(Dependency {
+            reference: name.to_string(),
+            path: lib_path,
+        });
+    }
+
+    Ok(deps)
+}
+
+/// Analyze a file, returning its format and shared library dependencies.
+///
+/// Returns `None` for files that aren't dynamically linked binaries.
+fn analyze(
+    path: &Path,
+    exclude_system: bool,
+) -> Result<Option<(BinaryFormat, Vec<Dependency>)>, String> {
+    let data =
+        std::fs::read(path).map_err(|e| format!("unable to read {}: {}", path.display(), e))?;
+
+    match Object::parse(&data) {
+        Ok(Object::Elf(elf)) if !elf.libraries.is_empty() => Ok(Some((
+            BinaryFormat::Elf,
+            elf_dependencies(path, exclude_system)?,
+        ))),
+        Ok(Object::Mach(mach)) => {
+            let machos = match mach {
+                goblin::mach::Mach::Binary(macho) => vec![macho],
+                goblin::mach::Mach::Fat(fat) => {
+                    let mut machos = Vec::new();
+
+                    for index in 0..fat.narches {
+                        if let Ok(goblin::mach::SingleArch::MachO(macho)) = fat.get(index) {
+                            machos.push(macho);
+                        }
+                    }
+
+                    machos
+                }
+            };
+
+            Ok(Some((
+                BinaryFormat::MachO,
+                macho_dependencies(&machos, path, exclude_system)?,
+            )))
+        }
+        Ok(Object::PE(pe)) => Ok(Some((
+            BinaryFormat::Pe,
+            pe_dependencies(&pe.libraries, path, exclude_system)?,
+        ))),
+        _ => Ok(None),
+    }
+}
+
+fn run(command: &mut Command) -> Result<(), String> {
+    crate::process::record_program(command.get_program());
+
+    let status = command
+        .status()
+        .map_err(|e| format!("error running {:?}: {}", command, e))?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("{:?} failed", command))
+    }
+}
+
+/// Path from the directory of manifest entry `key` to the library directory.
+fn relative_lib_dir(key: &str) -> String {
+    let depth = Path::new(key)
+        .parent()
+        .map(|p| p.components().count())
+        .unwrap_or(0);
+
+    format!("{}{}", "../".repeat(depth), LIB_DIR)
+}
+
+/// Modify a staged binary to load bundled libraries.
+fn rewrite(
+    format: BinaryFormat,
+    key: &str,
+    path: &Path,
+    deps: &[Dependency],
+    is_bundled_lib: bool,
+) -> Result<(), String> {
+    match format {
+        BinaryFormat::Elf => run(Command::new("patchelf")
+            .arg("--set-rpath")
+            .arg(format!("$ORIGIN/{}", relative_lib_dir(key)))
+            .arg(path)),
+        BinaryFormat::MachO => {
+            let mut command = Command::new("install_name_tool");
+
+            if is_bundled_lib {
+                let filename = Path::new(key).file_name().unwrap_or_default();
+                command
+                    .arg("-id")
+                    .arg(format!("@rpath/{}", filename.to_string_lossy()));
+            }
+
+            for dep in deps {
+                command.arg("-change").arg(&dep.reference).arg(format!(
+                    "@loader_path/{}/{}",
+                    relative_lib_dir(key),
+                    dep.filename()?
+                ));
+            }
+
+            run(command.arg(path))?;
+
+            // Modifying a binary invalidates its signature.
+            run(Command::new("codesign")
+                .arg("--force")
+                .arg("--sign")
+                .arg("-")
+                .arg(path))
+        }
+        BinaryFormat::Pe => Ok(()),
+    }
+}
+
+/// Copy a file into the staging directory, returning the new path.
+fn stage(content: &FileContent, dest: &Path, key: &str) -> Result<PathBuf, String> {
+    let path = dest.join(crate::filemanifest::key_path(key));
+    std::fs::create_dir_all(path.parent().unwrap())
+        .map_err(|e| format!("unable to create directory for {}: {}", path.display(), e))?;
+
+    if std::fs::symlink_metadata(&path).is_ok() {
+        std::fs::remove_file(&path)
+            .map_err(|e| format!("unable to remove {}: {}", path.display(), e))?;
+    }
+
+    std::fs::write(&path, content.read()?)
+        .map_err(|e| format!("unable to write {}: {}", path.display(), e))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mode = if content.is_executable() {
+            0o755
+        } else {
+            0o644
+        };
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode))
+            .map_err(|e| format!("unable to set permissions of {}: {}", path.display(), e))?;
+    }
+
+    Ok(path)
+}
+
+/// Bundle the shared library dependencies of binaries in a manifest.
+///
+/// ELF and Mach-O libraries are placed in a `lib` directory at the root
+/// of the manifest. DLLs are placed next to the binaries loading them.
+/// Libraries already in the manifest aren't bundled again. If
+/// `exclude_system` is true, libraries provided by the operating system
+/// are not bundled.
+///
+/// Binaries that need modification and bundled libraries are written
+/// to `dest`. Returns a new manifest referring to them.
+pub fn bundle_shared_libs(
+    files: &FileManifest,
+    dest: &Path,
+    exclude_system: bool,
+) -> Result<FileManifest, String> {
+    let mut manifest = files.clone();
+
+    // Libraries the manifest already provides.
+    let shipped = files
+        .values()
+        .filter_map(|content| match content {
+            FileContent::Path(path) => path.canonicalize().ok(),
+            FileContent::Data { .. } | FileContent::Symlink(_) => None,
+        })
+        .collect::<HashSet<_>>();
+
+    // Manifest keys of bundled libraries to their source paths.
+    let mut bundled: BTreeMap<String, PathBuf> = BTreeMap::new();
+
+    let mut queue = files
+        .iter()
+        .map(|(key, content)| (key.clone(), content.clone(), false))
+        .collect::<Vec<_>>();
+
+    while let Some((key, content, is_bundled_lib)) = queue.pop() {
+        // Dependencies are resolved relative to the original location.
+        let (source, staged) = match &content {
+            FileContent::Path(path) => (path.clone(), None),
+            FileContent::Data { .. } => {
+                let staged = stage(&content, dest, &key)?;
+                (staged.clone(), Some(staged))
+            }
+            // Links to binaries resolve to the binaries themselves.
+            FileContent::Symlink(_) => continue,
+        };
+
+        let (format, deps) = match analyze(&source, exclude_system)? {
+            Some(v) => v,
+            None if is_bundled_lib => {
+                let staged = match staged {
+                    Some(staged) => staged,
+                    None => stage(&content, dest, &key)?,
+                };
+                manifest.insert(key, staged.into());
+                continue;
+            }
+            None => continue,
+        };
+
+        let deps = deps
+            .into_iter()
+            .filter(|dep| match dep.path.canonicalize() {
+                Ok(path) => !shipped.contains(&path),
+                Err(_) => true,
+            })
+            .collect::<Vec<_>>();
+
+        if deps.is_empty() && !is_bundled_lib {
+            continue;
+        }
+
+        for dep in &deps {
+            let filename = dep.filename()?;
+            let lib_key = match format {
+                BinaryFormat::Pe => match key.rsplit_once('/') {
+                    Some((parent, _)) => format!("{}/{}", parent, filename),
+                    None => filename,
+                },
+                _ => format!("{}/{}", LIB_DIR, filename),
+            };
+
+            if let Some(existing) = bundled.get(&lib_key) {
+                if existing != &dep.path {
+                    return Err(format!(
+                        "{} and {} would both be bundled as {}",
+                        existing.display(),
+                        dep.path.display(),
+                        lib_key
+                    ));
+                }
+
+                continue;
+            }
+
+            if manifest.contains_key(&lib_key) {
+                return Err(format!(
+                    "bundling {} conflicts with {} in the manifest",
+                    dep.path.display(),
+                    lib_key
+                ));
+            }
+
+            bundled.insert(lib_key.clone(), dep.path.clone());
+            queue.push((lib_key, dep.path.clone().into(), true));
+        }
+
+        let staged = match staged {
+            Some(staged) => staged,
+            None => stage(&content, dest, &key)?,
+        };
+
+        rewrite(format, &key, &staged, &deps, is_bundled_lib)?;
+        manifest.insert(key, staged.into());
+    }
+
+    Ok(manifest)
+}
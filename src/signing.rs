@@ -0,0 +1,540 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Functionality for cryptographically signing artifacts.
+
+Signing is performed by invoking the canonical external tool for each
+signature format.
+*/
+
+use crate::secrets::{MaybeSecret, Secret};
+use crate::xml::xml_escape;
+use serde::Serialize;
+use slog::{warn, Logger};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Obtain the path of a file sitting next to `path` with `suffix` appended.
+pub fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut s = path.as_os_str().to_os_string();
+    s.push(suffix);
+    PathBuf::from(s)
+}
+
+/// Add a GPG signature header to an RPM using `rpmsign`.
+///
+/// `key` is the GPG key name (`%_gpg_name`) to sign with. The RPM is
+/// modified in place.
+///
+/// If `passphrase` is defined, it unlocks the key without prompting. It
+/// is handed to `gpg` in a temporary file that is deleted afterwards.
+pub fn rpm_sign(
+    logger: &Logger,
+    rpm_path: &Path,
+    key: &str,
+    passphrase: Option<&str>,
+) -> Result<(), String> {
+    if !rpm_path.exists() {
+        return Err(format!("{} does not exist", rpm_path.display()));
+    }
+
+    warn!(logger, "signing {} with {}", rpm_path.display(), key);
+
+    let mut command = Command::new("rpmsign");
+    command
+        .arg("--addsign")
+        .arg("--define")
+        .arg(format!("_gpg_name {}", key));
+
+    let passphrase_file = match passphrase {
+        Some(passphrase) => {
+            let mut tf = tempfile::NamedTempFile::new()
+                .map_err(|e| format!("unable to create temp file: {}", e))?;
+            tf.write_all(passphrase.as_bytes())
+                .map_err(|e| format!("unable to write passphrase: {}", e))?;
+
+            command.arg("--define").arg(format!(
+                "_gpg_sign_cmd_extra_args --batch --pinentry-mode loopback --passphrase-file {}",
+                tf.path().display()
+            ));
+
+            Some(tf)
+        }
+        None => None,
+    };
+
+    let res = crate::process::run_logged(logger, command.arg(rpm_path));
+    drop(passphrase_file);
+
+    res
+}
+
+/// Sign a file with `cosign sign-blob`.
+///
+/// If `key` is defined, it is a reference to a cosign private key (a path
+/// or a KMS URI). Otherwise keyless signing is performed using an OIDC
+/// identity and a certificate is written alongside the signature.
+/// `password` decrypts an encrypted private key.
+///
+/// Writes `<artifact>.sig` and `<artifact>.bundle` and, for keyless
+/// signing, `<artifact>.pem`.
+pub fn cosign_sign_blob(
+    logger: &Logger,
+    artifact: &Path,
+    key: Option<&str>,
+    password: Option<&str>,
+) -> Result<(), String> {
+    if !artifact.exists() {
+        return Err(format!("{} does not exist", artifact.display()));
+    }
+
+    warn!(logger, "signing {} with cosign", artifact.display());
+
+    let mut command = Command::new("cosign");
+    command
+        .arg("sign-blob")
+        .arg("--yes")
+        .arg("--output-signature")
+        .arg(sibling_path(artifact, ".sig"))
+        .arg("--bundle")
+        .arg(sibling_path(artifact, ".bundle"));
+
+    match key {
+        Some(key) => {
+            command.arg("--key").arg(key);
+        }
+        None => {
+            command
+                .arg("--output-certificate")
+                .arg(sibling_path(artifact, ".pem"));
+        }
+    }
+
+    if let Some(password) = password {
+        command.env("COSIGN_PASSWORD", password);
+    }
+
+    crate::process::run_logged(logger, command.arg(artifact))
+}
+
+/// Sign a container image with `cosign sign`.
+///
+/// `image` is an image reference, ideally pinned by digest. Signatures are
+/// pushed to the image's registry. `key` and `password` have the same
+/// meaning as for `cosign_sign_blob()`.
+pub fn cosign_sign_image(
+    logger: &Logger,
+    image: &str,
+    key: Option<&str>,
+    password: Option<&str>,
+) -> Result<(), String> {
+    warn!(logger, "signing image {} with cosign", image);
+
+    let mut command = Command::new("cosign");
+    command.arg("sign").arg("--yes");
+
+    if let Some(key) = key {
+        command.arg("--key").arg(key);
+    }
+
+    if let Some(password) = password {
+        command.env("COSIGN_PASSWORD", password);
+    }
+
+    crate::process::run_logged(logger, command.arg(image))
+}
+
+/// Sign files with `minisign`, producing a `.minisig` file next to each.
+///
+/// `secret_key` is the path to the minisign secret key. `trusted_comment`
+/// is an optional comment to embed in the signature.
+pub fn minisign_sign(
+    logger: &Logger,
+    artifacts: &[PathBuf],
+    secret_key: &Path,
+    trusted_comment: Option<&str>,
+) -> Result<(), String> {
+    for artifact in artifacts {
+        if !artifact.exists() {
+            return Err(format!("{} does not exist", artifact.display()));
+        }
+
+        warn!(logger, "signing {} with minisign", artifact.display());
+
+        let mut command = Command::new("minisign");
+        command
+            .arg("-S")
+            .arg("-s")
+            .arg(secret_key)
+            .arg("-m")
+            .arg(artifact)
+            .arg("-x")
+            .arg(sibling_path(artifact, ".minisig"));
+
+        if let Some(comment) = trusted_comment {
+            command.arg("-t").arg(comment);
+        }
+
+        // minisign reads the password of encrypted keys from stdin, so it
+        // inherits stdin when a password can be entered. Output isn't
+        // captured then, so its prompt is visible.
+        if crate::secrets::is_interactive() {
+            crate::process::record_program("minisign");
+            let status = command
+                .status()
+                .map_err(|e| format!("error running minisign: {}", e))?;
+            if !status.success() {
+                return Err(format!("minisign exited with {}", status));
+            }
+        } else {
+            crate::process::run_logged(logger, &mut command)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Keys verifying detached signatures of artifacts.
+#[derive(Clone, Debug, Default)]
+pub struct VerificationKeys {
+    /// minisign public key verifying `.minisig` signatures.
+    pub minisign: Option<PathBuf>,
+
+    /// cosign public key verifying `.sig` signatures.
+    pub cosign: Option<PathBuf>,
+
+    /// Whether `.asc` signatures are verified with the keyring of `gpg`.
+    pub gpg: bool,
+}
+
+/// Suffixes of detached signatures that can be verified.
+pub const SIGNATURE_SUFFIXES: &[&str] = &[".minisig", ".sig", ".asc"];
+
+/// Verify the detached signature of an artifact.
+///
+/// The kind of signature is determined by the suffix of `signature`.
+/// Returns `Ok(false)` if `keys` has no key verifying signatures of its
+/// kind.
+pub fn verify_signature(
+    logger: &Logger,
+    artifact: &Path,
+    signature: &Path,
+    keys: &VerificationKeys,
+) -> Result<bool, String> {
+    let filename = signature.to_string_lossy();
+
+    let mut command = if filename.ends_with(".minisig") {
+        let key = match &keys.minisign {
+            Some(key) => key,
+            None => return Ok(false),
+        };
+
+        let mut command = Command::new("minisign");
+        command
+            .arg("-V")
+            .arg("-p")
+            .arg(key)
+            .arg("-m")
+            .arg(artifact)
+            .arg("-x")
+            .arg(signature);
+        command
+    } else if filename.ends_with(".sig") {
+        let key = match &keys.cosign {
+            Some(key) => key,
+            None => return Ok(false),
+        };
+
+        let mut command = Command::new("cosign");
+        command
+            .arg("verify-blob")
+            .arg("--key")
+            .arg(key)
+            .arg("--signature")
+            .arg(signature)
+            .arg(artifact);
+        command
+    } else if filename.ends_with(".asc") {
+        if !keys.gpg {
+            return Ok(false);
+        }
+
+        let mut command = Command::new("gpg");
+        command
+            .arg("--batch")
+            .arg("--verify")
+            .arg(signature)
+            .arg(artifact);
+        command
+    } else {
+        return Err(format!(
+            "unknown kind of signature: {}",
+            signature.display()
+        ));
+    };
+
+    warn!(logger, "verifying signature {}", signature.display());
+    crate::process::run_logged(logger, &mut command)?;
+
+    Ok(true)
+}
+
+/// A value in an entitlements property list.
+#[derive(Clone, Debug, Serialize)]
+#[serde(untagged)]
+pub enum PlistValue {
+    Boolean(bool),
+    Integer(i64),
+    String(String),
+    Array(Vec<PlistValue>),
+    Dictionary(BTreeMap<String, PlistValue>),
+}
+
+impl PlistValue {
+    fn write_xml(&self, out: &mut String, indent: usize) {
+        let pad = "\t".repeat(indent);
+
+        match self {
+            PlistValue::Boolean(true) => out.push_str(&format!("{}<true/>\n", pad)),
+            PlistValue::Boolean(false) => out.push_str(&format!("{}<false/>\n", pad)),
+            PlistValue::Integer(i) => out.push_str(&format!("{}<integer>{}</integer>\n", pad, i)),
+            PlistValue::String(s) => {
+                out.push_str(&format!("{}<string>{}</string>\n", pad, xml_escape(s)))
+            }
+            PlistValue::Array(values) => {
+                out.push_str(&format!("{}<array>\n", pad));
+                for value in values {
+                    value.write_xml(out, indent + 1);
+                }
+                out.push_str(&format!("{}</array>\n", pad));
+            }
+            PlistValue::Dictionary(entries) => {
+                out.push_str(&format!("{}<dict>\n", pad));
+                for (key, value) in entries {
+                    out.push_str(&format!("{}\t<key>{}</key>\n", pad, xml_escape(key)));
+                    value.write_xml(out, indent + 1);
+                }
+                out.push_str(&format!("{}</dict>\n", pad));
+            }
+        }
+    }
+}
+
+/// Render entitlements as an XML property list.
+pub fn entitlements_plist(entitlements: &BTreeMap<String, PlistValue>) -> String {
+    let mut s = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+        "<!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" ",
+        "\"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n",
+        "<plist version=\"1.0\">\n",
+    ));
+    PlistValue::Dictionary(entitlements.clone()).write_xml(&mut s, 0);
+    s.push_str("</plist>\n");
+
+    s
+}
+
+/// Directory extensions of bundles that are signed as a unit.
+const BUNDLE_EXTENSIONS: &[&str] = &["app", "appex", "bundle", "framework", "plugin", "xpc"];
+
+fn is_bundle(path: &Path) -> bool {
+    path.is_dir()
+        && path
+            .extension()
+            .map(|ext| BUNDLE_EXTENSIONS.iter().any(|e| ext == *e))
+            .unwrap_or(false)
+}
+
+fn is_macho(path: &Path) -> bool {
+    let mut header = [0u8; 16];
+
+    let read = std::fs::File::open(path)
+        .and_then(|mut fh| std::io::Read::read_exact(&mut fh, &mut header))
+        .is_ok();
+
+    read && matches!(
+        goblin::peek_bytes(&header),
+        Ok(goblin::Hint::Mach(_)) | Ok(goblin::Hint::MachFat(_))
+    )
+}
+
+/// Find code nested in a bundle that must be signed before the bundle.
+///
+/// Nested bundles and Mach-O binaries are returned inside-out: the
+/// contents of a nested bundle precede the bundle itself. Symlinks are
+/// skipped, since they point at code that is signed through its real path.
+fn nested_code(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let mut entries = std::fs::read_dir(dir)
+        .map_err(|e| format!("unable to read {}: {}", dir.display(), e))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("unable to read {}: {}", dir.display(), e))?;
+    entries.sort();
+
+    let mut res = Vec::new();
+
+    for path in entries {
+        let metadata = std::fs::symlink_metadata(&path)
+            .map_err(|e| format!("unable to stat {}: {}", path.display(), e))?;
+
+        if metadata.file_type().is_symlink() {
+            continue;
+        } else if metadata.is_dir() {
+            res.extend(nested_code(&path)?);
+
+            if is_bundle(&path) {
+                res.push(path);
+            }
+        } else if is_macho(&path) {
+            res.push(path);
+        }
+    }
+
+    Ok(res)
+}
+
+/// Options for signing macOS code with `codesign`.
+#[derive(Clone, Debug, Serialize)]
+pub struct CodesignOptions {
+    /// Signing identity. `-` performs ad-hoc signing.
+    pub identity: MaybeSecret,
+
+    /// Entitlements granted to the signed program.
+    pub entitlements: Option<BTreeMap<String, PlistValue>>,
+
+    /// Whether to enable the hardened runtime.
+    pub hardened_runtime: bool,
+
+    /// Whether to request a secure timestamp. Ignored for ad-hoc signing.
+    pub timestamp: bool,
+
+    /// Whether to sign nested code before the artifact itself.
+    pub deep: bool,
+}
+
+/// Sign macOS programs and bundles with `codesign`.
+///
+/// With `deep`, frameworks, plug-ins, helper programs, and other code
+/// nested in a bundle are signed individually, innermost first, before
+/// the bundle. Entitlements are only applied to the artifact itself, as
+/// they describe the capabilities of the main program. This replaces
+/// `codesign --deep`, which signs nested code with the entitlements of
+/// the bundle and is rejected by notarization.
+pub fn codesign_sign(
+    logger: &Logger,
+    artifacts: &[PathBuf],
+    options: &CodesignOptions,
+) -> Result<(), String> {
+    let identity = options.identity.resolve()?;
+
+    let entitlements = match &options.entitlements {
+        Some(entitlements) => {
+            let mut tf = tempfile::NamedTempFile::new()
+                .map_err(|e| format!("unable to create temp file: {}", e))?;
+            tf.write_all(entitlements_plist(entitlements).as_bytes())
+                .map_err(|e| format!("unable to write entitlements: {}", e))?;
+
+            Some(tf)
+        }
+        None => None,
+    };
+
+    for artifact in artifacts {
+        if !artifact.exists() {
+            return Err(format!("{} does not exist", artifact.display()));
+        }
+
+        let mut paths = if options.deep && artifact.is_dir() {
+            nested_code(artifact)?
+        } else {
+            vec![]
+        };
+        paths.push(artifact.clone());
+
+        for path in &paths {
+            warn!(
+                logger,
+                "signing {} with codesign identity {}",
+                path.display(),
+                identity
+            );
+
+            let mut command = Command::new("codesign");
+            command.arg("--force").arg("--sign").arg(&identity);
+
+            if options.hardened_runtime {
+                command.arg("--options").arg("runtime");
+            }
+
+            // Ad-hoc signatures can't be timestamped.
+            if options.timestamp && identity != "-" {
+                command.arg("--timestamp");
+            }
+
+            if let Some(tf) = &entitlements {
+                if path == artifact {
+                    command.arg("--entitlements").arg(tf.path());
+                }
+            }
+
+            crate::process::run_logged(logger, command.arg(path))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Represents a request to sign an RPM.
+#[derive(Debug, Clone, Serialize)]
+pub struct RpmSign {
+    /// Path to the RPM, relative to the distribution path.
+    pub rpm: String,
+
+    /// GPG key name to sign with.
+    pub key: String,
+
+    /// Passphrase of the key.
+    pub passphrase: Option<Secret>,
+}
+
+/// Represents a request to sign an artifact with cosign.
+#[derive(Debug, Clone, Serialize)]
+pub struct CosignSign {
+    /// Path to a file relative to the distribution path, or an image reference.
+    pub artifact: String,
+
+    /// Reference to the signing key. `None` means keyless signing.
+    pub key: Option<String>,
+
+    /// Password of the signing key.
+    pub password: Option<Secret>,
+
+    /// Whether `artifact` is a container image reference.
+    pub image: bool,
+}
+
+/// Represents a request to sign artifacts with minisign.
+#[derive(Debug, Clone, Serialize)]
+pub struct MinisignSign {
+    /// Paths to files relative to the distribution path.
+    pub artifacts: Vec<String>,
+
+    /// Path to the minisign secret key.
+    pub secret_key: PathBuf,
+
+    /// Trusted comment to embed in signatures.
+    pub trusted_comment: Option<String>,
+}
+
+/// Represents a request to sign macOS programs and bundles.
+#[derive(Debug, Clone, Serialize)]
+pub struct CodesignSign {
+    /// Paths to programs or bundles relative to the distribution path.
+    pub artifacts: Vec<String>,
+
+    pub options: CodesignOptions,
+}
@@ -2,12 +2,18 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use crate::filemanifest::FileManifest;
+use crate::error::TuggerError;
+use crate::filemanifest::{FileManifest, InstallMode};
+use crate::mode_policy::ModePolicy;
+use crate::report::OutputLine;
 use serde::{Deserialize, Serialize};
 use slog::{warn, Logger};
 use std::collections::HashMap;
-use std::io::{BufRead, BufReader, Write};
-use std::path::Path;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Number of lines of output included in errors when snapcraft fails.
+const OUTPUT_TAIL_LINES: usize = 20;
 
 /// Represents a snapcraft.yaml part.* entry.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -143,6 +149,7 @@ pub struct Snap {
 /// `snap` represents the `snapcraft.yaml` file to create.
 /// `build_path` is the directory to operate in.
 /// `files` defines a manifest of files to constitute the build environment.
+/// `output` receives the output of `snapcraft`.
 ///
 /// If `build_path` exists, its content will be replaced by the content of
 /// `files` if `purge_build` is true.
@@ -152,6 +159,7 @@ pub struct Snap {
 /// enough to detect when the source path changes between invocations. By
 /// using a stable path and swapping out the content from beneath snapcraft,
 /// we work around the issue.
+#[allow(clippy::too_many_arguments)]
 pub fn execute_snapcraft(
     logger: &Logger,
     args: &Vec<String>,
@@ -159,10 +167,13 @@ pub fn execute_snapcraft(
     build_path: &Path,
     files: &FileManifest,
     purge_build: bool,
-) -> Result<(), String> {
+    install_mode: InstallMode,
+    modes: &ModePolicy,
+    output: &mut Vec<OutputLine>,
+) -> Result<(), TuggerError> {
     if !build_path.exists() {
         std::fs::create_dir_all(build_path)
-            .or_else(|_| Err(format!("error creating {}", build_path.display())))?;
+            .map_err(|e| TuggerError::io(format!("error creating {}", build_path.display()), e))?;
     }
 
     // Remove existing content of build directory and replace with our own.
@@ -173,46 +184,80 @@ pub fn execute_snapcraft(
             build_path.display()
         );
         for entry in walkdir::WalkDir::new(build_path).contents_first(true) {
-            let entry = entry.or_else(|_| Err("could not resolve directory entry".to_string()))?;
+            let entry = entry.map_err(|e| {
+                TuggerError::io("could not resolve directory entry", std::io::Error::from(e))
+            })?;
             let p = entry.path();
 
             if entry.path_is_symlink() || p.is_file() {
                 std::fs::remove_file(p)
-                    .or_else(|_| Err(format!("unable to remove {}", p.display())))?;
+                    .map_err(|e| TuggerError::io(format!("unable to remove {}", p.display()), e))?;
             } else {
                 std::fs::remove_dir(p)
-                    .or_else(|_| Err(format!("unable to remove {}", p.display())))?;
+                    .map_err(|e| TuggerError::io(format!("unable to remove {}", p.display()), e))?;
             }
         }
     }
 
-    super::filemanifest::install_files(build_path, files);
+    let snap_error = |source: TuggerError| TuggerError::Snap {
+        name: snap.name.clone(),
+        build_path: build_path.to_path_buf(),
+        source: Box::new(source),
+    };
+
+    super::filemanifest::install_files(build_path, files, install_mode, modes)
+        .map_err(|e| snap_error(e.into()))?;
 
     let snap_path = build_path.join("snap");
     if !snap_path.exists() {
-        std::fs::create_dir(&snap_path).expect("unable to create snap directory");
+        std::fs::create_dir(&snap_path)
+            .map_err(|e| TuggerError::io(format!("unable to create {}", snap_path.display()), e))?;
     }
     let snapcraft_yaml_path = snap_path.join("snapcraft.yaml");
 
-    let yaml = serde_yaml::to_vec(snap).expect("unable to format YAML");
-
-    let mut fh = std::fs::File::create(&snapcraft_yaml_path).unwrap();
-    fh.write_all(&yaml)
-        .expect(&format!("unable to write {:?}", &snapcraft_yaml_path));
-
-    let mut cmd = std::process::Command::new("snapcraft")
-        .args(args)
-        .current_dir(build_path)
-        .stdout(std::process::Stdio::piped())
-        .spawn()
-        .expect("error running snapcraft");
-    {
-        let stdout = cmd.stdout.as_mut().unwrap();
-        let reader = BufReader::new(stdout);
-        for line in reader.lines() {
-            warn!(logger, "{}", line.unwrap());
-        }
-    }
+    let yaml = serde_yaml::to_vec(snap)
+        .map_err(|e| snap_error(format!("unable to format snapcraft YAML: {}", e).into()))?;
+
+    let mut fh = std::fs::File::create(&snapcraft_yaml_path).map_err(|e| {
+        TuggerError::io(
+            format!("unable to create {}", snapcraft_yaml_path.display()),
+            e,
+        )
+    })?;
+    fh.write_all(&yaml).map_err(|e| {
+        TuggerError::io(
+            format!("unable to write {}", snapcraft_yaml_path.display()),
+            e,
+        )
+    })?;
+
+    let res = crate::process::run_captured(
+        logger,
+        std::process::Command::new("snapcraft")
+            .args(args)
+            .current_dir(build_path),
+        output,
+    );
+
+    // The end of the output is included to explain failures.
+    res.map_err(|message| {
+        snap_error(TuggerError::Snapcraft {
+            message,
+            output: output[output.len().saturating_sub(OUTPUT_TAIL_LINES)..]
+                .iter()
+                .map(|line| line.line.clone())
+                .collect(),
+        })
+    })
+}
 
-    Ok(())
+#[derive(Debug, Clone, Serialize)]
+pub struct Snapcraft {
+    pub args: Vec<String>,
+    pub snap: Snap,
+    pub build_path: PathBuf,
+    pub manifest: FileManifest,
+    pub purge_build: bool,
+    pub install_mode: InstallMode,
+    pub verbose: bool,
 }
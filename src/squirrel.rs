@@ -0,0 +1,378 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Producing releases for Squirrel.Windows updaters.
+
+A Squirrel release directory holds a `RELEASES` file listing the SHA-1,
+name, and size of every package, a full `.nupkg` of each release, and
+optionally a delta `.nupkg` transforming the previous release into the
+current one.
+
+Packages are NuGet packages with the application under `lib/net45/`.
+Delta packages hold, for each file of the previous release, a `.shasum`
+file and either an empty `.diff` file if the file is unchanged or a
+bsdiff patch, as produced by `bsdiff`, in a `.bsdiff` file. Files new to
+the release are held as is.
+*/
+
+use crate::filemanifest::{FileContent, FileManifest};
+use crate::package_metadata::PackageMetadata;
+use crate::reproducibility::Reproducibility;
+use crate::xml::xml_escape;
+use serde::Serialize;
+use slog::{warn, Logger};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Directory of packages holding the application.
+const LIB_DIR: &str = "lib/net45/";
+
+/// An entry of a `RELEASES` file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReleaseEntry {
+    /// Upper case hex encoded SHA-1 digest.
+    pub sha1: String,
+
+    pub filename: String,
+    pub size: u64,
+}
+
+impl ReleaseEntry {
+    /// Describe data with the given filename.
+    pub fn from_data(filename: &str, data: &[u8]) -> Self {
+        ReleaseEntry {
+            sha1: sha1_hex(data),
+            filename: filename.to_string(),
+            size: data.len() as u64,
+        }
+    }
+
+    /// Parse a line of a `RELEASES` file.
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut parts = line.split_whitespace();
+        let sha1 = parts.next()?;
+        let filename = parts.next()?;
+        let size = parts.next()?.parse().ok()?;
+
+        if sha1.len() != 40 || parts.next().is_some() {
+            return None;
+        }
+
+        Some(ReleaseEntry {
+            sha1: sha1.to_uppercase(),
+            filename: filename.to_string(),
+            size,
+        })
+    }
+
+    pub fn to_line(&self) -> String {
+        format!("{} {} {}", self.sha1, self.filename, self.size)
+    }
+}
+
+fn sha1_hex(data: &[u8]) -> String {
+    ring::digest::digest(&ring::digest::SHA1_FOR_LEGACY_USE_ONLY, data)
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect()
+}
+
+/// Render the `.nuspec` describing a package.
+fn nuspec(metadata: &PackageMetadata) -> String {
+    let authors = metadata.vendor().unwrap_or_else(|| metadata.name.clone());
+    let mut s = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n",
+        "<package xmlns=\"http://schemas.microsoft.com/packaging/2010/07/nuspec.xsd\">\n",
+        "  <metadata>\n",
+    ));
+
+    s.push_str(&format!("    <id>{}</id>\n", xml_escape(&metadata.name)));
+    s.push_str(&format!(
+        "    <version>{}</version>\n",
+        xml_escape(&metadata.version)
+    ));
+    s.push_str(&format!(
+        "    <title>{}</title>\n",
+        xml_escape(&metadata.name)
+    ));
+    s.push_str(&format!(
+        "    <authors>{}</authors>\n",
+        xml_escape(&authors)
+    ));
+    s.push_str(&format!(
+        "    <description>{}</description>\n",
+        xml_escape(&metadata.summary())
+    ));
+    if let Some(homepage) = &metadata.homepage {
+        s.push_str(&format!(
+            "    <projectUrl>{}</projectUrl>\n",
+            xml_escape(homepage)
+        ));
+    }
+    s.push_str("  </metadata>\n</package>\n");
+
+    s
+}
+
+fn data(data: impl Into<Vec<u8>>) -> FileContent {
+    FileContent::Data {
+        data: data.into(),
+        executable: false,
+    }
+}
+
+/// Add the NuGet metadata parts to the contents of a package.
+fn add_package_parts(files: &mut FileManifest, metadata: &PackageMetadata) {
+    let nuspec_name = format!("{}.nuspec", metadata.name);
+
+    let extensions: BTreeSet<String> = files
+        .keys()
+        .chain(std::iter::once(&nuspec_name))
+        .filter_map(|key| {
+            Path::new(key)
+                .extension()
+                .map(|e| e.to_string_lossy().to_lowercase())
+        })
+        .filter(|e| e != "rels")
+        .collect();
+
+    let mut types = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n",
+        "<Types xmlns=\"http://schemas.openxmlformats.org/package/2006/content-types\">\n",
+        "  <Default Extension=\"rels\" ContentType=\"application/vnd.openxmlformats-package.relationships+xml\" />\n",
+    ));
+    for extension in extensions {
+        types.push_str(&format!(
+            "  <Default Extension=\"{}\" ContentType=\"application/octet\" />\n",
+            xml_escape(&extension)
+        ));
+    }
+    types.push_str("</Types>\n");
+
+    let rels = format!(
+        concat!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n",
+            "<Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">\n",
+            "  <Relationship Type=\"http://schemas.microsoft.com/packaging/2010/07/manifest\" Target=\"/{}\" Id=\"R0\" />\n",
+            "</Relationships>\n",
+        ),
+        xml_escape(&nuspec_name)
+    );
+
+    files.insert(nuspec_name, data(nuspec(metadata)));
+    files.insert("[Content_Types].xml".to_string(), data(types));
+    files.insert("_rels/.rels".to_string(), data(rels));
+}
+
+/// Read the files of a package's application.
+fn read_package_lib(path: &Path) -> Result<BTreeMap<String, Vec<u8>>, String> {
+    let fh = std::fs::File::open(path)
+        .map_err(|e| format!("unable to open {}: {}", path.display(), e))?;
+    let mut archive = zip::ZipArchive::new(fh)
+        .map_err(|e| format!("unable to read {}: {}", path.display(), e))?;
+    let mut files = BTreeMap::new();
+
+    for index in 0..archive.len() {
+        let mut file = archive
+            .by_index(index)
+            .map_err(|e| format!("unable to read {}: {}", path.display(), e))?;
+
+        // Squirrel's own packages use backslashes.
+        let name = file.name().replace('\\', "/");
+
+        if file.is_dir() || !name.starts_with(LIB_DIR) {
+            continue;
+        }
+
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)
+            .map_err(|e| format!("unable to read {} from {}: {}", name, path.display(), e))?;
+
+        files.insert(name, data);
+    }
+
+    Ok(files)
+}
+
+/// Obtain the contents of a delta package transforming `base` into `files`.
+fn delta_files(
+    logger: &Logger,
+    base: &BTreeMap<String, Vec<u8>>,
+    files: &FileManifest,
+) -> Result<FileManifest, String> {
+    let temp = tempfile::TempDir::new().map_err(|e| format!("unable to create temp dir: {}", e))?;
+    let old_path = temp.path().join("old");
+    let new_path = temp.path().join("new");
+    let mut delta = FileManifest::new();
+
+    for (key, content) in files {
+        let new_data = content.read()?;
+        let filename = key.rsplit('/').next().unwrap();
+
+        let old_data = match base.get(key) {
+            Some(data) => data,
+            None => {
+                delta.insert(key.clone(), data(new_data));
+                continue;
+            }
+        };
+
+        if old_data == &new_data {
+            delta.insert(format!("{}.diff", key), data(vec![]));
+            delta.insert(format!("{}.shasum", key), data(vec![]));
+            continue;
+        }
+
+        std::fs::write(&old_path, old_data)
+            .map_err(|e| format!("unable to write {}: {}", old_path.display(), e))?;
+        std::fs::write(&new_path, &new_data)
+            .map_err(|e| format!("unable to write {}: {}", new_path.display(), e))?;
+
+        let patch = crate::delta::bsdiff(logger, &old_path, &new_path)?;
+        let patch_data = std::fs::read(&patch)
+            .map_err(|e| format!("unable to read {}: {}", patch.display(), e))?;
+
+        // Squirrel versions that don't support bsdiff patches fail to
+        // apply this invalid MSDelta patch instead of corrupting the file.
+        delta.insert(format!("{}.diff", key), data("1"));
+        delta.insert(format!("{}.bsdiff", key), data(patch_data));
+        // Like Squirrel, name the entry after the `.shasum` file. Only the
+        // digest and size are verified.
+        delta.insert(
+            format!("{}.shasum", key),
+            data(ReleaseEntry::from_data(&format!("{}.shasum", filename), &new_data).to_line()),
+        );
+    }
+
+    Ok(delta)
+}
+
+/// Write a package and return its `RELEASES` entry.
+fn write_package(
+    logger: &Logger,
+    dest_dir: &Path,
+    filename: &str,
+    files: &FileManifest,
+    reproducibility: &Reproducibility,
+) -> Result<ReleaseEntry, String> {
+    let path = dest_dir.join(filename);
+    warn!(logger, "writing Squirrel package {}", path.display());
+
+    crate::archive::write_zip(
+        logger,
+        &path,
+        files,
+        &crate::compression::CompressionSettings::default(),
+        reproducibility,
+        &crate::mode_policy::ModePolicy::default(),
+    )?;
+
+    let data =
+        std::fs::read(&path).map_err(|e| format!("unable to read {}: {}", path.display(), e))?;
+
+    Ok(ReleaseEntry::from_data(filename, &data))
+}
+
+/// Write a Squirrel release of an application into `dest_dir`.
+///
+/// `files` are the files of the application. `previous_dir` is an optional
+/// directory holding the published release files. If it has a `RELEASES`
+/// file, its entries are preserved and a delta package from the most
+/// recent full package it lists is written.
+pub fn squirrel_release(
+    logger: &Logger,
+    dest_dir: &Path,
+    metadata: &PackageMetadata,
+    files: &FileManifest,
+    previous_dir: Option<&Path>,
+    reproducibility: &Reproducibility,
+) -> Result<(), String> {
+    let mut lib = FileManifest::new();
+
+    for (key, content) in files {
+        if let FileContent::Symlink(_) = content {
+            return Err(format!(
+                "{} is a symlink, which Squirrel doesn't support",
+                key
+            ));
+        }
+
+        lib.insert(format!("{}{}", LIB_DIR, key), content.clone());
+    }
+
+    let full_name = format!("{}-{}-full.nupkg", metadata.name, metadata.version);
+    let mut entries = Vec::new();
+    let mut base: Option<PathBuf> = None;
+
+    if let Some(previous_dir) = previous_dir {
+        let path = previous_dir.join("RELEASES");
+
+        if path.exists() {
+            let content = std::fs::read_to_string(&path)
+                .map_err(|e| format!("unable to read {}: {}", path.display(), e))?;
+
+            for line in content.lines().filter(|l| !l.trim().is_empty()) {
+                let entry = ReleaseEntry::parse(line)
+                    .ok_or_else(|| format!("invalid entry in {}: {}", path.display(), line))?;
+
+                if entry.filename.ends_with("-full.nupkg") && entry.filename != full_name {
+                    base = Some(previous_dir.join(&entry.filename));
+                }
+
+                entries.push(entry);
+            }
+        }
+    }
+
+    std::fs::create_dir_all(dest_dir)
+        .map_err(|e| format!("unable to create {}: {}", dest_dir.display(), e))?;
+
+    let mut full = lib.clone();
+    add_package_parts(&mut full, metadata);
+    let full_entry = write_package(logger, dest_dir, &full_name, &full, reproducibility)?;
+
+    let mut new_entries = vec![full_entry];
+
+    if let Some(base) = base {
+        warn!(logger, "computing delta from {}", base.display());
+
+        let mut delta = delta_files(logger, &read_package_lib(&base)?, &lib)?;
+        add_package_parts(&mut delta, metadata);
+        let delta_name = format!("{}-{}-delta.nupkg", metadata.name, metadata.version);
+
+        new_entries.push(write_package(
+            logger,
+            dest_dir,
+            &delta_name,
+            &delta,
+            reproducibility,
+        )?);
+    }
+
+    // Entries of a rebuilt release are replaced.
+    entries.retain(|e| !new_entries.iter().any(|n| n.filename == e.filename));
+    entries.extend(new_entries);
+
+    let path = dest_dir.join("RELEASES");
+    warn!(logger, "writing {}", path.display());
+
+    let content: String = entries.iter().map(|e| e.to_line() + "\n").collect();
+    std::fs::write(&path, content).map_err(|e| format!("unable to write {}: {}", path.display(), e))
+}
+
+/// Represents a request to write a Squirrel.Windows release.
+#[derive(Debug, Clone, Serialize)]
+pub struct SquirrelRelease {
+    pub metadata: PackageMetadata,
+    pub files: FileManifest,
+
+    /// Directory holding the published release files.
+    pub previous_dir: Option<PathBuf>,
+
+    /// Directory to write to, relative to the distribution path.
+    pub dest: String,
+}
@@ -0,0 +1,125 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Stamping versions into files.
+
+Placeholders such as `@VERSION@` in files are replaced with their values
+when packaging, without modifying the source tree.
+
+Text files can have placeholders replaced by values of any length.
+Binary files can't change size without corrupting them, so a value in a
+binary file is padded with NUL bytes to the length of its placeholder
+and must not be longer than it. Placeholders in compiled programs should
+therefore be NUL-terminated strings with room for the longest expected
+value.
+*/
+
+use crate::filemanifest::{FileContent, FileManifest};
+
+/// Obtain the default placeholders for a version and build date.
+///
+/// Both `@NAME@` and `__NAME__` forms are recognized.
+pub fn default_replacements(version: &str, build_date: &str) -> Vec<(String, String)> {
+    vec![
+        ("@VERSION@".to_string(), version.to_string()),
+        ("__VERSION__".to_string(), version.to_string()),
+        ("@BUILD_DATE@".to_string(), build_date.to_string()),
+        ("__BUILD_DATE__".to_string(), build_date.to_string()),
+    ]
+}
+
+/// Format a timestamp in seconds since the UNIX epoch as a UTC date.
+pub fn build_date(timestamp: u64) -> String {
+    chrono::NaiveDateTime::from_timestamp_opt(timestamp as i64, 0)
+        .unwrap_or_else(|| chrono::NaiveDateTime::from_timestamp(0, 0))
+        .format("%Y-%m-%d")
+        .to_string()
+}
+
+/// Replace placeholders in file content.
+///
+/// Returns `None` if the content doesn't contain any placeholders.
+pub fn stamp(data: &[u8], replacements: &[(String, String)]) -> Result<Option<Vec<u8>>, String> {
+    let binary = std::str::from_utf8(data).is_err();
+    let mut res = data.to_vec();
+    let mut changed = false;
+
+    for (placeholder, value) in replacements {
+        let placeholder = placeholder.as_bytes();
+        let mut value = value.as_bytes().to_vec();
+
+        if placeholder.is_empty() || find(&res, placeholder, 0).is_none() {
+            continue;
+        }
+
+        if binary {
+            if value.len() > placeholder.len() {
+                return Err(format!(
+                    "value {} is longer than placeholder {} in binary file",
+                    String::from_utf8_lossy(&value),
+                    String::from_utf8_lossy(placeholder)
+                ));
+            }
+
+            value.resize(placeholder.len(), 0);
+        }
+
+        let mut out = Vec::with_capacity(res.len());
+        let mut start = 0;
+
+        while let Some(pos) = find(&res, placeholder, start) {
+            out.extend_from_slice(&res[start..pos]);
+            out.extend_from_slice(&value);
+            start = pos + placeholder.len();
+        }
+
+        out.extend_from_slice(&res[start..]);
+        res = out;
+        changed = true;
+    }
+
+    Ok(if changed { Some(res) } else { None })
+}
+
+fn find(haystack: &[u8], needle: &[u8], start: usize) -> Option<usize> {
+    haystack[start..]
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|pos| pos + start)
+}
+
+/// Replace placeholders in files of a manifest.
+///
+/// `keys` are the manifest paths of files to stamp. Returns a manifest
+/// with the stamped files held in memory. Other files are unchanged.
+pub fn stamp_manifest(
+    files: &FileManifest,
+    keys: &[String],
+    replacements: &[(String, String)],
+) -> Result<FileManifest, String> {
+    let mut res = files.clone();
+
+    for key in keys {
+        let content = &files[key];
+
+        if let FileContent::Symlink(_) = content {
+            continue;
+        }
+
+        if let Some(data) = stamp(&content.read()?, replacements)
+            .map_err(|e| format!("unable to stamp {}: {}", key, e))?
+        {
+            res.insert(
+                key.clone(),
+                FileContent::Data {
+                    data,
+                    executable: content.is_executable(),
+                },
+            );
+        }
+    }
+
+    Ok(res)
+}
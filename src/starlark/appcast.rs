@@ -0,0 +1,143 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::{
+    expand_channel, invalid_arg, optional_retry_arg, optional_secret_arg, optional_str_arg,
+    required_list_arg, required_str_arg,
+};
+use crate::appcast::{Appcast, AppcastFeed};
+use starlark::environment::Environment;
+use starlark::starlark_module;
+use starlark::values::{default_compare, TypedValue, Value, ValueError, ValueResult};
+use starlark::{
+    any, immutable, not_supported, starlark_fun, starlark_signature, starlark_signature_extraction,
+    starlark_signatures,
+};
+use std::any::Any;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+impl TypedValue for Appcast {
+    immutable!();
+    any!();
+    not_supported!(binop);
+    not_supported!(container);
+    not_supported!(function);
+    not_supported!(get_hash);
+    not_supported!(to_int);
+
+    fn to_str(&self) -> String {
+        format!(
+            "Appcast<filename={}, version={}, artifacts={:?}>",
+            self.feed.filename, self.feed.version, self.artifacts
+        )
+    }
+
+    fn to_repr(&self) -> String {
+        self.to_str()
+    }
+
+    fn get_type(&self) -> &'static str {
+        "Appcast"
+    }
+
+    fn to_bool(&self) -> bool {
+        true
+    }
+
+    fn compare(&self, other: &dyn TypedValue, _recursion: u32) -> Result<Ordering, ValueError> {
+        default_compare(self, other)
+    }
+}
+
+fn is_url(s: &str) -> bool {
+    s.starts_with("https://") || s.starts_with("http://")
+}
+
+starlark_module! { appcast_module =>
+    appcast(
+        env env,
+        artifacts,
+        feed_url,
+        version,
+        previous_feed=None,
+        download_url=None,
+        title="Updates",
+        release_notes_url=None,
+        minimum_system_version=None,
+        filename="appcast.xml",
+        ed_key_env="SPARKLE_PRIVATE_KEY",
+        os=None,
+        retry=None
+    ) {
+        required_list_arg("artifacts", "string", artifacts)?;
+        let feed_url = expand_channel(&env, &required_str_arg("feed_url", feed_url)?);
+        let version = required_str_arg("version", version)?;
+        let previous_feed = optional_str_arg("previous_feed", previous_feed)?;
+        let download_url = optional_str_arg("download_url", download_url)?;
+        let title = required_str_arg("title", title)?;
+        let release_notes_url = optional_str_arg("release_notes_url", release_notes_url)?;
+        let minimum_system_version =
+            optional_str_arg("minimum_system_version", minimum_system_version)?;
+        let filename = expand_channel(&env, &required_str_arg("filename", filename)?);
+        let ed_key = optional_secret_arg("ed_key_env", ed_key_env)?;
+        let os = optional_str_arg("os", os)?;
+        let retry = optional_retry_arg("retry", retry)?;
+
+        if !is_url(&feed_url) {
+            return Err(invalid_arg(
+                "feed_url",
+                format!("feed_url {} is not an HTTP URL", feed_url),
+            ));
+        }
+
+        if let Some(os) = &os {
+            if os != "macos" && os != "windows" {
+                return Err(invalid_arg(
+                    "os",
+                    format!("unknown os {}; expected macos or windows", os),
+                ));
+            }
+        }
+
+        let cwd = PathBuf::from(env.get("CWD").unwrap().to_str());
+        let artifacts = artifacts
+            .into_iter()?
+            .map(|a| expand_channel(&env, &a.to_str()))
+            .collect();
+
+        // Artifacts are served next to the feed unless told otherwise.
+        let download_url = match download_url {
+            Some(url) => expand_channel(&env, &url),
+            None => feed_url[..feed_url.rfind('/').unwrap()].to_string(),
+        };
+        let previous_feed = previous_feed.map(|feed| {
+            let feed = expand_channel(&env, &feed);
+
+            if is_url(&feed) {
+                feed
+            } else {
+                cwd.join(feed).display().to_string()
+            }
+        });
+
+        Ok(Value::new(Appcast {
+            artifacts,
+            feed: AppcastFeed {
+                feed_url,
+                title,
+                version,
+                download_url,
+                previous_feed,
+                release_notes_url,
+                minimum_system_version,
+                os,
+                filename,
+            },
+            ed_key,
+            retry,
+        }))
+    }
+}
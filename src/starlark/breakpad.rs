@@ -0,0 +1,66 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::values::FileManifest;
+use super::{expand_channel, required_str_arg, required_type_arg};
+use crate::breakpad::BreakpadSymbols;
+use starlark::environment::Environment;
+use starlark::starlark_module;
+use starlark::values::{default_compare, TypedValue, Value, ValueError, ValueResult};
+use starlark::{
+    any, immutable, not_supported, starlark_fun, starlark_signature, starlark_signature_extraction,
+    starlark_signatures,
+};
+use std::any::Any;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+impl TypedValue for BreakpadSymbols {
+    immutable!();
+    any!();
+    not_supported!(binop);
+    not_supported!(container);
+    not_supported!(function);
+    not_supported!(get_hash);
+    not_supported!(to_int);
+
+    fn to_str(&self) -> String {
+        format!(
+            "BreakpadSymbols<directory={}, files={}>",
+            self.directory,
+            self.files.len()
+        )
+    }
+
+    fn to_repr(&self) -> String {
+        self.to_str()
+    }
+
+    fn get_type(&self) -> &'static str {
+        "BreakpadSymbols"
+    }
+
+    fn to_bool(&self) -> bool {
+        true
+    }
+
+    fn compare(&self, other: &dyn TypedValue, _recursion: u32) -> Result<Ordering, ValueError> {
+        default_compare(self, other)
+    }
+}
+
+starlark_module! { breakpad_module =>
+    breakpad_symbols(env env, files, directory="symbols") {
+        required_type_arg("files", "FileManifest", files)?;
+        let directory = expand_channel(&env, &required_str_arg("directory", directory)?);
+
+        let raw_files = files.0.borrow();
+        let files: &FileManifest = raw_files.as_any().downcast_ref().unwrap();
+
+        Ok(Value::new(BreakpadSymbols {
+            files: files.files.clone(),
+            directory,
+        }))
+    }
+}
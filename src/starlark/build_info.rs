@@ -0,0 +1,113 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::values::FileManifest;
+use super::{release_channel, required_str_arg};
+use crate::build_info::{BuildInfo, BuildInfoFile};
+use starlark::environment::Environment;
+use starlark::starlark_module;
+use starlark::values::{default_compare, RuntimeError, TypedValue, Value, ValueError, ValueResult};
+use starlark::{
+    any, immutable, not_supported, starlark_fun, starlark_signature, starlark_signature_extraction,
+    starlark_signatures,
+};
+use std::any::Any;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+impl TypedValue for BuildInfoFile {
+    immutable!();
+    any!();
+    not_supported!(binop);
+    not_supported!(container);
+    not_supported!(function);
+    not_supported!(get_hash);
+    not_supported!(to_int);
+
+    fn to_str(&self) -> String {
+        format!("BuildInfoFile<filename={}>", self.filename)
+    }
+
+    fn to_repr(&self) -> String {
+        self.to_str()
+    }
+
+    fn get_type(&self) -> &'static str {
+        "BuildInfoFile"
+    }
+
+    fn to_bool(&self) -> bool {
+        true
+    }
+
+    fn compare(&self, other: &dyn TypedValue, _recursion: u32) -> Result<Ordering, ValueError> {
+        default_compare(self, other)
+    }
+}
+
+/// Collect build information from the global variables of an environment.
+fn collect(env: &Environment) -> Result<BuildInfo, ValueError> {
+    let cwd = PathBuf::from(env.get("CWD").unwrap().to_str());
+    let timestamp = env.get("BUILD_TIMESTAMP").unwrap().to_int()?;
+    let config_sha256 = env
+        .get("CONFIG_SHA256")
+        .ok()
+        .filter(|v| v.get_type() == "string")
+        .map(|v| v.to_str());
+
+    Ok(BuildInfo::collect(
+        &cwd,
+        timestamp as u64,
+        release_channel(env),
+        config_sha256,
+    ))
+}
+
+starlark_module! { build_info_module =>
+    build_info(env env, filename=crate::build_info::BUILD_INFO_FILENAME) {
+        let filename = required_str_arg("filename", &filename)?;
+
+        Ok(Value::new(BuildInfoFile {
+            info: collect(&env)?,
+            filename,
+        }))
+    }
+
+    build_info_manifest(env env, manifest=None, path=crate::build_info::BUILD_INFO_FILENAME) {
+        let path = required_str_arg("path", path)?;
+
+        let mut result = match manifest.get_type() {
+            "NoneType" => FileManifest::default(),
+            "FileManifest" => {
+                let raw_manifest = manifest.0.borrow();
+                raw_manifest.as_any().downcast_ref::<FileManifest>().unwrap().clone()
+            }
+            t => {
+                return Err(ValueError::TypeNotX {
+                    object_type: t.to_string(),
+                    op: "FileManifest".to_string(),
+                })
+            }
+        };
+
+        let data = collect(&env)?.to_json().map_err(|message| {
+            ValueError::Runtime(RuntimeError {
+                code: "build_info_manifest",
+                message,
+                label: "build_info_manifest()".to_string(),
+            })
+        })?;
+
+        result.files.insert(
+            path,
+            crate::filemanifest::FileContent::Data {
+                data,
+                executable: false,
+            },
+        );
+
+        Ok(Value::new(result))
+    }
+}
@@ -0,0 +1,100 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::{
+    expand_channel, parse_str_arg, required_dict_arg, required_list_arg, required_str_arg,
+    required_type_arg,
+};
+use crate::bundle::{BundleLayout, ReleaseBundle};
+use starlark::environment::Environment;
+use starlark::starlark_module;
+use starlark::values::{default_compare, TypedValue, Value, ValueError, ValueResult};
+use starlark::{
+    any, immutable, not_supported, starlark_fun, starlark_signature, starlark_signature_extraction,
+    starlark_signatures,
+};
+use std::any::Any;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+impl TypedValue for ReleaseBundle {
+    immutable!();
+    any!();
+    not_supported!(binop);
+    not_supported!(container);
+    not_supported!(function);
+    not_supported!(get_hash);
+    not_supported!(to_int);
+
+    fn to_str(&self) -> String {
+        format!(
+            "ReleaseBundle<directory={}, layout={:?}, artifacts={:?}>",
+            self.directory,
+            self.layout,
+            self.artifacts
+                .iter()
+                .map(|(artifact, _)| artifact)
+                .collect::<Vec<_>>()
+        )
+    }
+
+    fn to_repr(&self) -> String {
+        self.to_str()
+    }
+
+    fn get_type(&self) -> &'static str {
+        "ReleaseBundle"
+    }
+
+    fn to_bool(&self) -> bool {
+        true
+    }
+
+    fn compare(&self, other: &dyn TypedValue, _recursion: u32) -> Result<Ordering, ValueError> {
+        default_compare(self, other)
+    }
+}
+
+starlark_module! { bundle_module =>
+    release_bundle(
+        env env,
+        artifacts,
+        layout="by-platform",
+        index=true,
+        directory="release",
+        platforms=None
+    ) {
+        required_list_arg("artifacts", "string", artifacts)?;
+        let layout: BundleLayout = parse_str_arg("layout", &required_str_arg("layout", layout)?)?;
+        required_type_arg("index", "bool", index)?;
+        let directory = expand_channel(&env, &required_str_arg("directory", directory)?);
+
+        if platforms.get_type() != "NoneType" {
+            required_dict_arg("platforms", "string", "string", platforms)?;
+        }
+
+        let artifacts = artifacts
+            .into_iter()?
+            .map(|artifact| {
+                let platform = if platforms.get_type() == "NoneType" {
+                    None
+                } else {
+                    platforms
+                        .at(artifact.clone())
+                        .ok()
+                        .map(|platform| platform.to_str())
+                };
+
+                (expand_channel(&env, &artifact.to_str()), platform)
+            })
+            .collect();
+
+        Ok(Value::new(ReleaseBundle {
+            artifacts,
+            layout,
+            index: index.to_bool(),
+            directory,
+        }))
+    }
+}
@@ -0,0 +1,155 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::values::FileManifest;
+use super::{
+    invalid_arg, optional_list_arg, optional_retry_arg, optional_secret_arg, optional_str_arg,
+    parse_str_arg, required_str_arg,
+};
+use crate::cargo::{CargoBuildOptions, CargoPublish};
+use crate::filemanifest::manifest_key;
+use starlark::environment::Environment;
+use starlark::starlark_module;
+use starlark::values::{
+    default_compare, RuntimeError, TypedValue, Value, ValueError, ValueResult,
+    INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+};
+use starlark::{
+    any, check_type, immutable, not_supported, starlark_err, starlark_fun, starlark_signature,
+    starlark_signature_extraction, starlark_signatures,
+};
+use std::any::Any;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// Resolve a path to a Cargo project directory or `Cargo.toml` to the latter.
+fn resolve_manifest_path(cwd: &str, path: &str) -> PathBuf {
+    let path = PathBuf::from(cwd).join(path);
+
+    if path.is_dir() {
+        path.join("Cargo.toml")
+    } else {
+        path
+    }
+}
+
+impl TypedValue for CargoPublish {
+    immutable!();
+    any!();
+    not_supported!(binop);
+    not_supported!(container);
+    not_supported!(function);
+    not_supported!(get_hash);
+    not_supported!(to_int);
+
+    fn to_str(&self) -> String {
+        format!(
+            "CargoPublish<manifest_path={}, dry_run={}>",
+            self.manifest_path.display(),
+            self.dry_run
+        )
+    }
+
+    fn to_repr(&self) -> String {
+        self.to_str()
+    }
+
+    fn get_type(&self) -> &'static str {
+        "CargoPublish"
+    }
+
+    fn to_bool(&self) -> bool {
+        true
+    }
+
+    fn compare(&self, other: &dyn TypedValue, _recursion: u32) -> Result<Ordering, ValueError> {
+        default_compare(self, other)
+    }
+}
+
+starlark_module! { cargo_module =>
+    cargo_build(
+        env env,
+        path,
+        profile="release",
+        features=None,
+        target=None,
+        prefix=None,
+        builder="cargo",
+        target_dir=None
+    ) {
+        let path = required_str_arg("path", &path)?;
+        let profile = required_str_arg("profile", &profile)?;
+        optional_list_arg("features", "string", &features)?;
+        let target = optional_str_arg("target", &target)?;
+        let prefix = optional_str_arg("prefix", &prefix)?;
+        let builder = parse_str_arg("builder", &required_str_arg("builder", &builder)?)?;
+        let target_dir = optional_str_arg("target_dir", &target_dir)?;
+
+        let cwd = env.get("CWD").unwrap().to_str();
+        let features: Vec<String> = match features.get_type() {
+            "list" => features.into_iter()?.map(|f| f.to_str()).collect(),
+            _ => vec![],
+        };
+
+        // Expand {target} so outputs for different targets can be kept apart.
+        let target_name = target.clone().unwrap_or_else(crate::cargo::host_target);
+        let expand = |s: String| s.replace("{target}", &target_name);
+
+        let options = CargoBuildOptions {
+            profile,
+            features,
+            target,
+            builder,
+            target_dir: target_dir.map(|p| PathBuf::from(&cwd).join(expand(p))),
+        };
+        let prefix = prefix.map(expand);
+
+        let executables = crate::cargo::cargo_build(
+            &resolve_manifest_path(&cwd, &path),
+            &options,
+        )
+        .map_err(|message| {
+            ValueError::Runtime(RuntimeError {
+                code: "cargo_build",
+                message,
+                label: "cargo_build()".to_string(),
+            })
+        })?;
+
+        let mut manifest = FileManifest::default();
+
+        for executable in executables {
+            let filename = executable.file_name().unwrap().to_string_lossy().to_string();
+            let rel_path = match &prefix {
+                Some(prefix) => PathBuf::from(prefix).join(filename),
+                None => PathBuf::from(filename),
+            };
+
+            let key = manifest_key(&rel_path).map_err(|e| invalid_arg("prefix", e))?;
+            manifest.files.insert(key, executable.into());
+        }
+
+        Ok(Value::new(manifest))
+    }
+
+    cargo_publish(env env, path, dry_run=false, token_env=None, wait_timeout=600, retry=None) {
+        let path = required_str_arg("path", path)?;
+        check_type!(dry_run, "cargo_publish", bool);
+        let token = optional_secret_arg("token_env", token_env)?;
+        check_type!(wait_timeout, "cargo_publish", int);
+        let retry = optional_retry_arg("retry", retry)?;
+
+        let cwd = env.get("CWD").unwrap().to_str();
+
+        Ok(Value::new(CargoPublish {
+            manifest_path: resolve_manifest_path(&cwd, &path),
+            dry_run: dry_run.to_bool(),
+            token,
+            wait_timeout: wait_timeout.to_int()?.max(0) as u64,
+            retry,
+        }))
+    }
+}
@@ -0,0 +1,55 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::values::FileManifest;
+use super::{optional_str_arg, parse_str_arg, required_str_arg, to_value_error};
+use crate::completions::{CompletionLayout, Shell};
+use crate::filemanifest::FileContent;
+use starlark::starlark_module;
+use starlark::values::Value;
+use starlark::{
+    starlark_fun, starlark_signature, starlark_signature_extraction, starlark_signatures,
+};
+use std::path::PathBuf;
+
+starlark_module! { completions_module =>
+    shell_completions(env env, command, bash=None, zsh=None, fish=None, layout="deb") {
+        let command = required_str_arg("command", command)?;
+        let bash = optional_str_arg("bash", bash)?;
+        let zsh = optional_str_arg("zsh", zsh)?;
+        let fish = optional_str_arg("fish", fish)?;
+        let layout: CompletionLayout =
+            parse_str_arg("layout", &required_str_arg("layout", layout)?)?;
+
+        if command.is_empty() || command.contains('/') {
+            return Err(to_value_error(
+                "shell_completions",
+                format!("invalid command name: {}", command),
+            ));
+        }
+
+        let cwd = PathBuf::from(env.get("CWD").unwrap().to_str());
+        let mut manifest = FileManifest::default();
+
+        for (shell, source) in [(Shell::Bash, bash), (Shell::Zsh, zsh), (Shell::Fish, fish)] {
+            let source = match source {
+                Some(source) => cwd.join(source),
+                None => continue,
+            };
+
+            if !source.is_file() {
+                return Err(to_value_error("shell_completions", format!(
+                    "completion file {} does not exist",
+                    source.display()
+                )));
+            }
+
+            manifest
+                .files
+                .insert(layout.path(shell, &command), FileContent::Path(source));
+        }
+
+        Ok(Value::new(manifest))
+    }
+}
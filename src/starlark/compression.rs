@@ -0,0 +1,115 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::{invalid_arg, parse_str_arg, required_type_arg};
+use crate::compression::{Compression, CompressionSettings};
+use starlark::environment::Environment;
+use starlark::starlark_module;
+use starlark::values::{default_compare, TypedValue, Value, ValueError, ValueResult};
+use starlark::{
+    any, immutable, not_supported, starlark_fun, starlark_signature, starlark_signature_extraction,
+    starlark_signatures,
+};
+use std::any::Any;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+impl TypedValue for CompressionSettings {
+    immutable!();
+    any!();
+    not_supported!(binop);
+    not_supported!(container);
+    not_supported!(function);
+    not_supported!(get_hash);
+    not_supported!(to_int);
+
+    fn to_str(&self) -> String {
+        format!(
+            "CompressionSettings<algorithm={:?}, level={:?}, threads={:?}>",
+            self.algorithm.map(|a| a.as_str()),
+            self.level,
+            self.threads
+        )
+    }
+
+    fn to_repr(&self) -> String {
+        self.to_str()
+    }
+
+    fn get_type(&self) -> &'static str {
+        "CompressionSettings"
+    }
+
+    fn to_bool(&self) -> bool {
+        true
+    }
+
+    fn compare(&self, other: &dyn TypedValue, _recursion: u32) -> Result<Ordering, ValueError> {
+        default_compare(self, other)
+    }
+}
+
+/// Validate an optional integer argument that must be positive.
+fn optional_positive_int_arg(name: &str, value: &Value) -> Result<Option<i64>, ValueError> {
+    if value.get_type() == "NoneType" {
+        return Ok(None);
+    }
+
+    required_type_arg(name, "int", value)?;
+
+    match value.to_int()? {
+        n if n > 0 => Ok(Some(n)),
+        _ => Err(invalid_arg(name, format!("{} must be positive", name))),
+    }
+}
+
+starlark_module! { compression_module =>
+    compression(level=None, algorithm=None, threads=None) {
+        let algorithm = match algorithm.get_type() {
+            "NoneType" => None,
+            _ => {
+                required_type_arg("algorithm", "string", algorithm)?;
+                Some(parse_str_arg::<Compression>("algorithm", &algorithm.to_str())?)
+            }
+        };
+        let threads = optional_positive_int_arg("threads", threads)?;
+
+        let level = match level.get_type() {
+            "NoneType" => None,
+            _ => {
+                required_type_arg("level", "int", level)?;
+                let level = level.to_int()?;
+
+                if level < 0 {
+                    return Err(invalid_arg("level", "level must not be negative".to_string()));
+                }
+
+                Some(level as u32)
+            }
+        };
+
+        // Without an algorithm, levels are validated against the
+        // algorithm of each archive the settings apply to.
+        match (algorithm, level) {
+            (Some(algorithm), Some(level)) => {
+                algorithm
+                    .validate_level(level)
+                    .map_err(|e| invalid_arg("level", e))?;
+            }
+            (None, Some(level)) if level > *Compression::Zstd.levels().end() => {
+                return Err(invalid_arg(
+                    "level",
+                    format!("level {} is invalid for every algorithm", level),
+                ));
+            }
+            _ => {}
+        }
+
+        Ok(Value::new(CompressionSettings {
+            algorithm,
+            level,
+            threads: threads.map(|threads| threads as usize),
+        }))
+    }
+}
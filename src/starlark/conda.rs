@@ -0,0 +1,136 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::values::FileManifest;
+use super::{
+    invalid_arg, optional_list_arg, optional_str_arg, parse_str_arg, required_str_arg,
+    required_type_arg,
+};
+use crate::conda::{CondaFormat, CondaPackage, CondaPackageInfo};
+use crate::package_metadata::PackageMetadata;
+use starlark::environment::Environment;
+use starlark::starlark_module;
+use starlark::values::{default_compare, TypedValue, Value, ValueError, ValueResult};
+use starlark::{
+    any, immutable, not_supported, starlark_fun, starlark_signature, starlark_signature_extraction,
+    starlark_signatures,
+};
+use std::any::Any;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+impl TypedValue for CondaPackage {
+    immutable!();
+    any!();
+    not_supported!(binop);
+    not_supported!(container);
+    not_supported!(function);
+    not_supported!(get_hash);
+    not_supported!(to_int);
+
+    fn to_str(&self) -> String {
+        format!(
+            "CondaPackage<filename={}, subdir={}>",
+            self.package.filename(),
+            self.package.subdir
+        )
+    }
+
+    fn to_repr(&self) -> String {
+        self.to_str()
+    }
+
+    fn get_type(&self) -> &'static str {
+        "CondaPackage"
+    }
+
+    fn to_bool(&self) -> bool {
+        true
+    }
+
+    fn compare(&self, other: &dyn TypedValue, _recursion: u32) -> Result<Ordering, ValueError> {
+        default_compare(self, other)
+    }
+}
+
+starlark_module! { conda_module =>
+    conda_package(
+        env env,
+        metadata,
+        manifest,
+        format="conda",
+        build="0",
+        build_number=0,
+        subdir=None,
+        depends=None
+    ) {
+        required_type_arg("metadata", "PackageMetadata", metadata)?;
+        required_type_arg("manifest", "FileManifest", manifest)?;
+        let format: CondaFormat = parse_str_arg("format", &required_str_arg("format", format)?)?;
+        let build = required_str_arg("build", build)?;
+        required_type_arg("build_number", "int", build_number)?;
+        let subdir = optional_str_arg("subdir", subdir)?;
+        optional_list_arg("depends", "string", depends)?;
+
+        let raw_metadata = metadata.0.borrow();
+        let metadata: &PackageMetadata = raw_metadata.as_any().downcast_ref().unwrap();
+        let raw_manifest = manifest.0.borrow();
+        let manifest: &FileManifest = raw_manifest.as_any().downcast_ref().unwrap();
+
+        if metadata.name != metadata.name.to_lowercase() {
+            return Err(invalid_arg(
+                "metadata",
+                format!("conda package names must be lowercase; got {}", metadata.name),
+            ));
+        }
+        if metadata.version.contains('-') || build.contains('-') {
+            return Err(invalid_arg(
+                "metadata",
+                "conda versions and build strings can't contain -".to_string(),
+            ));
+        }
+
+        let build_number = build_number.to_int()?;
+        if build_number < 0 {
+            return Err(invalid_arg(
+                "build_number",
+                format!("build_number must not be negative; got {}", build_number),
+            ));
+        }
+
+        let subdir = match subdir {
+            Some(subdir) => subdir,
+            None => {
+                let target = env.get("HOST_TARGET").unwrap().to_str();
+
+                crate::conda::conda_subdir(&target)
+                    .ok_or_else(|| {
+                        invalid_arg(
+                            "subdir",
+                            format!("unable to determine conda subdir of {}; define subdir", target),
+                        )
+                    })?
+                    .to_string()
+            }
+        };
+
+        let depends = if depends.get_type() == "NoneType" {
+            Vec::new()
+        } else {
+            depends.into_iter()?.map(|d| d.to_str()).collect()
+        };
+
+        Ok(Value::new(CondaPackage {
+            package: CondaPackageInfo {
+                metadata: metadata.clone(),
+                build,
+                build_number: build_number as u64,
+                subdir,
+                depends,
+                format,
+            },
+            files: manifest.files.clone(),
+        }))
+    }
+}
@@ -0,0 +1,75 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::{expand_channel, required_str_arg};
+use crate::container::{PackageKind, TestInstall};
+use starlark::environment::Environment;
+use starlark::starlark_module;
+use starlark::values::{default_compare, RuntimeError, TypedValue, Value, ValueError, ValueResult};
+use starlark::{
+    any, immutable, not_supported, starlark_fun, starlark_signature, starlark_signature_extraction,
+    starlark_signatures,
+};
+use std::any::Any;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+impl TypedValue for TestInstall {
+    immutable!();
+    any!();
+    not_supported!(binop);
+    not_supported!(container);
+    not_supported!(function);
+    not_supported!(get_hash);
+    not_supported!(to_int);
+
+    fn to_str(&self) -> String {
+        format!(
+            "TestInstall<artifact={}, image={}, command={}>",
+            self.artifact, self.image, self.command
+        )
+    }
+
+    fn to_repr(&self) -> String {
+        self.to_str()
+    }
+
+    fn get_type(&self) -> &'static str {
+        "TestInstall"
+    }
+
+    fn to_bool(&self) -> bool {
+        true
+    }
+
+    fn compare(&self, other: &dyn TypedValue, _recursion: u32) -> Result<Ordering, ValueError> {
+        default_compare(self, other)
+    }
+}
+
+starlark_module! { container_module =>
+    test_install(env env, artifact, command, image="debian:stable") {
+        let artifact = expand_channel(&env, &required_str_arg("artifact", artifact)?);
+        let command = required_str_arg("command", command)?;
+        let image = required_str_arg("image", image)?;
+
+        if PackageKind::from_filename(&artifact).is_none() {
+            return Err(RuntimeError {
+                code: "test_install",
+                message: format!(
+                    "unable to determine how to install {}; expected a .deb, .rpm, or archive",
+                    artifact
+                ),
+                label: "test_install()".to_string(),
+            }
+            .into());
+        }
+
+        Ok(Value::new(TestInstall {
+            artifact,
+            image,
+            command,
+        }))
+    }
+}
@@ -5,11 +5,17 @@
 use debian::package::ControlParagraph;
 
 use super::{
-    optional_list_arg, optional_str_arg, required_list_arg, required_str_arg, required_type_arg,
+    invalid_arg, metadata_str_arg, optional_compression_arg, optional_list_arg,
+    optional_metadata_arg, optional_name_template_arg, optional_str_arg, required_list_arg,
+    required_str_arg, required_type_arg,
 };
+use crate::debian::DebianDebArchive;
+use crate::debian_version::DebianVersion;
 use crate::starlark::values::FileManifest;
+use serde::Serialize;
 use starlark::environment::Environment;
 use starlark::starlark_module;
+use starlark::values::tuple::Tuple;
 use starlark::values::{default_compare, TypedValue, Value, ValueError, ValueResult};
 use starlark::{
     any, immutable, not_supported, starlark_fun, starlark_signature, starlark_signature_extraction,
@@ -19,8 +25,9 @@ use std::any::Any;
 use std::cmp::Ordering;
 use std::collections::HashMap;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct DebianControlSourceBinaryPackage {
+    #[serde(serialize_with = "crate::debian::serialize_paragraph")]
     pub paragraph: ControlParagraph,
 }
 
@@ -54,8 +61,9 @@ impl TypedValue for DebianControlSourceBinaryPackage {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct DebianControl {
+    #[serde(serialize_with = "crate::debian::serialize_paragraphs")]
     pub paragraphs: Vec<ControlParagraph>,
 }
 
@@ -89,8 +97,9 @@ impl TypedValue for DebianControl {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct DebianControlBinaryPackage {
+    #[serde(serialize_with = "crate::debian::serialize_paragraph")]
     pub paragraph: ControlParagraph,
 }
 
@@ -124,12 +133,6 @@ impl TypedValue for DebianControlBinaryPackage {
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct DebianDebArchive {
-    pub control_file: DebianControlBinaryPackage,
-    pub files: FileManifest,
-}
-
 impl TypedValue for DebianDebArchive {
     immutable!();
     any!();
@@ -169,7 +172,37 @@ fn str_list_to_comma_string(value: &Value) -> String {
     strings.join(", ")
 }
 
+/// Parse a `str` argument holding a Debian version.
+fn debian_version_arg(name: &str, value: &Value) -> Result<DebianVersion, ValueError> {
+    let version = required_str_arg(name, value)?;
+
+    version
+        .parse()
+        .map_err(|message| invalid_arg(name, message))
+}
+
 starlark_module! { debian_module =>
+    debian_version_compare(a, b) {
+        let a = debian_version_arg("a", &a)?;
+        let b = debian_version_arg("b", &b)?;
+
+        Ok(Value::from(match a.cmp(&b) {
+            Ordering::Less => -1,
+            Ordering::Equal => 0,
+            Ordering::Greater => 1,
+        }))
+    }
+
+    debian_version_parse(version) {
+        let version = debian_version_arg("version", &version)?;
+
+        Ok(Value::new(Tuple::from((
+            i64::from(version.epoch),
+            version.upstream_version,
+            version.debian_revision.unwrap_or_default(),
+        ))))
+    }
+
     debian_control_source_binary_package(
         package,
         architecture,
@@ -326,11 +359,11 @@ starlark_module! { debian_module =>
     }
 
     debian_control_binary_package(
-        package,
-        version,
-        architecture,
-        maintainer,
-        description,
+        package=None,
+        version=None,
+        architecture=None,
+        maintainer=None,
+        description=None,
         source=None,
         section=None,
         priority=None,
@@ -344,13 +377,23 @@ starlark_module! { debian_module =>
         conflicts=None,
         installed_size=None,
         homepage=None,
-        built_using=None) {
+        built_using=None,
+        metadata=None) {
 
-        let package = required_str_arg("package", &package)?;
-        let version = required_str_arg("version", &version)?;
+        let metadata = optional_metadata_arg("metadata", &metadata)?;
+        let package = metadata_str_arg("package", &package, metadata.as_ref().map(|m| m.name.clone()))?;
+        let version = metadata_str_arg("version", &version, metadata.as_ref().map(|m| m.version.clone()))?;
         let architecture = required_str_arg("architecture", &architecture)?;
-        let maintainer = required_str_arg("maintainer", &maintainer)?;
-        let description = required_str_arg("description", &description)?;
+        let maintainer = metadata_str_arg(
+            "maintainer",
+            &maintainer,
+            metadata.as_ref().and_then(|m| m.maintainer.clone()),
+        )?;
+        let description = metadata_str_arg(
+            "description",
+            &description,
+            metadata.as_ref().map(|m| m.debian_description()),
+        )?;
         let source = optional_str_arg("source", &source)?;
         let section = optional_str_arg("section", &section)?;
         let priority = optional_str_arg("priority", &priority)?;
@@ -365,7 +408,8 @@ starlark_module! { debian_module =>
         optional_list_arg("conflicts", "string", &conflicts)?;
 
         let installed_size = optional_str_arg("installed_size", &installed_size)?;
-        let homepage = optional_str_arg("homepage", &homepage)?;
+        let homepage = optional_str_arg("homepage", &homepage)?
+            .or_else(|| metadata.as_ref().and_then(|m| m.homepage.clone()));
         let built_using = optional_str_arg("built_using", &built_using)?;
 
         let mut paragraph = ControlParagraph::new();
@@ -421,18 +465,29 @@ starlark_module! { debian_module =>
         Ok(Value::new(DebianControlBinaryPackage { paragraph }))
     }
 
-    debian_deb_archive(control_binary_package, files) {
+    debian_deb_archive(control_binary_package, files, systemd=true, compression=None, name_template=None) {
         required_type_arg("control_binary_package", "DebianControlBinaryPackage", &control_binary_package)?;
         required_type_arg("files", "FileManifest", &files)?;
+        required_type_arg("systemd", "bool", systemd)?;
+        let compression = optional_compression_arg("compression", compression)?.unwrap_or_default();
+
+        if let Some(algorithm) = compression.algorithm {
+            if let Err(message) = compression.validate(algorithm) {
+                return Err(invalid_arg("compression", message));
+            }
+        }
 
         let raw_package = control_binary_package.0.borrow();
         let package: &DebianControlBinaryPackage = raw_package.as_any().downcast_ref().unwrap();
         let raw_manifest = files.0.borrow();
         let manifest: &FileManifest = raw_manifest.as_any().downcast_ref().unwrap();
 
-        Ok(Value::new(DebianDebArchive {
-            control_file: package.clone(),
-            files: manifest.clone(),
-        }))
+        Ok(Value::new(DebianDebArchive::new(
+            package.paragraph.clone(),
+            manifest.files.clone(),
+            systemd.to_bool(),
+            compression,
+            optional_name_template_arg("name_template", name_template)?,
+        )))
     }
 }
@@ -0,0 +1,105 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::{
+    expand_channel, invalid_arg, optional_str_arg, required_dict_arg, required_list_arg,
+    required_type_arg,
+};
+use crate::delta::DeltaUpdate;
+use starlark::environment::Environment;
+use starlark::starlark_module;
+use starlark::values::{
+    default_compare, RuntimeError, TypedValue, Value, ValueError, ValueResult,
+    INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+};
+use starlark::{
+    any, immutable, not_supported, starlark_fun, starlark_signature, starlark_signature_extraction,
+    starlark_signatures,
+};
+use std::any::Any;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+
+impl TypedValue for DeltaUpdate {
+    immutable!();
+    any!();
+    not_supported!(binop);
+    not_supported!(container);
+    not_supported!(function);
+    not_supported!(get_hash);
+    not_supported!(to_int);
+
+    fn to_str(&self) -> String {
+        format!(
+            "DeltaUpdate<artifacts={:?}, zsync={}, previous={:?}>",
+            self.artifacts, self.zsync, self.previous
+        )
+    }
+
+    fn to_repr(&self) -> String {
+        self.to_str()
+    }
+
+    fn get_type(&self) -> &'static str {
+        "DeltaUpdate"
+    }
+
+    fn to_bool(&self) -> bool {
+        true
+    }
+
+    fn compare(&self, other: &dyn TypedValue, _recursion: u32) -> Result<Ordering, ValueError> {
+        default_compare(self, other)
+    }
+}
+
+starlark_module! { delta_module =>
+    delta_update(env env, artifacts, previous=None, zsync=true, url=None) {
+        required_list_arg("artifacts", "string", artifacts)?;
+        required_type_arg("zsync", "bool", zsync)?;
+        let url = optional_str_arg("url", url)?.map(|url| expand_channel(&env, &url));
+
+        let cwd = PathBuf::from(env.get("CWD").unwrap().to_str());
+        let artifacts: Vec<String> = artifacts
+            .into_iter()?
+            .map(|a| expand_channel(&env, &a.to_str()))
+            .collect();
+
+        let mut previous_paths = BTreeMap::new();
+
+        if previous.get_type() != "NoneType" {
+            required_dict_arg("previous", "string", "string", previous)?;
+
+            for key in previous.into_iter()? {
+                let artifact = expand_channel(&env, &key.to_str());
+
+                if !artifacts.contains(&artifact) {
+                    return Err(invalid_arg(
+                        "previous",
+                        format!("previous defines {}, which isn't in artifacts", artifact),
+                    ));
+                }
+
+                previous_paths.insert(artifact, cwd.join(previous.at(key.clone())?.to_str()));
+            }
+        }
+
+        if !zsync.to_bool() && previous_paths.is_empty() {
+            return Err(RuntimeError {
+                code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                message: "delta_update() requires zsync or previous".to_string(),
+                label: "delta_update()".to_string(),
+            }
+            .into());
+        }
+
+        Ok(Value::new(DeltaUpdate {
+            artifacts,
+            previous: previous_paths,
+            zsync: zsync.to_bool(),
+            url,
+        }))
+    }
+}
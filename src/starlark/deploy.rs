@@ -0,0 +1,293 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::{
+    concurrency_arg, expand_channel, optional_retry_arg, optional_str_arg, parse_str_arg,
+    required_list_arg, required_secret_arg, required_str_arg,
+};
+use crate::deploy::{AptRepositoryPublish, HostKeyPolicy, RemoteCopy, SshOptions};
+use crate::package_hosting::{GemfuryPush, PackagecloudPush};
+use starlark::environment::Environment;
+use starlark::starlark_module;
+use starlark::values::{default_compare, TypedValue, Value, ValueError, ValueResult};
+use starlark::{
+    any, immutable, not_supported, starlark_fun, starlark_signature, starlark_signature_extraction,
+    starlark_signatures,
+};
+use std::any::Any;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+impl TypedValue for RemoteCopy {
+    immutable!();
+    any!();
+    not_supported!(binop);
+    not_supported!(container);
+    not_supported!(function);
+    not_supported!(get_hash);
+    not_supported!(to_int);
+
+    fn to_str(&self) -> String {
+        format!(
+            "RemoteCopy<host={}, dest={}, artifacts={:?}>",
+            self.host, self.dest, self.artifacts
+        )
+    }
+
+    fn to_repr(&self) -> String {
+        self.to_str()
+    }
+
+    fn get_type(&self) -> &'static str {
+        "RemoteCopy"
+    }
+
+    fn to_bool(&self) -> bool {
+        true
+    }
+
+    fn compare(&self, other: &dyn TypedValue, _recursion: u32) -> Result<Ordering, ValueError> {
+        default_compare(self, other)
+    }
+}
+
+impl TypedValue for AptRepositoryPublish {
+    immutable!();
+    any!();
+    not_supported!(binop);
+    not_supported!(container);
+    not_supported!(function);
+    not_supported!(get_hash);
+    not_supported!(to_int);
+
+    fn to_str(&self) -> String {
+        format!(
+            "AptRepositoryPublish<repo_dir={}, destination={:?}>",
+            self.repo_dir, self.destination
+        )
+    }
+
+    fn to_repr(&self) -> String {
+        self.to_str()
+    }
+
+    fn get_type(&self) -> &'static str {
+        "AptRepositoryPublish"
+    }
+
+    fn to_bool(&self) -> bool {
+        true
+    }
+
+    fn compare(&self, other: &dyn TypedValue, _recursion: u32) -> Result<Ordering, ValueError> {
+        default_compare(self, other)
+    }
+}
+
+impl TypedValue for PackagecloudPush {
+    immutable!();
+    any!();
+    not_supported!(binop);
+    not_supported!(container);
+    not_supported!(function);
+    not_supported!(get_hash);
+    not_supported!(to_int);
+
+    fn to_str(&self) -> String {
+        format!(
+            "PackagecloudPush<repo={}, artifacts={:?}>",
+            self.repo, self.artifacts
+        )
+    }
+
+    fn to_repr(&self) -> String {
+        self.to_str()
+    }
+
+    fn get_type(&self) -> &'static str {
+        "PackagecloudPush"
+    }
+
+    fn to_bool(&self) -> bool {
+        true
+    }
+
+    fn compare(&self, other: &dyn TypedValue, _recursion: u32) -> Result<Ordering, ValueError> {
+        default_compare(self, other)
+    }
+}
+
+impl TypedValue for GemfuryPush {
+    immutable!();
+    any!();
+    not_supported!(binop);
+    not_supported!(container);
+    not_supported!(function);
+    not_supported!(get_hash);
+    not_supported!(to_int);
+
+    fn to_str(&self) -> String {
+        format!(
+            "GemfuryPush<account={}, artifacts={:?}>",
+            self.account, self.artifacts
+        )
+    }
+
+    fn to_repr(&self) -> String {
+        self.to_str()
+    }
+
+    fn get_type(&self) -> &'static str {
+        "GemfuryPush"
+    }
+
+    fn to_bool(&self) -> bool {
+        true
+    }
+
+    fn compare(&self, other: &dyn TypedValue, _recursion: u32) -> Result<Ordering, ValueError> {
+        default_compare(self, other)
+    }
+}
+
+starlark_module! { deploy_module =>
+    apt_repository_publish(
+        env env,
+        repo_dir,
+        destination,
+        host_key_policy="strict",
+        known_hosts_file=None,
+        cloudfront_distribution=None,
+        retry=None
+    ) {
+        let repo_dir = required_str_arg("repo_dir", &repo_dir)?;
+        let destination = parse_str_arg(
+            "destination",
+            &expand_channel(&env, &required_str_arg("destination", &destination)?),
+        )?;
+        let host_key_policy: HostKeyPolicy =
+            parse_str_arg("host_key_policy", &required_str_arg("host_key_policy", &host_key_policy)?)?;
+        let known_hosts_file = optional_str_arg("known_hosts_file", &known_hosts_file)?;
+        let cloudfront_distribution =
+            optional_str_arg("cloudfront_distribution", &cloudfront_distribution)?;
+        let retry = optional_retry_arg("retry", &retry)?;
+
+        let cwd = PathBuf::from(env.get("CWD").unwrap().to_str());
+
+        Ok(Value::new(AptRepositoryPublish {
+            repo_dir,
+            destination,
+            ssh: SshOptions {
+                host_key_policy,
+                known_hosts_file: known_hosts_file.map(|p| cwd.join(p)),
+            },
+            cloudfront_distribution,
+            retry,
+        }))
+    }
+
+    gemfury_push(
+        env env,
+        account,
+        artifacts,
+        token_env="GEMFURY_PUSH_TOKEN",
+        concurrency=4,
+        retry=None
+    ) {
+        let account = required_str_arg("account", &account)?;
+        required_list_arg("artifacts", "string", &artifacts)?;
+        let token = required_secret_arg("token_env", &token_env)?;
+        let concurrency = concurrency_arg(&concurrency)?;
+        let retry = optional_retry_arg("retry", &retry)?;
+
+        let artifacts = artifacts
+            .into_iter()?
+            .map(|a| expand_channel(&env, &a.to_str()))
+            .collect();
+
+        Ok(Value::new(GemfuryPush {
+            account,
+            artifacts,
+            token,
+            concurrency,
+            retry,
+        }))
+    }
+
+    packagecloud_push(
+        env env,
+        repo,
+        artifacts,
+        distro=None,
+        token_env="PACKAGECLOUD_TOKEN",
+        url=crate::package_hosting::PACKAGECLOUD_URL,
+        concurrency=4,
+        retry=None
+    ) {
+        let repo = expand_channel(&env, &required_str_arg("repo", &repo)?);
+        required_list_arg("artifacts", "string", &artifacts)?;
+        let distro = optional_str_arg("distro", &distro)?;
+        let token = required_secret_arg("token_env", &token_env)?;
+        let url = required_str_arg("url", &url)?;
+        let concurrency = concurrency_arg(&concurrency)?;
+        let retry = optional_retry_arg("retry", &retry)?;
+
+        let artifacts = artifacts
+            .into_iter()?
+            .map(|a| expand_channel(&env, &a.to_str()))
+            .collect();
+
+        Ok(Value::new(PackagecloudPush {
+            repo,
+            artifacts,
+            distro,
+            token,
+            url,
+            concurrency,
+            retry,
+        }))
+    }
+
+    remote_copy(
+        env env,
+        host,
+        dest,
+        artifacts,
+        protocol="sftp",
+        host_key_policy="strict",
+        known_hosts_file=None,
+        concurrency=4,
+        retry=None
+    ) {
+        let host = required_str_arg("host", host)?;
+        let dest = expand_channel(&env, &required_str_arg("dest", dest)?);
+        required_list_arg("artifacts", "string", artifacts)?;
+        let protocol = parse_str_arg("protocol", &required_str_arg("protocol", protocol)?)?;
+        let host_key_policy: HostKeyPolicy =
+            parse_str_arg("host_key_policy", &required_str_arg("host_key_policy", host_key_policy)?)?;
+        let known_hosts_file = optional_str_arg("known_hosts_file", known_hosts_file)?;
+        let concurrency = concurrency_arg(concurrency)?;
+        let retry = optional_retry_arg("retry", retry)?;
+
+        let cwd = PathBuf::from(env.get("CWD").unwrap().to_str());
+        let artifacts = artifacts
+            .into_iter()?
+            .map(|a| expand_channel(&env, &a.to_str()))
+            .collect();
+
+        Ok(Value::new(RemoteCopy {
+            host,
+            dest,
+            artifacts,
+            protocol,
+            ssh: SshOptions {
+                host_key_policy,
+                known_hosts_file: known_hosts_file.map(|p| cwd.join(p)),
+            },
+            concurrency,
+            retry,
+        }))
+    }
+}
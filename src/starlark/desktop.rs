@@ -0,0 +1,96 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::values::FileManifest;
+use super::{optional_list_arg, optional_str_arg, required_str_arg, to_value_error};
+use crate::desktop::DesktopEntry;
+use crate::filemanifest::FileContent;
+use starlark::values::{RuntimeError, Value, ValueError, INCORRECT_PARAMETER_TYPE_ERROR_CODE};
+use starlark::{
+    check_type, starlark_err, starlark_fun, starlark_module, starlark_signature,
+    starlark_signature_extraction, starlark_signatures,
+};
+
+fn str_list(value: &Value) -> Result<Vec<String>, ValueError> {
+    match value.get_type() {
+        "list" => Ok(value.into_iter()?.map(|v| v.to_str()).collect()),
+        _ => Ok(vec![]),
+    }
+}
+
+/// Derive a desktop file ID from an application name.
+fn default_id(name: &str) -> String {
+    name.trim()
+        .to_lowercase()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '.' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
+
+starlark_module! { desktop_module =>
+    desktop_entry(
+        name,
+        exec,
+        icon=None,
+        categories=None,
+        comment=None,
+        generic_name=None,
+        keywords=None,
+        mime_types=None,
+        terminal=false,
+        id=None,
+        prefix="usr"
+    ) {
+        let name = required_str_arg("name", name)?;
+        let exec = required_str_arg("exec", exec)?;
+        let icon = optional_str_arg("icon", icon)?;
+        optional_list_arg("categories", "string", categories)?;
+        let comment = optional_str_arg("comment", comment)?;
+        let generic_name = optional_str_arg("generic_name", generic_name)?;
+        optional_list_arg("keywords", "string", keywords)?;
+        optional_list_arg("mime_types", "string", mime_types)?;
+        check_type!(terminal, "desktop_entry", bool);
+        let id = optional_str_arg("id", id)?;
+        let prefix = required_str_arg("prefix", prefix)?;
+
+        let entry = DesktopEntry {
+            id: id.unwrap_or_else(|| default_id(&name)),
+            name,
+            exec,
+            icon,
+            categories: str_list(categories)?,
+            comment,
+            generic_name,
+            keywords: str_list(keywords)?,
+            mime_types: str_list(mime_types)?,
+            terminal: terminal.to_bool(),
+        };
+
+        entry.validate().map_err(|e| to_value_error("desktop_entry", e))?;
+
+        let prefix = prefix.trim_matches('/');
+        let key = if prefix.is_empty() {
+            format!("share/applications/{}", entry.filename())
+        } else {
+            format!("{}/share/applications/{}", prefix, entry.filename())
+        };
+
+        let mut manifest = FileManifest::default();
+        manifest.files.insert(
+            key,
+            FileContent::Data {
+                data: entry.render().into_bytes(),
+                executable: false,
+            },
+        );
+
+        Ok(Value::new(manifest))
+    }
+}
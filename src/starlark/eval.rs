@@ -2,14 +2,23 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use super::values::{Pipeline, Step};
-use super::EnvironmentContext;
+use super::CONFIG_FILENAME;
+use crate::error::{SourceLocation, TuggerError};
+use crate::observer::ExecutionObserver;
+use crate::pipeline::{
+    execute_pipeline, execute_step_timed, provision_tools, EnvironmentContext, Pipeline,
+};
+use crate::report::{duration_secs, ExecutionReport, PipelineReport, StepReport};
 use codemap::CodeMap;
 use codemap_diagnostic::{Diagnostic, Level};
-use slog::{warn, Logger};
+use slog::{o, warn, Logger};
 use starlark::environment::Environment;
-use std::path::Path;
+use starlark::values::list::List;
+use starlark::values::Value;
+use std::cell::RefCell;
+use std::path::{Component, Path, PathBuf};
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
 /// Represents the result of evaluating an environment.
 pub struct EvalResult {
@@ -19,15 +28,31 @@ pub struct EvalResult {
     pub context: EnvironmentContext,
 
     logger: Logger,
+
+    /// Records pipeline execution.
+    report: RefCell<ExecutionReport>,
+
+    /// Notified of pipeline execution.
+    observer: RefCell<Option<Box<dyn ExecutionObserver>>>,
 }
 
 impl EvalResult {
-    pub fn execute_all_pipelines(&self) -> Result<(), String> {
+    /// Obtain a report of pipelines executed so far.
+    pub fn report(&self) -> ExecutionReport {
+        self.report.borrow().clone()
+    }
+
+    /// Register an observer to be notified as pipelines execute.
+    pub fn set_observer(&mut self, observer: Box<dyn ExecutionObserver>) {
+        self.observer = RefCell::new(Some(observer));
+    }
+
+    pub fn execute_all_pipelines(&self) -> Result<(), TuggerError> {
         let pipelines = self.env.get("PIPELINES").unwrap();
 
         let it = pipelines
             .into_iter()
-            .or_else(|e| Err(format!("could not iterate PIPELINES: {:#?}", e)))?;
+            .map_err(|e| TuggerError::Other(format!("could not iterate PIPELINES: {:#?}", e)))?;
 
         for pv in it {
             let raw_value = pv.0.borrow();
@@ -39,13 +64,30 @@ impl EvalResult {
         Ok(())
     }
 
+    /// Obtain the names of all defined pipelines, in definition order.
+    pub fn pipeline_names(&self) -> Result<Vec<String>, TuggerError> {
+        let pipelines = self.env.get("PIPELINES").unwrap();
+
+        let it = pipelines
+            .into_iter()
+            .map_err(|e| TuggerError::Other(format!("could not iterate PIPELINES: {:#?}", e)))?;
+
+        Ok(it
+            .map(|pv| {
+                let raw_value = pv.0.borrow();
+                let pipeline: &Pipeline = raw_value.as_any().downcast_ref().unwrap();
+                pipeline.name.clone()
+            })
+            .collect())
+    }
+
     /// Execute a defined pipeline.
-    pub fn execute_pipeline(&self, name: &str) -> Result<(), String> {
+    pub fn execute_pipeline(&self, name: &str) -> Result<(), TuggerError> {
         let pipelines = self.env.get("PIPELINES").unwrap();
 
         let it = pipelines
             .into_iter()
-            .or_else(|e| Err(format!("could not iterate PIPELINES: {:#?}", e)))?;
+            .map_err(|e| TuggerError::Other(format!("could not iterate PIPELINES: {:#?}", e)))?;
 
         for pv in it {
             let raw_value = pv.0.borrow();
@@ -56,43 +98,235 @@ impl EvalResult {
             }
         }
 
-        Err(format!("could not find pipeline {}", name))
+        Err(TuggerError::PipelineNotFound(name.to_string()))
     }
 
-    fn execute_raw_pipeline(&self, pipeline: &Pipeline) -> Result<(), String> {
-        warn!(self.logger, "executing pipeline: {}", pipeline.name);
-        for step in &pipeline.steps {
-            match step {
-                Step::DebianDebArchive(deb) => {
-                    crate::debian::execute_deb_archive(
-                        &self.logger,
-                        &pipeline.dist_path,
-                        &deb.control_file.paragraph,
-                        &deb.files.files,
-                    )?;
-                }
-                Step::Snapcraft(snapcraft) => {
-                    crate::snap::execute_snapcraft(
-                        &self.logger,
-                        &snapcraft.args,
-                        &snapcraft.snap.snap,
-                        &snapcraft.build_path,
-                        &snapcraft.manifest.files,
-                        snapcraft.purge_build,
-                    )?;
-                }
-                Step::TarArchive(ta) => {
-                    ta.execute(&self.logger, &pipeline.dist_path)?;
-                }
+    /// Execute a single step of a defined pipeline.
+    ///
+    /// `step` is either the 0-based index of the step within the pipeline or
+    /// the kind of the step (e.g. `tar_archive`). A kind must match exactly
+    /// one step in the pipeline.
+    pub fn execute_pipeline_step(&self, name: &str, step: &str) -> Result<(), TuggerError> {
+        let pipelines = self.env.get("PIPELINES").unwrap();
+
+        let it = pipelines
+            .into_iter()
+            .map_err(|e| TuggerError::Other(format!("could not iterate PIPELINES: {:#?}", e)))?;
+
+        for pv in it {
+            let raw_value = pv.0.borrow();
+            let pipeline: &Pipeline = raw_value.as_any().downcast_ref().unwrap();
+
+            if pipeline.name != name {
+                continue;
             }
+
+            let index = resolve_step(pipeline, step)?;
+
+            // Earlier steps are described by the last execution of the
+            // pipeline, which also produced the artifacts this step consumes.
+            let mut record = crate::runs::read(pipeline).unwrap_or_else(|| PipelineReport {
+                name: pipeline.name.clone(),
+                target: pipeline.target.clone(),
+                dist_dir: pipeline.dist_dir.clone(),
+                duration_secs: 0.0,
+                steps: vec![],
+                error: None,
+            });
+            let earlier: Vec<StepReport> = record
+                .steps
+                .iter()
+                .filter(|s| s.index < index)
+                .cloned()
+                .collect();
+
+            check_step_inputs(pipeline, index, &earlier)?;
+
+            let logger = self.logger.new(o!("pipeline" => pipeline.name.clone()));
+
+            warn!(
+                logger,
+                "executing step {} ({}) of pipeline {}",
+                index,
+                pipeline.steps[index].kind(),
+                pipeline.name
+            );
+
+            let _env = crate::process::EnvironmentOverride::new(&pipeline.env);
+            let _tools = provision_tools(&logger, &self.context, pipeline)?;
+            let mut observer = self.observer.borrow_mut();
+            let mut observer = observer.as_deref_mut();
+
+            if let Some(observer) = observer.as_deref_mut() {
+                observer.on_pipeline_start(pipeline);
+            }
+
+            let start = Instant::now();
+            let progress = PipelineReport {
+                name: pipeline.name.clone(),
+                target: pipeline.target.clone(),
+                dist_dir: pipeline.dist_dir.clone(),
+                duration_secs: 0.0,
+                error: earlier.iter().find_map(|s| s.error.clone()),
+                steps: earlier,
+            };
+            let (step_report, res) = execute_step_timed(
+                &logger,
+                &self.context,
+                pipeline,
+                index,
+                &progress,
+                observer.as_deref_mut(),
+            );
+
+            record.steps.retain(|s| s.index != index);
+            record.steps.push(step_report.clone());
+            record.steps.sort_by_key(|s| s.index);
+            record.error = record.steps.iter().find_map(|s| s.error.clone());
+            if let Err(e) = crate::runs::write(pipeline, &record) {
+                warn!(logger, "unable to record execution: {}", e);
+            }
+
+            let report = PipelineReport {
+                name: pipeline.name.clone(),
+                target: pipeline.target.clone(),
+                dist_dir: pipeline.dist_dir.clone(),
+                duration_secs: duration_secs(start.elapsed()),
+                error: step_report.error.clone(),
+                steps: vec![step_report],
+            };
+
+            if let Some(observer) = observer {
+                observer.on_pipeline_end(pipeline, &report);
+            }
+
+            self.report.borrow_mut().pipelines.push(report);
+
+            return res;
         }
 
-        Ok(())
+        Err(TuggerError::PipelineNotFound(name.to_string()))
+    }
+
+    fn execute_raw_pipeline(&self, pipeline: &Pipeline) -> Result<(), TuggerError> {
+        let (report, res) = execute_pipeline(
+            &self.context,
+            pipeline,
+            self.observer.borrow_mut().as_deref_mut(),
+        );
+        if let Err(e) = crate::runs::write(pipeline, &report) {
+            warn!(
+                self.logger,
+                "unable to record execution of {}: {}", pipeline.name, e
+            );
+        }
+        self.report.borrow_mut().pipelines.push(report);
+
+        res
+    }
+}
+
+/// Ensure the artifacts a step consumes exist before executing it alone.
+///
+/// `earlier` describes the earlier steps of the last execution of the
+/// pipeline, which name the step that produced a missing artifact.
+fn check_step_inputs(
+    pipeline: &Pipeline,
+    index: usize,
+    earlier: &[StepReport],
+) -> Result<(), TuggerError> {
+    let step = &pipeline.steps[index];
+
+    for input in step.inputs() {
+        if pipeline.dist_path.join(input).exists() {
+            continue;
+        }
+
+        let producer = earlier
+            .iter()
+            .find(|s| s.artifacts.iter().any(|a| a == input));
+
+        let reason = match producer {
+            Some(s) => format!(
+                "step {} ({}) produced it in the last run, but it no longer exists",
+                s.index, s.kind
+            ),
+            None => "no earlier step produced it in the last run".to_string(),
+        };
+
+        return Err(TuggerError::Other(format!(
+            "step {} ({}) of pipeline {} needs {}: {}; run the pipeline to produce it",
+            index,
+            step.kind(),
+            pipeline.name,
+            pipeline.dist_path.join(input).display(),
+            reason
+        )));
+    }
+
+    Ok(())
+}
+
+/// Resolve a step index or kind to the index of a step in a pipeline.
+fn resolve_step(pipeline: &Pipeline, step: &str) -> Result<usize, String> {
+    if let Ok(index) = step.parse::<usize>() {
+        return if index < pipeline.steps.len() {
+            Ok(index)
+        } else {
+            Err(format!(
+                "pipeline {} has {} steps; step {} does not exist",
+                pipeline.name,
+                pipeline.steps.len(),
+                index
+            ))
+        };
+    }
+
+    let matches: Vec<usize> = pipeline
+        .steps
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| s.kind() == step)
+        .map(|(i, _)| i)
+        .collect();
+
+    match matches.len() {
+        0 => Err(format!("pipeline {} has no {} step", pipeline.name, step)),
+        1 => Ok(matches[0]),
+        _ => Err(format!(
+            "pipeline {} has multiple {} steps (indices {:?}); specify an index",
+            pipeline.name, step, matches
+        )),
     }
 }
 
 /// Evaluate an app distribution starlark file in the context of a current working directory.
 pub fn evaluate_file(path: &Path, context: &EnvironmentContext) -> Result<EvalResult, Diagnostic> {
+    evaluate_file_with_codemap(&Arc::new(Mutex::new(CodeMap::new())), path, context)
+}
+
+/// Evaluate a starlark file, recording its source in a `CodeMap`.
+///
+/// Spans of a returned `Diagnostic` can be resolved with the `CodeMap`,
+/// e.g. with `diagnostic_location()`.
+pub fn evaluate_file_with_codemap(
+    map: &Arc<Mutex<CodeMap>>,
+    path: &Path,
+    context: &EnvironmentContext,
+) -> Result<EvalResult, Diagnostic> {
+    evaluate_config(map, path, context, &mut vec![])
+}
+
+/// Evaluate a configuration file reached through the workspace members in `ancestors`.
+///
+/// `ancestors` holds the canonical paths of the configuration files
+/// currently being evaluated, so a workspace including itself is detected.
+fn evaluate_config(
+    map: &Arc<Mutex<CodeMap>>,
+    path: &Path,
+    context: &EnvironmentContext,
+    ancestors: &mut Vec<PathBuf>,
+) -> Result<EvalResult, Diagnostic> {
     let mut env = super::global_environment(context).or_else(|_| {
         Err(Diagnostic {
             level: Level::Error,
@@ -102,15 +336,133 @@ pub fn evaluate_file(path: &Path, context: &EnvironmentContext) -> Result<EvalRe
         })
     })?;
 
-    let map = Arc::new(Mutex::new(CodeMap::new()));
-
     warn!(context.logger, "evaluating {}", path.display());
 
-    starlark::eval::simple::eval_file(&map, &path.display().to_string(), false, &mut env)?;
+    starlark::eval::simple::eval_file(map, &path.display().to_string(), false, &mut env)?;
+
+    let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    if ancestors.contains(&canonical) {
+        return Err(Diagnostic {
+            level: Level::Error,
+            message: format!(
+                "workspace cycle: {} is a member of itself",
+                canonical.display()
+            ),
+            code: Some("workspace".to_string()),
+            spans: vec![],
+        });
+    }
+
+    ancestors.push(canonical);
+    let members = evaluate_workspace_members(map, &env, context, ancestors);
+    ancestors.pop();
+    members?;
 
     Ok(EvalResult {
         env,
         context: context.clone(),
         logger: context.logger.clone(),
+        report: RefCell::new(ExecutionReport {
+            channel: context.channel,
+            ..ExecutionReport::default()
+        }),
+        observer: RefCell::new(None),
+    })
+}
+
+/// Obtain where in a file a `Diagnostic` was raised.
+///
+/// `map` is the `CodeMap` the file was evaluated with.
+pub fn diagnostic_location(map: &CodeMap, diagnostic: &Diagnostic) -> Option<SourceLocation> {
+    let span = diagnostic.spans.first()?;
+    let location = map.look_up_span(span.span);
+
+    Some(SourceLocation {
+        path: PathBuf::from(location.file.name()),
+        line: location.begin.line + 1,
+        column: location.begin.column + 1,
     })
 }
+
+/// Evaluate the members of a workspace and add their pipelines to `env`.
+///
+/// Pipelines of members are named `<member>:<pipeline>`.
+fn evaluate_workspace_members(
+    map: &Arc<Mutex<CodeMap>>,
+    env: &Environment,
+    context: &EnvironmentContext,
+    ancestors: &mut Vec<PathBuf>,
+) -> Result<(), Diagnostic> {
+    let to_diagnostic = |message: String| Diagnostic {
+        level: Level::Error,
+        message,
+        code: Some("workspace".to_string()),
+        spans: vec![],
+    };
+
+    let members = env.get("WORKSPACE_MEMBERS").unwrap();
+    let pipelines = env.get("PIPELINES").unwrap();
+
+    for member in members
+        .into_iter()
+        .map_err(|e| to_diagnostic(format!("could not iterate WORKSPACE_MEMBERS: {:#?}", e)))?
+    {
+        let name = member.to_str().trim_end_matches('/').to_string();
+
+        // Members are directories below the workspace root.
+        if !Path::new(&name)
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+        {
+            return Err(to_diagnostic(format!(
+                "workspace member {} is not a relative path without .. components",
+                name
+            )));
+        }
+
+        let path = context.cwd.join(&name).join(CONFIG_FILENAME);
+
+        if !path.is_file() {
+            return Err(to_diagnostic(format!(
+                "workspace member {} does not contain {}",
+                name, CONFIG_FILENAME
+            )));
+        }
+
+        let cwd = path.parent().unwrap().to_path_buf();
+        let member_context = EnvironmentContext {
+            logger: context.logger.new(o!("member" => name.clone())),
+            dist_path: cwd.join("dist"),
+            config_path: Some(path.clone()),
+            cwd,
+            ..context.clone()
+        };
+
+        let member_result = evaluate_config(map, &path, &member_context, ancestors)?;
+
+        for pv in member_result
+            .env
+            .get("PIPELINES")
+            .unwrap()
+            .into_iter()
+            .map_err(|e| to_diagnostic(format!("could not iterate PIPELINES: {:#?}", e)))?
+        {
+            let raw_value = pv.0.borrow();
+            let pipeline: &Pipeline = raw_value.as_any().downcast_ref().unwrap();
+
+            let pipeline = Value::new(Pipeline {
+                name: format!("{}:{}", name, pipeline.name),
+                ..pipeline.clone()
+            });
+
+            List::mutate(&pipelines, &|values: &mut Vec<Value>| {
+                values.push(pipeline.clone());
+
+                Ok(Value::from(None))
+            })
+            .map_err(|e| to_diagnostic(format!("could not add pipeline: {:#?}", e)))?;
+        }
+    }
+
+    Ok(())
+}
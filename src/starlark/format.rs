@@ -0,0 +1,785 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Canonical formatting of Starlark configuration files.
+
+The `starlark` crate's lexer discards comments, so we can't reuse its
+parser for reprinting source. Instead, this module implements a small
+token-based formatter in the spirit of `buildifier`:
+
+* Blocks are indented with 4 spaces.
+* Tokens are spaced canonically (spaces around binary operators, none
+  around `=` in call arguments and parameter lists, etc).
+* Bracketed groups are kept on one line if they fit within
+  `MAX_WIDTH` columns. Otherwise they are exploded to one item per line
+  with a trailing comma. Groups which were split across lines and
+  ended with a trailing comma in the input stay exploded.
+* Call arguments keep their source order. Arguments are evaluated in
+  order, so reordering them could change what a call does.
+* Runs of blank lines are collapsed to a single blank line.
+
+Comments are preserved.
+*/
+
+/// Maximum line width before bracketed groups are split across lines.
+const MAX_WIDTH: usize = 100;
+
+const INDENT: &str = "    ";
+
+const KEYWORDS: &[&str] = &[
+    "and", "break", "continue", "def", "elif", "else", "for", "if", "in", "is", "lambda", "load",
+    "not", "or", "pass", "return", "while",
+];
+
+const OPERATORS: &[&str] = &[
+    "**=", "//=", "==", "!=", "<=", ">=", "+=", "-=", "*=", "/=", "%=", "|=", "&=", "^=", "**",
+    "//", "<<", ">>", "->", "=", "<", ">", "-", "+", "*", "/", "%", ".", "|", "&", "^", "~", ";",
+];
+
+#[derive(Clone, Debug, PartialEq)]
+enum Tok {
+    Word(String),
+    Str(String),
+    Op(String),
+    Open(char),
+    Close(char),
+    Comma,
+    Colon,
+    Comment(String),
+}
+
+impl Tok {
+    fn is_keyword(&self) -> bool {
+        match self {
+            Tok::Word(w) => KEYWORDS.contains(&w.as_str()),
+            _ => false,
+        }
+    }
+
+    fn is_identifier(&self) -> bool {
+        match self {
+            Tok::Word(w) => {
+                !self.is_keyword() && w.chars().next().is_some_and(|c| !c.is_ascii_digit())
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    tok: Tok,
+    line: usize,
+}
+
+#[derive(Debug)]
+enum Line {
+    Blank,
+    Comment {
+        indent: usize,
+        text: String,
+    },
+    Code {
+        indent: usize,
+        tokens: Vec<Token>,
+        comment: Option<String>,
+    },
+}
+
+fn tokenize(source: &str) -> Result<Vec<Line>, String> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut lines = Vec::new();
+    let mut pos = 0;
+    let mut line_no = 1;
+
+    while pos < chars.len() {
+        // We're at the start of a physical line outside any brackets.
+        let mut indent = 0;
+        while pos < chars.len() && (chars[pos] == ' ' || chars[pos] == '\t') {
+            indent += if chars[pos] == '\t' {
+                8 - indent % 8
+            } else {
+                1
+            };
+            pos += 1;
+        }
+
+        if pos >= chars.len() {
+            break;
+        }
+
+        match chars[pos] {
+            '\n' => {
+                lines.push(Line::Blank);
+                pos += 1;
+                line_no += 1;
+                continue;
+            }
+            '\r' => {
+                pos += 1;
+                continue;
+            }
+            '#' => {
+                let start = pos;
+                while pos < chars.len() && chars[pos] != '\n' {
+                    pos += 1;
+                }
+                let text: String = chars[start..pos].iter().collect();
+                lines.push(Line::Comment {
+                    indent,
+                    text: text.trim_end().to_string(),
+                });
+                pos += 1;
+                line_no += 1;
+                continue;
+            }
+            _ => {}
+        }
+
+        let mut tokens = Vec::new();
+        let mut comment = None;
+        let mut depth: usize = 0;
+
+        while pos < chars.len() {
+            let c = chars[pos];
+
+            if c == '\n' {
+                line_no += 1;
+                pos += 1;
+                if depth == 0 {
+                    break;
+                }
+                continue;
+            }
+
+            if c == ' ' || c == '\t' || c == '\r' {
+                pos += 1;
+                continue;
+            }
+
+            if c == '\\' && pos + 1 < chars.len() && chars[pos + 1] == '\n' {
+                pos += 2;
+                line_no += 1;
+                continue;
+            }
+
+            if c == '#' {
+                let start = pos;
+                while pos < chars.len() && chars[pos] != '\n' {
+                    pos += 1;
+                }
+                let text: String = chars[start..pos].iter().collect();
+                let text = text.trim_end().to_string();
+                if depth == 0 {
+                    comment = Some(text);
+                } else {
+                    tokens.push(Token {
+                        tok: Tok::Comment(text),
+                        line: line_no,
+                    });
+                }
+                continue;
+            }
+
+            let token_line = line_no;
+
+            let tok = if c == '"' || c == '\'' || is_string_prefix(&chars, pos) {
+                let start = pos;
+                while chars[pos] != '"' && chars[pos] != '\'' {
+                    pos += 1;
+                }
+                let quote = chars[pos];
+                let triple =
+                    pos + 2 < chars.len() && chars[pos + 1] == quote && chars[pos + 2] == quote;
+                pos += if triple { 3 } else { 1 };
+
+                loop {
+                    if pos >= chars.len() {
+                        return Err(format!("unterminated string on line {}", token_line));
+                    }
+                    let c = chars[pos];
+                    if c == '\\' {
+                        if pos + 1 < chars.len() && chars[pos + 1] == '\n' {
+                            line_no += 1;
+                        }
+                        pos += 2;
+                        continue;
+                    }
+                    if c == '\n' {
+                        if !triple {
+                            return Err(format!("unterminated string on line {}", token_line));
+                        }
+                        line_no += 1;
+                    }
+                    if c == quote {
+                        if !triple {
+                            pos += 1;
+                            break;
+                        }
+                        if pos + 2 < chars.len()
+                            && chars[pos + 1] == quote
+                            && chars[pos + 2] == quote
+                        {
+                            pos += 3;
+                            break;
+                        }
+                    }
+                    pos += 1;
+                }
+
+                Tok::Str(chars[start..pos].iter().collect())
+            } else if c.is_ascii_alphanumeric() || c == '_' {
+                let start = pos;
+                let numeric = c.is_ascii_digit();
+                while pos < chars.len()
+                    && (chars[pos].is_ascii_alphanumeric()
+                        || chars[pos] == '_'
+                        || (numeric && chars[pos] == '.'))
+                {
+                    pos += 1;
+                }
+                Tok::Word(chars[start..pos].iter().collect())
+            } else if c == '(' || c == '[' || c == '{' {
+                depth += 1;
+                pos += 1;
+                Tok::Open(c)
+            } else if c == ')' || c == ']' || c == '}' {
+                if depth == 0 {
+                    return Err(format!("unbalanced '{}' on line {}", c, token_line));
+                }
+                depth -= 1;
+                pos += 1;
+                Tok::Close(c)
+            } else if c == ',' {
+                pos += 1;
+                Tok::Comma
+            } else if c == ':' {
+                pos += 1;
+                Tok::Colon
+            } else if let Some(op) = OPERATORS.iter().find(|op| {
+                op.chars()
+                    .enumerate()
+                    .all(|(i, oc)| chars.get(pos + i) == Some(&oc))
+            }) {
+                pos += op.len();
+                Tok::Op(op.to_string())
+            } else {
+                return Err(format!(
+                    "unexpected character '{}' on line {}",
+                    c, token_line
+                ));
+            };
+
+            tokens.push(Token {
+                tok,
+                line: token_line,
+            });
+        }
+
+        if depth != 0 {
+            return Err("unexpected end of file inside brackets".to_string());
+        }
+
+        if !tokens.is_empty() {
+            lines.push(Line::Code {
+                indent,
+                tokens,
+                comment,
+            });
+        } else if let Some(text) = comment {
+            lines.push(Line::Comment { indent, text });
+        }
+    }
+
+    Ok(lines)
+}
+
+fn is_string_prefix(chars: &[char], pos: usize) -> bool {
+    let is_prefix_char = |c: char| c == 'r' || c == 'R' || c == 'b' || c == 'B';
+
+    if pos > 0 && (chars[pos - 1].is_ascii_alphanumeric() || chars[pos - 1] == '_') {
+        return false;
+    }
+
+    let mut i = pos;
+    while i < chars.len() && i < pos + 2 && is_prefix_char(chars[i]) {
+        i += 1;
+    }
+
+    i > pos && i < chars.len() && (chars[i] == '"' || chars[i] == '\'')
+}
+
+#[derive(Debug)]
+enum Node {
+    Tok(Tok),
+    Group(Group),
+}
+
+#[derive(Debug)]
+struct Group {
+    open: char,
+    close: char,
+    items: Vec<Item>,
+    /// Comments between the last item and the closing bracket.
+    trailing_comments: Vec<String>,
+    force_break: bool,
+    tuple_comma: bool,
+}
+
+#[derive(Debug, Default)]
+struct Item {
+    comments: Vec<String>,
+    nodes: Vec<Node>,
+    trailing_comment: Option<String>,
+}
+
+/// Parse tokens into nodes until reaching the end of the current group item.
+///
+/// Comments appearing in the middle of an item are collected into `comments`.
+fn parse_nodes(
+    tokens: &[Token],
+    pos: &mut usize,
+    close: Option<char>,
+    comments: &mut Vec<String>,
+) -> Vec<Node> {
+    let mut nodes: Vec<Node> = Vec::new();
+
+    while *pos < tokens.len() {
+        let token = &tokens[*pos];
+        match &token.tok {
+            Tok::Close(c) if Some(*c) == close => break,
+            Tok::Comma if close.is_some() => break,
+            Tok::Comment(text) => comments.push(text.clone()),
+            Tok::Open(c) => {
+                let is_call = *c == '('
+                    && match nodes.last() {
+                        Some(Node::Tok(t)) if t.is_identifier() => {
+                            let before = if nodes.len() >= 2 {
+                                nodes.get(nodes.len() - 2)
+                            } else {
+                                None
+                            };
+                            !matches!(before, Some(Node::Tok(Tok::Word(w))) if w == "def")
+                        }
+                        Some(Node::Group(_)) => true,
+                        _ => false,
+                    };
+                let open_line = token.line;
+                *pos += 1;
+                nodes.push(Node::Group(parse_group(
+                    tokens, pos, *c, open_line, is_call,
+                )));
+                continue;
+            }
+            tok => nodes.push(Node::Tok(tok.clone())),
+        }
+        *pos += 1;
+    }
+
+    nodes
+}
+
+fn parse_group(
+    tokens: &[Token],
+    pos: &mut usize,
+    open: char,
+    open_line: usize,
+    is_call: bool,
+) -> Group {
+    let close = match open {
+        '(' => ')',
+        '[' => ']',
+        _ => '}',
+    };
+
+    let mut group = Group {
+        open,
+        close,
+        items: Vec::new(),
+        trailing_comments: Vec::new(),
+        force_break: false,
+        tuple_comma: false,
+    };
+
+    let mut pending_comments = Vec::new();
+    let mut trailing_comma = false;
+    let mut last_line = open_line;
+
+    while *pos < tokens.len() {
+        let token = &tokens[*pos];
+        match &token.tok {
+            Tok::Close(_) => {
+                if token.line != open_line && trailing_comma {
+                    group.force_break = true;
+                }
+                *pos += 1;
+                break;
+            }
+            Tok::Comma => {
+                trailing_comma = true;
+                last_line = token.line;
+                *pos += 1;
+            }
+            Tok::Comment(text) => {
+                group.force_break = true;
+                if token.line == last_line && !group.items.is_empty() {
+                    group.items.last_mut().unwrap().trailing_comment = Some(text.clone());
+                } else {
+                    pending_comments.push(text.clone());
+                }
+                *pos += 1;
+            }
+            _ => {
+                let mut comments = std::mem::take(&mut pending_comments);
+                let nodes = parse_nodes(tokens, pos, Some(close), &mut comments);
+                last_line = tokens[*pos - 1].line;
+                trailing_comma = false;
+                if !comments.is_empty() {
+                    group.force_break = true;
+                }
+                group.items.push(Item {
+                    comments,
+                    nodes,
+                    trailing_comment: None,
+                });
+            }
+        }
+    }
+
+    group.trailing_comments = pending_comments;
+    group.tuple_comma = open == '(' && !is_call && group.items.len() == 1 && trailing_comma;
+
+    group
+}
+
+/// Classification of the previously emitted token, used for spacing decisions.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Last {
+    Start,
+    Word,
+    Keyword,
+    Load,
+    Str,
+    BinaryOp,
+    UnaryOp,
+    KwargEquals,
+    Dot,
+    Open,
+    Close,
+    Comma,
+    Colon,
+}
+
+struct Renderer {
+    out: String,
+    last: Last,
+}
+
+impl Renderer {
+    fn column(&self) -> usize {
+        match self.out.rfind('\n') {
+            Some(pos) => self.out.len() - pos - 1,
+            None => self.out.len(),
+        }
+    }
+
+    fn space_before(&mut self, tok: &Tok, context: Option<char>) -> bool {
+        match (self.last, tok) {
+            (Last::Start, _) | (Last::Open, _) | (Last::UnaryOp, _) | (Last::Dot, _) => false,
+            (Last::KwargEquals, _) => false,
+            (_, Tok::Comma) | (_, Tok::Colon) | (_, Tok::Close(_)) => false,
+            (_, Tok::Op(op)) if op == "." => false,
+            (_, Tok::Op(op)) if op == "=" && context == Some('(') => false,
+            (Last::Load, Tok::Open(_)) => false,
+            (Last::Word, Tok::Open(_))
+            | (Last::Str, Tok::Open(_))
+            | (Last::Close, Tok::Open(_)) => false,
+            (Last::Colon, _) => context != Some('['),
+            _ => true,
+        }
+    }
+
+    fn push_tok(&mut self, tok: &Tok, context: Option<char>) {
+        if self.space_before(tok, context) {
+            self.out.push(' ');
+        }
+
+        let unary_position = matches!(
+            self.last,
+            Last::Start
+                | Last::Open
+                | Last::Comma
+                | Last::BinaryOp
+                | Last::UnaryOp
+                | Last::KwargEquals
+                | Last::Keyword
+                | Last::Colon
+        );
+
+        self.last = match tok {
+            Tok::Word(w) if w == "load" => Last::Load,
+            Tok::Word(_) if tok.is_keyword() => Last::Keyword,
+            Tok::Word(_) => Last::Word,
+            Tok::Str(_) => Last::Str,
+            Tok::Op(op) if op == "." => Last::Dot,
+            Tok::Op(op) if op == "=" && context == Some('(') => Last::KwargEquals,
+            Tok::Op(op) if unary_position && ["-", "+", "~", "*", "**"].contains(&op.as_str()) => {
+                Last::UnaryOp
+            }
+            Tok::Op(_) => Last::BinaryOp,
+            Tok::Open(_) => Last::Open,
+            Tok::Close(_) => Last::Close,
+            Tok::Comma => Last::Comma,
+            Tok::Colon => Last::Colon,
+            Tok::Comment(_) => Last::Start,
+        };
+
+        match tok {
+            Tok::Word(s) | Tok::Str(s) | Tok::Op(s) | Tok::Comment(s) => self.out.push_str(s),
+            Tok::Open(c) | Tok::Close(c) => self.out.push(*c),
+            Tok::Comma => self.out.push(','),
+            Tok::Colon => self.out.push(':'),
+        }
+    }
+
+    fn render_nodes(&mut self, nodes: &[Node], level: usize, context: Option<char>, suffix: usize) {
+        for (i, node) in nodes.iter().enumerate() {
+            match node {
+                Node::Tok(tok) => self.push_tok(tok, context),
+                Node::Group(group) => {
+                    let rest = flat_nodes(&nodes[i + 1..], context).map_or(0, |s| s.len());
+                    self.render_group(group, level, context, rest + suffix);
+                }
+            }
+        }
+    }
+
+    fn render_group(&mut self, group: &Group, level: usize, context: Option<char>, suffix: usize) {
+        self.push_tok(&Tok::Open(group.open), context);
+
+        if group.items.is_empty() && group.trailing_comments.is_empty() {
+            self.push_tok(&Tok::Close(group.close), context);
+            return;
+        }
+
+        if !group.force_break {
+            if let Some(flat) = flat_group_body(group) {
+                if self.column() + flat.len() + 1 + suffix <= MAX_WIDTH {
+                    self.out.push_str(&flat);
+                    self.last = Last::Open;
+                    self.push_tok(&Tok::Close(group.close), context);
+                    return;
+                }
+            }
+        }
+
+        let inner = INDENT.repeat(level + 1);
+
+        for item in &group.items {
+            for comment in &item.comments {
+                self.out.push('\n');
+                self.out.push_str(&inner);
+                self.out.push_str(comment);
+            }
+
+            self.out.push('\n');
+            self.out.push_str(&inner);
+            self.last = Last::Start;
+            self.render_nodes(&item.nodes, level + 1, Some(group.open), 1);
+            self.out.push(',');
+
+            if let Some(comment) = &item.trailing_comment {
+                self.out.push_str("  ");
+                self.out.push_str(comment);
+            }
+        }
+
+        for comment in &group.trailing_comments {
+            self.out.push('\n');
+            self.out.push_str(&inner);
+            self.out.push_str(comment);
+        }
+
+        self.out.push('\n');
+        self.out.push_str(&INDENT.repeat(level));
+        self.out.push(group.close);
+        self.last = Last::Close;
+    }
+}
+
+fn flat_group_body(group: &Group) -> Option<String> {
+    if !group.trailing_comments.is_empty() {
+        return None;
+    }
+
+    let mut parts = Vec::new();
+    for item in &group.items {
+        if !item.comments.is_empty() || item.trailing_comment.is_some() {
+            return None;
+        }
+        parts.push(flat_nodes(&item.nodes, Some(group.open))?);
+    }
+
+    let mut body = parts.join(", ");
+    if group.tuple_comma {
+        body.push(',');
+    }
+
+    Some(body)
+}
+
+fn flat_nodes(nodes: &[Node], context: Option<char>) -> Option<String> {
+    let mut renderer = Renderer {
+        out: String::new(),
+        last: Last::Start,
+    };
+
+    for node in nodes {
+        match node {
+            Node::Tok(tok) => renderer.push_tok(tok, context),
+            Node::Group(group) => {
+                let body = flat_group_body(group)?;
+                renderer.push_tok(&Tok::Open(group.open), context);
+                renderer.out.push_str(&body);
+                renderer.last = Last::Open;
+                renderer.push_tok(&Tok::Close(group.close), context);
+            }
+        }
+    }
+
+    Some(renderer.out)
+}
+
+/// Format Starlark source code canonically.
+pub fn format_source(source: &str) -> Result<String, String> {
+    let lines = tokenize(source)?;
+
+    let mut out = String::new();
+    let mut indent_stack: Vec<usize> = vec![0];
+    let mut pending_blank = false;
+
+    let level_for = |stack: &Vec<usize>, indent: usize| -> usize {
+        stack.iter().filter(|&&i| i <= indent).count().max(1) - 1
+    };
+
+    for line in &lines {
+        match line {
+            Line::Blank => {
+                pending_blank = !out.is_empty();
+            }
+            Line::Comment { indent, text } => {
+                if pending_blank {
+                    out.push('\n');
+                    pending_blank = false;
+                }
+                let level = level_for(&indent_stack, *indent);
+                out.push_str(&INDENT.repeat(level));
+                out.push_str(text);
+                out.push('\n');
+            }
+            Line::Code {
+                indent,
+                tokens,
+                comment,
+            } => {
+                if pending_blank {
+                    out.push('\n');
+                    pending_blank = false;
+                }
+
+                if *indent > *indent_stack.last().unwrap() {
+                    indent_stack.push(*indent);
+                } else {
+                    while *indent < *indent_stack.last().unwrap() {
+                        indent_stack.pop();
+                    }
+                    if *indent != *indent_stack.last().unwrap() {
+                        return Err(format!(
+                            "inconsistent indentation on line {}",
+                            tokens[0].line
+                        ));
+                    }
+                }
+
+                let level = indent_stack.len() - 1;
+                let mut pos = 0;
+                let nodes = parse_nodes(tokens, &mut pos, None, &mut Vec::new());
+
+                let mut renderer = Renderer {
+                    out: INDENT.repeat(level),
+                    last: Last::Start,
+                };
+                renderer.render_nodes(&nodes, level, None, 0);
+
+                out.push_str(&renderer.out);
+                if let Some(comment) = comment {
+                    out.push_str("  ");
+                    out.push_str(comment);
+                }
+                out.push('\n');
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    /// Format each `<name>.in.ship` file in `testdata/format` and compare
+    /// the result with `<name>.out.ship`.
+    #[test]
+    fn test_golden_files() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("src")
+            .join("starlark")
+            .join("testdata")
+            .join("format");
+
+        let mut count = 0;
+
+        for entry in std::fs::read_dir(&dir).unwrap() {
+            let path = entry.unwrap().path();
+            let name = path.file_name().unwrap().to_string_lossy().to_string();
+            let stem = match name.strip_suffix(".in.ship") {
+                Some(stem) => stem,
+                None => continue,
+            };
+
+            let input = std::fs::read_to_string(&path).unwrap();
+            let expected = std::fs::read_to_string(dir.join(format!("{}.out.ship", stem))).unwrap();
+
+            assert_eq!(format_source(&input).unwrap(), expected, "{}", name);
+            assert_eq!(
+                format_source(&expected).unwrap(),
+                expected,
+                "{} isn't stable",
+                stem
+            );
+            count += 1;
+        }
+
+        assert!(count > 0, "no golden files in {}", dir.display());
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            format_source("x = 'abc\n").unwrap_err(),
+            "unterminated string on line 1"
+        );
+        assert_eq!(
+            format_source("x = 1)\n").unwrap_err(),
+            "unbalanced ')' on line 1"
+        );
+        assert_eq!(
+            format_source("if x:\n        y\n    z\n").unwrap_err(),
+            "inconsistent indentation on line 3"
+        );
+    }
+}
@@ -0,0 +1,99 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::values::FileManifest;
+use super::{optional_list_arg, required_str_arg};
+use crate::filemanifest::FileContent;
+use crate::icons::{is_svg, png_path, render_png, svg_path, DEFAULT_SIZES};
+use starlark::values::{RuntimeError, Value, ValueError, INCORRECT_PARAMETER_TYPE_ERROR_CODE};
+use starlark::{
+    check_type, starlark_err, starlark_fun, starlark_module, starlark_signature,
+    starlark_signature_extraction, starlark_signatures,
+};
+use std::path::PathBuf;
+
+starlark_module! { icons_module =>
+    icons(env env, source, name, sizes=None, appimage=false) {
+        let source = required_str_arg("source", source)?;
+        let name = required_str_arg("name", name)?;
+        optional_list_arg("sizes", "int", sizes)?;
+        check_type!(appimage, "icons", bool);
+
+        let to_value_error = |message: String| {
+            ValueError::Runtime(RuntimeError {
+                code: "icons",
+                message,
+                label: "icons()".to_string(),
+            })
+        };
+
+        if name.is_empty() || name.contains('/') {
+            return Err(to_value_error(format!("invalid icon name: {}", name)));
+        }
+
+        let mut sizes: Vec<u32> = match sizes.get_type() {
+            "list" => {
+                let mut res = Vec::new();
+                for size in sizes.into_iter()? {
+                    let size = size.to_int()?;
+                    if !(1..=1024).contains(&size) {
+                        return Err(to_value_error(format!(
+                            "icon sizes must be between 1 and 1024; got {}",
+                            size
+                        )));
+                    }
+                    res.push(size as u32);
+                }
+                res
+            }
+            _ => DEFAULT_SIZES.to_vec(),
+        };
+        sizes.sort_unstable();
+        sizes.dedup();
+
+        if sizes.is_empty() {
+            return Err(to_value_error("at least one icon size is required".to_string()));
+        }
+
+        let source = PathBuf::from(env.get("CWD").unwrap().to_str()).join(source);
+        if !source.is_file() {
+            return Err(to_value_error(format!(
+                "icon {} does not exist",
+                source.display()
+            )));
+        }
+
+        let mut manifest = FileManifest::default();
+
+        for size in &sizes {
+            manifest.files.insert(
+                png_path(&name, *size),
+                FileContent::Data {
+                    data: render_png(&source, *size).map_err(to_value_error)?,
+                    executable: false,
+                },
+            );
+        }
+
+        if is_svg(&source) {
+            manifest
+                .files
+                .insert(svg_path(&name), FileContent::Path(source.clone()));
+        }
+
+        // AppImages use the icon at the root of the AppDir, and .DirIcon
+        // for file managers.
+        if appimage.to_bool() {
+            let largest = png_path(&name, *sizes.last().unwrap());
+            let content = manifest.files[&largest].clone();
+            manifest.files.insert(format!("{}.png", name), content);
+            manifest.files.insert(
+                ".DirIcon".to_string(),
+                FileContent::Symlink(PathBuf::from(format!("{}.png", name))),
+            );
+        }
+
+        Ok(Value::new(manifest))
+    }
+}
@@ -0,0 +1,60 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::values::FileManifest;
+use super::{optional_str_arg, parse_str_arg, required_str_arg, to_value_error};
+use crate::make::BuildSystem;
+use starlark::starlark_module;
+use starlark::values::Value;
+use starlark::{
+    starlark_fun, starlark_signature, starlark_signature_extraction, starlark_signatures,
+};
+use std::path::PathBuf;
+
+starlark_module! { make_module =>
+    make_install(env env, dir, destdir=None, build_system=None, prefix=None) {
+        let dir = required_str_arg("dir", dir)?;
+        let destdir = optional_str_arg("destdir", destdir)?;
+        let build_system: Option<BuildSystem> =
+            match optional_str_arg("build_system", build_system)? {
+                Some(value) => Some(parse_str_arg("build_system", &value)?),
+                None => None,
+            };
+        let prefix = optional_str_arg("prefix", prefix)?;
+
+        let cwd = PathBuf::from(env.get("CWD").unwrap().to_str());
+        let dir = cwd.join(dir);
+        let build_system = build_system.unwrap_or_else(|| BuildSystem::detect(&dir));
+
+        let destdir = match destdir {
+            Some(destdir) => {
+                let destdir = cwd.join(destdir);
+
+                // Files left over from previous installs must not end up
+                // in the manifest.
+                if destdir.exists() {
+                    std::fs::remove_dir_all(&destdir)
+                        .map_err(|e| format!("unable to remove {}: {}", destdir.display(), e))
+                        .map_err(|e| to_value_error("make_install", e))?;
+                }
+
+                destdir
+            }
+            None => tempfile::Builder::new()
+                .prefix("tugger-destdir-")
+                .tempdir()
+                .map_err(|e| format!("unable to create staging directory: {}", e))
+                .map_err(|e| to_value_error("make_install", e))?
+                .into_path(),
+        };
+
+        crate::make::make_install(&dir, build_system, &destdir, prefix.as_deref())
+            .map_err(|e| to_value_error("make_install", e))?;
+
+        Ok(Value::new(FileManifest {
+            files: crate::filemanifest::manifest_from_dir(&destdir, &[], None)
+                .map_err(|e| to_value_error("make_install", e))?,
+        }))
+    }
+}
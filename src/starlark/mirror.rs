@@ -0,0 +1,275 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::{
+    concurrency_arg, expand_channel, invalid_arg, optional_concurrency_arg, optional_retry_arg,
+    optional_secret_arg, optional_size_arg, optional_str_arg, parse_str_arg, required_list_arg,
+    required_secret_arg, required_str_arg,
+};
+use crate::deploy::{HostKeyPolicy, SshOptions};
+use crate::mirror::{HttpProtocol, Mirror, MirrorDestination};
+use starlark::environment::Environment;
+use starlark::starlark_module;
+use starlark::values::{default_compare, TypedValue, Value, ValueError, ValueResult};
+use starlark::{
+    any, immutable, not_supported, starlark_fun, starlark_signature, starlark_signature_extraction,
+    starlark_signatures,
+};
+use std::any::Any;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+impl TypedValue for MirrorDestination {
+    immutable!();
+    any!();
+    not_supported!(binop);
+    not_supported!(container);
+    not_supported!(function);
+    not_supported!(get_hash);
+    not_supported!(to_int);
+
+    fn to_str(&self) -> String {
+        format!("MirrorDestination<{}>", self.describe())
+    }
+
+    fn to_repr(&self) -> String {
+        self.to_str()
+    }
+
+    fn get_type(&self) -> &'static str {
+        "MirrorDestination"
+    }
+
+    fn to_bool(&self) -> bool {
+        true
+    }
+
+    fn compare(&self, other: &dyn TypedValue, _recursion: u32) -> Result<Ordering, ValueError> {
+        default_compare(self, other)
+    }
+}
+
+impl TypedValue for Mirror {
+    immutable!();
+    any!();
+    not_supported!(binop);
+    not_supported!(container);
+    not_supported!(function);
+    not_supported!(get_hash);
+    not_supported!(to_int);
+
+    fn to_str(&self) -> String {
+        format!(
+            "Mirror<artifacts={:?}, destinations={:?}>",
+            self.artifacts,
+            self.destinations
+                .iter()
+                .map(|d| d.describe())
+                .collect::<Vec<_>>()
+        )
+    }
+
+    fn to_repr(&self) -> String {
+        self.to_str()
+    }
+
+    fn get_type(&self) -> &'static str {
+        "Mirror"
+    }
+
+    fn to_bool(&self) -> bool {
+        true
+    }
+
+    fn compare(&self, other: &dyn TypedValue, _recursion: u32) -> Result<Ordering, ValueError> {
+        default_compare(self, other)
+    }
+}
+
+starlark_module! { mirror_module =>
+    gcs(
+        env env,
+        url,
+        part_size=None,
+        concurrency=None,
+        api_url=crate::resumable::GCS_API_URL
+    ) {
+        let url = expand_channel(&env, &required_str_arg("url", &url)?);
+        let part_size = optional_size_arg("part_size", &part_size)?
+            .unwrap_or(crate::resumable::DEFAULT_CHUNK_SIZE);
+        let concurrency = optional_concurrency_arg(&concurrency)?;
+        let api_url = required_str_arg("api_url", &api_url)?;
+
+        if !url.starts_with("gs://") {
+            return Err(invalid_arg("url", format!("{} is not a gs:// URL", url)));
+        }
+        if part_size < crate::resumable::GCS_CHUNK_ALIGNMENT {
+            return Err(invalid_arg(
+                "part_size",
+                format!("part_size {} is smaller than the minimum of 256KiB", part_size),
+            ));
+        }
+
+        Ok(Value::new(MirrorDestination::Gcs {
+            url: url.trim_end_matches('/').to_string(),
+            part_size,
+            api_url: api_url.trim_end_matches('/').to_string(),
+            concurrency,
+        }))
+    }
+
+    github_release(
+        env env,
+        repo,
+        tag,
+        token_env="GITHUB_TOKEN",
+        url=crate::github::GITHUB_API_URL,
+        concurrency=None
+    ) {
+        let repo = required_str_arg("repo", &repo)?;
+        let tag = expand_channel(&env, &required_str_arg("tag", &tag)?);
+        let token = required_secret_arg("token_env", &token_env)?;
+        let url = required_str_arg("url", &url)?;
+        let concurrency = optional_concurrency_arg(&concurrency)?;
+
+        if repo.split('/').count() != 2 {
+            return Err(invalid_arg(
+                "repo",
+                format!("repo {} is not of the form owner/repo", repo),
+            ));
+        }
+
+        Ok(Value::new(MirrorDestination::GithubRelease {
+            repo,
+            tag,
+            token,
+            url,
+            concurrency,
+        }))
+    }
+
+    http(
+        env env,
+        url,
+        protocol="put",
+        token_env=None,
+        part_size=None,
+        concurrency=None
+    ) {
+        let url = expand_channel(&env, &required_str_arg("url", &url)?);
+        let protocol = parse_str_arg("protocol", &required_str_arg("protocol", &protocol)?)?;
+        let token = optional_secret_arg("token_env", &token_env)?;
+        let part_size = optional_size_arg("part_size", &part_size)?
+            .unwrap_or(crate::resumable::DEFAULT_CHUNK_SIZE);
+        let concurrency = optional_concurrency_arg(&concurrency)?;
+
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            return Err(invalid_arg("url", format!("{} is not an HTTP URL", url)));
+        }
+
+        // tus servers may distinguish their endpoint from it with a slash.
+        let url = match protocol {
+            HttpProtocol::Put => url.trim_end_matches('/').to_string(),
+            HttpProtocol::Tus => url,
+        };
+
+        Ok(Value::new(MirrorDestination::Http {
+            url,
+            protocol,
+            part_size,
+            token,
+            concurrency,
+        }))
+    }
+
+    mirror(env env, artifacts, destinations, require="all", concurrency=4, retry=None) {
+        required_list_arg("artifacts", "string", &artifacts)?;
+        required_list_arg("destinations", "MirrorDestination", &destinations)?;
+        let require = parse_str_arg("require", &required_str_arg("require", &require)?)?;
+        let concurrency = concurrency_arg(&concurrency)?;
+        let retry = optional_retry_arg("retry", &retry)?;
+
+        let artifacts = artifacts
+            .into_iter()?
+            .map(|a| expand_channel(&env, &a.to_str()))
+            .collect();
+        let destinations: Vec<MirrorDestination> = destinations
+            .into_iter()?
+            .map(|d| {
+                d.0.borrow()
+                    .as_any()
+                    .downcast_ref::<MirrorDestination>()
+                    .unwrap()
+                    .clone()
+            })
+            .collect();
+
+        if destinations.is_empty() {
+            return Err(invalid_arg(
+                "destinations",
+                "mirror() requires at least one destination".to_string(),
+            ));
+        }
+
+        Ok(Value::new(Mirror {
+            artifacts,
+            destinations,
+            require,
+            concurrency,
+            retry,
+        }))
+    }
+
+    s3(env env, url, part_size=None, concurrency=None) {
+        let url = expand_channel(&env, &required_str_arg("url", &url)?);
+        let part_size = optional_size_arg("part_size", &part_size)?
+            .unwrap_or(crate::multipart::DEFAULT_PART_SIZE);
+        let concurrency = optional_concurrency_arg(&concurrency)?;
+
+        if !url.starts_with("s3://") {
+            return Err(invalid_arg("url", format!("{} is not an s3:// URL", url)));
+        }
+        if part_size < crate::multipart::MIN_PART_SIZE {
+            return Err(invalid_arg(
+                "part_size",
+                format!("part_size {} is smaller than the minimum of 5MiB", part_size),
+            ));
+        }
+
+        Ok(Value::new(MirrorDestination::S3 {
+            url: url.trim_end_matches('/').to_string(),
+            part_size,
+            concurrency,
+        }))
+    }
+
+    sftp(
+        env env,
+        host,
+        dest,
+        host_key_policy="strict",
+        known_hosts_file=None,
+        concurrency=None
+    ) {
+        let host = required_str_arg("host", host)?;
+        let dest = expand_channel(&env, &required_str_arg("dest", dest)?);
+        let host_key_policy: HostKeyPolicy =
+            parse_str_arg("host_key_policy", &required_str_arg("host_key_policy", host_key_policy)?)?;
+        let known_hosts_file = optional_str_arg("known_hosts_file", known_hosts_file)?;
+        let concurrency = optional_concurrency_arg(concurrency)?;
+
+        let cwd = PathBuf::from(env.get("CWD").unwrap().to_str());
+
+        Ok(Value::new(MirrorDestination::Sftp {
+            host,
+            dest,
+            ssh: SshOptions {
+                host_key_policy,
+                known_hosts_file: known_hosts_file.map(|p| cwd.join(p)),
+            },
+            concurrency,
+        }))
+    }
+}
@@ -0,0 +1,83 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::{invalid_arg, required_type_arg};
+use crate::mode_policy::ModePolicy;
+use starlark::environment::Environment;
+use starlark::starlark_module;
+use starlark::values::{default_compare, TypedValue, Value, ValueError, ValueResult};
+use starlark::{
+    any, immutable, not_supported, starlark_fun, starlark_signature, starlark_signature_extraction,
+    starlark_signatures,
+};
+use std::any::Any;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+impl TypedValue for ModePolicy {
+    immutable!();
+    any!();
+    not_supported!(binop);
+    not_supported!(container);
+    not_supported!(function);
+    not_supported!(get_hash);
+    not_supported!(to_int);
+
+    fn to_str(&self) -> String {
+        format!(
+            "ModePolicy<files={:o}, executables={:o}, dirs={:o}, umask={:?}>",
+            self.files,
+            self.executables,
+            self.dirs,
+            self.umask.map(|umask| format!("{:o}", umask))
+        )
+    }
+
+    fn to_repr(&self) -> String {
+        self.to_str()
+    }
+
+    fn get_type(&self) -> &'static str {
+        "ModePolicy"
+    }
+
+    fn to_bool(&self) -> bool {
+        true
+    }
+
+    fn compare(&self, other: &dyn TypedValue, _recursion: u32) -> Result<Ordering, ValueError> {
+        default_compare(self, other)
+    }
+}
+
+/// Obtain a mode from an `int` argument.
+fn mode_arg(name: &str, value: &Value) -> Result<u32, ValueError> {
+    required_type_arg(name, "int", value)?;
+
+    let mode = value.to_int()?;
+    if mode < 0 || mode > i64::from(u32::MAX) {
+        return Err(invalid_arg(name, format!("invalid {} {}", name, mode)));
+    }
+
+    Ok(mode as u32)
+}
+
+starlark_module! { mode_policy_module =>
+    mode_policy(files=0o644, executables=0o755, dirs=0o755, umask=None) {
+        let umask = match umask.get_type() {
+            "NoneType" => None,
+            _ => Some(mode_arg("umask", umask)?),
+        };
+
+        let policy = ModePolicy {
+            files: mode_arg("files", files)?,
+            executables: mode_arg("executables", executables)?,
+            dirs: mode_arg("dirs", dirs)?,
+            umask,
+        };
+        policy.validate().map_err(|e| invalid_arg("mode_policy", e))?;
+
+        Ok(Value::new(policy))
+    }
+}
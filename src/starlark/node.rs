@@ -0,0 +1,45 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::values::FileManifest;
+use super::{optional_str_arg, parse_str_arg, required_str_arg, to_value_error};
+use crate::node::PackageManager;
+use starlark::starlark_module;
+use starlark::values::Value;
+use starlark::{
+    starlark_fun, starlark_signature, starlark_signature_extraction, starlark_signatures,
+};
+use std::path::PathBuf;
+
+starlark_module! { node_module =>
+    node_build(
+        env env,
+        dir,
+        command="build",
+        output_dir="dist",
+        package_manager=None,
+        prefix=None
+    ) {
+        let dir = required_str_arg("dir", dir)?;
+        let command = required_str_arg("command", command)?;
+        let output_dir = required_str_arg("output_dir", output_dir)?;
+        let package_manager: Option<PackageManager> =
+            match optional_str_arg("package_manager", package_manager)? {
+                Some(value) => Some(parse_str_arg("package_manager", &value)?),
+                None => None,
+            };
+        let prefix = optional_str_arg("prefix", prefix)?;
+
+        let dir = PathBuf::from(env.get("CWD").unwrap().to_str()).join(dir);
+        let package_manager = package_manager.unwrap_or_else(|| PackageManager::detect(&dir));
+
+        let output_path = crate::node::node_build(&dir, package_manager, &command, &output_dir)
+            .map_err(|e| to_value_error("node_build", e))?;
+
+        Ok(Value::new(FileManifest {
+            files: crate::filemanifest::manifest_from_dir(&output_path, &[], prefix.as_deref())
+                .map_err(|e| to_value_error("node_build", e))?,
+        }))
+    }
+}
@@ -0,0 +1,88 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::{
+    optional_retry_arg, optional_secret_arg, optional_str_arg, parse_str_arg, required_str_arg,
+};
+use crate::notify::Notify;
+use starlark::environment::Environment;
+use starlark::starlark_module;
+use starlark::values::{
+    default_compare, RuntimeError, TypedValue, Value, ValueError, ValueResult,
+    INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+};
+use starlark::{
+    any, immutable, not_supported, starlark_fun, starlark_signature, starlark_signature_extraction,
+    starlark_signatures,
+};
+use std::any::Any;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+impl TypedValue for Notify {
+    immutable!();
+    any!();
+    not_supported!(binop);
+    not_supported!(container);
+    not_supported!(function);
+    not_supported!(get_hash);
+    not_supported!(to_int);
+
+    fn to_str(&self) -> String {
+        format!("Notify<format={:?}, when={:?}>", self.format, self.when)
+    }
+
+    fn to_repr(&self) -> String {
+        self.to_str()
+    }
+
+    fn get_type(&self) -> &'static str {
+        "Notify"
+    }
+
+    fn to_bool(&self) -> bool {
+        true
+    }
+
+    fn compare(&self, other: &dyn TypedValue, _recursion: u32) -> Result<Ordering, ValueError> {
+        default_compare(self, other)
+    }
+}
+
+starlark_module! { notify_module =>
+    notify(
+        webhook_url=None,
+        template=None,
+        format="json",
+        when="always",
+        webhook_url_env=None,
+        retry=None
+    ) {
+        let webhook_url = optional_str_arg("webhook_url", webhook_url)?;
+        let template = optional_str_arg("template", template)?;
+        let format = parse_str_arg("format", &required_str_arg("format", format)?)?;
+        let when = parse_str_arg("when", &required_str_arg("when", when)?)?;
+        let webhook_url_env = optional_secret_arg("webhook_url_env", webhook_url_env)?;
+        let retry = optional_retry_arg("retry", retry)?;
+
+        if webhook_url.is_some() == webhook_url_env.is_some() {
+            return Err(RuntimeError {
+                code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                message: "exactly one of webhook_url and webhook_url_env must be defined"
+                    .to_string(),
+                label: "notify()".to_string(),
+            }
+            .into());
+        }
+
+        Ok(Value::new(Notify {
+            webhook_url,
+            webhook_url_env,
+            template,
+            format,
+            when,
+            retry,
+        }))
+    }
+}
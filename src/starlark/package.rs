@@ -0,0 +1,210 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::values::FileManifest;
+use super::{
+    invalid_arg, optional_list_arg, optional_name_template_arg, optional_str_arg, required_type_arg,
+};
+use crate::archive::{Archive, TarArchive};
+use crate::compression::CompressionSettings;
+use crate::debian::DebianDebArchive;
+use crate::extract::ArchiveFormat;
+use crate::naming::ArtifactName;
+use crate::package_metadata::PackageMetadata;
+use crate::pipeline::Step;
+use debian::package::ControlParagraph;
+use serde::Serialize;
+use starlark::environment::Environment;
+use starlark::starlark_module;
+use starlark::values::{default_compare, TypedValue, Value, ValueError, ValueResult};
+use starlark::{
+    any, immutable, not_supported, starlark_fun, starlark_signature, starlark_signature_extraction,
+    starlark_signatures,
+};
+use std::any::Any;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// Formats produced by `package()` if none are given.
+const DEFAULT_FORMATS: &[&str] = &["deb", "tar.gz", "zip"];
+
+/// Represents packages of the same files in several formats.
+///
+/// Pipelines expand it into a step per format.
+#[derive(Debug, Clone, Serialize)]
+pub struct Package {
+    pub steps: Vec<Step>,
+}
+
+impl TypedValue for Package {
+    immutable!();
+    any!();
+    not_supported!(binop);
+    not_supported!(container);
+    not_supported!(function);
+    not_supported!(get_hash);
+    not_supported!(to_int);
+
+    fn to_str(&self) -> String {
+        format!(
+            "Package<steps={:?}>",
+            self.steps.iter().map(|s| s.kind()).collect::<Vec<_>>()
+        )
+    }
+
+    fn to_repr(&self) -> String {
+        self.to_str()
+    }
+
+    fn get_type(&self) -> &'static str {
+        "Package"
+    }
+
+    fn to_bool(&self) -> bool {
+        true
+    }
+
+    fn compare(&self, other: &dyn TypedValue, _recursion: u32) -> Result<Ordering, ValueError> {
+        default_compare(self, other)
+    }
+}
+
+/// Derive a Debian binary package's control paragraph from metadata.
+fn debian_control(
+    metadata: &PackageMetadata,
+    architecture: &str,
+) -> Result<ControlParagraph, ValueError> {
+    let maintainer = metadata.maintainer.clone().ok_or_else(|| {
+        invalid_arg(
+            "metadata",
+            "deb packages require metadata to define maintainer".to_string(),
+        )
+    })?;
+
+    let mut paragraph = ControlParagraph::new();
+    paragraph.add_entry("Package", metadata.name.clone());
+    paragraph.add_entry("Version", metadata.version.clone());
+    paragraph.add_entry("Architecture", architecture.to_string());
+    paragraph.add_entry("Maintainer", maintainer);
+    paragraph.add_entry("Description", metadata.debian_description());
+
+    if let Some(homepage) = &metadata.homepage {
+        paragraph.add_entry("Homepage", homepage.clone());
+    }
+
+    Ok(paragraph)
+}
+
+starlark_module! { package_module =>
+    package(env env, metadata, manifest, formats=None, architecture=None, name_template=None) {
+        required_type_arg("metadata", "PackageMetadata", metadata)?;
+        required_type_arg("manifest", "FileManifest", manifest)?;
+        optional_list_arg("formats", "string", formats)?;
+        let architecture = optional_str_arg("architecture", architecture)?;
+        let name_template = optional_name_template_arg("name_template", name_template)?;
+
+        let formats: Vec<String> = match formats.get_type() {
+            "NoneType" => DEFAULT_FORMATS.iter().map(|f| f.to_string()).collect(),
+            _ => formats.into_iter()?.map(|f| f.to_str()).collect(),
+        };
+
+        if formats.is_empty() {
+            return Err(invalid_arg(
+                "formats",
+                "package() requires at least one format".to_string(),
+            ));
+        }
+
+        let raw_metadata = metadata.0.borrow();
+        let metadata: &PackageMetadata = raw_metadata.as_any().downcast_ref().unwrap();
+        let raw_manifest = manifest.0.borrow();
+        let manifest: &FileManifest = raw_manifest.as_any().downcast_ref().unwrap();
+
+        let basename = format!("{}-{}", metadata.name, metadata.version);
+        let artifact_name = |ext: &str| ArtifactName {
+            name: metadata.name.clone(),
+            version: metadata.version.clone(),
+            ext: ext.to_string(),
+            template: name_template.clone(),
+        };
+        let mut steps = Vec::new();
+
+        for (i, format) in formats.iter().enumerate() {
+            if formats[..i].contains(format) {
+                return Err(invalid_arg("formats", format!("format {} is listed twice", format)));
+            }
+
+            let step = match format.as_str() {
+                "deb" => {
+                    let architecture = match &architecture {
+                        Some(architecture) => architecture.clone(),
+                        None => {
+                            let target = env.get("HOST_TARGET").unwrap().to_str();
+
+                            crate::debian::debian_architecture(&target)
+                                .ok_or_else(|| {
+                                    invalid_arg(
+                                        "architecture",
+                                        format!(
+                                            "unable to determine Debian architecture of {}; define architecture",
+                                            target
+                                        ),
+                                    )
+                                })?
+                                .to_string()
+                        }
+                    };
+
+                    Step::DebianDebArchive(DebianDebArchive::new(
+                        debian_control(metadata, &architecture)?,
+                        manifest.files.clone(),
+                        true,
+                        CompressionSettings::default(),
+                        name_template.clone(),
+                    ))
+                }
+                "rpm" => {
+                    return Err(invalid_arg(
+                        "formats",
+                        "rpm packages can't be produced yet".to_string(),
+                    ));
+                }
+                "zip" => Step::Archive(Archive {
+                    dest_name: format!("{}.zip", basename),
+                    file_manifest: manifest.files.clone(),
+                    format: ArchiveFormat::Zip,
+                    compression: CompressionSettings::default(),
+                    split_size: None,
+                    artifact_name: Some(artifact_name(format)),
+                }),
+                "tar" | "tar.gz" | "tar.xz" | "tar.zst" => Step::TarArchive(TarArchive {
+                    dest_name: format!("{}.{}", basename, format),
+                    file_manifest: manifest.files.clone(),
+                    compression: CompressionSettings::default(),
+                    owner: "root".to_string(),
+                    group: "root".to_string(),
+                    uid: 0,
+                    gid: 0,
+                    prefix: Some(basename.clone()),
+                    dereference: false,
+                    split_size: None,
+                    artifact_name: Some(artifact_name(format)),
+                }),
+                _ => {
+                    return Err(invalid_arg(
+                        "formats",
+                        format!(
+                            "unknown format {}; expected deb, rpm, tar, tar.gz, tar.xz, tar.zst, or zip",
+                            format
+                        ),
+                    ));
+                }
+            };
+
+            steps.push(step);
+        }
+
+        Ok(Value::new(Package { steps }))
+    }
+}
@@ -0,0 +1,133 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::{optional_str_arg, required_str_arg};
+use crate::package_metadata::PackageMetadata;
+use starlark::environment::Environment;
+use starlark::starlark_module;
+use starlark::values::{
+    default_compare, RuntimeError, TypedValue, Value, ValueError, ValueResult,
+    INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+};
+use starlark::{
+    any, immutable, not_supported, starlark_fun, starlark_signature, starlark_signature_extraction,
+    starlark_signatures,
+};
+use std::any::Any;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// Fields of `PackageMetadata` accessible as attributes.
+const FIELDS: &[&str] = &[
+    "description",
+    "homepage",
+    "license",
+    "maintainer",
+    "name",
+    "summary",
+    "vendor",
+    "version",
+];
+
+impl TypedValue for PackageMetadata {
+    immutable!();
+    any!();
+    not_supported!(binop);
+    not_supported!(iterable, sequence, indexable, set_attr);
+    not_supported!(function);
+    not_supported!(get_hash);
+    not_supported!(to_int);
+
+    fn to_str(&self) -> String {
+        format!(
+            "PackageMetadata<name={}, version={}>",
+            self.name, self.version
+        )
+    }
+
+    fn to_repr(&self) -> String {
+        self.to_str()
+    }
+
+    fn get_type(&self) -> &'static str {
+        "PackageMetadata"
+    }
+
+    fn to_bool(&self) -> bool {
+        true
+    }
+
+    fn compare(&self, other: &dyn TypedValue, _recursion: u32) -> Result<Ordering, ValueError> {
+        default_compare(self, other)
+    }
+
+    fn get_attr(&self, attribute: &str) -> ValueResult {
+        let value = match attribute {
+            "name" => Some(self.name.clone()),
+            "version" => Some(self.version.clone()),
+            "description" => Some(self.description.clone()),
+            "summary" => Some(self.summary()),
+            "license" => self.license.clone(),
+            "homepage" => self.homepage.clone(),
+            "maintainer" => self.maintainer.clone(),
+            "vendor" => self.vendor(),
+            _ => {
+                return Err(RuntimeError {
+                    code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                    message: format!("PackageMetadata has no field {}", attribute),
+                    label: format!("unknown field {}", attribute),
+                }
+                .into())
+            }
+        };
+
+        Ok(match value {
+            Some(value) => Value::from(value),
+            None => Value::from(None),
+        })
+    }
+
+    fn has_attr(&self, attribute: &str) -> Result<bool, ValueError> {
+        Ok(FIELDS.contains(&attribute))
+    }
+
+    fn dir_attr(&self) -> Result<Vec<String>, ValueError> {
+        Ok(FIELDS.iter().map(|f| f.to_string()).collect())
+    }
+}
+
+starlark_module! { package_metadata_module =>
+    package_metadata(
+        name,
+        version,
+        description,
+        summary=None,
+        license=None,
+        homepage=None,
+        maintainer=None,
+        vendor=None
+    ) {
+        let metadata = PackageMetadata {
+            name: required_str_arg("name", name)?,
+            version: required_str_arg("version", version)?,
+            description: required_str_arg("description", description)?,
+            summary: optional_str_arg("summary", summary)?,
+            license: optional_str_arg("license", license)?,
+            homepage: optional_str_arg("homepage", homepage)?,
+            maintainer: optional_str_arg("maintainer", maintainer)?,
+            vendor: optional_str_arg("vendor", vendor)?,
+        };
+
+        if metadata.summary().is_empty() {
+            return Err(RuntimeError {
+                code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                message: "description must not be empty".to_string(),
+                label: "package_metadata()".to_string(),
+            }
+            .into());
+        }
+
+        Ok(Value::new(metadata))
+    }
+}
@@ -0,0 +1,75 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::{optional_str_arg, required_str_arg};
+use crate::release_notes::ReleaseNotes;
+use starlark::environment::Environment;
+use starlark::starlark_module;
+use starlark::values::{default_compare, TypedValue, Value, ValueError, ValueResult};
+use starlark::{
+    any, immutable, not_supported, starlark_fun, starlark_signature, starlark_signature_extraction,
+    starlark_signatures,
+};
+use std::any::Any;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+impl TypedValue for ReleaseNotes {
+    immutable!();
+    any!();
+    not_supported!(binop);
+    not_supported!(container);
+    not_supported!(function);
+    not_supported!(get_hash);
+    not_supported!(to_int);
+
+    fn to_str(&self) -> String {
+        format!("ReleaseNotes<filename={}>", self.filename)
+    }
+
+    fn to_repr(&self) -> String {
+        self.to_str()
+    }
+
+    fn get_type(&self) -> &'static str {
+        "ReleaseNotes"
+    }
+
+    fn to_bool(&self) -> bool {
+        true
+    }
+
+    fn compare(&self, other: &dyn TypedValue, _recursion: u32) -> Result<Ordering, ValueError> {
+        default_compare(self, other)
+    }
+}
+
+starlark_module! { release_module =>
+    release_notes(
+        env env,
+        template=None,
+        since_tag=None,
+        changelog=None,
+        download_url=None,
+        filename="RELEASE-NOTES.md"
+    ) {
+        let template = optional_str_arg("template", template)?;
+        let since_tag = optional_str_arg("since_tag", since_tag)?;
+        let changelog = optional_str_arg("changelog", changelog)?;
+        let download_url = optional_str_arg("download_url", download_url)?;
+        let filename = required_str_arg("filename", filename)?;
+
+        let cwd = PathBuf::from(env.get("CWD").unwrap().to_str());
+
+        Ok(Value::new(ReleaseNotes {
+            template: template.map(|p| cwd.join(p)),
+            since_tag,
+            changelog: changelog.map(|p| cwd.join(p)),
+            download_url,
+            filename,
+            repo_path: cwd,
+        }))
+    }
+}
@@ -0,0 +1,76 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::{invalid_arg, optional_resources_arg};
+use starlark::environment::Environment;
+use starlark::starlark_module;
+use starlark::values::{default_compare, TypedValue, Value, ValueError, ValueResult};
+use starlark::{
+    any, immutable, not_supported, starlark_fun, starlark_signature, starlark_signature_extraction,
+    starlark_signatures,
+};
+use std::any::Any;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// Steps holding resources while they execute.
+///
+/// `steps` is a step or a list of steps, flattened when added to a
+/// pipeline.
+#[derive(Clone)]
+pub struct RequiredResources {
+    pub requires: Vec<String>,
+    pub steps: Value,
+}
+
+impl TypedValue for RequiredResources {
+    immutable!();
+    any!();
+    not_supported!(binop);
+    not_supported!(container);
+    not_supported!(function);
+    not_supported!(get_hash);
+    not_supported!(to_int);
+
+    fn to_str(&self) -> String {
+        format!(
+            "RequiredResources<requires={:?}, steps={}>",
+            self.requires,
+            self.steps.to_repr()
+        )
+    }
+
+    fn to_repr(&self) -> String {
+        self.to_str()
+    }
+
+    fn get_type(&self) -> &'static str {
+        "RequiredResources"
+    }
+
+    fn to_bool(&self) -> bool {
+        true
+    }
+
+    fn compare(&self, other: &dyn TypedValue, _recursion: u32) -> Result<Ordering, ValueError> {
+        default_compare(self, other)
+    }
+}
+
+starlark_module! { resources_module =>
+    with_resources(requires, steps) {
+        let requires = optional_resources_arg("requires", requires)?;
+        if requires.is_empty() {
+            return Err(invalid_arg(
+                "requires",
+                "requires must name at least one resource".to_string(),
+            ));
+        }
+
+        Ok(Value::new(RequiredResources {
+            requires,
+            steps: steps.clone(),
+        }))
+    }
+}
@@ -0,0 +1,90 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::{invalid_arg, optional_list_arg, parse_str_arg, required_type_arg};
+use crate::retry::RetryPolicy;
+use starlark::environment::Environment;
+use starlark::starlark_module;
+use starlark::values::{default_compare, TypedValue, Value, ValueError, ValueResult};
+use starlark::{
+    any, immutable, not_supported, starlark_fun, starlark_signature, starlark_signature_extraction,
+    starlark_signatures,
+};
+use std::any::Any;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+impl TypedValue for RetryPolicy {
+    immutable!();
+    any!();
+    not_supported!(binop);
+    not_supported!(container);
+    not_supported!(function);
+    not_supported!(get_hash);
+    not_supported!(to_int);
+
+    fn to_str(&self) -> String {
+        format!(
+            "RetryPolicy<max_attempts={}, initial_delay={}, max_delay={}, retry_on={:?}>",
+            self.max_attempts,
+            self.initial_delay,
+            self.max_delay,
+            self.retry_on.iter().map(|c| c.as_str()).collect::<Vec<_>>()
+        )
+    }
+
+    fn to_repr(&self) -> String {
+        self.to_str()
+    }
+
+    fn get_type(&self) -> &'static str {
+        "RetryPolicy"
+    }
+
+    fn to_bool(&self) -> bool {
+        true
+    }
+
+    fn compare(&self, other: &dyn TypedValue, _recursion: u32) -> Result<Ordering, ValueError> {
+        default_compare(self, other)
+    }
+}
+
+/// Validate an integer argument that must not be negative.
+fn non_negative_int_arg(name: &str, value: &Value) -> Result<i64, ValueError> {
+    required_type_arg(name, "int", value)?;
+
+    match value.to_int()? {
+        n if n >= 0 => Ok(n),
+        _ => Err(invalid_arg(name, format!("{} must not be negative", name))),
+    }
+}
+
+starlark_module! { retry_module =>
+    retry_policy(max_attempts=3, initial_delay=2, max_delay=60, retry_on=None) {
+        let max_attempts = non_negative_int_arg("max_attempts", max_attempts)?;
+        let initial_delay = non_negative_int_arg("initial_delay", initial_delay)?;
+        let max_delay = non_negative_int_arg("max_delay", max_delay)?;
+        optional_list_arg("retry_on", "string", retry_on)?;
+
+        if max_attempts < 1 {
+            return Err(invalid_arg("max_attempts", "max_attempts must be at least 1".to_string()));
+        }
+
+        let retry_on = match retry_on.get_type() {
+            "list" => retry_on
+                .into_iter()?
+                .map(|class| parse_str_arg("retry_on", &class.to_str()))
+                .collect::<Result<Vec<_>, _>>()?,
+            _ => RetryPolicy::default().retry_on,
+        };
+
+        Ok(Value::new(RetryPolicy {
+            max_attempts: max_attempts as u32,
+            initial_delay: initial_delay as u64,
+            max_delay: max_delay as u64,
+            retry_on,
+        }))
+    }
+}
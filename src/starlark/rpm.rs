@@ -0,0 +1,111 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::values::FileManifest;
+use super::{
+    invalid_arg, optional_list_arg, optional_str_arg, parse_str_arg, required_str_arg,
+    required_type_arg,
+};
+use crate::package_metadata::PackageMetadata;
+use crate::rpm::{RpmBackend, RpmBuild, RpmPackageInfo};
+use starlark::environment::Environment;
+use starlark::starlark_module;
+use starlark::values::{default_compare, TypedValue, Value, ValueError, ValueResult};
+use starlark::{
+    any, immutable, not_supported, starlark_fun, starlark_signature, starlark_signature_extraction,
+    starlark_signatures,
+};
+use std::any::Any;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+impl TypedValue for RpmBuild {
+    immutable!();
+    any!();
+    not_supported!(binop);
+    not_supported!(container);
+    not_supported!(function);
+    not_supported!(get_hash);
+    not_supported!(to_int);
+
+    fn to_str(&self) -> String {
+        format!(
+            "RpmBuild<name={}, version={}, release={}>",
+            self.package.metadata.name, self.package.metadata.version, self.package.release
+        )
+    }
+
+    fn to_repr(&self) -> String {
+        self.to_str()
+    }
+
+    fn get_type(&self) -> &'static str {
+        "RpmBuild"
+    }
+
+    fn to_bool(&self) -> bool {
+        true
+    }
+
+    fn compare(&self, other: &dyn TypedValue, _recursion: u32) -> Result<Ordering, ValueError> {
+        default_compare(self, other)
+    }
+}
+
+starlark_module! { rpm_module =>
+    rpm_build(
+        metadata,
+        manifest,
+        release="1",
+        arch=None,
+        requires=None,
+        backend="rpmbuild",
+        mock_root=None
+    ) {
+        required_type_arg("metadata", "PackageMetadata", metadata)?;
+        required_type_arg("manifest", "FileManifest", manifest)?;
+        let release = required_str_arg("release", release)?;
+        let arch = optional_str_arg("arch", arch)?;
+        optional_list_arg("requires", "string", requires)?;
+        let backend: RpmBackend =
+            parse_str_arg("backend", &required_str_arg("backend", backend)?)?;
+        let mock_root = optional_str_arg("mock_root", mock_root)?;
+
+        let raw_metadata = metadata.0.borrow();
+        let metadata: &PackageMetadata = raw_metadata.as_any().downcast_ref().unwrap();
+        let raw_manifest = manifest.0.borrow();
+        let manifest: &FileManifest = raw_manifest.as_any().downcast_ref().unwrap();
+
+        if metadata.version.contains('-') || release.contains('-') {
+            return Err(invalid_arg(
+                "metadata",
+                "RPM versions and releases can't contain -".to_string(),
+            ));
+        }
+        if mock_root.is_some() && backend != RpmBackend::Mock {
+            return Err(invalid_arg(
+                "mock_root",
+                "mock_root requires backend=\"mock\"".to_string(),
+            ));
+        }
+
+        let requires = if requires.get_type() == "NoneType" {
+            Vec::new()
+        } else {
+            requires.into_iter()?.map(|r| r.to_str()).collect()
+        };
+
+        Ok(Value::new(RpmBuild {
+            package: RpmPackageInfo {
+                metadata: metadata.clone(),
+                release,
+                arch,
+                requires,
+                backend,
+                mock_root,
+            },
+            files: manifest.files.clone(),
+        }))
+    }
+}
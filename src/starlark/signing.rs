@@ -0,0 +1,285 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::{
+    expand_channel, optional_secret_arg, optional_str_arg, required_list_arg,
+    required_maybe_secret_arg,
+};
+use crate::signing::{
+    CodesignOptions, CodesignSign, CosignSign, MinisignSign, PlistValue, RpmSign,
+};
+use starlark::environment::Environment;
+use starlark::starlark_module;
+use starlark::values::{
+    default_compare, RuntimeError, TypedValue, Value, ValueError, ValueResult,
+    INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+};
+use starlark::{
+    any, check_type, immutable, not_supported, starlark_err, starlark_fun, starlark_signature,
+    starlark_signature_extraction, starlark_signatures,
+};
+use std::any::Any;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+
+impl TypedValue for RpmSign {
+    immutable!();
+    any!();
+    not_supported!(binop);
+    not_supported!(container);
+    not_supported!(function);
+    not_supported!(get_hash);
+    not_supported!(to_int);
+
+    fn to_str(&self) -> String {
+        format!("RpmSign<rpm={}, key={}>", self.rpm, self.key)
+    }
+
+    fn to_repr(&self) -> String {
+        self.to_str()
+    }
+
+    fn get_type(&self) -> &'static str {
+        "RpmSign"
+    }
+
+    fn to_bool(&self) -> bool {
+        true
+    }
+
+    fn compare(&self, other: &dyn TypedValue, _recursion: u32) -> Result<Ordering, ValueError> {
+        default_compare(self, other)
+    }
+}
+
+impl TypedValue for CosignSign {
+    immutable!();
+    any!();
+    not_supported!(binop);
+    not_supported!(container);
+    not_supported!(function);
+    not_supported!(get_hash);
+    not_supported!(to_int);
+
+    fn to_str(&self) -> String {
+        format!(
+            "CosignSign<artifact={}, key={:?}, image={}>",
+            self.artifact, self.key, self.image
+        )
+    }
+
+    fn to_repr(&self) -> String {
+        self.to_str()
+    }
+
+    fn get_type(&self) -> &'static str {
+        "CosignSign"
+    }
+
+    fn to_bool(&self) -> bool {
+        true
+    }
+
+    fn compare(&self, other: &dyn TypedValue, _recursion: u32) -> Result<Ordering, ValueError> {
+        default_compare(self, other)
+    }
+}
+
+impl TypedValue for MinisignSign {
+    immutable!();
+    any!();
+    not_supported!(binop);
+    not_supported!(container);
+    not_supported!(function);
+    not_supported!(get_hash);
+    not_supported!(to_int);
+
+    fn to_str(&self) -> String {
+        format!(
+            "MinisignSign<artifacts={:?}, secret_key={}>",
+            self.artifacts,
+            self.secret_key.display()
+        )
+    }
+
+    fn to_repr(&self) -> String {
+        self.to_str()
+    }
+
+    fn get_type(&self) -> &'static str {
+        "MinisignSign"
+    }
+
+    fn to_bool(&self) -> bool {
+        true
+    }
+
+    fn compare(&self, other: &dyn TypedValue, _recursion: u32) -> Result<Ordering, ValueError> {
+        default_compare(self, other)
+    }
+}
+
+impl TypedValue for CodesignSign {
+    immutable!();
+    any!();
+    not_supported!(binop);
+    not_supported!(container);
+    not_supported!(function);
+    not_supported!(get_hash);
+    not_supported!(to_int);
+
+    fn to_str(&self) -> String {
+        format!(
+            "CodesignSign<artifacts={:?}, identity={:?}>",
+            self.artifacts, self.options.identity
+        )
+    }
+
+    fn to_repr(&self) -> String {
+        self.to_str()
+    }
+
+    fn get_type(&self) -> &'static str {
+        "CodesignSign"
+    }
+
+    fn to_bool(&self) -> bool {
+        true
+    }
+
+    fn compare(&self, other: &dyn TypedValue, _recursion: u32) -> Result<Ordering, ValueError> {
+        default_compare(self, other)
+    }
+}
+
+/// Convert a Starlark value to a property list value.
+fn plist_value(value: &Value) -> Result<PlistValue, ValueError> {
+    match value.get_type() {
+        "bool" => Ok(PlistValue::Boolean(value.to_bool())),
+        "int" => Ok(PlistValue::Integer(value.to_int()?)),
+        "string" => Ok(PlistValue::String(value.to_str())),
+        "list" | "tuple" => Ok(PlistValue::Array(
+            value
+                .into_iter()?
+                .map(|v| plist_value(&v))
+                .collect::<Result<_, _>>()?,
+        )),
+        "dict" => Ok(PlistValue::Dictionary(plist_dict(value)?)),
+        t => Err(RuntimeError {
+            code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+            message: format!("entitlement values can't be of type {}", t),
+            label: format!("unsupported type {}", t),
+        }
+        .into()),
+    }
+}
+
+fn plist_dict(value: &Value) -> Result<BTreeMap<String, PlistValue>, ValueError> {
+    let mut res = BTreeMap::new();
+
+    for k in value.into_iter()? {
+        if k.get_type() != "string" {
+            return Err(RuntimeError {
+                code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                message: format!("entitlement keys must be strings; got {}", k.get_type()),
+                label: format!("expected type string; got {}", k.get_type()),
+            }
+            .into());
+        }
+
+        res.insert(k.to_str(), plist_value(&value.at(k.clone())?)?);
+    }
+
+    Ok(res)
+}
+
+starlark_module! { signing_module =>
+    codesign_sign(
+        env env,
+        artifacts,
+        identity="-",
+        entitlements=None,
+        hardened_runtime=true,
+        timestamp=true,
+        deep=true
+    ) {
+        required_list_arg("artifacts", "string", &artifacts)?;
+        let identity = required_maybe_secret_arg("identity", &identity)?;
+        check_type!(hardened_runtime, "codesign_sign", bool);
+        check_type!(timestamp, "codesign_sign", bool);
+        check_type!(deep, "codesign_sign", bool);
+
+        let entitlements = match entitlements.get_type() {
+            "NoneType" => None,
+            "dict" => Some(plist_dict(&entitlements)?),
+            t => {
+                return Err(ValueError::TypeNotX {
+                    object_type: t.to_string(),
+                    op: "dict".to_string(),
+                })
+            }
+        };
+
+        let artifacts = artifacts
+            .into_iter()?
+            .map(|a| expand_channel(&env, &a.to_str()))
+            .collect();
+
+        Ok(Value::new(CodesignSign {
+            artifacts,
+            options: CodesignOptions {
+                identity,
+                entitlements,
+                hardened_runtime: hardened_runtime.to_bool(),
+                timestamp: timestamp.to_bool(),
+                deep: deep.to_bool(),
+            },
+        }))
+    }
+
+    cosign_sign(env env, artifact, key=None, password=None, image=false) {
+        check_type!(artifact, "cosign_sign", string);
+        let key = optional_str_arg("key", &key)?;
+        let password = optional_secret_arg("password", &password)?;
+        check_type!(image, "cosign_sign", bool);
+
+        Ok(Value::new(CosignSign {
+            artifact: expand_channel(&env, &artifact.to_str()),
+            key,
+            password,
+            image: image.to_bool(),
+        }))
+    }
+
+    minisign_sign(env env, artifacts, secret_key, trusted_comment=None) {
+        required_list_arg("artifacts", "string", &artifacts)?;
+        check_type!(secret_key, "minisign_sign", string);
+        let trusted_comment = optional_str_arg("trusted_comment", &trusted_comment)?;
+
+        let cwd = env.get("CWD").unwrap().to_str();
+        let artifacts = artifacts
+            .into_iter()?
+            .map(|a| expand_channel(&env, &a.to_str()))
+            .collect();
+
+        Ok(Value::new(MinisignSign {
+            artifacts,
+            secret_key: PathBuf::from(cwd).join(secret_key.to_str()),
+            trusted_comment,
+        }))
+    }
+
+    rpm_sign(env env, rpm, key, passphrase=None) {
+        check_type!(rpm, "rpm_sign", string);
+        check_type!(key, "rpm_sign", string);
+        let passphrase = optional_secret_arg("passphrase", passphrase)?;
+
+        Ok(Value::new(RpmSign {
+            rpm: expand_channel(&env, &rpm.to_str()),
+            key: key.to_str(),
+            passphrase,
+        }))
+    }
+}
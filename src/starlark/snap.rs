@@ -3,7 +3,13 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use super::values::FileManifest;
-use super::{optional_str_arg, required_dict_arg, required_list_arg, required_str_arg};
+use super::{
+    metadata_str_arg, optional_metadata_arg, optional_str_arg, parse_str_arg, release_channel,
+    required_dict_arg, required_list_arg, required_str_arg,
+};
+use crate::filemanifest::InstallMode;
+use crate::snap::Snapcraft;
+use serde::Serialize;
 use starlark::environment::Environment;
 use starlark::starlark_module;
 use starlark::values::{
@@ -19,7 +25,7 @@ use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::path::PathBuf;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Snap {
     pub snap: crate::snap::Snap,
 }
@@ -54,7 +60,7 @@ impl TypedValue for Snap {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct SnapPart {
     pub part: crate::snap::SnapPart,
 }
@@ -89,7 +95,7 @@ impl TypedValue for SnapPart {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct SnapApp {
     pub app: crate::snap::SnapApp,
 }
@@ -124,15 +130,6 @@ impl TypedValue for SnapApp {
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct Snapcraft {
-    pub args: Vec<String>,
-    pub snap: Snap,
-    pub build_path: PathBuf,
-    pub manifest: FileManifest,
-    pub purge_build: bool,
-}
-
 impl TypedValue for Snapcraft {
     immutable!();
     any!();
@@ -286,9 +283,12 @@ starlark_module! { snapcraft_module =>
         Ok(Value::new(SnapApp { app }))
     }
 
-    snap(name, description, summary, version, adopt_info=None, assumes=None, base=None,
-         confinement=None, grade=None, icon=None, license=None, plugs=None, slots=None,
-         title=None, snap_type=None, parts=None, apps=None) {
+    snap(env env, name=None, description=None, summary=None, version=None, adopt_info=None,
+         assumes=None, base=None, confinement=None, grade=None, icon=None, license=None,
+         plugs=None, slots=None, title=None, snap_type=None, parts=None, apps=None,
+         metadata=None) {
+
+        let metadata = optional_metadata_arg("metadata", &metadata)?;
 
         required_dict_arg("apps", "string", "SnapApp", &apps)?;
         required_dict_arg("parts", "string", "SnapPart", &parts)?;
@@ -329,17 +329,33 @@ starlark_module! { snapcraft_module =>
             assumes: None,
             base: optional_str_arg("base", &base)?,
             confinement: optional_str_arg("confinement", &confinement)?,
-            description: required_str_arg("description", &description)?,
-            grade: optional_str_arg("grade", &grade)?,
+            description: metadata_str_arg(
+                "description",
+                &description,
+                metadata.as_ref().map(|m| m.description.clone()),
+            )?,
+            grade: Some(
+                optional_str_arg("grade", &grade)?
+                    .unwrap_or_else(|| release_channel(&env).snap_grade().to_string()),
+            ),
             icon: optional_str_arg("icon", &icon)?,
-            license: optional_str_arg("license", &license)?,
-            name: required_str_arg("name", &name)?,
+            license: optional_str_arg("license", &license)?
+                .or_else(|| metadata.as_ref().and_then(|m| m.license.clone())),
+            name: metadata_str_arg("name", &name, metadata.as_ref().map(|m| m.name.clone()))?,
             plugs: None,
             slots: None,
-            summary: required_str_arg("summary", &summary)?,
+            summary: metadata_str_arg(
+                "summary",
+                &summary,
+                metadata.as_ref().map(|m| m.summary()),
+            )?,
             title: optional_str_arg("title", &title)?,
             snap_type: optional_str_arg("snap_type", &snap_type)?,
-            version: required_str_arg("version", &version)?,
+            version: metadata_str_arg(
+                "version",
+                &version,
+                metadata.as_ref().map(|m| m.version.clone()),
+            )?,
             apps: raw_apps,
             parts: raw_parts,
         };
@@ -347,12 +363,15 @@ starlark_module! { snapcraft_module =>
         Ok(Value::new(Snap { snap }))
     }
 
-    snapcraft(args, snap, build_path, manifest, purge_build=true) {
+    snapcraft(args, snap, build_path, manifest, purge_build=true, install_mode="copy", verbose=false) {
         required_list_arg("args", "string", &args)?;
         check_type!(snap, "snapcraft", Snap);
         check_type!(build_path, "snapcraft", string);
         check_type!(manifest, "snapcraft", FileManifest);
         check_type!(purge_build, "snapcraft", bool);
+        check_type!(verbose, "snapcraft", bool);
+        let install_mode: InstallMode =
+            parse_str_arg("install_mode", &required_str_arg("install_mode", install_mode)?)?;
 
         let raw_args = args.into_iter()?.map(|a| a.to_string()).collect();
         let raw_snap = snap.0.borrow();
@@ -362,10 +381,12 @@ starlark_module! { snapcraft_module =>
 
         Ok(Value::new(Snapcraft {
             args: raw_args,
-            snap: snap.clone(),
+            snap: snap.snap.clone(),
             build_path: PathBuf::from(build_path.to_string()),
-            manifest: manifest.clone(),
+            manifest: manifest.files.clone(),
             purge_build: purge_build.to_bool(),
+            install_mode,
+            verbose: verbose.to_bool(),
         }))
     }
 }
@@ -0,0 +1,75 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::values::FileManifest;
+use super::{expand_channel, optional_str_arg, required_str_arg, required_type_arg};
+use crate::package_metadata::PackageMetadata;
+use crate::squirrel::SquirrelRelease;
+use starlark::environment::Environment;
+use starlark::starlark_module;
+use starlark::values::{default_compare, TypedValue, Value, ValueError, ValueResult};
+use starlark::{
+    any, immutable, not_supported, starlark_fun, starlark_signature, starlark_signature_extraction,
+    starlark_signatures,
+};
+use std::any::Any;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+impl TypedValue for SquirrelRelease {
+    immutable!();
+    any!();
+    not_supported!(binop);
+    not_supported!(container);
+    not_supported!(function);
+    not_supported!(get_hash);
+    not_supported!(to_int);
+
+    fn to_str(&self) -> String {
+        format!(
+            "SquirrelRelease<id={}, version={}, dest={}>",
+            self.metadata.name, self.metadata.version, self.dest
+        )
+    }
+
+    fn to_repr(&self) -> String {
+        self.to_str()
+    }
+
+    fn get_type(&self) -> &'static str {
+        "SquirrelRelease"
+    }
+
+    fn to_bool(&self) -> bool {
+        true
+    }
+
+    fn compare(&self, other: &dyn TypedValue, _recursion: u32) -> Result<Ordering, ValueError> {
+        default_compare(self, other)
+    }
+}
+
+starlark_module! { squirrel_module =>
+    squirrel_release(env env, metadata, manifest, previous_dir=None, dest="squirrel") {
+        required_type_arg("metadata", "PackageMetadata", metadata)?;
+        required_type_arg("manifest", "FileManifest", manifest)?;
+        let previous_dir = optional_str_arg("previous_dir", previous_dir)?;
+        let dest = expand_channel(&env, &required_str_arg("dest", dest)?);
+
+        let raw_metadata = metadata.0.borrow();
+        let metadata: &PackageMetadata = raw_metadata.as_any().downcast_ref().unwrap();
+        let raw_manifest = manifest.0.borrow();
+        let manifest: &FileManifest = raw_manifest.as_any().downcast_ref().unwrap();
+
+        let cwd = PathBuf::from(env.get("CWD").unwrap().to_str());
+
+        Ok(Value::new(SquirrelRelease {
+            metadata: metadata.clone(),
+            files: manifest.files.clone(),
+            previous_dir: previous_dir.map(|dir| cwd.join(expand_channel(&env, &dir))),
+            dest,
+        }))
+    }
+}
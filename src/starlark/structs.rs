@@ -0,0 +1,378 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::{invalid_arg, required_str_arg};
+use starlark::environment::Environment;
+use starlark::starlark_module;
+use starlark::values::{
+    default_compare, RuntimeError, TypedValue, Value, ValueError, ValueResult,
+    INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+};
+use starlark::{
+    any, not_supported, starlark_fun, starlark_signature, starlark_signature_extraction,
+    starlark_signatures,
+};
+use std::any::Any;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+
+/// Type name accepting values of any type in a record schema.
+const ANY_TYPE: &str = "any";
+
+/// An immutable value with named fields.
+///
+/// Values returned by `struct()` have no record name. Values returned by
+/// the constructor of a `record()` carry the name of the record.
+#[derive(Clone)]
+pub struct Struct {
+    pub record: Option<String>,
+    pub fields: BTreeMap<String, Value>,
+}
+
+impl Struct {
+    fn describe(&self) -> String {
+        match &self.record {
+            Some(name) => name.clone(),
+            None => "struct".to_string(),
+        }
+    }
+}
+
+impl TypedValue for Struct {
+    any!();
+    not_supported!(binop);
+    not_supported!(iterable, sequence, indexable, set_attr);
+    not_supported!(function);
+    not_supported!(get_hash);
+    not_supported!(to_int);
+
+    fn immutable(&self) -> bool {
+        true
+    }
+
+    fn freeze(&mut self) {
+        for value in self.fields.values_mut() {
+            value.freeze();
+        }
+    }
+
+    fn to_str(&self) -> String {
+        format!(
+            "{}({})",
+            self.describe(),
+            self.fields
+                .iter()
+                .map(|(name, value)| format!("{}={}", name, value.to_repr()))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    }
+
+    fn to_repr(&self) -> String {
+        self.to_str()
+    }
+
+    fn get_type(&self) -> &'static str {
+        "struct"
+    }
+
+    fn to_bool(&self) -> bool {
+        true
+    }
+
+    fn compare(&self, other: &dyn TypedValue, recursion: u32) -> Result<Ordering, ValueError> {
+        let other = match other.as_any().downcast_ref::<Struct>() {
+            Some(other) => other,
+            None => return default_compare(self, other),
+        };
+
+        match self.record.cmp(&other.record) {
+            Ordering::Equal => {}
+            ordering => return Ok(ordering),
+        }
+
+        let mut theirs = other.fields.iter();
+        for (name, value) in &self.fields {
+            match theirs.next() {
+                Some((other_name, other_value)) => {
+                    match name.cmp(other_name) {
+                        Ordering::Equal => {}
+                        ordering => return Ok(ordering),
+                    }
+                    match value.compare(other_value, recursion + 1)? {
+                        Ordering::Equal => {}
+                        ordering => return Ok(ordering),
+                    }
+                }
+                None => return Ok(Ordering::Greater),
+            }
+        }
+
+        if theirs.next().is_some() {
+            Ok(Ordering::Less)
+        } else {
+            Ok(Ordering::Equal)
+        }
+    }
+
+    fn get_attr(&self, attribute: &str) -> ValueResult {
+        self.fields.get(attribute).cloned().ok_or_else(|| {
+            RuntimeError {
+                code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                message: format!("{} has no field {}", self.describe(), attribute),
+                label: format!("unknown field {}", attribute),
+            }
+            .into()
+        })
+    }
+
+    fn has_attr(&self, attribute: &str) -> Result<bool, ValueError> {
+        Ok(self.fields.contains_key(attribute))
+    }
+
+    fn dir_attr(&self) -> Result<Vec<String>, ValueError> {
+        Ok(self.fields.keys().cloned().collect())
+    }
+}
+
+/// The type and requiredness of a field of a record.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldSchema {
+    pub type_name: String,
+    pub optional: bool,
+}
+
+impl FieldSchema {
+    /// Parse a type name such as `string` or `string?`.
+    ///
+    /// A trailing `?` makes the field optional.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let (type_name, optional) = match value.strip_suffix('?') {
+            Some(type_name) => (type_name, true),
+            None => (value, false),
+        };
+
+        if type_name.is_empty()
+            || !type_name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_')
+        {
+            return Err(format!("invalid type name {}", value));
+        }
+
+        Ok(FieldSchema {
+            type_name: type_name.to_string(),
+            optional,
+        })
+    }
+
+    /// Whether a value has the type of the field.
+    fn accepts(&self, value: &Value) -> bool {
+        if self.type_name == ANY_TYPE || value.get_type() == self.type_name {
+            return true;
+        }
+        if self.optional && value.get_type() == "NoneType" {
+            return true;
+        }
+
+        // Fields can hold instances of other records.
+        value.get_type() == "struct"
+            && value
+                .downcast_apply(|s: &Struct| s.record.as_deref() == Some(self.type_name.as_str()))
+    }
+
+    fn describe(&self) -> String {
+        if self.optional {
+            format!("{}?", self.type_name)
+        } else {
+            self.type_name.clone()
+        }
+    }
+}
+
+/// A schema of named fields, constructing `Struct` values.
+#[derive(Clone, Debug)]
+pub struct RecordType {
+    pub name: String,
+    pub fields: BTreeMap<String, FieldSchema>,
+}
+
+impl RecordType {
+    fn invalid(&self, message: String) -> ValueError {
+        RuntimeError {
+            code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+            message,
+            label: format!("{}()", self.name),
+        }
+        .into()
+    }
+
+    /// Validate the fields of an instance and construct it.
+    ///
+    /// Optional fields that are absent are `None`.
+    pub fn construct(&self, mut values: BTreeMap<String, Value>) -> ValueResult {
+        if let Some(name) = values.keys().find(|k| !self.fields.contains_key(*k)) {
+            return Err(self.invalid(format!(
+                "{} has no field {}; expected one of {}",
+                self.name,
+                name,
+                self.fields.keys().cloned().collect::<Vec<_>>().join(", ")
+            )));
+        }
+
+        for (name, schema) in &self.fields {
+            match values.get(name) {
+                Some(value) if !schema.accepts(value) => {
+                    return Err(self.invalid(format!(
+                        "field {} of {} expects a {}; got type {}",
+                        name,
+                        self.name,
+                        schema.describe(),
+                        value.get_type()
+                    )));
+                }
+                Some(_) => {}
+                None if schema.optional => {
+                    values.insert(name.clone(), Value::from(None));
+                }
+                None => {
+                    return Err(self.invalid(format!("{} requires field {}", self.name, name)));
+                }
+            }
+        }
+
+        Ok(Value::new(Struct {
+            record: Some(self.name.clone()),
+            fields: values,
+        }))
+    }
+}
+
+impl TypedValue for RecordType {
+    any!();
+    not_supported!(binop);
+    not_supported!(container);
+    not_supported!(get_hash);
+    not_supported!(to_int);
+
+    fn immutable(&self) -> bool {
+        true
+    }
+
+    fn freeze(&mut self) {}
+
+    fn to_str(&self) -> String {
+        format!(
+            "record({}, {})",
+            self.name,
+            self.fields
+                .iter()
+                .map(|(name, schema)| format!("{}={}", name, schema.describe()))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    }
+
+    fn to_repr(&self) -> String {
+        self.to_str()
+    }
+
+    fn get_type(&self) -> &'static str {
+        "record"
+    }
+
+    fn to_bool(&self) -> bool {
+        true
+    }
+
+    fn compare(&self, other: &dyn TypedValue, _recursion: u32) -> Result<Ordering, ValueError> {
+        default_compare(self, other)
+    }
+
+    fn call(
+        &self,
+        _call_stack: &Vec<(String, String)>,
+        _env: Environment,
+        positional: Vec<Value>,
+        named: HashMap<String, Value>,
+        args: Option<Value>,
+        kwargs: Option<Value>,
+    ) -> ValueResult {
+        if !positional.is_empty() || args.is_some() {
+            return Err(self.invalid(format!("{} only accepts keyword arguments", self.name)));
+        }
+
+        let mut values = named.into_iter().collect::<BTreeMap<_, _>>();
+
+        if let Some(kwargs) = kwargs {
+            for key in kwargs.into_iter()? {
+                let value = kwargs.at(key.clone())?;
+                if values.insert(key.to_str(), value).is_some() {
+                    return Err(self.invalid(format!(
+                        "field {} of {} is set more than once",
+                        key.to_str(),
+                        self.name
+                    )));
+                }
+            }
+        }
+
+        self.construct(values)
+    }
+}
+
+/// Collect the keyword arguments of a call.
+fn kwargs_map(kwargs: &Value) -> Result<BTreeMap<String, Value>, ValueError> {
+    let mut values = BTreeMap::new();
+
+    for key in kwargs.into_iter()? {
+        values.insert(key.to_str(), kwargs.at(key.clone())?);
+    }
+
+    Ok(values)
+}
+
+starlark_module! { structs_module =>
+    __struct__(**kwargs) {
+        Ok(Value::new(Struct {
+            record: None,
+            fields: kwargs_map(&kwargs)?,
+        }))
+    }
+
+    record(name, **kwargs) {
+        let name = required_str_arg("name", name)?;
+        if FieldSchema::parse(&name).map(|s| s.optional).unwrap_or(true) {
+            return Err(invalid_arg("name", format!("invalid record name {}", name)));
+        }
+
+        let mut fields = BTreeMap::new();
+        for (field, value) in kwargs_map(kwargs)? {
+            if value.get_type() != "string" {
+                return Err(invalid_arg(
+                    &field,
+                    format!(
+                        "field {} must be declared with a type name; got type {}",
+                        field,
+                        value.get_type()
+                    ),
+                ));
+            }
+
+            let schema = FieldSchema::parse(&value.to_str())
+                .map_err(|message| invalid_arg(&field, format!("field {}: {}", field, message)))?;
+
+            fields.insert(field, schema);
+        }
+
+        if fields.is_empty() {
+            return Err(invalid_arg(
+                "fields",
+                format!("record {} must declare at least one field", name),
+            ));
+        }
+
+        Ok(Value::new(RecordType { name, fields }))
+    }
+}
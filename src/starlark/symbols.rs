@@ -0,0 +1,155 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::values::FileManifest;
+use super::{
+    expand_channel, invalid_arg, optional_retry_arg, optional_secret_arg, optional_str_arg,
+    required_str_arg, required_type_arg,
+};
+use crate::symbols::{SymbolFiles, SymbolStore, UploadSymbols};
+use starlark::environment::Environment;
+use starlark::starlark_module;
+use starlark::values::{default_compare, TypedValue, Value, ValueError, ValueResult};
+use starlark::{
+    any, immutable, not_supported, starlark_fun, starlark_signature, starlark_signature_extraction,
+    starlark_signatures,
+};
+use std::any::Any;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+impl TypedValue for UploadSymbols {
+    immutable!();
+    any!();
+    not_supported!(binop);
+    not_supported!(container);
+    not_supported!(function);
+    not_supported!(get_hash);
+    not_supported!(to_int);
+
+    fn to_str(&self) -> String {
+        match &self.files {
+            SymbolFiles::Manifest(files) => format!(
+                "UploadSymbols<store={:?}, files={}>",
+                self.store,
+                files.len()
+            ),
+            SymbolFiles::Directory(dir) => {
+                format!("UploadSymbols<store={:?}, directory={}>", self.store, dir)
+            }
+        }
+    }
+
+    fn to_repr(&self) -> String {
+        self.to_str()
+    }
+
+    fn get_type(&self) -> &'static str {
+        "UploadSymbols"
+    }
+
+    fn to_bool(&self) -> bool {
+        true
+    }
+
+    fn compare(&self, other: &dyn TypedValue, _recursion: u32) -> Result<Ordering, ValueError> {
+        default_compare(self, other)
+    }
+}
+
+/// Obtain an argument required by a backend.
+fn backend_arg(name: &str, backend: &str, value: Option<String>) -> Result<String, ValueError> {
+    value.ok_or_else(|| invalid_arg(name, format!("the {} backend requires {}", backend, name)))
+}
+
+starlark_module! { symbols_module =>
+    upload_symbols(
+        env env,
+        files,
+        backend="sentry",
+        credentials=None,
+        org=None,
+        project=None,
+        url=None,
+        store=None,
+        product=None,
+        version=None,
+        retry=None
+    ) {
+        let files = match files.get_type() {
+            "string" => SymbolFiles::Directory(expand_channel(&env, &files.to_str())),
+            _ => {
+                required_type_arg("files", "FileManifest", files)?;
+                let raw_files = files.0.borrow();
+                let files: &FileManifest = raw_files.as_any().downcast_ref().unwrap();
+
+                SymbolFiles::Manifest(files.files.clone())
+            }
+        };
+        let backend = required_str_arg("backend", backend)?;
+        let credentials = optional_secret_arg("credentials", credentials)?;
+        let org = optional_str_arg("org", org)?;
+        let project = optional_str_arg("project", project)?;
+        let url = optional_str_arg("url", url)?;
+        let store = optional_str_arg("store", store)?;
+        let product = optional_str_arg("product", product)?;
+        let version = optional_str_arg("version", version)?;
+        let retry = optional_retry_arg("retry", retry)?;
+
+        let store = match backend.as_str() {
+            "sentry" => {
+                for (name, value) in &[("store", &store), ("product", &product), ("version", &version)] {
+                    if value.is_some() {
+                        return Err(invalid_arg(
+                            name,
+                            format!("{} only applies to the symstore backend", name),
+                        ));
+                    }
+                }
+
+                SymbolStore::Sentry {
+                    org: backend_arg("org", &backend, org)?,
+                    project: backend_arg("project", &backend, project)?,
+                    url,
+                }
+            }
+            "symstore" => {
+                for (name, value) in &[("org", &org), ("project", &project), ("url", &url)] {
+                    if value.is_some() {
+                        return Err(invalid_arg(
+                            name,
+                            format!("{} only applies to the sentry backend", name),
+                        ));
+                    }
+                }
+                if credentials.is_some() {
+                    return Err(invalid_arg(
+                        "credentials",
+                        "symbol stores are accessed with the credentials of the user running tugger"
+                            .to_string(),
+                    ));
+                }
+
+                SymbolStore::Symstore {
+                    store: backend_arg("store", &backend, store)?,
+                    product: backend_arg("product", &backend, product)?,
+                    version,
+                }
+            }
+            _ => {
+                return Err(invalid_arg(
+                    "backend",
+                    format!("unknown backend {}; expected sentry or symstore", backend),
+                ));
+            }
+        };
+
+        Ok(Value::new(UploadSymbols {
+            files,
+            store,
+            credentials,
+            retry,
+        }))
+    }
+}
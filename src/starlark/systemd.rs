@@ -0,0 +1,104 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::values::FileManifest;
+use super::{optional_str_arg, required_str_arg, to_value_error};
+use crate::filemanifest::FileContent;
+use crate::systemd::UnitSections;
+use starlark::starlark_module;
+use starlark::values::{Value, ValueError};
+use starlark::{
+    starlark_fun, starlark_signature, starlark_signature_extraction, starlark_signatures,
+};
+
+/// Convert a value in a unit file section to its string representations.
+///
+/// Lists produce a repeated key for each element.
+fn section_values(key: &str, value: &Value) -> Result<Vec<String>, ValueError> {
+    match value.get_type() {
+        "string" | "int" => Ok(vec![value.to_str()]),
+        "bool" => Ok(vec![if value.to_bool() { "yes" } else { "no" }.to_string()]),
+        "list" => {
+            let mut res = Vec::new();
+            for v in value.into_iter()? {
+                res.extend(section_values(key, &v)?);
+            }
+            Ok(res)
+        }
+        t => Err(to_value_error(
+            "systemd_unit",
+            format!(
+                "value of {} must be a str, int, bool, or list; got {}",
+                key, t
+            ),
+        )),
+    }
+}
+
+/// Convert a `dict` of section name to `dict` of settings to unit sections.
+fn parse_sections(sections: &Value) -> Result<UnitSections, ValueError> {
+    let mut res = Vec::new();
+
+    for section in sections.into_iter()? {
+        let entries = sections.at(section.clone())?;
+
+        if section.get_type() != "string" || entries.get_type() != "dict" {
+            return Err(to_value_error(
+                "systemd_unit",
+                format!(
+                    "sections must map str section names to dicts; got {} to {}",
+                    section.get_type(),
+                    entries.get_type()
+                ),
+            ));
+        }
+
+        let mut values = Vec::new();
+
+        for key in entries.into_iter()? {
+            let value = entries.at(key.clone())?;
+            let key = key.to_str();
+
+            for v in section_values(&key, &value)? {
+                values.push((key.clone(), v));
+            }
+        }
+
+        res.push((section.to_str(), values));
+    }
+
+    Ok(res)
+}
+
+starlark_module! { systemd_module =>
+    systemd_unit(name, contents=None, sections=None) {
+        let name = required_str_arg("name", name)?;
+        let contents = optional_str_arg("contents", contents)?;
+
+        let name = crate::systemd::unit_name(&name).map_err(|e| to_value_error("systemd_unit", e))?;
+
+        let data = match (contents, sections.get_type()) {
+            (Some(contents), "NoneType") => contents,
+            (None, "dict") => {
+                crate::systemd::render_unit(&parse_sections(sections)?)
+            }
+            _ => {
+                return Err(to_value_error("systemd_unit",
+                    "exactly one of contents or sections must be defined".to_string(),
+                ))
+            }
+        };
+
+        let mut manifest = FileManifest::default();
+        manifest.files.insert(
+            format!("{}/{}", crate::systemd::UNIT_DIR, name),
+            FileContent::Data {
+                data: data.into_bytes(),
+                executable: false,
+            },
+        );
+
+        Ok(Value::new(manifest))
+    }
+}
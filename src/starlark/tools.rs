@@ -0,0 +1,78 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::{invalid_arg, optional_str_arg, required_str_arg};
+use crate::tools::ToolSpec;
+use starlark::environment::Environment;
+use starlark::starlark_module;
+use starlark::values::{default_compare, TypedValue, Value, ValueError, ValueResult};
+use starlark::{
+    any, immutable, not_supported, starlark_fun, starlark_signature, starlark_signature_extraction,
+    starlark_signatures,
+};
+use std::any::Any;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+impl TypedValue for ToolSpec {
+    immutable!();
+    any!();
+    not_supported!(binop);
+    not_supported!(container);
+    not_supported!(function);
+    not_supported!(get_hash);
+    not_supported!(to_int);
+
+    fn to_str(&self) -> String {
+        format!("Tool<name={}, url={}>", self.name, self.url)
+    }
+
+    fn to_repr(&self) -> String {
+        self.to_str()
+    }
+
+    fn get_type(&self) -> &'static str {
+        "Tool"
+    }
+
+    fn to_bool(&self) -> bool {
+        true
+    }
+
+    fn compare(&self, other: &dyn TypedValue, _recursion: u32) -> Result<Ordering, ValueError> {
+        default_compare(self, other)
+    }
+}
+
+starlark_module! { tools_module =>
+    tool(name, url, sha256=None, path=None) {
+        let name = required_str_arg("name", name)?;
+        let url = required_str_arg("url", url)?;
+        let sha256 = optional_str_arg("sha256", sha256)?.map(|s| s.to_lowercase());
+        let path = optional_str_arg("path", path)?;
+
+        if name.is_empty() || name.contains('/') || name.contains('\\') {
+            return Err(invalid_arg(
+                "name",
+                format!("{} isn't a filename", name),
+            ));
+        }
+
+        if let Some(sha256) = &sha256 {
+            if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(invalid_arg(
+                    "sha256",
+                    format!("{} is not a SHA-256 digest", sha256),
+                ));
+            }
+        }
+
+        Ok(Value::new(ToolSpec {
+            name,
+            url,
+            sha256,
+            path,
+        }))
+    }
+}
@@ -2,16 +2,18 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use slog::warn;
+use crate::archive::{Archive, TarArchive};
+use crate::pipeline::Pipeline;
+use serde::Serialize;
 use starlark::environment::Environment;
 use starlark::values::{default_compare, TypedValue, Value, ValueError, ValueResult};
 use starlark::{any, immutable, not_supported};
 use std::any::Any;
 use std::cmp::Ordering;
 use std::collections::HashMap;
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct SourceFile {
     pub path: PathBuf,
 }
@@ -51,7 +53,7 @@ impl TypedValue for SourceFile {
 /// This may expand in the future to abstract the source of file content so
 /// that file data can come from memory, etc. We may also want to add per-file
 /// metadata, such as the owner, permissions, etc. For now things are simple.
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, Serialize)]
 pub struct FileManifest {
     pub files: crate::filemanifest::FileManifest,
 }
@@ -86,52 +88,6 @@ impl TypedValue for FileManifest {
     }
 }
 
-/// Represents a step to produce a tar archive.
-#[derive(Debug, Clone)]
-pub struct TarArchive {
-    /// Filename of produced tar archive.
-    pub dest_name: String,
-
-    /// Manifest denoting content to be added to archive.
-    pub file_manifest: FileManifest,
-}
-
-impl TarArchive {
-    pub fn execute(&self, logger: &slog::Logger, dist_path: &Path) -> Result<(), String> {
-        let dest_path = dist_path.join(&self.dest_name);
-
-        warn!(logger, "writing tarball to {}", dest_path.display());
-
-        std::fs::create_dir_all(dest_path.parent().unwrap()).or_else(|_| {
-            Err(format!(
-                "unable to create directory for {}",
-                dest_path.display()
-            ))
-        })?;
-
-        let fh = std::fs::File::create(&dest_path).or_else(|_| {
-            Err(format!(
-                "unable to open {} for writing",
-                dest_path.display()
-            ))
-        })?;
-
-        let mut builder = tar::Builder::new(fh);
-        builder.mode(tar::HeaderMode::Deterministic);
-
-        for (rel_path, fs_path) in &self.file_manifest.files {
-            warn!(logger, "adding {} as {}", fs_path.display(), rel_path);
-            builder
-                .append_path_with_name(fs_path, rel_path)
-                .or_else(|e| Err(e.to_string()))?;
-        }
-
-        builder.finish().or_else(|e| Err(e.to_string()))?;
-
-        Ok(())
-    }
-}
-
 impl TypedValue for TarArchive {
     immutable!();
     any!();
@@ -165,25 +121,37 @@ impl TypedValue for TarArchive {
     }
 }
 
-/// Represents a generic step.
-#[derive(Debug, Clone)]
-pub enum Step {
-    DebianDebArchive(super::debian::DebianDebArchive),
-    Snapcraft(super::snap::Snapcraft),
-    TarArchive(TarArchive),
-}
+impl TypedValue for Archive {
+    immutable!();
+    any!();
+    not_supported!(binop);
+    not_supported!(container);
+    not_supported!(function);
+    not_supported!(get_hash);
+    not_supported!(to_int);
 
-/// Represents a series of `Step`s to execute.
-#[derive(Debug, Default, Clone)]
-pub struct Pipeline {
-    /// The name of this pipeline.
-    pub name: String,
+    fn to_str(&self) -> String {
+        format!(
+            "Archive<dest_name={}, format={:?}, file_manifest={:#?}",
+            self.dest_name, self.format, self.file_manifest
+        )
+    }
 
-    /// Path to write distribution files.
-    pub dist_path: PathBuf,
+    fn to_repr(&self) -> String {
+        self.to_str()
+    }
 
-    /// The series of steps to execute.
-    pub steps: Vec<Step>,
+    fn get_type(&self) -> &'static str {
+        "Archive"
+    }
+
+    fn to_bool(&self) -> bool {
+        false
+    }
+
+    fn compare(&self, other: &dyn TypedValue, _recursion: u32) -> Result<Ordering, ValueError> {
+        default_compare(self, other)
+    }
 }
 
 impl TypedValue for Pipeline {
@@ -0,0 +1,91 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::values::FileManifest;
+use super::{
+    optional_metadata_arg, optional_str_arg, required_dict_arg, required_str_arg, required_type_arg,
+};
+use crate::filemanifest::FileContent;
+use starlark::starlark_module;
+use starlark::values::{RuntimeError, Value, ValueError};
+use starlark::{
+    starlark_fun, starlark_signature, starlark_signature_extraction, starlark_signatures,
+};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+starlark_module! { windows_module =>
+    windows_exe_resources(env env, manifest, exe, version_info=None, icon=None, metadata=None) {
+        required_type_arg("manifest", "FileManifest", manifest)?;
+        let exe = required_str_arg("exe", exe)?;
+        if version_info.get_type() != "NoneType" {
+            required_dict_arg("version_info", "string", "string", version_info)?;
+        }
+        let icon = optional_str_arg("icon", icon)?;
+        let metadata = optional_metadata_arg("metadata", metadata)?;
+
+        let to_value_error = |message: String| {
+            ValueError::Runtime(RuntimeError {
+                code: "windows_exe_resources",
+                message,
+                label: "windows_exe_resources()".to_string(),
+            })
+        };
+
+        let raw_manifest = manifest.0.borrow();
+        let manifest: &FileManifest = raw_manifest.as_any().downcast_ref().unwrap();
+
+        let content = manifest
+            .files
+            .get(&exe)
+            .ok_or_else(|| to_value_error(format!("{} is not in the manifest", exe)))?;
+        let data = content.read().map_err(to_value_error)?;
+
+        let mut tree = crate::pe_resources::read_resources(&data)
+            .map_err(|e| to_value_error(format!("unable to read resources of {}: {}", exe, e)))?;
+
+        if version_info.get_type() == "dict" || metadata.is_some() {
+            // Explicit version strings take precedence over metadata.
+            let mut strings = metadata
+                .map(|m| m.windows_version_info())
+                .unwrap_or_else(BTreeMap::new);
+
+            if version_info.get_type() == "dict" {
+                for key in version_info.into_iter()? {
+                    let value = version_info.at(key.clone())?;
+                    strings.insert(key.to_str(), value.to_str());
+                }
+            }
+
+            let dll = crate::pe_resources::is_dll(&data).map_err(to_value_error)?;
+            crate::pe_resources::set_version_info(
+                &mut tree,
+                crate::pe_resources::version_info(&strings, dll),
+            );
+        }
+
+        if let Some(icon) = icon {
+            let path = PathBuf::from(env.get("CWD").unwrap().to_str()).join(icon);
+            let ico = std::fs::read(&path)
+                .map_err(|e| to_value_error(format!("unable to read {}: {}", path.display(), e)))?;
+
+            crate::pe_resources::set_icon(&mut tree, &ico)
+                .map_err(|e| to_value_error(format!("{}: {}", path.display(), e)))?;
+        }
+
+        let data = crate::pe_resources::write_resources(&data, &tree)
+            .map_err(|e| to_value_error(format!("unable to write resources of {}: {}", exe, e)))?;
+
+        let mut result = manifest.clone();
+        result.files.insert(
+            exe,
+            FileContent::Data {
+                data,
+                executable: content.is_executable(),
+            },
+        );
+
+        Ok(Value::new(result))
+    }
+}
@@ -0,0 +1,193 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Uploading debug symbols.
+
+Crash reports of released binaries can only be symbolicated if the debug
+information of those binaries is available to the crash reporting
+service. Debug files, such as split DWARF `.debug` files, dSYM bundles,
+PDBs, and Breakpad `.sym` files, are uploaded with the tool of each
+backend:
+
+* [Sentry](https://sentry.io/) with `sentry-cli debug-files upload`.
+* Microsoft symbol stores, such as a file share served by a symbol
+  server, with `symstore add`.
+*/
+
+use crate::filemanifest::{install_files, FileManifest, InstallMode};
+use crate::mode_policy::ModePolicy;
+use crate::retry::{AttemptError, RetryPolicy};
+use crate::secrets::Secret;
+use serde::Serialize;
+use slog::{warn, Logger};
+use std::path::Path;
+use std::process::Command;
+
+/// Environment variable `sentry-cli` reads its auth token from.
+const SENTRY_AUTH_TOKEN: &str = "SENTRY_AUTH_TOKEN";
+
+/// Where debug symbols are uploaded to.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "backend", rename_all = "snake_case")]
+pub enum SymbolStore {
+    /// A Sentry project.
+    Sentry {
+        org: String,
+        project: String,
+
+        /// URL of a self-hosted Sentry server.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        url: Option<String>,
+    },
+
+    /// A Microsoft symbol store.
+    Symstore {
+        /// Path of the store, e.g. `\\server\symbols`.
+        store: String,
+
+        /// Name of the product the files belong to.
+        product: String,
+
+        /// Version of the product.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        version: Option<String>,
+    },
+}
+
+impl SymbolStore {
+    /// Obtain the command uploading the debug files in a directory.
+    fn command(&self, dir: &Path, token: Option<&str>) -> Command {
+        match self {
+            SymbolStore::Sentry { org, project, url } => {
+                let mut command = Command::new("sentry-cli");
+
+                if let Some(url) = url {
+                    command.arg("--url").arg(url);
+                }
+                if let Some(token) = token {
+                    command.env(SENTRY_AUTH_TOKEN, token);
+                }
+
+                command
+                    .arg("debug-files")
+                    .arg("upload")
+                    .arg("--org")
+                    .arg(org)
+                    .arg("--project")
+                    .arg(project)
+                    .arg(dir);
+
+                command
+            }
+            SymbolStore::Symstore {
+                store,
+                product,
+                version,
+            } => {
+                let mut command = Command::new("symstore");
+                command
+                    .arg("add")
+                    .arg("/r")
+                    .arg("/f")
+                    .arg(dir)
+                    .arg("/s")
+                    .arg(store)
+                    .arg("/t")
+                    .arg(product);
+
+                if let Some(version) = version {
+                    command.arg("/v").arg(version);
+                }
+
+                command
+            }
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            SymbolStore::Sentry { org, project, .. } => {
+                format!("Sentry project {}/{}", org, project)
+            }
+            SymbolStore::Symstore { store, .. } => format!("symbol store {}", store),
+        }
+    }
+}
+
+/// Upload debug files to a symbol store.
+///
+/// `token` authenticates with the store, if it requires authentication.
+pub fn upload_symbols(
+    logger: &Logger,
+    files: &FileManifest,
+    store: &SymbolStore,
+    token: Option<&str>,
+    retry: &RetryPolicy,
+) -> Result<(), String> {
+    if files.is_empty() {
+        return Err("no debug files to upload".to_string());
+    }
+
+    // The tools upload directories, so only the files of the manifest
+    // are exposed to them.
+    let temp_dir = tempfile::Builder::new()
+        .prefix("tugger-symbols-")
+        .tempdir()
+        .map_err(|e| format!("unable to create temp directory: {}", e))?;
+    install_files(
+        temp_dir.path(),
+        files,
+        InstallMode::Copy,
+        &ModePolicy::default(),
+    )?;
+
+    upload_symbols_dir(logger, temp_dir.path(), store, token, retry)
+}
+
+/// Upload the debug files in a directory to a symbol store.
+///
+/// Subdirectories are searched as well.
+pub fn upload_symbols_dir(
+    logger: &Logger,
+    dir: &Path,
+    store: &SymbolStore,
+    token: Option<&str>,
+    retry: &RetryPolicy,
+) -> Result<(), String> {
+    if !dir.is_dir() {
+        return Err(format!("{} is not a directory", dir.display()));
+    }
+
+    warn!(
+        logger,
+        "uploading debug files in {} to {}",
+        dir.display(),
+        store.describe()
+    );
+
+    retry.retry(logger, &format!("upload to {}", store.describe()), || {
+        crate::process::run_logged(logger, &mut store.command(dir, token))
+            .map_err(AttemptError::process)
+    })
+}
+
+/// Debug files to upload.
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum SymbolFiles {
+    Manifest(FileManifest),
+
+    /// A directory relative to the distribution path.
+    Directory(String),
+}
+
+/// Represents a request to upload debug symbols.
+#[derive(Debug, Clone, Serialize)]
+pub struct UploadSymbols {
+    pub files: SymbolFiles,
+    pub store: SymbolStore,
+    pub credentials: Option<Secret>,
+    pub retry: Option<RetryPolicy>,
+}
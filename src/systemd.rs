@@ -0,0 +1,189 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+systemd unit files.
+
+Unit files are installed to `lib/systemd/system/`. Debian packages
+containing units get maintainer scripts which enable and start them on
+installation and stop them on removal, like those generated by
+`dh_installsystemd` using `deb-systemd-helper` and `deb-systemd-invoke`.
+*/
+
+use crate::filemanifest::{FileContent, FileManifest};
+use std::collections::BTreeMap;
+
+/// Directory in a manifest containing unit files.
+pub const UNIT_DIR: &str = "lib/systemd/system";
+
+/// Unit file suffixes, which define the type of unit.
+const UNIT_SUFFIXES: &[&str] = &[
+    ".automount",
+    ".mount",
+    ".path",
+    ".service",
+    ".slice",
+    ".socket",
+    ".swap",
+    ".target",
+    ".timer",
+];
+
+/// Normalize the name of a unit, defaulting to a service.
+pub fn unit_name(name: &str) -> Result<String, String> {
+    if name.is_empty() || name.contains('/') {
+        return Err(format!("invalid systemd unit name: {}", name));
+    }
+
+    if UNIT_SUFFIXES.iter().any(|suffix| name.ends_with(suffix)) {
+        Ok(name.to_string())
+    } else {
+        Ok(format!("{}.service", name))
+    }
+}
+
+/// Sections of a unit file, as section names and lists of key-value pairs.
+///
+/// Keys may be repeated, e.g. for multiple `ExecStartPre` commands.
+pub type UnitSections = Vec<(String, Vec<(String, String)>)>;
+
+/// Render the content of a unit file from its sections.
+pub fn render_unit(sections: &UnitSections) -> String {
+    sections
+        .iter()
+        .map(|(section, entries)| {
+            let mut s = format!("[{}]\n", section);
+
+            for (key, value) in entries {
+                s.push_str(&format!("{}={}\n", key, value));
+            }
+
+            s
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Obtain the names of units in a manifest that should be enabled.
+///
+/// These are units in `lib/systemd/system/` or `usr/lib/systemd/system/`
+/// with an `[Install]` section. Template units can't be enabled without
+/// an instance name, so they are excluded.
+pub fn enabled_units(files: &FileManifest) -> Result<Vec<String>, String> {
+    let mut units = Vec::new();
+
+    for (key, content) in files {
+        let name = match key
+            .strip_prefix("usr/")
+            .unwrap_or(key)
+            .strip_prefix(UNIT_DIR)
+            .and_then(|name| name.strip_prefix('/'))
+        {
+            Some(name) => name,
+            None => continue,
+        };
+
+        if name.contains('/')
+            || name.contains('@')
+            || !UNIT_SUFFIXES.iter().any(|suffix| name.ends_with(suffix))
+            || matches!(content, FileContent::Symlink(_))
+        {
+            continue;
+        }
+
+        let data = content.read()?;
+        let has_install = String::from_utf8_lossy(&data)
+            .lines()
+            .any(|line| line.trim() == "[Install]");
+
+        if has_install {
+            units.push(name.to_string());
+        }
+    }
+
+    Ok(units)
+}
+
+/// Obtain Debian maintainer scripts enabling and starting units.
+///
+/// Returns a mapping of script name (e.g. `postinst`) to content. The
+/// mapping is empty if there are no units to enable.
+pub fn deb_maintainer_scripts(units: &[String]) -> BTreeMap<String, String> {
+    let mut scripts = BTreeMap::new();
+
+    if units.is_empty() {
+        return scripts;
+    }
+
+    let names = units
+        .iter()
+        .map(|unit| format!("'{}'", unit))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    scripts.insert(
+        "postinst".to_string(),
+        format!(
+            r#"#!/bin/sh
+set -e
+if [ "$1" = "configure" ] || [ "$1" = "abort-upgrade" ] || [ "$1" = "abort-deconfigure" ] || [ "$1" = "abort-remove" ] ; then
+    for unit in {names}; do
+        # This will only remove masks created by deb-systemd-helper on removal.
+        deb-systemd-helper unmask "$unit" >/dev/null || true
+
+        # was-enabled defaults to true, so new installations run enable.
+        if deb-systemd-helper --quiet was-enabled "$unit"; then
+            deb-systemd-helper enable "$unit" >/dev/null || true
+        else
+            deb-systemd-helper update-state "$unit" >/dev/null || true
+        fi
+    done
+
+    if [ -d /run/systemd/system ]; then
+        systemctl --system daemon-reload >/dev/null || true
+        deb-systemd-invoke restart {names} >/dev/null || true
+    fi
+fi
+"#,
+            names = names
+        ),
+    );
+
+    scripts.insert(
+        "prerm".to_string(),
+        format!(
+            r#"#!/bin/sh
+set -e
+if [ -z "${{DPKG_ROOT:-}}" ] && [ "$1" = remove ] && [ -d /run/systemd/system ] ; then
+    deb-systemd-invoke stop {names} >/dev/null || true
+fi
+"#,
+            names = names
+        ),
+    );
+
+    scripts.insert(
+        "postrm".to_string(),
+        format!(
+            r#"#!/bin/sh
+set -e
+if [ "$1" = remove ] && [ -d /run/systemd/system ] ; then
+    systemctl --system daemon-reload >/dev/null || true
+fi
+if [ -x "/usr/bin/deb-systemd-helper" ]; then
+    if [ "$1" = "remove" ]; then
+        deb-systemd-helper mask {names} >/dev/null || true
+    fi
+    if [ "$1" = "purge" ]; then
+        deb-systemd-helper purge {names} >/dev/null || true
+        deb-systemd-helper unmask {names} >/dev/null || true
+    fi
+fi
+"#,
+            names = names
+        ),
+    );
+
+    scripts
+}
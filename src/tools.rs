@@ -0,0 +1,199 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Provisioning of external tools.
+
+Many steps run external tools, such as `wixl` or `appimagetool`, which
+bare CI runners don't have. Tools can instead be declared with a URL and
+SHA-256 digest. They are downloaded into the cache when a pipeline using
+them executes, and their directory is put on `PATH` while its steps
+execute.
+
+A tool is either downloaded as the executable itself, e.g. an AppImage
+or a static build, or extracted from an archive. Downloads are pinned by
+the configuration or by the lockfile.
+*/
+
+use crate::extract::ArchiveFormat;
+use crate::lock::Lock;
+use crate::retry::RetryPolicy;
+use serde::Serialize;
+use slog::{warn, Logger};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Namespace of provisioned tools in the cache.
+const CACHE_NAMESPACE: &str = "tools";
+
+/// Describes an external tool to provision.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ToolSpec {
+    /// Filename of the executable, e.g. `appimagetool`.
+    pub name: String,
+
+    /// URL of the executable or of an archive containing it.
+    pub url: String,
+
+    /// SHA-256 digest of the downloaded file.
+    ///
+    /// If not defined, the download is pinned by the lockfile.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+
+    /// Path of the executable within the archive.
+    ///
+    /// If not defined, the archive must contain a single file named `name`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+}
+
+impl ToolSpec {
+    /// The format of the downloaded file, if it is an archive.
+    fn archive_format(&self) -> Option<ArchiveFormat> {
+        let filename = self.url.split(['?', '#']).next().unwrap();
+
+        ArchiveFormat::from_filename(filename)
+    }
+}
+
+/// Obtain the executable of an extracted archive.
+fn find_executable(
+    tool: &ToolSpec,
+    files: &crate::filemanifest::FileManifest,
+) -> Result<PathBuf, String> {
+    let candidates: Vec<_> = match &tool.path {
+        Some(path) => files
+            .iter()
+            .filter(|(key, _)| *key == path.trim_start_matches("./"))
+            .collect(),
+        None => files
+            .iter()
+            .filter(|(key, _)| key.rsplit('/').next() == Some(tool.name.as_str()))
+            .collect(),
+    };
+
+    match candidates.as_slice() {
+        [(_, crate::filemanifest::FileContent::Path(path))] => Ok(path.clone()),
+        [] => Err(format!(
+            "{} doesn't contain {}",
+            tool.url,
+            tool.path.as_deref().unwrap_or(&tool.name)
+        )),
+        [(key, _)] => Err(format!("{} in {} isn't a regular file", key, tool.url)),
+        _ => Err(format!(
+            "{} contains several files named {}; define path",
+            tool.url, tool.name
+        )),
+    }
+}
+
+/// Download a tool into the cache unless it is already there.
+///
+/// Returns the directory holding the tool's executable.
+pub fn provision(
+    logger: &Logger,
+    tool: &ToolSpec,
+    lock: &Lock,
+    retry: &RetryPolicy,
+) -> Result<PathBuf, String> {
+    if let Some(sha256) = lock.expected_digest(&tool.url, tool.sha256.as_deref())? {
+        let dir = crate::cache::content_path(CACHE_NAMESPACE, &sha256)?;
+        let dest = dir.join(&tool.name);
+
+        if dest.is_file() {
+            crate::cache::touch(&dest);
+            lock.record(&tool.url, &sha256);
+            return Ok(dir);
+        }
+    }
+
+    warn!(logger, "provisioning {} from {}", tool.name, tool.url);
+
+    let (download, sha256) = lock.fetch(logger, &tool.url, tool.sha256.as_deref(), retry)?;
+    let dir = crate::cache::content_path(CACHE_NAMESPACE, &sha256)?;
+    let dest = dir.join(&tool.name);
+
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| format!("unable to create {}: {}", dir.display(), e))?;
+
+    // The executable is copied to a temporary file in the same directory so
+    // the final rename is atomic and failures never populate the cache.
+    let temp = tempfile::NamedTempFile::new_in(&dir)
+        .map_err(|e| format!("unable to create temp file: {}", e))?;
+
+    match tool.archive_format() {
+        Some(format) => {
+            let extract_dir = tempfile::TempDir::new()
+                .map_err(|e| format!("unable to create temp dir: {}", e))?;
+            let files = crate::extract::extract_archive(
+                &download,
+                &format!("{}.{}", tool.name, format.extension()),
+                extract_dir.path(),
+                0,
+            )?;
+            let executable = find_executable(tool, &files)?;
+
+            std::fs::copy(&executable, temp.path())
+                .map_err(|e| format!("unable to copy {}: {}", executable.display(), e))?;
+        }
+        None => {
+            std::fs::copy(&download, temp.path())
+                .map_err(|e| format!("unable to copy {}: {}", download.display(), e))?;
+        }
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        temp.as_file()
+            .set_permissions(std::fs::Permissions::from_mode(0o755))
+            .map_err(|e| format!("unable to set permissions: {}", e))?;
+    }
+
+    temp.persist(&dest)
+        .map_err(|e| format!("unable to write {}: {}", dest.display(), e))?;
+
+    Ok(dir)
+}
+
+/// Provision tools and obtain the environment putting them on `PATH`.
+///
+/// Tools precede the existing `PATH`, in the order they are defined.
+pub fn provision_all(
+    logger: &Logger,
+    tools: &[ToolSpec],
+    lock: &Lock,
+    retry: &RetryPolicy,
+) -> Result<BTreeMap<String, String>, String> {
+    let mut env = BTreeMap::new();
+
+    if tools.is_empty() {
+        return Ok(env);
+    }
+
+    let mut paths = Vec::new();
+
+    for tool in tools {
+        let dir = provision(logger, tool, lock, retry)?;
+
+        if !paths.contains(&dir) {
+            paths.push(dir);
+        }
+    }
+
+    if let Some(path) = std::env::var_os("PATH") {
+        paths.extend(std::env::split_paths(&path));
+    }
+
+    let path = std::env::join_paths(paths)
+        .map_err(|e| format!("unable to define PATH: {}", e))?
+        .into_string()
+        .map_err(|path| format!("PATH isn't UTF-8: {}", Path::new(&path).display()))?;
+
+    env.insert("PATH".to_string(), path);
+
+    Ok(env)
+}
@@ -0,0 +1,88 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Concurrent uploading of artifacts.
+*/
+
+use crate::release_notes::format_size;
+use slog::{warn, Logger};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// Upload artifacts concurrently.
+///
+/// `upload` is called for every artifact, with at most `concurrency`
+/// calls running at once. Aggregate progress is logged as each upload
+/// completes. A failed upload doesn't stop other uploads; failures are
+/// reported together once all uploads are done.
+pub fn upload_concurrently<F>(
+    logger: &Logger,
+    artifacts: &[PathBuf],
+    concurrency: usize,
+    upload: F,
+) -> Result<(), String>
+where
+    F: Fn(&Path) -> Result<(), String> + Sync,
+{
+    let mut total_bytes = 0;
+
+    for artifact in artifacts {
+        total_bytes += std::fs::metadata(artifact)
+            .map_err(|e| format!("unable to stat {}: {}", artifact.display(), e))?
+            .len();
+    }
+
+    let next = AtomicUsize::new(0);
+    let completed = AtomicUsize::new(0);
+    let completed_bytes = AtomicU64::new(0);
+    let failures = Mutex::new(Vec::new());
+
+    std::thread::scope(|scope| {
+        for _ in 0..concurrency.max(1).min(artifacts.len()) {
+            scope.spawn(|| {
+                while let Some(artifact) = artifacts.get(next.fetch_add(1, Ordering::SeqCst)) {
+                    match upload(artifact) {
+                        Ok(()) => {
+                            let size = std::fs::metadata(artifact).map(|m| m.len()).unwrap_or(0);
+                            let count = completed.fetch_add(1, Ordering::SeqCst) + 1;
+                            let bytes = completed_bytes.fetch_add(size, Ordering::SeqCst) + size;
+
+                            warn!(
+                                logger,
+                                "uploaded {} ({} of {} artifacts, {} of {})",
+                                artifact.display(),
+                                count,
+                                artifacts.len(),
+                                format_size(bytes),
+                                format_size(total_bytes)
+                            );
+                        }
+                        Err(e) => {
+                            warn!(logger, "uploading {} failed: {}", artifact.display(), e);
+                            failures
+                                .lock()
+                                .unwrap()
+                                .push(format!("{}: {}", artifact.display(), e));
+                        }
+                    }
+                }
+            });
+        }
+    });
+
+    let failures = failures.into_inner().unwrap();
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} of {} uploads failed: {}",
+            failures.len(),
+            artifacts.len(),
+            failures.join("; ")
+        ))
+    }
+}
@@ -0,0 +1,164 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Inferring the version of the project being built.
+
+Versions are detected from an ordered list of sources. The first source
+providing a version wins, so the same configuration can use the version
+of a tagged release, the version in `Cargo.toml`, or a version chosen by
+CI for nightly builds.
+*/
+
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// A place a version can be obtained from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum VersionSource {
+    /// The version of the package defined by a `Cargo.toml`.
+    Cargo(PathBuf),
+
+    /// A version tag, such as `v1.2.3`, pointing at `HEAD`.
+    GitTag,
+
+    /// An environment variable.
+    Env(String),
+}
+
+impl VersionSource {
+    /// Describe the source in messages.
+    pub fn describe(&self) -> String {
+        match self {
+            VersionSource::Cargo(path) => format!("cargo:{}", path.display()),
+            VersionSource::GitTag => "git_tag".to_string(),
+            VersionSource::Env(name) => format!("env:{}", name),
+        }
+    }
+
+    /// Obtain the version from this source.
+    ///
+    /// Returns `Ok(None)` if the source doesn't provide a version, e.g.
+    /// because `HEAD` isn't tagged or the environment variable isn't set.
+    pub fn detect(&self, cwd: &Path) -> Result<Option<String>, String> {
+        match self {
+            VersionSource::Cargo(path) => {
+                let path = cwd.join(path);
+
+                if !path.exists() {
+                    return Ok(None);
+                }
+
+                let (_, version) = crate::cargo::package_name_version(&path)?;
+
+                Ok(Some(version))
+            }
+            VersionSource::GitTag => git_tag_version(cwd),
+            VersionSource::Env(name) => Ok(std::env::var(name)
+                .ok()
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())),
+        }
+    }
+}
+
+impl FromStr for VersionSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "cargo" => Ok(VersionSource::Cargo(PathBuf::from("Cargo.toml"))),
+            "git_tag" => Ok(VersionSource::GitTag),
+            _ => {
+                if let Some(path) = s.strip_prefix("cargo:").filter(|p| !p.is_empty()) {
+                    Ok(VersionSource::Cargo(PathBuf::from(path)))
+                } else if let Some(name) = s.strip_prefix("env:").filter(|n| !n.is_empty()) {
+                    Ok(VersionSource::Env(name.to_string()))
+                } else {
+                    Err(format!(
+                        "unknown version source {}; expected cargo, cargo:<path>, git_tag, or env:<name>",
+                        s
+                    ))
+                }
+            }
+        }
+    }
+}
+
+/// Strip the `v` prefix of a version tag.
+///
+/// Returns `None` if the tag doesn't look like a version.
+fn tag_version(tag: &str) -> Option<&str> {
+    let version = tag.strip_prefix('v').unwrap_or(tag);
+
+    if version.starts_with(|c: char| c.is_ascii_digit()) {
+        Some(version)
+    } else {
+        None
+    }
+}
+
+/// Numeric components of a version, for choosing between tags.
+fn version_key(version: &str) -> Vec<u64> {
+    version
+        .split(|c: char| !c.is_ascii_digit())
+        .filter_map(|part| part.parse().ok())
+        .collect()
+}
+
+/// Obtain the version of the tag pointing at `HEAD` of the repository
+/// containing `cwd`.
+///
+/// If several version tags point at `HEAD`, the highest version is used.
+fn git_tag_version(cwd: &Path) -> Result<Option<String>, String> {
+    let repo = match git2::Repository::discover(cwd) {
+        Ok(repo) => repo,
+        Err(_) => return Ok(None),
+    };
+    let head = match repo.head().and_then(|head| head.peel_to_commit()) {
+        Ok(head) => head.id(),
+        Err(_) => return Ok(None),
+    };
+
+    let mut versions = Vec::new();
+
+    for name in repo
+        .tag_names(None)
+        .map_err(|e| e.to_string())?
+        .iter()
+        .flatten()
+    {
+        let commit = repo
+            .revparse_single(&format!("refs/tags/{}", name))
+            .and_then(|o| o.peel_to_commit());
+
+        if let (Ok(commit), Some(version)) = (commit, tag_version(name)) {
+            if commit.id() == head {
+                versions.push(version.to_string());
+            }
+        }
+    }
+
+    Ok(versions.into_iter().max_by_key(|v| version_key(v)))
+}
+
+/// Detect a version from the first source providing one.
+///
+/// Fails if no source provides a version.
+pub fn detect_version(cwd: &Path, sources: &[VersionSource]) -> Result<String, String> {
+    for source in sources {
+        if let Some(version) = source.detect(cwd)? {
+            return Ok(version);
+        }
+    }
+
+    Err(format!(
+        "unable to detect version from {}",
+        sources
+            .iter()
+            .map(|s| s.describe())
+            .collect::<Vec<_>>()
+            .join(", ")
+    ))
+}
@@ -0,0 +1,699 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests executing pipelines with `tugger run`.
+
+#![cfg(all(unix, feature = "cli"))]
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process::{Command, Output};
+use std::sync::{Arc, Mutex};
+
+/// Create a project directory with a configuration file and a `bin`
+/// directory searched for programs first.
+fn project(config: &str) -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(dir.path().join("bin")).unwrap();
+    std::fs::write(dir.path().join("tugger.ship"), config).unwrap();
+    std::fs::write(dir.path().join("app.txt"), "hello\n").unwrap();
+
+    dir
+}
+
+/// Install a program into the `bin` directory of a project.
+fn install_program(dir: &Path, name: &str, script: &str) {
+    let path = dir.join("bin").join(name);
+    std::fs::write(&path, script).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+fn tugger(dir: &Path, args: &[&str]) -> Output {
+    let mut paths = vec![dir.join("bin")];
+    paths.extend(std::env::split_paths(
+        &std::env::var_os("PATH").unwrap_or_default(),
+    ));
+
+    Command::new(env!("CARGO_BIN_EXE_tugger"))
+        .arg("--non-interactive")
+        .args(args)
+        .current_dir(dir)
+        .env("PATH", std::env::join_paths(paths).unwrap())
+        .env("TUGGER_CACHE_DIR", dir.join("cache"))
+        .output()
+        .unwrap()
+}
+
+fn tugger_run(dir: &Path, args: &[&str]) -> Output {
+    tugger(dir, &[&["run"], args].concat())
+}
+
+/// Obtain the stdout and stderr of a run.
+///
+/// Logs go to stdout, so failures are found in both.
+fn output_text(output: &Output) -> String {
+    format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    )
+}
+
+fn assert_success(output: &Output) {
+    assert!(
+        output.status.success(),
+        "tugger run failed:\n{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+/// A request received by `serve()`.
+struct Request {
+    method: String,
+    path: String,
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(&name.to_lowercase()).map(String::as_str)
+    }
+}
+
+/// Serve HTTP requests with `handler` and obtain the base URL of the server.
+///
+/// `handler` returns the status and headers of the response, which has no
+/// body.
+fn serve<F>(handler: F) -> String
+where
+    F: Fn(&Request) -> (u16, Vec<(&'static str, String)>) + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let mut parts = line.split_whitespace();
+            let method = parts.next().unwrap_or_default().to_string();
+            let path = parts.next().unwrap_or_default().to_string();
+
+            let mut headers = HashMap::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                match line.trim_end().split_once(':') {
+                    Some((name, value)) => {
+                        headers.insert(name.to_lowercase(), value.trim().to_string());
+                    }
+                    None => break,
+                }
+            }
+
+            let len = headers
+                .get("content-length")
+                .map(|l| l.parse().unwrap())
+                .unwrap_or(0);
+            let mut body = vec![0; len];
+            reader.read_exact(&mut body).unwrap();
+
+            let (status, headers) = handler(&Request {
+                method,
+                path,
+                headers,
+                body,
+            });
+
+            let mut response = format!("HTTP/1.1 {} Status\r\nConnection: close\r\n", status);
+            for (name, value) in headers {
+                response.push_str(&format!("{}: {}\r\n", name, value));
+            }
+            response.push_str("Content-Length: 0\r\n\r\n");
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+    });
+
+    url
+}
+
+#[test]
+fn test_snapcraft_step() {
+    let dir = project(
+        r#"
+files = file_manifest_from_files(glob(["app.txt"]))
+config = snap(name="demo", version="1.0", summary="Demo", description="A demo.", base="core22", apps={}, parts={})
+pipeline("snap", steps=[snapcraft(["pack", "--destructive-mode"], config, "build", files)])
+"#,
+    );
+    let record = dir.path().join("snapcraft.log");
+    install_program(
+        dir.path(),
+        "snapcraft",
+        &format!(
+            "#!/bin/sh\n\
+             test \"$1\" = --version && echo 'snapcraft 8.0' && exit 0\n\
+             echo \"args: $*\" > {record}\n\
+             echo \"cwd: $(pwd)\" >> {record}\n\
+             cat snap/snapcraft.yaml >> {record}\n\
+             test -f app.txt\n",
+            record = record.display()
+        ),
+    );
+
+    assert_success(&tugger_run(dir.path(), &[]));
+
+    let build = dir.path().join("build").canonicalize().unwrap();
+    let log = std::fs::read_to_string(&record).unwrap();
+    let mut lines = log.lines();
+    assert_eq!(lines.next(), Some("args: pack --destructive-mode"));
+    assert_eq!(
+        lines.next(),
+        Some(format!("cwd: {}", build.display()).as_str())
+    );
+    assert!(log.contains("name: demo"), "{}", log);
+    assert!(log.contains("base: core22"), "{}", log);
+}
+
+#[test]
+fn test_locked_tool_versions() {
+    let dir = project(
+        r#"
+files = file_manifest_from_files(glob(["app.txt"]))
+config = snap(name="demo", version="1.0", summary="Demo", description="A demo.", apps={}, parts={})
+pipeline("snap", steps=[snapcraft(["pack"], config, "build", files)])
+"#,
+    );
+    let marker = dir.path().join("packed");
+    let snapcraft = |version: &str| {
+        format!(
+            "#!/bin/sh\n\
+             test \"$1\" = --version && echo 'snapcraft {}' && exit 0\n\
+             touch {}\n",
+            version,
+            marker.display()
+        )
+    };
+    let lockfile = dir.path().join("tugger.lock");
+    let read_lockfile = || -> serde_json::Value {
+        serde_json::from_slice(&std::fs::read(&lockfile).unwrap()).unwrap()
+    };
+
+    // Programs missing from the lockfile are refused in locked mode.
+    std::fs::write(&lockfile, "{\"resources\": {}}\n").unwrap();
+    install_program(dir.path(), "snapcraft", &snapcraft("8.0"));
+    let output = tugger_run(dir.path(), &["--locked"]);
+    assert!(!output.status.success());
+    let text = output_text(&output);
+    assert!(
+        text.contains("snapcraft isn't in tugger.lock; run `tugger update-lock`"),
+        "{}",
+        text
+    );
+
+    // Runs record the versions of programs they run.
+    assert_success(&tugger_run(dir.path(), &[]));
+    assert_eq!(read_lockfile()["tools"]["snapcraft"], "snapcraft 8.0");
+    assert_success(&tugger_run(dir.path(), &["--locked"]));
+
+    // Other versions are refused before any step executes.
+    std::fs::remove_file(&marker).unwrap();
+    install_program(dir.path(), "snapcraft", &snapcraft("8.1"));
+    let output = tugger_run(dir.path(), &["--locked"]);
+    assert!(!output.status.success());
+    let text = output_text(&output);
+    assert!(
+        text.contains(
+            "snapcraft is snapcraft 8.1 but tugger.lock records snapcraft 8.0; \
+             run `tugger update-lock`"
+        ),
+        "{}",
+        text
+    );
+    assert!(!marker.exists());
+
+    // Without --locked, the version of the lockfile is kept.
+    let output = tugger_run(dir.path(), &[]);
+    assert_success(&output);
+    assert!(String::from_utf8_lossy(&output.stdout)
+        .contains("snapcraft is snapcraft 8.1 but tugger.lock records snapcraft 8.0"));
+    assert_eq!(read_lockfile()["tools"]["snapcraft"], "snapcraft 8.0");
+
+    // Updating the lockfile records the version installed.
+    assert_success(&tugger(dir.path(), &["update-lock"]));
+    assert_eq!(read_lockfile()["tools"]["snapcraft"], "snapcraft 8.1");
+    assert_success(&tugger_run(dir.path(), &["--locked"]));
+}
+
+#[test]
+fn test_snapcraft_failure() {
+    let dir = project(
+        r#"
+files = file_manifest_from_files(glob(["app.txt"]))
+config = snap(name="demo", version="1.0", summary="Demo", description="A demo.", apps={}, parts={})
+pipeline("snap", steps=[snapcraft(["pack"], config, "build", files)])
+pipeline("other", steps=[snapcraft(["pack"], config, "build", files)])
+"#,
+    );
+    install_program(
+        dir.path(),
+        "snapcraft",
+        "#!/bin/sh\necho 'no space left' >&2\nexit 1\n",
+    );
+
+    let output = tugger_run(dir.path(), &["--keep-going"]);
+    assert!(!output.status.success());
+
+    let text = output_text(&output);
+    assert!(text.contains("unable to build snap demo in"), "{}", text);
+    assert!(text.contains("no space left"), "{}", text);
+    assert!(
+        text.contains("2 pipeline(s) failed: snap, other"),
+        "{}",
+        text
+    );
+}
+
+#[test]
+fn test_debian_deb_archive_step() {
+    let dir = project(
+        r#"
+files = file_manifest_from_files(glob(["app.txt"]), prefix="usr/share/demo")
+control = debian_control_binary_package(
+    package="demo",
+    version="1.0-1",
+    architecture="all",
+    maintainer="Demo <demo@example.com>",
+    description="Demo package",
+)
+pipeline("deb", steps=[debian_deb_archive(control, files)])
+"#,
+    );
+
+    // The dist directory doesn't exist before the step runs.
+    assert!(!dir.path().join("dist").exists());
+    assert_success(&tugger_run(dir.path(), &[]));
+
+    let deb = dir.path().join("dist").join("demo_1.0-1.deb");
+    let data = std::fs::read(&deb).unwrap();
+    assert!(data.starts_with(b"!<arch>\ndebian-binary"));
+
+    // dpkg-deb validates the package format more thoroughly, if available.
+    if let Ok(output) = Command::new("dpkg-deb").arg("-c").arg(&deb).output() {
+        let contents = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success(), "{}", contents);
+        assert!(
+            contents.contains(" ./usr/share/demo/app.txt"),
+            "{}",
+            contents
+        );
+    }
+}
+
+#[test]
+fn test_debian_deb_archive_compression() {
+    // dpkg-deb is needed to validate the packages.
+    if Command::new("dpkg-deb").arg("--version").output().is_err() {
+        eprintln!("dpkg-deb not found; skipping");
+        return;
+    }
+
+    for (algorithm, member) in &[
+        ("None", "data.tar"),
+        ("\"gzip\"", "data.tar.gz"),
+        ("\"xz\"", "data.tar.xz"),
+        ("\"zstd\"", "data.tar.zst"),
+    ] {
+        let dir = project(&format!(
+            r#"
+files = file_manifest_from_files(glob(["app.txt"]), prefix="usr/share/demo")
+control = debian_control_binary_package(
+    package="demo",
+    version="1.0-1",
+    architecture="all",
+    maintainer="Demo <demo@example.com>",
+    description="Demo package",
+)
+pipeline("deb", steps=[debian_deb_archive(control, files, compression=compression(algorithm={}))])
+"#,
+            algorithm
+        ));
+        assert_success(&tugger_run(dir.path(), &[]));
+
+        let deb = dir.path().join("dist").join("demo_1.0-1.deb");
+        let data = std::fs::read(&deb).unwrap();
+        // ar member names are padded with spaces to 16 bytes.
+        let name = format!("{:16}", member);
+        assert!(
+            data.windows(16).any(|w| w == name.as_bytes()),
+            "{} has no {} member",
+            deb.display(),
+            member
+        );
+
+        let output = Command::new("dpkg-deb")
+            .arg("-I")
+            .arg(&deb)
+            .output()
+            .unwrap();
+        let info = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            output.status.success(),
+            "{}: {}{}",
+            algorithm,
+            info,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert!(info.contains(" Package: demo"), "{}: {}", algorithm, info);
+        assert!(info.contains(" Version: 1.0-1"), "{}: {}", algorithm, info);
+
+        let output = Command::new("dpkg-deb")
+            .arg("-c")
+            .arg(&deb)
+            .output()
+            .unwrap();
+        let contents = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            output.status.success(),
+            "{}: {}{}",
+            algorithm,
+            contents,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert!(
+            contents.contains(" ./usr/share/demo/app.txt"),
+            "{}: {}",
+            algorithm,
+            contents
+        );
+    }
+}
+
+#[test]
+fn test_workspace_member_outside_root() {
+    for member in &["../other", "/tmp"] {
+        let dir = project(&format!("workspace([{:?}])\n", member));
+
+        let output = tugger_run(dir.path(), &[]);
+        assert!(!output.status.success());
+
+        let text = output_text(&output);
+        assert!(
+            text.contains(&format!(
+                "workspace member {} is not a relative path without .. components",
+                member
+            )),
+            "{}",
+            text
+        );
+    }
+}
+
+#[test]
+fn test_workspace_cycle() {
+    let dir = project("workspace([\"member\"])\n");
+    std::fs::create_dir(dir.path().join("member")).unwrap();
+    std::fs::write(
+        dir.path().join("member").join("tugger.ship"),
+        "workspace([\"nested\"])\n",
+    )
+    .unwrap();
+    std::os::unix::fs::symlink("..", dir.path().join("member").join("nested")).unwrap();
+
+    let output = tugger_run(dir.path(), &[]);
+    assert!(!output.status.success());
+
+    let text = output_text(&output);
+    assert!(text.contains("workspace cycle:"), "{}", text);
+    assert!(text.contains("is a member of itself"), "{}", text);
+}
+
+#[test]
+fn test_step_consumes_artifacts_of_last_run() {
+    let dir = project(
+        r#"
+files = file_manifest_from_files(glob(["app.txt"]))
+pipeline("release", steps=[
+    tar_archive("app.tar.gz", files),
+    release_bundle(["app.tar.gz"], layout="flat"),
+])
+"#,
+    );
+    let bundled = dir.path().join("dist").join("release").join("app.tar.gz");
+
+    // Without a previous run, the archive the bundle needs doesn't exist.
+    let output = tugger_run(dir.path(), &["--pipeline", "release", "--step", "1"]);
+    assert!(!output.status.success());
+    let text = output_text(&output);
+    assert!(
+        text.contains("step 1 (release_bundle) of pipeline release needs"),
+        "{}",
+        text
+    );
+    assert!(
+        text.contains("no earlier step produced it in the last run"),
+        "{}",
+        text
+    );
+
+    assert_success(&tugger_run(dir.path(), &["--pipeline", "release"]));
+    std::fs::remove_dir_all(dir.path().join("dist").join("release")).unwrap();
+
+    // The archive of the last run is bundled again.
+    assert_success(&tugger_run(
+        dir.path(),
+        &["--pipeline", "release", "--step", "release_bundle"],
+    ));
+    assert!(bundled.is_file());
+
+    std::fs::remove_file(dir.path().join("dist").join("app.tar.gz")).unwrap();
+    let output = tugger_run(dir.path(), &["--pipeline", "release", "--step", "1"]);
+    assert!(!output.status.success());
+    let text = output_text(&output);
+    assert!(
+        text.contains("step 0 (tar_archive) produced it in the last run, but it no longer exists"),
+        "{}",
+        text
+    );
+}
+
+#[test]
+fn test_mirror_uploads_concurrently() {
+    let dir = project(
+        r#"
+files = file_manifest_from_files(glob(["app.txt"]))
+pipeline("release", steps=[
+    tar_archive("a.tar.gz", files),
+    tar_archive("b.tar.gz", files),
+    tar_archive("c.tar.gz", files),
+    mirror(["a.tar.gz", "b.tar.gz", "c.tar.gz"], [s3("s3://bucket/app")], concurrency=2),
+])
+"#,
+    );
+    install_program(
+        dir.path(),
+        "aws",
+        &format!(
+            "#!/bin/sh\necho \"$@\" >> {}\n",
+            dir.path().join("aws.log").display()
+        ),
+    );
+
+    let output = tugger_run(dir.path(), &[]);
+    assert_success(&output);
+
+    let log = std::fs::read_to_string(dir.path().join("aws.log")).unwrap();
+    let mut uploads: Vec<&str> = log.lines().filter(|l| l.starts_with("s3 cp")).collect();
+    uploads.sort_unstable();
+    assert_eq!(uploads.len(), 3, "{}", log);
+    for (upload, name) in uploads.iter().zip(&["a", "b", "c"]) {
+        assert!(
+            upload.ends_with(&format!("{}.tar.gz s3://bucket/app/{}.tar.gz", name, name)),
+            "{}",
+            log
+        );
+    }
+
+    let text = output_text(&output);
+    assert!(text.contains("(3 of 3 artifacts,"), "{}", text);
+}
+
+/// Content received by a fake resumable upload server.
+#[derive(Default)]
+struct Upload {
+    data: Vec<u8>,
+
+    /// Offsets content was sent at.
+    offsets: Vec<usize>,
+
+    /// Number of chunks to accept before refusing the rest.
+    accept: Option<usize>,
+
+    /// Number of upload sessions created.
+    sessions: usize,
+}
+
+/// Upload a tar archive of `2.5 * chunk` bytes to a resumable upload server
+/// failing after the first chunk, then upload it again with `run --step`.
+///
+/// Returns the archive and the offsets content was sent at.
+fn interrupted_upload(
+    destination: &str,
+    chunk: usize,
+    upload: &Arc<Mutex<Upload>>,
+    setup: impl Fn(&Path),
+) -> (Vec<u8>, Vec<usize>) {
+    let dir = project(&format!(
+        r#"
+files = file_manifest_from_files(glob(["app.txt"]))
+pipeline("release", steps=[
+    tar_archive("app.tar", files),
+    mirror(["app.tar"], [{}]),
+])
+"#,
+        destination
+    ));
+    // A tar archive has a 512 byte header and a 1024 byte trailer.
+    std::fs::write(dir.path().join("app.txt"), vec![b'a'; chunk * 5 / 2 - 1536]).unwrap();
+    setup(dir.path());
+
+    upload.lock().unwrap().accept = Some(1);
+    let output = tugger_run(dir.path(), &[]);
+    assert!(!output.status.success(), "{}", output_text(&output));
+    assert_eq!(upload.lock().unwrap().data.len(), chunk);
+
+    upload.lock().unwrap().accept = None;
+    let output = tugger_run(dir.path(), &["--pipeline", "release", "--step", "mirror"]);
+    assert_success(&output);
+    let text = output_text(&output);
+    let resumed = format!("at byte {} of {}", chunk, chunk * 5 / 2);
+    assert!(text.contains(&resumed), "{}", text);
+
+    let upload = upload.lock().unwrap();
+    // The second run resumes the session saved by the first.
+    assert_eq!(upload.sessions, 1);
+    (
+        std::fs::read(dir.path().join("dist").join("app.tar")).unwrap(),
+        upload.offsets.clone(),
+    )
+}
+
+/// Accept a chunk of an upload at `offset`, unless no more are accepted.
+fn accept_chunk(upload: &mut Upload, offset: usize, data: &[u8]) -> bool {
+    upload.offsets.push(offset);
+
+    if upload.accept == Some(0) || offset != upload.data.len() {
+        return false;
+    }
+
+    upload.accept = upload.accept.map(|n| n - 1);
+    upload.data.extend_from_slice(data);
+
+    true
+}
+
+#[test]
+fn test_tus_upload_resumes() {
+    let upload = Arc::new(Mutex::new(Upload::default()));
+    let server = upload.clone();
+
+    let url = serve(move |request| {
+        let mut upload = server.lock().unwrap();
+        let offset = || ("Upload-Offset", upload.data.len().to_string());
+
+        match request.method.as_str() {
+            "POST" => {
+                upload.sessions += 1;
+                assert_eq!(request.path, "/files/");
+                assert_eq!(request.header("upload-length"), Some("10240"));
+                // "app.tar" in base64.
+                assert_eq!(
+                    request.header("upload-metadata"),
+                    Some("filename YXBwLnRhcg==")
+                );
+                (201, vec![("Location", "/files/1".to_string())])
+            }
+            "HEAD" => (200, vec![offset()]),
+            "PATCH" => {
+                let start = request.header("upload-offset").unwrap().parse().unwrap();
+                if accept_chunk(&mut upload, start, &request.body) {
+                    (204, vec![("Upload-Offset", upload.data.len().to_string())])
+                } else {
+                    (409, vec![])
+                }
+            }
+            _ => (405, vec![]),
+        }
+    });
+
+    let (data, offsets) = interrupted_upload(
+        &format!(r#"http("{}/files/", protocol="tus", part_size=4096)"#, url),
+        4096,
+        &upload,
+        |_| {},
+    );
+
+    assert_eq!(upload.lock().unwrap().data, data);
+    assert_eq!(offsets, vec![0, 4096, 4096, 8192]);
+}
+
+#[test]
+fn test_gcs_upload_resumes() {
+    let upload = Arc::new(Mutex::new(Upload::default()));
+    let server = upload.clone();
+
+    let url = serve(move |request| {
+        let mut upload = server.lock().unwrap();
+        let received = |upload: &Upload| match upload.data.len() {
+            0 => vec![],
+            len => vec![("Range", format!("bytes=0-{}", len - 1))],
+        };
+
+        match (request.method.as_str(), request.header("content-range")) {
+            ("POST", _) => {
+                upload.sessions += 1;
+                assert_eq!(
+                    request.path,
+                    "/upload/storage/v1/b/bucket/o?uploadType=resumable&name=app%2Fapp.tar"
+                );
+                assert_eq!(request.header("authorization"), Some("Bearer secret"));
+                assert_eq!(request.header("x-upload-content-length"), Some("655360"));
+                (200, vec![("Location", "/session/1".to_string())])
+            }
+            ("PUT", Some("bytes */655360")) => (308, received(&upload)),
+            ("PUT", Some(range)) => {
+                let start = range[6..].split('-').next().unwrap().parse().unwrap();
+                if !accept_chunk(&mut upload, start, &request.body) {
+                    (403, vec![])
+                } else if upload.data.len() == 655360 {
+                    (200, vec![])
+                } else {
+                    (308, received(&upload))
+                }
+            }
+            _ => (405, vec![]),
+        }
+    });
+
+    let (data, offsets) = interrupted_upload(
+        &format!(
+            r#"gcs("gs://bucket/app", part_size="256KiB", api_url="{}")"#,
+            url
+        ),
+        256 << 10,
+        &upload,
+        |dir| install_program(dir, "gcloud", "#!/bin/sh\necho secret\n"),
+    );
+
+    assert_eq!(upload.lock().unwrap().data, data);
+    assert_eq!(offsets, vec![0, 256 << 10, 256 << 10, 512 << 10]);
+}